@@ -0,0 +1,56 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use dictionary_lsp::dictionary_data::PrefixIndex;
+
+// Synthetic headwords standing in for a large JSON dictionary
+fn generate_words(count: usize) -> Vec<String> {
+  let syllables = ["ka", "ri", "to", "me", "su", "na", "lo", "pe", "di", "ba"];
+  (0..count)
+    .map(|i| {
+      let mut word = String::new();
+      let mut n = i;
+      loop {
+        word.push_str(syllables[n % syllables.len()]);
+        n /= syllables.len();
+        if n == 0 {
+          break;
+        }
+      }
+      word
+    })
+    .collect()
+}
+
+fn benchmark_json_prefix_lookup(c: &mut Criterion) {
+  let dictionary: serde_json::Map<String, serde_json::Value> = generate_words(200_000)
+    .into_iter()
+    .map(|word| (word, serde_json::json!({"noun": ["definition"]})))
+    .collect();
+  let index = PrefixIndex::new(dictionary.keys(), str::to_lowercase);
+
+  let mut group = c.benchmark_group("json_prefix_lookup");
+  for prefix in ["k", "kari", "karitome", "zzz"] {
+    group.bench_with_input(
+      BenchmarkId::new("linear_scan", prefix),
+      prefix,
+      |b, prefix| {
+        b.iter(|| {
+          dictionary
+            .keys()
+            .filter(|word| word.to_lowercase().starts_with(black_box(prefix)))
+            .take(50)
+            .count()
+        })
+      },
+    );
+
+    group.bench_with_input(
+      BenchmarkId::new("prefix_index", prefix),
+      prefix,
+      |b, prefix| b.iter(|| index.words_with_prefix(black_box(prefix)).take(50).count()),
+    );
+  }
+  group.finish();
+}
+
+criterion_group!(benches, benchmark_json_prefix_lookup);
+criterion_main!(benches);
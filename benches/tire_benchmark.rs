@@ -1,10 +1,10 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::collections::HashMap;
 use std::path::Path;
-use std::time::Instant;
 
 // Import necessary items from the project
 extern crate dictionary_lsp;
-use dictionary_lsp::tire;
+use dictionary_lsp::tire::{self, CaseMode};
 
 fn initialize_trie() -> Result<(), String> {
   // Use the test database file in the project directory
@@ -17,7 +17,7 @@ fn initialize_trie() -> Result<(), String> {
     ));
   }
 
-  tire::initialize_global_trie(freq_db_path.to_str().unwrap())
+  tire::initialize_global_trie(freq_db_path.to_str().unwrap(), false)
     .map_err(|e| format!("Failed to initialize trie: {:?}", e))?;
 
   if !tire::is_trie_initialized() {
@@ -67,75 +67,64 @@ fn benchmark_find_words_respecting_case(c: &mut Criterion) {
 
   let mut group = c.benchmark_group("find_words_respecting_case");
   for (prefix, description) in test_cases {
-    group.bench_function(format!("{}: '{}'", description, prefix), |b| {
-      b.iter(|| {
-        black_box(tire::find_words_respecting_case(
-          black_box(prefix),
-          black_box(10),
-        ))
-      });
-    });
+    for (mode, mode_name) in [
+      (CaseMode::CapitalizeFirst, "capitalize first"),
+      (CaseMode::Preserve, "preserve case"),
+    ] {
+      group.bench_function(
+        format!("{} ({}): '{}'", description, mode_name, prefix),
+        |b| {
+          b.iter(|| {
+            black_box(tire::find_words_respecting_case(
+              black_box(prefix),
+              black_box(10),
+              mode,
+            ))
+          });
+        },
+      );
+    }
   }
   group.finish();
 }
 
 fn benchmark_trie_fill_rate() {
-  // Check how many words are in each trie
-  let high_count = match &*tire::HIGH_FREQ_TRIE.read().unwrap() {
-    Some(trie) => {
-      let start = Instant::now();
-      let count = trie
-        .predictive_search(&vec![])
-        .collect::<Vec<Vec<char>>>()
-        .len();
-      println!(
-        "HIGH_FREQ_TRIE has {} words (listed in {:?})",
-        count,
-        start.elapsed()
-      );
-      count
-    }
-    None => 0,
-  };
-
-  let mid_count = match &*tire::MID_FREQ_TRIE.read().unwrap() {
-    Some(trie) => {
-      let start = Instant::now();
-      let count = trie
-        .predictive_search(&vec![])
-        .collect::<Vec<Vec<char>>>()
-        .len();
-      println!(
-        "MID_FREQ_TRIE has {} words (listed in {:?})",
-        count,
-        start.elapsed()
-      );
-      count
-    }
-    None => 0,
-  };
-
-  let low_count = match &*tire::LOW_FREQ_TRIE.read().unwrap() {
-    Some(trie) => {
-      let start = Instant::now();
-      let count = trie
-        .predictive_search(&vec![])
-        .collect::<Vec<Vec<char>>>()
-        .len();
-      println!(
-        "LOW_FREQ_TRIE has {} words (listed in {:?})",
-        count,
-        start.elapsed()
-      );
-      count
-    }
-    None => 0,
-  };
+  println!("Total words in trie: {}", tire::word_count());
+}
 
-  println!(
-    "Total words in tries: {}",
-    high_count + mid_count + low_count
-  );
+fn benchmark_top_k_selection(c: &mut Criterion) {
+  // Synthetic match set standing in for a short prefix with many completions
+  let words: Vec<String> = (0..20_000).map(|i| format!("pre{}", i)).collect();
+  let freq_map: HashMap<String, i64> = words
+    .iter()
+    .enumerate()
+    .map(|(i, word)| (word.clone(), ((i * 7919) % 100_000) as i64))
+    .collect();
+
+  let mut group = c.benchmark_group("top_k_selection");
+  group.bench_function("Full sort then take 10", |b| {
+    b.iter(|| {
+      let mut sorted = words.clone();
+      sorted.sort_by(|a, b| {
+        freq_map
+          .get(b)
+          .unwrap_or(&0)
+          .cmp(freq_map.get(a).unwrap_or(&0))
+      });
+      sorted.truncate(10);
+      black_box(sorted)
+    });
+  });
+  group.bench_function("Bounded heap top 10", |b| {
+    b.iter(|| {
+      black_box(tire::top_k_by_frequency(
+        words.iter().cloned(),
+        &freq_map,
+        black_box(10),
+      ))
+    });
+  });
+  group.finish();
 }
 
 fn benchmark_cache_effectiveness(c: &mut Criterion) {
@@ -178,6 +167,7 @@ fn criterion_benchmark(c: &mut Criterion) {
   // Run the actual benchmarks
   benchmark_find_words_by_prefix(c);
   benchmark_find_words_respecting_case(c);
+  benchmark_top_k_selection(c);
   benchmark_cache_effectiveness(c);
 }
 
@@ -1,19 +1,48 @@
-use crate::config::Config;
-use crate::dictionary_data::{self, DictionaryProvider};
+use crate::config::{Config, MatchMode, ScriptMode, UndefinedWords};
+use crate::dictionary_data::{self, DictionaryProvider, DictionaryResponse, Script};
 use crate::formatting::{self, FormattingConfig};
 use futures;
 use serde_json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 
+/// Detail of completion items the dictionary has no definition for
+const NO_DEFINITION_DETAIL: &str = "(no definition)";
+
 pub struct CompletionHandler {
   document_map: Arc<Mutex<HashMap<Url, String>>>,
-  dictionary_path: String,
-  freq_path: String,
-  provider: Option<Box<dyn DictionaryProvider + Send + Sync>>,
+  /// Finds the completions and looks up the definitions of resolved items. Built once, so
+  /// its caches last across requests, e.g. as the client re-resolves items while the list
+  /// is scrolled.
+  provider: Box<dyn DictionaryProvider>,
+  /// Caps the number of completion requests doing lookups at the same time
+  request_limiter: Semaphore,
+  /// Id of the most recent completion request per document
+  latest_requests: Mutex<HashMap<Url, u64>>,
+  next_request_id: AtomicU64,
+  match_mode: MatchMode,
+  script_mode: ScriptMode,
+  /// Templates for the documentation of resolved items, shared with hover
+  formatting: FormattingConfig,
+  insert_lemma: bool,
+  /// Lowercase words never offered as completions
+  stopwords: HashSet<String>,
+  dedup: bool,
+  expand_inflections: bool,
+  only_at_word_end: bool,
+  /// Senses shown in the documentation of resolved items
+  max_documentation_senses: Option<usize>,
+  undefined_words: UndefinedWords,
+  max_items: usize,
+  /// Words with uppercase letters keep their casing after a capitalized prefix
+  preserve_case: bool,
+  /// Whether the client renders markdown documentation, learned from its capabilities on
+  /// `initialize`
+  markdown_supported: AtomicBool,
 }
 
 impl CompletionHandler {
@@ -21,18 +50,149 @@ impl CompletionHandler {
     document_map: Arc<Mutex<HashMap<Url, String>>>,
     dictionary_path: String,
     freq_path: String,
+    config: &Config,
   ) -> Self {
+    let completion = &config.completion;
     CompletionHandler {
       document_map,
-      dictionary_path,
-      freq_path,
-      provider: None,
+      provider: dictionary_data::create_dictionary_provider(
+        Some(dictionary_path),
+        Some(freq_path),
+        config,
+      ),
+      request_limiter: Semaphore::new(completion.max_concurrent_requests.max(1)),
+      latest_requests: Mutex::new(HashMap::new()),
+      next_request_id: AtomicU64::new(0),
+      match_mode: completion.match_mode,
+      script_mode: completion.script,
+      formatting: config.formatting.clone(),
+      insert_lemma: completion.insert_lemma,
+      stopwords: config.stopwords.load(),
+      dedup: completion.dedup,
+      expand_inflections: completion.expand_inflections,
+      only_at_word_end: completion.only_at_word_end,
+      max_documentation_senses: completion.max_documentation_senses,
+      undefined_words: completion.undefined_words,
+      max_items: completion.item_limit(),
+      preserve_case: completion.preserve_case,
+      markdown_supported: AtomicBool::new(true),
     }
   }
 
-  #[cfg(test)]
-  pub fn get_paths(&self) -> (String, String) {
-    (self.dictionary_path.clone(), self.freq_path.clone())
+  /// Overrides the configured number of concurrent completion requests
+  pub fn with_concurrency_limit(mut self, limit: usize) -> Self {
+    self.request_limiter = Semaphore::new(limit.max(1));
+    self
+  }
+
+  /// Overrides the configured document script
+  pub fn with_script_mode(mut self, script_mode: ScriptMode) -> Self {
+    self.script_mode = script_mode;
+    self
+  }
+
+  /// Overrides the configured formatting of resolved items
+  pub fn with_formatting(mut self, formatting: FormattingConfig) -> Self {
+    self.formatting = formatting;
+    self
+  }
+
+  /// Overrides whether inflected words insert their dictionary form
+  pub fn with_insert_lemma(mut self, insert_lemma: bool) -> Self {
+    self.insert_lemma = insert_lemma;
+    self
+  }
+
+  /// Overrides whether case and diacritic duplicates are collapsed
+  pub fn with_dedup(mut self, dedup: bool) -> Self {
+    self.dedup = dedup;
+    self
+  }
+
+  /// Overrides whether words keep their dictionary casing
+  pub fn with_preserve_case(mut self, preserve_case: bool) -> Self {
+    self.preserve_case = preserve_case;
+    self
+  }
+
+  /// Overrides whether inflected forms of the completed words are offered too
+  pub fn with_expand_inflections(mut self, expand_inflections: bool) -> Self {
+    self.expand_inflections = expand_inflections;
+    self
+  }
+
+  /// Overrides whether completion is offered inside words
+  pub fn with_only_at_word_end(mut self, only_at_word_end: bool) -> Self {
+    self.only_at_word_end = only_at_word_end;
+    self
+  }
+
+  /// Overrides the configured number of senses in the documentation of resolved items
+  pub fn with_max_documentation_senses(mut self, max_senses: Option<usize>) -> Self {
+    self.max_documentation_senses = max_senses;
+    self
+  }
+
+  /// Overrides how candidates without a definition are offered
+  pub fn with_undefined_words(mut self, undefined_words: UndefinedWords) -> Self {
+    self.undefined_words = undefined_words;
+    self
+  }
+
+  /// Overrides the configured number of completion items
+  pub fn with_max_items(mut self, max_items: usize) -> Self {
+    self.max_items = max_items.max(1);
+    self
+  }
+
+  /// Records the documentation formats the client accepts
+  /// (`CompletionItemCapability::documentation_format`). Markdown is used unless the client
+  /// lists formats without it.
+  pub fn set_documentation_format(&self, formats: Option<&[MarkupKind]>) {
+    let markdown_supported = formats.is_none_or(|formats| formats.contains(&MarkupKind::Markdown));
+    self
+      .markdown_supported
+      .store(markdown_supported, Ordering::Relaxed);
+  }
+
+  /// Overrides the configured stopwords, which must be lowercase
+  pub fn with_stopwords(mut self, stopwords: HashSet<String>) -> Self {
+    self.stopwords = stopwords;
+    self
+  }
+
+  /// Drops cached definitions, e.g. after the dictionary was reloaded
+  pub async fn clear_cache(&self) {
+    self.provider.clear_cache().await;
+  }
+
+  /// Loads the dictionary that resolved items are looked up in
+  pub async fn warm_up(&self) -> Result<()> {
+    self.provider.warm_up().await
+  }
+
+  /// Registers a new completion request for `uri` and returns its id
+  async fn register_request(&self, uri: &Url) -> u64 {
+    let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+    self
+      .latest_requests
+      .lock()
+      .await
+      .insert(uri.clone(), request_id);
+    request_id
+  }
+
+  /// A request is superseded once a newer request for the same document arrived.
+  /// The newest request removes its entry once dequeued, so closed documents leave none behind.
+  async fn is_superseded(&self, uri: &Url, request_id: u64) -> bool {
+    let mut latest_requests = self.latest_requests.lock().await;
+    match latest_requests.get(uri) {
+      Some(latest) if *latest != request_id => true,
+      _ => {
+        latest_requests.remove(uri);
+        false
+      }
+    }
   }
 
   #[cfg(test)]
@@ -40,7 +200,9 @@ impl CompletionHandler {
     mut self,
     provider: impl DictionaryProvider + Send + Sync + 'static,
   ) -> Self {
-    self.provider = Some(Box::new(provider));
+    self.provider = Box::new(dictionary_data::CachedDictionaryProvider::new(Box::new(
+      provider,
+    )));
     self
   }
 
@@ -82,6 +244,7 @@ impl CompletionHandler {
 
   /// Completion request handler
   /// ### expected behavior
+  /// - If a newer request for the same document arrived while this one was queued, return None
   /// - If the document is not found, return None
   /// - If the current word is less than 2 characters, return None (boost performance)
   /// - If the provider is available, use it to find words by prefix
@@ -94,6 +257,16 @@ impl CompletionHandler {
     let document_uri = params.text_document_position.text_document.uri.clone();
     let position = params.text_document_position.position;
 
+    // Queue behind in-flight requests, and drop this one if the user kept typing meanwhile
+    let request_id = self.register_request(&document_uri).await;
+    let _permit = match self.request_limiter.acquire().await {
+      Ok(permit) => permit,
+      Err(_) => return Ok(None),
+    };
+    if self.is_superseded(&document_uri, request_id).await {
+      return Ok(None);
+    }
+
     let content = match self.document_map.lock().await.get(&document_uri) {
       Some(content) => content.clone(),
       None => return Ok(None),
@@ -105,23 +278,37 @@ impl CompletionHandler {
       None => return Ok(None),
     };
 
-    // Use the existing provider (which might be our mock in tests) if available,
-    // otherwise create a new one
-    let words = if let Some(provider) = &self.provider {
-      match provider.find_words_by_prefix(&current_word).await {
-        Ok(Some(words)) => words,
-        _ => return Ok(None),
-      }
-    } else {
-      let provider = dictionary_data::create_dictionary_provider(
-        Some(self.dictionary_path.clone()),
-        Some(self.freq_path.clone()),
-      );
-      match provider.find_words_by_prefix(&current_word).await {
-        Ok(Some(words)) => words,
-        _ => return Ok(None),
-      }
+    let provider = self.provider.as_ref();
+    let mut words = match provider.find_words(&current_word, self.match_mode).await {
+      Ok(Some(words)) => words,
+      _ => return Ok(None),
     };
+    // Providers fetch one word past `max_items`, so getting it back means the list was cut off
+    // and the client re-queries as the user keeps typing. Counted before filtering, which can
+    // shrink a cut-off list below the limit.
+    let is_incomplete = words.len() > self.max_items;
+    if !self.stopwords.is_empty() {
+      words.retain(|word| !self.stopwords.contains(&word.to_lowercase()));
+    }
+    if self.dedup {
+      // Keeps the first, best-ranked spelling of each word
+      let mut seen = HashSet::new();
+      words.retain(|word| seen.insert(dictionary_data::dedup_key(word)));
+    }
+
+    words.truncate(self.max_items);
+    let mut words = match self.expand_inflections {
+      true => expand_inflections(provider, words).await,
+      false => words.into_iter().map(|word| (word, None)).collect(),
+    };
+    // Looked up with the resolve provider, so resolving the items hits its cache
+    let undefined = match self.undefined_words {
+      UndefinedWords::Offer => HashSet::new(),
+      _ => find_undefined(provider, &words).await,
+    };
+    if self.undefined_words == UndefinedWords::Hide {
+      words.retain(|(word, _)| !undefined.contains(word));
+    }
 
     // Pre-allocate with capacity for better performance
     let mut items = Vec::with_capacity(words.len());
@@ -133,16 +320,29 @@ impl CompletionHandler {
       .map_or(false, |c| c.is_uppercase());
 
     // Process words without fetching meanings
-    for word in words {
+    for (rank, (word, inflects)) in words.into_iter().enumerate() {
       // Apply capitalization if needed
-      let final_word = if starts_with_uppercase && !word.is_empty() {
-        let mut capitalized = word.to_string();
-        if let Some(first_char) = capitalized.get_mut(0..1) {
-          first_char.make_ascii_uppercase();
+      let capitalize = |word: &str| {
+        let keeps_case = self.preserve_case && word.chars().any(char::is_uppercase);
+        if starts_with_uppercase && !keeps_case {
+          crate::tire::capitalize_first(word)
+        } else {
+          word.to_string()
         }
-        capitalized
-      } else {
-        word.clone()
+      };
+      let final_word = capitalize(&word);
+
+      // Inflected words insert their lemma but keep the typed form in the label
+      let lemma = match self.insert_lemma && inflects.is_none() {
+        true => find_lemma(provider, &word).await,
+        false => None,
+      };
+      let (label, new_text) = match &lemma {
+        Some(lemma) => (
+          format!("{} ({})", final_word, capitalize(lemma)),
+          capitalize(lemma),
+        ),
+        None => (final_word.clone(), final_word.clone()),
       };
 
       let text_edit = TextEdit {
@@ -153,18 +353,26 @@ impl CompletionHandler {
           },
           end: position,
         },
-        new_text: final_word.clone(),
+        new_text,
       };
 
-      // Store the original word as data for later resolution
-      let data = serde_json::to_value(word.clone()).unwrap_or_default();
+      // Store the original word as data for later resolution; inflected forms resolve to
+      // the definition of the word they inflect
+      let data = serde_json::to_value(inflects.as_ref().unwrap_or(&word)).unwrap_or_default();
 
       // Create completion item without documentation (will be resolved later)
       let item = CompletionItem {
-        label: final_word.clone(),
+        detail: undefined
+          .contains(&word)
+          .then(|| NO_DEFINITION_DETAIL.to_string()),
+        label,
         kind: Some(CompletionItemKind::KEYWORD),
         text_edit: Some(CompletionTextEdit::Edit(text_edit)),
         data: Some(data),
+        // Clients sort by label unless `sort_text` is set; keep the provider's ranking
+        sort_text: Some(format!("{:04}", rank)),
+        // Fuzzy corrections may not start with the typed text and would be filtered out
+        filter_text: (!shares_prefix(&final_word, &current_word)).then(|| current_word.clone()),
         ..Default::default()
       };
 
@@ -172,7 +380,7 @@ impl CompletionHandler {
     }
 
     let list = CompletionList {
-      is_incomplete: items.len() >= Config::get().completion.max_distance as usize,
+      is_incomplete,
       items,
     };
 
@@ -181,6 +389,7 @@ impl CompletionHandler {
 
   /// Extracts the current word and its starting position from the content at the given position
   /// This function is enhaced to support CJK characters
+  /// Positions count UTF-16 code units, as LSP clients do
   /// ### expected behavior
   /// 你好check$%^&你好你好test <---cursor plased here
   /// should return "test" and the start position of "test" in the line
@@ -193,35 +402,35 @@ impl CompletionHandler {
     // Get the line at the cursor position
     let line = content.lines().nth(position.line as usize)?;
 
-    // Convert cursor position to byte index
-    let char_pos = position.character as usize;
-    let mut char_indices = line.char_indices();
-    let before_cursor_end = if char_pos == 0 {
-      0
-    } else {
-      // Find the byte index for the character at position
-      let mut last_idx = 0;
-      let mut last_char_len = 0;
-
-      for _ in 0..char_pos {
-        if let Some((idx, c)) = char_indices.next() {
-          last_idx = idx;
-          last_char_len = c.len_utf8();
-        } else {
-          return None; // Position is out of bounds
-        }
-      }
-      last_idx + last_char_len
-    };
+    // Convert the cursor position (UTF-16 code units) to a byte index
+    let before_cursor_end = dictionary_data::utf16_to_byte_index(line, position.character)?;
+
+    // Completing inside a word would replace only the letters before the cursor
+    if self.only_at_word_end && continues_word(&line[before_cursor_end..]) {
+      return None;
+    }
 
     // Get text before cursor
     let before_cursor = &line[..before_cursor_end];
 
+    let script = match self.script_mode {
+      ScriptMode::Auto => dictionary_data::detect_script(content),
+      ScriptMode::Cjk => Some(Script::Cjk),
+      ScriptMode::Latin => Some(Script::Latin),
+    };
+
+    // In mostly English documents the typed word is usually a plain run of Latin letters,
+    // so try that before scanning the line for Chinese+English patterns
+    if script == Some(Script::Latin) {
+      if let Some(word) = self.extract_latin_word_before_cursor(before_cursor, line) {
+        return Some(word);
+      }
+    }
+
     // First, check for Chinese+English pattern
-    if let Some((english_part, start_char_count)) =
-      self.extract_english_after_chinese(before_cursor)
+    if let Some((english_part, start_character)) = self.extract_english_after_chinese(before_cursor)
     {
-      return Some((english_part, start_char_count));
+      return Some((english_part, start_character));
     }
 
     // If the last character is CJK, return None
@@ -254,14 +463,31 @@ impl CompletionHandler {
     if let Some(start_idx) = english_start_idx {
       let english_part = &text[start_idx..];
       if english_part.chars().all(|c| c.is_alphabetic()) && !english_part.is_empty() {
-        let start_char_count = text[..start_idx].chars().count() as u32;
-        return Some((english_part.to_string(), start_char_count));
+        let start_character = dictionary_data::utf16_len(&text[..start_idx]);
+        return Some((english_part.to_string(), start_character));
       }
     }
 
     None
   }
 
+  // Helper method to extract the non-CJK letters right before the cursor
+  fn extract_latin_word_before_cursor(
+    &self,
+    before_cursor: &str,
+    line: &str,
+  ) -> Option<(String, u32)> {
+    let start_byte_idx = before_cursor
+      .char_indices()
+      .rev()
+      .take_while(|(_, c)| c.is_alphabetic() && !dictionary_data::is_cjk_char(*c))
+      .last()
+      .map(|(i, _)| i)?;
+
+    let start_character = dictionary_data::utf16_len(&line[..start_byte_idx]);
+    Some((before_cursor[start_byte_idx..].to_string(), start_character))
+  }
+
   // Helper method to extract alphabetic word before cursor
   fn extract_alphabetic_word_before_cursor(
     &self,
@@ -290,8 +516,8 @@ impl CompletionHandler {
     if current_word.is_empty() {
       None
     } else {
-      let start_char_count = line[..start_byte_idx].chars().count() as u32;
-      Some((current_word, start_char_count))
+      let start_character = dictionary_data::utf16_len(&line[..start_byte_idx]);
+      Some((current_word, start_character))
     }
   }
 
@@ -300,20 +526,40 @@ impl CompletionHandler {
     // Extract the word from the item's data
     if let Some(data) = &item.data {
       if let Ok(word) = serde_json::from_value::<String>(data.clone()) {
-        // Create a provider to look up the definition
-        let provider = dictionary_data::SqliteDictionaryProvider::new(
-          Some(self.dictionary_path.clone()),
-          Some(self.freq_path.clone()),
-        );
-
         // Get the meaning for the word
-        if let Ok(Some(meaning)) = provider.get_meaning(&word).await {
-          // Use the formatting utility to create well-formatted markdown documentation
-          let documentation = formatting::format_definition_as_markdown(&word, &meaning);
+        if let Ok(Some(mut meaning)) = self.provider.get_meaning(&word).await {
+          // Render with the same templates as hover, keeping the popup short
+          let hidden = truncate_senses(&mut meaning, self.max_documentation_senses);
+          let markdown = self.markdown_supported.load(Ordering::Relaxed);
+          let mut documentation = match markdown {
+            true => formatting::format_definition_as_markdown_with_config(
+              &word,
+              &meaning,
+              &self.formatting,
+            ),
+            false => formatting::format_definition_as_plaintext_with_config(
+              &word,
+              &meaning,
+              &self.formatting,
+            ),
+          };
+          let more = match hidden {
+            0 => None,
+            1 => Some("… and 1 more sense".to_string()),
+            _ => Some(format!("… and {} more senses", hidden)),
+          };
+          match (more, markdown) {
+            (Some(more), true) => documentation.push_str(&format!("_{}_\n", more)),
+            (Some(more), false) => documentation.push_str(&format!("{}\n", more)),
+            (None, _) => {}
+          }
 
           if !documentation.is_empty() {
             item.documentation = Some(Documentation::MarkupContent(MarkupContent {
-              kind: MarkupKind::Markdown,
+              kind: match markdown {
+                true => MarkupKind::Markdown,
+                false => MarkupKind::PlainText,
+              },
               value: documentation,
             }));
 
@@ -322,7 +568,7 @@ impl CompletionHandler {
           }
         } else {
           // If no definition found
-          item.detail = Some("No definition found".to_string());
+          item.detail = Some(NO_DEFINITION_DETAIL.to_string());
         }
       }
     }
@@ -331,6 +577,110 @@ impl CompletionHandler {
   }
 }
 
+/// Keeps the first `max_senses` senses of `response` across its parts of speech, returning
+/// how many were dropped. `None` or 0 keeps every sense.
+fn truncate_senses(response: &mut DictionaryResponse, max_senses: Option<usize>) -> usize {
+  let Some(mut remaining) = max_senses.filter(|max| *max > 0) else {
+    return 0;
+  };
+  let mut hidden = 0;
+  // Parts of speech whose senses were all cut are dropped with them
+  response.meanings.retain_mut(|meaning| {
+    let total = meaning.definitions.len();
+    let kept = total.min(remaining);
+    hidden += total - kept;
+    remaining -= kept;
+    meaning.definitions.truncate(kept);
+    kept > 0 || total == 0
+  });
+  hidden
+}
+
+/// Candidates of `words` without a definition of their own. Inflected forms are
+/// defined by the word they inflect, which the dictionary had.
+async fn find_undefined(
+  provider: &dyn DictionaryProvider,
+  words: &[(String, Option<String>)],
+) -> HashSet<String> {
+  let lookups: Vec<&str> = words
+    .iter()
+    .filter(|(_, inflects)| inflects.is_none())
+    .map(|(word, _)| word.as_str())
+    .collect();
+  let meanings = provider.get_meanings(&lookups).await.unwrap_or_default();
+  meanings
+    .into_iter()
+    .filter(|(word, response)| {
+      // Fuzzy matches define another word
+      !response
+        .as_ref()
+        .is_some_and(|response| response.word.eq_ignore_ascii_case(word))
+    })
+    .map(|(word, _)| word)
+    .collect()
+}
+
+/// First lemma candidate of `word` that is a dictionary headword
+async fn find_lemma(provider: &dyn DictionaryProvider, word: &str) -> Option<String> {
+  for candidate in crate::stemming::lemma_candidates(word) {
+    if provider
+      .check_word(&candidate)
+      .await
+      .is_ok_and(|check| check.known)
+    {
+      return Some(candidate);
+    }
+  }
+  None
+}
+
+/// Each of `words` followed by the inflected forms of its definitions' parts of speech,
+/// paired with the word a form inflects. Forms already in the list are not repeated.
+async fn expand_inflections(
+  provider: &dyn DictionaryProvider,
+  words: Vec<String>,
+) -> Vec<(String, Option<String>)> {
+  let lookups: Vec<&str> = words.iter().map(String::as_str).collect();
+  let meanings = provider.get_meanings(&lookups).await.unwrap_or_default();
+  let mut seen: HashSet<String> = words.iter().map(|word| word.to_lowercase()).collect();
+  let mut expanded = Vec::with_capacity(words.len());
+  for word in words {
+    let response = meanings
+      .iter()
+      .find(|(lookup, _)| *lookup == word)
+      .and_then(|(_, response)| response.as_ref())
+      // Fuzzy matches define another word
+      .filter(|response| response.word.eq_ignore_ascii_case(&word));
+    let forms: Vec<String> = response
+      .map(|response| {
+        response
+          .meanings
+          .iter()
+          .flat_map(|meaning| crate::stemming::inflected_forms(&word, &meaning.part_of_speech))
+          .filter(|form| seen.insert(form.clone()))
+          .collect()
+      })
+      .unwrap_or_default();
+    expanded.push((word.clone(), None));
+    expanded.extend(forms.into_iter().map(|form| (form, Some(word.clone()))));
+  }
+  expanded
+}
+
+/// Whether the text after the cursor starts with a letter of the word being typed.
+/// CJK text has no spaces between words, so a following CJK character is a boundary.
+fn continues_word(after_cursor: &str) -> bool {
+  after_cursor
+    .chars()
+    .next()
+    .is_some_and(|c| c.is_alphanumeric() && !dictionary_data::is_cjk_char(c))
+}
+
+/// Whether `word` starts with `typed`, ignoring case
+fn shares_prefix(word: &str, typed: &str) -> bool {
+  word.to_lowercase().starts_with(&typed.to_lowercase())
+}
+
 /////// Tests ///////
 #[cfg(test)]
 mod tests {
@@ -354,10 +704,7 @@ mod tests {
     let dict_path = "./test/test_dict.db".to_string();
     let freq_path = "./test/test_freq.db".to_string();
 
-    let handler = CompletionHandler::new(document_map, dict_path.clone(), freq_path.clone());
-    #[cfg(test)]
-    assert_eq!(handler.get_paths(), (dict_path, freq_path));
-    handler
+    CompletionHandler::new(document_map, dict_path, freq_path, &Config::default())
   }
 
   #[tokio::test]
@@ -396,6 +743,36 @@ mod tests {
     assert_eq!(result_1, None);
   }
 
+  /// Forcing either script must extract the same words as detection does
+  #[tokio::test]
+  async fn test_current_word_is_independent_of_script() {
+    let documents = [
+      ("今天学习新单词 appl", 12, Some(("appl".to_string(), 8))),
+      ("今天学习新单词appl再见", 9, Some(("ap".to_string(), 7))),
+      ("今天学习新单词appl再见", 12, None),
+      ("Learning 单词 tod", 14, Some(("to".to_string(), 12))),
+      ("Learning 单词tod", 14, Some(("tod".to_string(), 11))),
+      ("Learning words to-day", 21, Some(("day".to_string(), 18))),
+    ];
+
+    for mode in [ScriptMode::Auto, ScriptMode::Cjk, ScriptMode::Latin] {
+      let handler = setup_test_handler().with_script_mode(mode);
+      for (content, character, expected) in &documents {
+        let position = Position {
+          line: 0,
+          character: *character,
+        };
+        assert_eq!(
+          handler.get_current_word_and_start(content, position).await,
+          *expected,
+          "{:?} {:?}",
+          mode,
+          content
+        );
+      }
+    }
+  }
+
   #[tokio::test]
   async fn test_get_current_word_and_start_middle_of_text() {
     let handler = setup_test_handler();
@@ -477,21 +854,14 @@ mod tests {
 
     let handler = setup_test_handler().with_provider(mock_dict);
 
-    match &handler.provider {
-      Some(provider) => {
-        let result = provider.find_words_by_prefix(test_prefix).await;
-
-        match result {
-          Ok(Some(words)) => {
-            assert_eq!(words.len(), 2);
-            assert_eq!(words[0], "word");
-            assert_eq!(words[1], "world");
-          }
-          Ok(None) => panic!("Expected words but got None"),
-          Err(e) => panic!("Error finding words: {:?}", e),
-        }
+    match handler.provider.find_words_by_prefix(test_prefix).await {
+      Ok(Some(words)) => {
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0], "word");
+        assert_eq!(words[1], "world");
       }
-      None => panic!("No provider available"),
+      Ok(None) => panic!("Expected words but got None"),
+      Err(e) => panic!("Error finding words: {:?}", e),
     }
   }
 
@@ -516,9 +886,10 @@ mod tests {
 
     let dict_path = "test_dict.db".to_string();
     let freq_path = "test_freq.db".to_string();
-    let mut handler = CompletionHandler::new(document_map, dict_path, freq_path);
+    let mut handler =
+      CompletionHandler::new(document_map, dict_path, freq_path, &Config::default());
 
-    handler.provider = Some(Box::new(mock_dict));
+    handler.provider = Box::new(mock_dict);
 
     let params = CompletionParams {
       text_document_position: TextDocumentPositionParams {
@@ -574,4 +945,938 @@ mod tests {
     test_complete_end_to_end_workflow("wor", vec!["word".to_string(), "world".to_string()]).await;
     test_complete_end_to_end_workflow("Wo", vec!["Word".to_string(), "World".to_string()]).await;
   }
+
+  async fn complete_with_results(results: Vec<String>) -> CompletionList {
+    complete_typed_with_results("w", results).await
+  }
+
+  async fn complete_typed_with_results(typed: &str, results: Vec<String>) -> CompletionList {
+    complete_typed_with(typed, results, |handler| handler).await
+  }
+
+  /// Completes `typed` from `results` with a handler adjusted by `configure`
+  async fn complete_typed_with(
+    typed: &str,
+    results: Vec<String>,
+    configure: impl FnOnce(CompletionHandler) -> CompletionHandler,
+  ) -> CompletionList {
+    let mut mock_dict = MockDictionaryProvider::new();
+    mock_dict
+      .expect_find_words_by_prefix()
+      .returning(move |_| Ok(Some(results.clone())));
+
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let test_uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(test_uri.clone(), typed.to_string());
+    let handler = configure(
+      CompletionHandler::new(
+        document_map,
+        String::new(),
+        String::new(),
+        &Config::default(),
+      )
+      .with_provider(mock_dict),
+    );
+
+    let params = CompletionParams {
+      text_document_position: TextDocumentPositionParams {
+        text_document: TextDocumentIdentifier { uri: test_uri },
+        position: Position {
+          line: 0,
+          character: dictionary_data::utf16_len(typed),
+        },
+      },
+      context: None,
+      work_done_progress_params: WorkDoneProgressParams::default(),
+      partial_result_params: PartialResultParams::default(),
+    };
+
+    match handler.on_completion(params).await {
+      Ok(Some(CompletionResponse::List(list))) => list,
+      _ => panic!("Expected CompletionResponse::List"),
+    }
+  }
+
+  /// The edit range is in UTF-16 code units, so characters outside the BMP count twice
+  #[tokio::test]
+  async fn test_edit_range_after_cjk_covers_typed_word() {
+    for (typed, start) in [("你好test", 2), ("𠀀𠀁test", 4)] {
+      let list = complete_typed_with_results(typed, vec!["testing".to_string()]).await;
+      let Some(CompletionTextEdit::Edit(edit)) = &list.items[0].text_edit else {
+        panic!("Expected CompletionTextEdit::Edit");
+      };
+      assert_eq!(edit.range.start.character, start, "{}", typed);
+      assert_eq!(edit.range.end.character, start + 4, "{}", typed);
+
+      // Applying the edit replaces exactly "test"
+      let from = dictionary_data::utf16_to_byte_index(typed, edit.range.start.character).unwrap();
+      let to = dictionary_data::utf16_to_byte_index(typed, edit.range.end.character).unwrap();
+      assert_eq!(&typed[from..to], "test");
+      let edited = format!("{}{}{}", &typed[..from], edit.new_text, &typed[to..]);
+      assert_eq!(edited, typed.replace("test", "testing"));
+    }
+  }
+
+  /// `is_incomplete` must be set exactly when the provider returned more words than shown
+  #[tokio::test]
+  async fn test_is_incomplete_only_when_truncated() {
+    let limit = dictionary_data::PREFIX_RESULT_LIMIT;
+    let words = |count: usize| (0..count).map(|i| format!("w{}", i)).collect::<Vec<_>>();
+
+    let exact = complete_with_results(words(limit)).await;
+    assert_eq!(exact.items.len(), limit);
+    assert!(!exact.is_incomplete);
+
+    let truncated = complete_with_results(words(limit + 1)).await;
+    assert_eq!(truncated.items.len(), limit);
+    assert!(truncated.is_incomplete);
+
+    // Dropping a duplicate leaves fewer words than the limit, but the provider still cut off
+    let mut duplicated = words(limit);
+    duplicated.insert(1, "W0".to_string());
+    let deduplicated =
+      complete_typed_with("w", duplicated, |handler| handler.with_dedup(true)).await;
+    assert_eq!(deduplicated.items.len(), limit);
+    assert!(deduplicated.is_incomplete);
+  }
+
+  #[tokio::test]
+  async fn test_case_and_diacritic_duplicates_are_collapsed() {
+    let words = ["color", "Color", "colour", "café", "Cafe"].map(String::from);
+    let labels = |list: CompletionList| {
+      list
+        .items
+        .into_iter()
+        .map(|item| item.label)
+        .collect::<Vec<_>>()
+    };
+
+    let deduplicated = complete_with_results(words.to_vec()).await;
+    assert_eq!(labels(deduplicated), vec!["color", "colour", "café"]);
+  }
+
+  /// `sort_text` must preserve the provider's frequency order instead of the alphabetical one
+  #[tokio::test]
+  async fn test_sort_text_follows_result_order() {
+    let words = vec!["zebra", "apple", "mango", "banana"];
+    let list = complete_with_results(words.iter().map(|w| w.to_string()).collect()).await;
+
+    let labels: Vec<&str> = list.items.iter().map(|item| item.label.as_str()).collect();
+    assert_eq!(labels, words);
+
+    let sort_texts: Vec<&String> = list
+      .items
+      .iter()
+      .map(|item| item.sort_text.as_ref().unwrap())
+      .collect();
+    assert!(sort_texts.windows(2).all(|pair| pair[0] < pair[1]));
+  }
+
+  /// Corrections that don't start with the typed text carry it as `filter_text`
+  #[tokio::test]
+  async fn test_filter_text_keeps_fuzzy_corrections() {
+    let list = complete_typed_with_results(
+      "acommodate",
+      vec!["accommodate".to_string(), "acommodates".to_string()],
+    )
+    .await;
+
+    assert_eq!(list.items[0].label, "accommodate");
+    assert_eq!(list.items[0].filter_text.as_deref(), Some("acommodate"));
+    // Ordinary prefix matches are left to the client's default filtering
+    assert_eq!(list.items[1].filter_text, None);
+  }
+
+  #[tokio::test]
+  async fn test_capitalizes_multibyte_first_char() {
+    let list = complete_typed_with_results("Él", vec!["élan".to_string()]).await;
+    assert_eq!(list.items[0].label, "Élan");
+
+    let list = complete_typed_with_results("Ab", vec!["äbte".to_string()]).await;
+    assert_eq!(list.items[0].label, "Äbte");
+  }
+
+  #[tokio::test]
+  async fn test_preserve_case_keeps_dictionary_casing() {
+    let results = vec!["iPhone".to_string(), "iphonography".to_string()];
+    let labels = |list: CompletionList| -> Vec<String> {
+      list.items.into_iter().map(|item| item.label).collect()
+    };
+
+    let list = complete_typed_with("Iph", results.clone(), |handler| {
+      handler.with_preserve_case(true)
+    })
+    .await;
+    assert_eq!(labels(list), ["iPhone", "Iphonography"]);
+
+    let list =
+      complete_typed_with("Iph", results, |handler| handler.with_preserve_case(false)).await;
+    assert_eq!(labels(list), ["IPhone", "Iphonography"]);
+  }
+
+  /// Provider that records how many prefix lookups run at the same time
+  struct SlowProvider {
+    in_flight: std::sync::atomic::AtomicUsize,
+    max_in_flight: Arc<std::sync::atomic::AtomicUsize>,
+  }
+
+  #[async_trait::async_trait]
+  impl DictionaryProvider for SlowProvider {
+    async fn get_meaning(&self, _word: &str) -> Result<Option<DictionaryResponse>> {
+      Ok(None)
+    }
+
+    fn get_word_at_position(&self, _content: &str, _position: Position) -> Option<String> {
+      None
+    }
+
+    async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>> {
+      let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+      self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+      tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+      self.in_flight.fetch_sub(1, Ordering::SeqCst);
+      Ok(Some(vec![prefix.to_string()]))
+    }
+  }
+
+  fn completion_params(uri: Url, character: u32) -> CompletionParams {
+    CompletionParams {
+      text_document_position: TextDocumentPositionParams {
+        text_document: TextDocumentIdentifier { uri },
+        position: Position { line: 0, character },
+      },
+      context: None,
+      work_done_progress_params: WorkDoneProgressParams::default(),
+      partial_result_params: PartialResultParams::default(),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_concurrency_cap_is_respected() {
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let mut uris = Vec::new();
+    for i in 0..10 {
+      let uri = Url::parse(&format!("file:///test{}.txt", i)).unwrap();
+      document_map
+        .lock()
+        .await
+        .insert(uri.clone(), "word".to_string());
+      uris.push(uri);
+    }
+
+    let max_in_flight = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let handler = CompletionHandler::new(
+      document_map,
+      String::new(),
+      String::new(),
+      &Config::default(),
+    )
+    .with_provider(SlowProvider {
+      in_flight: std::sync::atomic::AtomicUsize::new(0),
+      max_in_flight: max_in_flight.clone(),
+    })
+    .with_concurrency_limit(2);
+
+    let results = futures::future::join_all(
+      uris
+        .into_iter()
+        .map(|uri| handler.on_completion(completion_params(uri, 4))),
+    )
+    .await;
+
+    assert!(results.iter().all(|result| matches!(result, Ok(Some(_)))));
+    assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+  }
+
+  /// Provider that offers many candidates and records how many meanings are fetched at
+  /// the same time
+  struct MeaningCounter {
+    in_flight: std::sync::atomic::AtomicUsize,
+    max_in_flight: Arc<std::sync::atomic::AtomicUsize>,
+    lookups: Arc<std::sync::atomic::AtomicUsize>,
+  }
+
+  #[async_trait::async_trait]
+  impl DictionaryProvider for MeaningCounter {
+    async fn get_meaning(&self, _word: &str) -> Result<Option<DictionaryResponse>> {
+      let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+      self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+      self.lookups.fetch_add(1, Ordering::SeqCst);
+      tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+      self.in_flight.fetch_sub(1, Ordering::SeqCst);
+      Ok(None)
+    }
+
+    fn get_word_at_position(&self, _content: &str, _position: Position) -> Option<String> {
+      None
+    }
+
+    async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>> {
+      Ok(Some((0..10).map(|i| format!("{}{}", prefix, i)).collect()))
+    }
+  }
+
+  #[tokio::test]
+  async fn test_candidate_meanings_are_not_fetched_all_at_once() {
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "wo".to_string());
+    let max_in_flight = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let lookups = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let handler = CompletionHandler::new(
+      document_map,
+      String::new(),
+      String::new(),
+      &Config::default(),
+    )
+    .with_provider(MeaningCounter {
+      in_flight: std::sync::atomic::AtomicUsize::new(0),
+      max_in_flight: max_in_flight.clone(),
+      lookups: lookups.clone(),
+    })
+    .with_max_items(10)
+    .with_undefined_words(UndefinedWords::Mark);
+
+    let result = handler.on_completion(completion_params(uri, 2)).await;
+
+    assert!(matches!(result, Ok(Some(_))));
+    assert_eq!(lookups.load(Ordering::SeqCst), 10);
+    // Candidates are looked up through one `get_meanings` call, one word after another,
+    // so a large candidate list never opens a connection per word
+    assert_eq!(max_in_flight.load(Ordering::SeqCst), 1);
+  }
+
+  #[tokio::test]
+  async fn test_superseded_requests_are_dropped() {
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "word".to_string());
+
+    let handler = CompletionHandler::new(
+      document_map,
+      String::new(),
+      String::new(),
+      &Config::default(),
+    )
+    .with_provider(SlowProvider {
+      in_flight: std::sync::atomic::AtomicUsize::new(0),
+      max_in_flight: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+    })
+    .with_concurrency_limit(1);
+
+    let (first, second, third) = futures::join!(
+      handler.on_completion(completion_params(uri.clone(), 2)),
+      handler.on_completion(completion_params(uri.clone(), 3)),
+      handler.on_completion(completion_params(uri.clone(), 4)),
+    );
+
+    // The first request was already running; the second was queued and then superseded
+    assert!(matches!(first, Ok(Some(_))));
+    assert_eq!(second.unwrap(), None);
+    assert!(matches!(third, Ok(Some(_))));
+    assert!(handler.latest_requests.lock().await.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_insert_lemma_keeps_inflected_label() {
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "Runn".to_string());
+    let dictionary = serde_json::json!({
+      "run": {"verb": ["move quickly"]},
+      "runner": {"noun": ["one who runs"]},
+      "running": {"noun": ["the action of running"]}
+    });
+
+    for insert_lemma in [false, true] {
+      let handler = CompletionHandler::new(
+        document_map.clone(),
+        String::new(),
+        String::new(),
+        &Config::default(),
+      )
+      .with_provider(dictionary_data::JsonDictionaryProvider::from_value(
+        dictionary.clone(),
+      ))
+      .with_insert_lemma(insert_lemma);
+      let items = match handler
+        .on_completion(completion_params(uri.clone(), 4))
+        .await
+        .unwrap()
+      {
+        Some(CompletionResponse::List(list)) => list.items,
+        other => panic!("Expected a completion list, got {:?}", other),
+      };
+      let shown: Vec<(String, String)> = items
+        .into_iter()
+        .map(|item| match item.text_edit {
+          Some(CompletionTextEdit::Edit(edit)) => (item.label, edit.new_text),
+          other => panic!("Expected a text edit, got {:?}", other),
+        })
+        .collect();
+
+      let running = if insert_lemma {
+        ("Running (Run)".to_string(), "Run".to_string())
+      } else {
+        ("Running".to_string(), "Running".to_string())
+      };
+      assert_eq!(
+        shown,
+        vec![("Runner".to_string(), "Runner".to_string()), running]
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn test_expand_inflections() {
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "run".to_string());
+    let dictionary = serde_json::json!({
+      "run": {"verb": ["move quickly"]},
+      "runway": {"noun": ["a strip for aircraft"]}
+    });
+
+    for expand_inflections in [false, true] {
+      let handler = CompletionHandler::new(
+        document_map.clone(),
+        String::new(),
+        String::new(),
+        &Config::default(),
+      )
+      .with_provider(dictionary_data::JsonDictionaryProvider::from_value(
+        dictionary.clone(),
+      ))
+      .with_expand_inflections(expand_inflections);
+      let items = match handler
+        .on_completion(completion_params(uri.clone(), 3))
+        .await
+        .unwrap()
+      {
+        Some(CompletionResponse::List(list)) => list.items,
+        other => panic!("Expected a completion list, got {:?}", other),
+      };
+      let shown: Vec<(String, serde_json::Value)> = items
+        .into_iter()
+        .map(|item| (item.label, item.data.unwrap()))
+        .collect();
+
+      let expected = if expand_inflections {
+        vec![
+          ("run", "run"),
+          ("runs", "run"),
+          ("running", "run"),
+          ("ran", "run"),
+          ("runway", "runway"),
+          ("runways", "runway"),
+        ]
+      } else {
+        vec![("run", "run"), ("runway", "runway")]
+      };
+      let expected: Vec<(String, serde_json::Value)> = expected
+        .into_iter()
+        .map(|(label, word)| (label.to_string(), serde_json::json!(word)))
+        .collect();
+      assert_eq!(shown, expected);
+    }
+  }
+
+  #[tokio::test]
+  async fn test_only_at_word_end() {
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "hello wor world, 单词wo单".to_string());
+    let dictionary = serde_json::json!({"world": {"noun": ["the earth"]}});
+
+    for only_at_word_end in [false, true] {
+      let handler = CompletionHandler::new(
+        document_map.clone(),
+        String::new(),
+        String::new(),
+        &Config::default(),
+      )
+      .with_provider(dictionary_data::JsonDictionaryProvider::from_value(
+        dictionary.clone(),
+      ))
+      .with_only_at_word_end(only_at_word_end);
+      let completes = |character| {
+        let handler = &handler;
+        let uri = uri.clone();
+        async move {
+          handler
+            .on_completion(completion_params(uri, character))
+            .await
+            .unwrap()
+            .is_some()
+        }
+      };
+
+      // Before a space, punctuation or a CJK character
+      assert!(completes(9).await);
+      assert!(completes(15).await);
+      assert!(completes(21).await);
+      // "wo|rld"
+      assert_eq!(completes(12).await, !only_at_word_end);
+    }
+  }
+
+  #[tokio::test]
+  async fn test_stopwords_are_excluded() {
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "Th".to_string());
+    let dictionary = serde_json::json!({
+      "the": {"article": ["definite article"]},
+      "then": {"adverb": ["at that time"]},
+      "theory": {"noun": ["a supposition"]}
+    });
+
+    let handler = CompletionHandler::new(
+      document_map,
+      String::new(),
+      String::new(),
+      &Config::default(),
+    )
+    .with_provider(dictionary_data::JsonDictionaryProvider::from_value(
+      dictionary,
+    ))
+    .with_stopwords(HashSet::from(["the".to_string(), "then".to_string()]));
+    let labels: Vec<String> = match handler
+      .on_completion(completion_params(uri, 2))
+      .await
+      .unwrap()
+    {
+      Some(CompletionResponse::List(list)) => {
+        list.items.into_iter().map(|item| item.label).collect()
+      }
+      other => panic!("Expected a completion list, got {:?}", other),
+    };
+    assert_eq!(labels, vec!["Theory".to_string()]);
+  }
+
+  #[tokio::test]
+  async fn test_frequency_only_words() {
+    let complete = |undefined_words| async move {
+      let mut mock_dict = MockDictionaryProvider::new();
+      // "runnel" is in the frequency database but not in the dictionary
+      mock_dict
+        .expect_find_words_by_prefix()
+        .returning(|_| Ok(Some(vec!["run".to_string(), "runnel".to_string()])));
+      mock_dict.expect_get_meaning().returning(|word| {
+        Ok((word == "run").then(|| DictionaryResponse {
+          word: word.to_string(),
+          ..Default::default()
+        }))
+      });
+      let document_map = Arc::new(Mutex::new(HashMap::new()));
+      let uri = Url::parse("file:///test.txt").unwrap();
+      document_map
+        .lock()
+        .await
+        .insert(uri.clone(), "ru".to_string());
+      let handler = CompletionHandler::new(
+        document_map,
+        String::new(),
+        String::new(),
+        &Config::default(),
+      )
+      .with_provider(mock_dict)
+      .with_undefined_words(undefined_words);
+      match handler
+        .on_completion(completion_params(uri, 2))
+        .await
+        .unwrap()
+      {
+        Some(CompletionResponse::List(list)) => list
+          .items
+          .into_iter()
+          .map(|item| (item.label, item.detail))
+          .collect::<Vec<_>>(),
+        other => panic!("Expected a completion list, got {:?}", other),
+      }
+    };
+
+    let marked = vec![
+      ("run".to_string(), None),
+      ("runnel".to_string(), Some("(no definition)".to_string())),
+    ];
+    assert_eq!(complete(UndefinedWords::Mark).await, marked);
+    assert_eq!(
+      complete(UndefinedWords::Hide).await,
+      vec![("run".to_string(), None)]
+    );
+    assert_eq!(
+      complete(UndefinedWords::Offer).await,
+      vec![("run".to_string(), None), ("runnel".to_string(), None)]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_resolve_caches_definitions() {
+    let mut mock_dict = MockDictionaryProvider::new();
+    mock_dict.expect_get_meaning().times(2).returning(|word| {
+      Ok(Some(DictionaryResponse {
+        word: word.to_string(),
+        ..Default::default()
+      }))
+    });
+    let handler = setup_test_handler().with_provider(mock_dict);
+    let item = CompletionItem {
+      label: "run".to_string(),
+      data: Some(serde_json::json!("run")),
+      ..Default::default()
+    };
+
+    // The second resolve of the same word is answered from the cache
+    handler.resolve_completion_item(item.clone()).await.unwrap();
+    handler.resolve_completion_item(item.clone()).await.unwrap();
+
+    // Clearing the cache, as a reload does, looks the word up again
+    handler.clear_cache().await;
+    handler.resolve_completion_item(item).await.unwrap();
+  }
+
+  /// The dictionary is loaded once, not on every keystroke, until the cache is cleared
+  #[tokio::test]
+  async fn test_provider_is_kept_across_requests() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dictionary.json");
+    std::fs::write(&path, r#"{"run": {"verb": ["move quickly on foot"]}}"#).unwrap();
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "ru".to_string());
+    let handler = CompletionHandler::new(
+      document_map,
+      path.to_string_lossy().into_owned(),
+      String::new(),
+      &Config::default(),
+    );
+    let labels = || async {
+      match handler
+        .on_completion(completion_params(uri.clone(), 2))
+        .await
+      {
+        Ok(Some(CompletionResponse::List(list))) => {
+          list.items.into_iter().map(|item| item.label).collect()
+        }
+        _ => Vec::new(),
+      }
+    };
+
+    assert_eq!(labels().await, vec!["run"]);
+    std::fs::write(&path, r#"{"rug": {"noun": ["a floor covering"]}}"#).unwrap();
+    assert_eq!(labels().await, vec!["run"]);
+    handler.clear_cache().await;
+    assert_eq!(labels().await, vec!["rug"]);
+  }
+
+  /// The given configuration reaches the provider too, not only the handler
+  #[tokio::test]
+  async fn test_new_follows_given_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dictionary.json");
+    std::fs::write(
+      &path,
+      r#"{"run": {"verb": ["move"]}, "rung": {"noun": ["step"]}, "runt": {"noun": ["small"]}}"#,
+    )
+    .unwrap();
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "ru".to_string());
+    let mut config = Config::default();
+    config.completion.max_items = 1;
+    config.completion.sort_order = crate::config::SortOrder::Alphabetical;
+    let handler = CompletionHandler::new(
+      document_map,
+      path.to_string_lossy().into_owned(),
+      String::new(),
+      &config,
+    );
+
+    let Ok(Some(CompletionResponse::List(list))) =
+      handler.on_completion(completion_params(uri, 2)).await
+    else {
+      panic!("Expected CompletionResponse::List");
+    };
+    assert_eq!(list.items.len(), 1);
+    assert_eq!(list.items[0].label, "run");
+    assert!(list.is_incomplete);
+  }
+
+  #[tokio::test]
+  async fn test_resolve_from_json_dictionary() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dictionary.json");
+    std::fs::write(&path, r#"{"run": {"verb": ["move quickly on foot"]}}"#).unwrap();
+    let handler = CompletionHandler::new(
+      Arc::new(Mutex::new(HashMap::new())),
+      path.to_string_lossy().into_owned(),
+      String::new(),
+      &Config::default(),
+    );
+    let resolve = |word: &str| {
+      handler.resolve_completion_item(CompletionItem {
+        label: word.to_string(),
+        data: Some(serde_json::json!(word)),
+        ..Default::default()
+      })
+    };
+
+    let item = resolve("run").await.unwrap();
+    assert_eq!(item.detail, None);
+    assert_eq!(
+      item.documentation,
+      Some(Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: "**run**\n_verb_\n1. move quickly on foot\n".to_string(),
+      }))
+    );
+
+    let missing = resolve("zzyzx").await.unwrap();
+    assert_eq!(missing.detail.as_deref(), Some(NO_DEFINITION_DETAIL));
+  }
+
+  #[tokio::test]
+  async fn test_documentation_senses_are_capped() {
+    let sense = |text: &str| dictionary_data::Definition {
+      definition: text.to_string(),
+      example: None,
+      more_examples: Vec::new(),
+      sub_definitions: Vec::new(),
+      label: None,
+    };
+    let response = DictionaryResponse {
+      word: "run".to_string(),
+      meanings: vec![
+        dictionary_data::Meaning {
+          part_of_speech: "verb".to_string(),
+          definitions: vec![sense("move quickly"), sense("manage")],
+          lang: None,
+          source: None,
+        },
+        dictionary_data::Meaning {
+          part_of_speech: "noun".to_string(),
+          definitions: vec![sense("a jog"), sense("a series")],
+          lang: None,
+          source: None,
+        },
+      ],
+      ..Default::default()
+    };
+    let resolve = |max_senses: Option<usize>| {
+      let response = response.clone();
+      async move {
+        let mut mock = MockDictionaryProvider::new();
+        mock
+          .expect_get_meaning()
+          .returning(move |_| Ok(Some(response.clone())));
+        let item = setup_test_handler()
+          .with_provider(mock)
+          .with_max_documentation_senses(max_senses)
+          .resolve_completion_item(CompletionItem {
+            label: "run".to_string(),
+            data: Some(serde_json::json!("run")),
+            ..Default::default()
+          })
+          .await
+          .unwrap();
+        match item.documentation {
+          Some(Documentation::MarkupContent(content)) => content.value,
+          other => panic!("Expected markdown documentation, got {:?}", other),
+        }
+      }
+    };
+
+    let full = "**run**\n_verb_\n1. move quickly\n2. manage\n_noun_\n1. a jog\n2. a series\n";
+    assert_eq!(resolve(None).await, full);
+    assert_eq!(resolve(Some(0)).await, full);
+    assert_eq!(resolve(Some(4)).await, full);
+    assert_eq!(
+      resolve(Some(3)).await,
+      "**run**\n_verb_\n1. move quickly\n2. manage\n_noun_\n1. a jog\n_… and 1 more sense_\n"
+    );
+    assert_eq!(
+      resolve(Some(2)).await,
+      "**run**\n_verb_\n1. move quickly\n2. manage\n_… and 2 more senses_\n"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_documentation_follows_client_format() {
+    let resolve = |formats: Option<Vec<MarkupKind>>| async move {
+      let mut mock = MockDictionaryProvider::new();
+      mock.expect_get_meaning().returning(|word| {
+        Ok(Some(DictionaryResponse {
+          word: word.to_string(),
+          meanings: vec![dictionary_data::Meaning {
+            part_of_speech: "verb".to_string(),
+            definitions: ["move quickly", "manage"]
+              .iter()
+              .map(|text| dictionary_data::Definition {
+                definition: text.to_string(),
+                example: None,
+                more_examples: Vec::new(),
+                sub_definitions: Vec::new(),
+                label: None,
+              })
+              .collect(),
+            lang: None,
+            source: None,
+          }],
+          ..Default::default()
+        }))
+      });
+      let handler = setup_test_handler()
+        .with_provider(mock)
+        .with_max_documentation_senses(Some(1));
+      handler.set_documentation_format(formats.as_deref());
+      let item = handler
+        .resolve_completion_item(CompletionItem {
+          label: "run".to_string(),
+          data: Some(serde_json::json!("run")),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      match item.documentation {
+        Some(Documentation::MarkupContent(content)) => (content.kind, content.value),
+        other => panic!("Expected markup documentation, got {:?}", other),
+      }
+    };
+
+    let markdown = (
+      MarkupKind::Markdown,
+      "**run**\n_verb_\n1. move quickly\n_… and 1 more sense_\n".to_string(),
+    );
+    assert_eq!(resolve(None).await, markdown);
+    assert_eq!(
+      resolve(Some(vec![MarkupKind::PlainText, MarkupKind::Markdown])).await,
+      markdown
+    );
+    assert_eq!(
+      resolve(Some(vec![MarkupKind::PlainText])).await,
+      (
+        MarkupKind::PlainText,
+        "run\nverb\n1. move quickly\n… and 1 more sense\n".to_string()
+      )
+    );
+  }
+
+  #[tokio::test]
+  async fn test_resolve_matches_hover_formatting() {
+    let response = DictionaryResponse {
+      word: "run".to_string(),
+      meanings: vec![dictionary_data::Meaning {
+        part_of_speech: "verb".to_string(),
+        definitions: vec![dictionary_data::Definition {
+          definition: "move quickly on foot".to_string(),
+          example: Some("she runs daily".to_string()),
+          more_examples: Vec::new(),
+          sub_definitions: Vec::new(),
+          label: None,
+        }],
+        lang: None,
+        source: None,
+      }],
+      ..Default::default()
+    };
+    let mock = |response: DictionaryResponse| {
+      let mut mock = MockDictionaryProvider::new();
+      mock
+        .expect_get_meaning()
+        .returning(move |_| Ok(Some(response.clone())));
+      mock
+        .expect_get_word_at_position()
+        .returning(|_, _| Some("run".to_string()));
+      mock
+    };
+
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "run".to_string());
+
+    for example_style in [
+      formatting::ExampleStyle::Blockquote,
+      formatting::ExampleStyle::Inline,
+    ] {
+      let mut config = Config::default();
+      config.formatting.example_style = example_style;
+
+      let handler = CompletionHandler::new(
+        document_map.clone(),
+        String::new(),
+        String::new(),
+        &Config::default(),
+      )
+      .with_provider(mock(response.clone()))
+      .with_formatting(config.formatting.clone());
+      let item = handler
+        .resolve_completion_item(CompletionItem {
+          label: "run".to_string(),
+          data: Some(serde_json::json!("run")),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      let resolved = match item.documentation {
+        Some(Documentation::MarkupContent(content)) => content.value,
+        other => panic!("Expected markdown documentation, got {:?}", other),
+      };
+
+      let mut hover_handler =
+        crate::hover::HoverHandler::new(document_map.clone(), String::new(), String::new(), config);
+      hover_handler.dictionary_provider = Box::new(mock(response.clone()));
+      let hover = hover_handler
+        .on_hover(HoverParams {
+          text_document_position_params: TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            position: Position {
+              line: 0,
+              character: 1,
+            },
+          },
+          work_done_progress_params: WorkDoneProgressParams::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+      let hovered = match hover.contents {
+        HoverContents::Markup(content) => content.value,
+        other => panic!("Expected markdown hover, got {:?}", other),
+      };
+
+      assert_eq!(resolved, hovered);
+      assert!(resolved.contains("she runs daily"));
+    }
+  }
 }
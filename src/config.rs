@@ -1,21 +1,391 @@
 use crate::formatting::FormattingConfig;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Config {
+  #[serde(default)]
   pub formatting: FormattingConfig,
   pub dictionary_path: Option<String>,
+  #[serde(default)]
   pub completion: CmpConfig,
   pub freq_path: Option<String>,
+  /// Writable JSON dictionary whose entries take precedence over the main dictionary
+  #[serde(default)]
+  pub personal_dictionary_path: Option<String>,
+  /// Documents larger than this many bytes get no diagnostics; hover and completion still
+  /// work (unset or 0: unlimited)
+  #[serde(default)]
+  pub max_analyze_bytes: Option<usize>,
+  /// Load the dictionary once the client is ready, as `dictionary.warmup` does, so the first
+  /// hover doesn't wait for it
+  #[serde(default)]
+  pub warm_up_on_start: bool,
+  /// Seconds a looked up definition stays cached before it is looked up again (unset or 0:
+  /// until the dictionary is reloaded)
+  #[serde(default)]
+  pub meaning_cache_ttl_secs: Option<u64>,
+  #[serde(default)]
+  pub fuzzy: FuzzyConfig,
+  #[serde(default)]
+  pub lookup: LookupConfig,
+  #[serde(default)]
+  pub signature_help: SignatureHelpConfig,
+  #[serde(default)]
+  pub hover: HoverConfig,
+  #[serde(default)]
+  pub stopwords: StopwordConfig,
+  #[serde(default)]
+  pub sql_schema: SqlSchemaConfig,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
 pub struct CmpConfig {
   pub enabled: bool,
   pub max_distance: u8,
+  /// Maximum number of completion requests processed at the same time
+  pub max_concurrent_requests: usize,
+  /// How typed text is matched against dictionary words
+  pub match_mode: MatchMode,
+  /// Script of the documents, used to pick how the typed word is extracted
+  pub script: ScriptMode,
+  /// Order of the completion items
+  pub sort_order: SortOrder,
+  /// Insert the dictionary form of inflected words, labelled like "running (run)"
+  pub insert_lemma: bool,
+  /// Show words differing only in case or diacritics ("color", "Color") once
+  pub dedup: bool,
+  /// Also offer the inflected forms of completed verbs and nouns ("runs", "running", "ran")
+  pub expand_inflections: bool,
+  /// Only complete at the end of a word, not with letters right after the cursor
+  pub only_at_word_end: bool,
+  /// Show at most this many senses in the documentation of a completion item; hover shows
+  /// them all (unset or 0: unlimited)
+  pub max_documentation_senses: Option<usize>,
+  /// What to do with candidates the dictionary has no definition for, such as words only
+  /// found in the frequency database
+  pub undefined_words: UndefinedWords,
+  /// Number of completion items shown for a prefix
+  pub max_items: usize,
+  /// Offer words in their dictionary casing ("iPhone", "NASA") whatever the case typed,
+  /// instead of only capitalizing the first letter to match it
+  pub preserve_case: bool,
+}
+
+/// Order in which completion candidates are ranked before the list is cut off
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+  /// Most frequent words first
+  #[default]
+  Frequency,
+  Alphabetical,
+  /// Shortest words first, then alphabetical
+  Length,
+}
+
+/// Handling of completion candidates without a definition
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UndefinedWords {
+  /// Offer them like any other word, without looking definitions up while completing
+  #[default]
+  Offer,
+  /// Offer them with a "(no definition)" detail
+  Mark,
+  /// Leave them out
+  Hide,
+}
+
+/// Strategy used to find completion candidates for the typed text
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+  /// Words starting with the typed text
+  #[default]
+  Prefix,
+  /// Words containing the typed text anywhere (costlier, scans every word)
+  Contains,
+  /// Words within a small edit distance of the typed text
+  Fuzzy,
+}
+
+/// Similarity used to pick the best of several fuzzy matches
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FuzzyRanking {
+  /// Fewest edits first
+  #[default]
+  Levenshtein,
+  /// Highest Jaro-Winkler similarity first, which favors transposed letters and a shared
+  /// prefix ("form" -> "from" rather than "foam")
+  JaroWinkler,
+}
+
+/// Which script completion assumes a document is written in
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptMode {
+  /// Detect the dominant script of each document
+  #[default]
+  Auto,
+  Cjk,
+  Latin,
+}
+
+impl Default for CmpConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      max_distance: 3,
+      max_concurrent_requests: 4,
+      match_mode: MatchMode::Prefix,
+      script: ScriptMode::Auto,
+      sort_order: SortOrder::Frequency,
+      insert_lemma: false,
+      dedup: true,
+      expand_inflections: false,
+      only_at_word_end: false,
+      max_documentation_senses: Some(10),
+      undefined_words: UndefinedWords::Offer,
+      max_items: crate::dictionary_data::PREFIX_RESULT_LIMIT,
+      preserve_case: false,
+    }
+  }
+}
+
+impl CmpConfig {
+  /// `max_items`, showing at least one item
+  pub fn item_limit(&self) -> usize {
+    self.max_items.max(1)
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FuzzyConfig {
+  /// Fall back to the closest dictionary word when an exact lookup misses
+  pub enabled: bool,
+  /// Letters tried in addition to a–z when generating corrections (e.g. "éèêàçœ" for French)
+  pub extra_letters: String,
+  /// Most candidate words generated for one completion or correction
+  pub max_candidates: usize,
+  /// Stop generating candidates after this many milliseconds (unset: no limit)
+  pub time_budget_ms: Option<u64>,
+  /// Substitute and delete letters anywhere in the word. When off only the second half is
+  /// edited, which generates fewer candidates but misses typos near the start ("wprld").
+  pub edit_first_half: bool,
+  /// Largest edit distance between a looked up word and its fuzzy match
+  pub max_distance: usize,
+  /// Shorter words only get corrections one edit away, as two edits turn most short words
+  /// into another word ("cat" -> "car", "bat")
+  pub distance_2_min_length: usize,
+  /// Only match SQLite words with the same first and last letter as the looked up word.
+  /// Faster on large dictionaries, but misses typos in those letters ("xat", "cax").
+  pub anchor_ends: bool,
+  /// How fuzzy matches within `max_distance` are ranked
+  pub ranking: FuzzyRanking,
+  /// Jaro-Winkler ranking only applies to words shorter than this; longer words, where the
+  /// edit distance is a finer signal, keep Levenshtein ranking
+  pub jaro_winkler_max_length: usize,
+}
+
+impl Default for FuzzyConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      extra_letters: String::new(),
+      max_candidates: 1000,
+      time_budget_ms: None,
+      edit_first_half: true,
+      max_distance: 2,
+      distance_2_min_length: 6,
+      anchor_ends: true,
+      ranking: FuzzyRanking::Levenshtein,
+      jaro_winkler_max_length: 6,
+    }
+  }
+}
+
+impl FuzzyConfig {
+  /// Largest edit distance allowed for corrections of `word`
+  pub fn max_distance_for(&self, word: &str) -> usize {
+    match word.chars().count() < self.distance_2_min_length {
+      true => self.max_distance.min(1),
+      false => self.max_distance,
+    }
+  }
+
+  /// Ranking used for the fuzzy matches of `word`
+  pub fn ranking_for(&self, word: &str) -> FuzzyRanking {
+    match word.chars().count() < self.jaro_winkler_max_length {
+      true => self.ranking,
+      false => FuzzyRanking::Levenshtein,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct LookupConfig {
+  /// Split unknown words into two dictionary words (e.g. German compounds)
+  pub compound_words: bool,
+  /// Treat words differing only in case as different words
+  pub case_sensitive: bool,
+  /// Parts of speech shown first, in this order (e.g. noun, verb, adjective).
+  /// Unlisted ones follow alphabetically.
+  pub pos_order: Vec<String>,
+  /// Look up dotted abbreviations ("e.g.", "U.S.A.") whole when the dictionary has them,
+  /// instead of the letters under the cursor
+  pub abbreviations: bool,
+  /// Treat digits next to letters as part of the word, to look up "mp3" or "3d"
+  pub alphanumeric_words: bool,
+}
+
+impl LookupConfig {
+  /// Returns the form of `word` used for dictionary keys: unchanged when case sensitive,
+  /// lowercased otherwise
+  pub fn fold_case(&self, word: &str) -> String {
+    if self.case_sensitive {
+      word.to_string()
+    } else {
+      word.to_lowercase()
+    }
+  }
+
+  /// Sort key of a part of speech: its index in `pos_order`, or past the end when unlisted
+  pub fn pos_rank(&self, part_of_speech: &str) -> usize {
+    self
+      .pos_order
+      .iter()
+      .position(|pos| pos.eq_ignore_ascii_case(part_of_speech))
+      .unwrap_or(self.pos_order.len())
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SignatureHelpConfig {
+  /// Offer signature help at all
+  pub enabled: bool,
+  /// Show a "No definition found" signature for words missing from the dictionary
+  pub show_missing_definition: bool,
+  /// Include usage examples; hiding them keeps the popup small (hover is unaffected)
+  pub show_examples: bool,
+}
+
+impl Default for SignatureHelpConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      show_missing_definition: true,
+      show_examples: true,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HoverConfig {
+  /// Show definitions on hover
+  pub enabled: bool,
+  /// A selection of several words that is not an entry itself lists a short definition of
+  /// each word, leaving out stopwords
+  pub define_each_selected_word: bool,
+}
+
+impl Default for HoverConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      define_each_selected_word: false,
+    }
+  }
+}
+
+/// Table and column names of a SQLite dictionary, for databases laid out differently
+/// from the ones written by `dictionary_lsp convert`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SqlSchemaConfig {
+  pub words_table: String,
+  /// Primary key of the words table
+  pub word_id_column: String,
+  pub word_column: String,
+  pub definitions_table: String,
+  pub definition_column: String,
+  /// Column of the definitions table referencing the word
+  pub definition_word_column: String,
+  /// Column of the definitions table referencing the part of speech
+  pub definition_pos_column: String,
+  pub pos_table: String,
+  /// Primary key of the parts of speech table
+  pub pos_id_column: String,
+  pub pos_name_column: String,
+  /// Optional column of the definitions table with usage labels ("informal", "archaic")
+  pub label_column: Option<String>,
+  /// Optional table of spelling variants, with a `variant` column holding the other
+  /// spelling ("colour") and a `word` column holding the headword ("color")
+  pub variants_table: String,
+}
+
+impl Default for SqlSchemaConfig {
+  fn default() -> Self {
+    Self {
+      words_table: "words".to_string(),
+      word_id_column: "id".to_string(),
+      word_column: "word".to_string(),
+      definitions_table: "definitions".to_string(),
+      definition_column: "definition".to_string(),
+      definition_word_column: "word_id".to_string(),
+      definition_pos_column: "pos_id".to_string(),
+      pos_table: "parts_of_speech".to_string(),
+      pos_id_column: "id".to_string(),
+      pos_name_column: "name".to_string(),
+      label_column: None,
+      variants_table: "variants".to_string(),
+    }
+  }
+}
+
+/// Words left out of completion and spelling diagnostics, e.g. "the", "a", "of"
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct StopwordConfig {
+  pub words: Vec<String>,
+  /// File with one stopword per line; blank lines and lines starting with `#` are skipped
+  pub path: Option<String>,
+}
+
+impl StopwordConfig {
+  /// Collects the configured words and the ones read from `path`, lowercased
+  pub fn load(&self) -> HashSet<String> {
+    let mut stopwords: HashSet<String> = self
+      .words
+      .iter()
+      .map(|word| word.trim().to_lowercase())
+      .collect();
+
+    if let Some(path) = &self.path {
+      match fs::read_to_string(path) {
+        Ok(contents) => stopwords.extend(
+          contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_lowercase),
+        ),
+        Err(e) => eprintln!("Failed to read stopwords from {}: {}", path, e),
+      }
+    }
+
+    stopwords.remove("");
+    stopwords
+  }
 }
 
 // Config manager to hold shared in-memory configuration
@@ -64,20 +434,6 @@ impl ConfigManager {
   }
 }
 
-impl Default for Config {
-  fn default() -> Self {
-    Self {
-      formatting: FormattingConfig::default(),
-      dictionary_path: None,
-      freq_path: None,
-      completion: CmpConfig {
-        max_distance: 3,
-        enabled: true,
-      },
-    }
-  }
-}
-
 impl Config {
   // Get the current global configuration
   pub fn get() -> Self {
@@ -93,12 +449,32 @@ impl Config {
     update_fn(&mut config);
   }
 
+  /// Reads a config file, expanding `~` and environment variables in its paths
   pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
     let contents = fs::read_to_string(path)?;
-    let config: Config = toml::from_str(&contents)?;
+    let mut config: Config = toml::from_str(&contents)?;
+    config.expand_paths();
     Ok(config)
   }
 
+  /// Expands `~` and `$VAR`/`${VAR}` in the configured file paths, so a dictionary can
+  /// live under e.g. `$XDG_DATA_HOME` while the config stays in the config directory
+  pub fn expand_paths(&mut self) {
+    for path in [
+      &mut self.dictionary_path,
+      &mut self.freq_path,
+      &mut self.personal_dictionary_path,
+      &mut self.stopwords.path,
+    ]
+    .into_iter()
+    .flatten()
+    {
+      *path = expand_path(path, dirs::home_dir().as_deref(), |name| {
+        std::env::var(name).ok()
+      });
+    }
+  }
+
   pub fn is_sqlite(path: Option<&str>) -> bool {
     match path {
       Some(path) => path.ends_with(".db"),
@@ -106,12 +482,34 @@ impl Config {
     }
   }
 
+  pub fn is_mdx(path: Option<&str>) -> bool {
+    match path {
+      Some(path) => path.to_lowercase().ends_with(".mdx"),
+      None => false,
+    }
+  }
+
+  /// Plain word lists: one word per line (`.txt`) or a hunspell dictionary (`.dic`)
+  pub fn is_word_list(path: Option<&str>) -> bool {
+    match path {
+      Some(path) => {
+        let path = path.to_lowercase();
+        path.ends_with(".txt") || path.ends_with(".dic")
+      }
+      None => false,
+    }
+  }
+
+  /// Config file locations in lookup order: the platform config directory
+  /// (`~/.config` on Linux, `~/Library/Application Support` on macOS, `%APPDATA%` on Windows),
+  /// then the legacy `~/.config` path
+  pub fn config_paths() -> Vec<PathBuf> {
+    candidate_config_paths(dirs::config_dir(), dirs::home_dir())
+  }
+
   // Load config from disk
   pub fn load_from_disk() -> Self {
-    let possible_paths =
-      vec![dirs::home_dir().map(|p| p.join(".config/dictionary-lsp/config.toml"))];
-
-    for path in possible_paths.into_iter().flatten() {
+    for path in Self::config_paths() {
       if let Ok(config) = Self::load_from_file(&path) {
         // debug output
         // eprintln!("Loaded config from: {}", path.display());
@@ -126,21 +524,269 @@ impl Config {
   pub fn save_to_disk(
     config: &Config,
   ) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
-    let possible_paths =
-      vec![dirs::home_dir().map(|p| p.join(".config/dictionary-lsp/config.toml"))];
-
-    let path = possible_paths
-      .into_iter()
-      .flatten()
-      .next()
+    let paths = Self::config_paths();
+    // Overwrite the file that was loaded, otherwise create it in the platform location
+    let path = paths
+      .iter()
+      .find(|path| path.exists())
+      .or_else(|| paths.first())
+      .cloned()
       .ok_or_else(|| "No valid path found to save config".to_string())?;
 
-    if let Some(parent) = path.parent() {
+    Self::save_to_file(config, &path)?;
+    Ok(path)
+  }
+
+  /// Writes `config` as commented TOML to `path`, creating its directory
+  pub fn save_to_file<P: AsRef<Path>>(
+    config: &Config,
+    path: P,
+  ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(parent) = path.as_ref().parent() {
       fs::create_dir_all(parent)?;
     }
 
-    let toml = toml::to_string(config)?;
-    fs::write(&path, toml)?;
-    Ok(path)
+    let toml = format!("{}{}", CONFIG_HEADER, toml::to_string(config)?);
+    fs::write(path, toml)?;
+    Ok(())
+  }
+}
+
+/// Written above the settings, since unset optional keys don't appear in the TOML
+const CONFIG_HEADER: &str = "\
+# dictionary-lsp configuration, see the README for every setting.
+# Unset optional keys are left out, e.g.:
+# dictionary_path = \"/path/to/your/dictionary.json\"
+# freq_path = \"/path/to/your/freq.db\"
+";
+
+/// `--print-config`: prints the effective configuration, defaults included, as TOML
+pub fn run_print_config() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+  print!("{}", toml::to_string(&Config::get())?);
+  Ok(())
+}
+
+/// `--write-default-config`: writes the default configuration to the standard path,
+/// refusing to replace an existing config file
+pub fn run_write_default_config() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+  if let Some(existing) = Config::config_paths().iter().find(|path| path.exists()) {
+    return Err(format!("{} already exists, not overwriting it", existing.display()).into());
+  }
+  let path = Config::save_to_disk(&Config::default())?;
+  println!("Wrote the default configuration to {}", path.display());
+  Ok(())
+}
+
+/// Replaces a leading `~` with `home` and `$NAME` or `${NAME}` with the value `var` gives
+/// for it. Variables without a value, and `~` without a home directory, are kept as written.
+fn expand_path(path: &str, home: Option<&Path>, var: impl Fn(&str) -> Option<String>) -> String {
+  let mut expanded = String::with_capacity(path.len());
+  let mut rest = path;
+  if let (Some(after), Some(home)) = (rest.strip_prefix('~'), home) {
+    if after.is_empty() || after.starts_with(['/', std::path::MAIN_SEPARATOR]) {
+      expanded.push_str(&home.to_string_lossy());
+      rest = after;
+    }
+  }
+
+  while let Some(start) = rest.find('$') {
+    expanded.push_str(&rest[..start]);
+    let after = &rest[start + 1..];
+    let (name, remainder) = match after.strip_prefix('{') {
+      Some(braced) => match braced.find('}') {
+        Some(end) => (&braced[..end], &braced[end + 1..]),
+        None => ("", after),
+      },
+      None => {
+        let end = after
+          .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+          .unwrap_or(after.len());
+        (&after[..end], &after[end..])
+      }
+    };
+    match (!name.is_empty()).then(|| var(name)).flatten() {
+      Some(value) => {
+        expanded.push_str(&value);
+        rest = remainder;
+      }
+      None => {
+        expanded.push('$');
+        rest = after;
+      }
+    }
+  }
+  expanded.push_str(rest);
+  expanded
+}
+
+fn candidate_config_paths(config_dir: Option<PathBuf>, home_dir: Option<PathBuf>) -> Vec<PathBuf> {
+  let mut paths = Vec::new();
+  let candidates = [
+    config_dir.map(|dir| dir.join("dictionary-lsp").join("config.toml")),
+    home_dir.map(|home| home.join(".config/dictionary-lsp/config.toml")),
+  ];
+  for path in candidates.into_iter().flatten() {
+    if !paths.contains(&path) {
+      paths.push(path);
+    }
+  }
+  paths
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_config_paths_linux() {
+    assert_eq!(
+      candidate_config_paths(
+        Some(PathBuf::from("/home/alice/.config")),
+        Some(PathBuf::from("/home/alice"))
+      ),
+      vec![PathBuf::from(
+        "/home/alice/.config/dictionary-lsp/config.toml"
+      )]
+    );
+  }
+
+  #[test]
+  fn test_config_paths_macos() {
+    assert_eq!(
+      candidate_config_paths(
+        Some(PathBuf::from("/Users/alice/Library/Application Support")),
+        Some(PathBuf::from("/Users/alice"))
+      ),
+      vec![
+        PathBuf::from("/Users/alice/Library/Application Support/dictionary-lsp/config.toml"),
+        PathBuf::from("/Users/alice/.config/dictionary-lsp/config.toml"),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_config_paths_windows() {
+    let appdata = PathBuf::from(r"C:\Users\alice\AppData\Roaming");
+    let home = PathBuf::from(r"C:\Users\alice");
+    assert_eq!(
+      candidate_config_paths(Some(appdata.clone()), Some(home.clone())),
+      vec![
+        appdata.join("dictionary-lsp").join("config.toml"),
+        home.join(".config/dictionary-lsp/config.toml"),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_stopwords_from_list_and_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("stopwords.txt");
+    fs::write(&path, "# articles\nThe\n\n  an \nof\n").unwrap();
+
+    let config = StopwordConfig {
+      words: vec!["A".to_string()],
+      path: Some(path.to_string_lossy().into_owned()),
+    };
+    let mut stopwords: Vec<String> = config.load().into_iter().collect();
+    stopwords.sort();
+    assert_eq!(stopwords, vec!["a", "an", "of", "the"]);
+
+    let missing = StopwordConfig {
+      words: vec!["of".to_string()],
+      path: Some(
+        dir
+          .path()
+          .join("missing.txt")
+          .to_string_lossy()
+          .into_owned(),
+      ),
+    };
+    assert_eq!(missing.load(), HashSet::from(["of".to_string()]));
+  }
+
+  #[test]
+  fn test_config_paths_without_config_dir() {
+    assert_eq!(
+      candidate_config_paths(None, Some(PathBuf::from("/home/alice"))),
+      vec![PathBuf::from(
+        "/home/alice/.config/dictionary-lsp/config.toml"
+      )]
+    );
+  }
+
+  #[test]
+  fn test_paths_expand_home_and_variables() {
+    let home = Path::new("/home/alice");
+    let var = |name: &str| (name == "MYDATA").then(|| "/srv/data".to_string());
+    let expand = |path: &str| expand_path(path, Some(home), var);
+
+    assert_eq!(expand("~/dict.db"), "/home/alice/dict.db");
+    assert_eq!(expand("~"), "/home/alice");
+    assert_eq!(expand("$MYDATA/dict.db"), "/srv/data/dict.db");
+    assert_eq!(expand("${MYDATA}dicts/dict.db"), "/srv/datadicts/dict.db");
+    // Only a leading `~` names the home directory, and unknown variables stay as written
+    assert_eq!(expand("/dicts/~dict.db"), "/dicts/~dict.db");
+    assert_eq!(expand("~bob/dict.db"), "~bob/dict.db");
+    assert_eq!(expand("$UNSET/dict.db"), "$UNSET/dict.db");
+    assert_eq!(expand("${MYDATA/dict.db"), "${MYDATA/dict.db");
+    assert_eq!(expand("/cost$/dict.db"), "/cost$/dict.db");
+    assert_eq!(expand_path("~/dict.db", None, var), "~/dict.db");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    let written = Config {
+      freq_path: Some("~/freq.db".to_string()),
+      ..Config::default()
+    };
+    Config::save_to_file(&written, &path).unwrap();
+    if let Some(home) = dirs::home_dir() {
+      assert_eq!(
+        Config::load_from_file(&path).unwrap().freq_path,
+        Some(home.join("freq.db").to_string_lossy().into_owned())
+      );
+    }
+  }
+
+  #[test]
+  fn test_partial_lookup_table_keeps_other_settings() {
+    let config: Config = toml::from_str(
+      "dictionary_path = \"/dicts/dictionary.db\"\n[lookup]\ncase_sensitive = true\n",
+    )
+    .unwrap();
+    assert!(config.lookup.case_sensitive);
+    assert!(!config.lookup.compound_words);
+    assert_eq!(
+      config.dictionary_path,
+      Some("/dicts/dictionary.db".to_string())
+    );
+  }
+
+  #[test]
+  fn test_saved_config_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dictionary-lsp").join("config.toml");
+
+    let mut changed = Config {
+      dictionary_path: Some("/dicts/dictionary.db".to_string()),
+      max_analyze_bytes: Some(1_000_000),
+      ..Config::default()
+    };
+    changed.formatting.max_definition_chars = Some(200);
+    changed.completion.match_mode = MatchMode::Fuzzy;
+    changed.lookup.pos_order = vec!["noun".to_string(), "verb".to_string()];
+    changed.stopwords.words = vec!["the".to_string()];
+
+    for config in [Config::default(), changed] {
+      Config::save_to_file(&config, &path).unwrap();
+      let written = fs::read_to_string(&path).unwrap();
+      assert!(written.starts_with("# dictionary-lsp configuration"));
+
+      let loaded = Config::load_from_file(&path).unwrap();
+      assert_eq!(
+        toml::to_string(&loaded).unwrap(),
+        toml::to_string(&config).unwrap()
+      );
+    }
   }
 }
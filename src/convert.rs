@@ -0,0 +1,317 @@
+use crate::dictionary_data::{self, get_lossy_text};
+use rusqlite::{params, Connection, OpenFlags};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+pub type ConvertResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Schema expected by `SqliteDictionaryProvider`
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS words (
+    id INTEGER PRIMARY KEY,
+    word TEXT UNIQUE
+);
+CREATE TABLE IF NOT EXISTS parts_of_speech (
+    id INTEGER PRIMARY KEY,
+    name TEXT UNIQUE
+);
+CREATE TABLE IF NOT EXISTS definitions (
+    id INTEGER PRIMARY KEY,
+    word_id INTEGER,
+    pos_id INTEGER,
+    definition TEXT,
+    FOREIGN KEY (word_id) REFERENCES words (id),
+    FOREIGN KEY (pos_id) REFERENCES parts_of_speech (id),
+    UNIQUE (word_id, pos_id, definition)
+);
+CREATE INDEX IF NOT EXISTS idx_word ON words(word);
+CREATE TABLE IF NOT EXISTS variants (
+    variant TEXT,
+    word TEXT,
+    UNIQUE (variant, word)
+);
+"#;
+
+/// Rows ordered so that every word and part of speech is contiguous, which lets the
+/// exporter stream them without grouping in memory
+const EXPORT_QUERY: &str = r#"
+SELECT
+    w.word,
+    COALESCE(p.name, 'unknown') AS pos,
+    d.definition
+FROM words w
+JOIN definitions d ON w.id = d.word_id
+JOIN parts_of_speech p ON d.pos_id = p.id
+WHERE d.definition IS NOT NULL
+ORDER BY w.word, pos, d.id
+"#;
+
+/// Parses `<input> [--output <path>]` style arguments for the conversion subcommands.
+/// Returns the input path and the optional output path.
+pub fn parse_io_args(args: &[String]) -> Option<(String, Option<String>)> {
+  let mut input = None;
+  let mut output = None;
+  let mut iter = args.iter();
+
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--output" | "-o" => output = iter.next().cloned(),
+      _ => {
+        if input.is_none() {
+          input = Some(arg.clone());
+        }
+      }
+    }
+  }
+
+  input.map(|input| (input, output))
+}
+
+/// Converts a JSON dictionary (`{word: {pos: [definitions]}}`, or an array of entries)
+/// into the SQLite schema
+/// used by `SqliteDictionaryProvider`.
+/// Rows are inserted with `INSERT OR IGNORE`, so running the conversion twice into the
+/// same database does not duplicate anything.
+/// ## Returns
+/// - usize - Number of newly inserted definitions
+pub fn json_to_sqlite(input: &str, output: &str) -> ConvertResult<usize> {
+  let contents = fs::read_to_string(input)?;
+  let dictionary = dictionary_data::parse_json_dictionary(&contents)?;
+  let entries = dictionary
+    .as_object()
+    .ok_or("JSON dictionary must be an object keyed by word or an array of entries")?;
+
+  let mut conn = Connection::open(output)?;
+  conn.execute_batch(SCHEMA)?;
+
+  let tx = conn.transaction()?;
+  let mut inserted = 0;
+  {
+    let mut insert_word = tx.prepare("INSERT OR IGNORE INTO words (word) VALUES (?1)")?;
+    let mut select_word = tx.prepare("SELECT id FROM words WHERE word = ?1")?;
+    let mut insert_pos = tx.prepare("INSERT OR IGNORE INTO parts_of_speech (name) VALUES (?1)")?;
+    let mut select_pos = tx.prepare("SELECT id FROM parts_of_speech WHERE name = ?1")?;
+    let mut insert_definition = tx.prepare(
+      "INSERT OR IGNORE INTO definitions (word_id, pos_id, definition) VALUES (?1, ?2, ?3)",
+    )?;
+    let mut insert_variant =
+      tx.prepare("INSERT OR IGNORE INTO variants (variant, word) VALUES (?1, ?2)")?;
+
+    for (word, entry) in entries {
+      let parts = match entry.as_object() {
+        // Variant spellings go to the variants table rather than becoming words
+        Some(_) if dictionary_data::is_variant_entry(entry) => continue,
+        Some(parts) => parts,
+        None => continue,
+      };
+
+      insert_word.execute([word])?;
+      let word_id: i64 = select_word.query_row([word], |row| row.get(0))?;
+      for variant in dictionary_data::json_variants(entry) {
+        insert_variant.execute([&variant, word])?;
+      }
+
+      for (part_of_speech, definitions) in parts {
+        if part_of_speech == dictionary_data::VARIANTS_FIELD {
+          continue;
+        }
+        // Non-array fields (e.g. "ipa") are entry metadata, not parts of speech
+        let definitions = match definitions.as_array() {
+          Some(definitions) => definitions,
+          None => continue,
+        };
+
+        insert_pos.execute([part_of_speech])?;
+        let pos_id: i64 = select_pos.query_row([part_of_speech], |row| row.get(0))?;
+
+        for definition in definitions {
+          // Structured senses keep their main text; sub-senses have no column to go to
+          let definition = definition
+            .as_str()
+            .or_else(|| definition.get("definition").and_then(|text| text.as_str()));
+          if let Some(definition) = definition {
+            inserted += insert_definition.execute(params![word_id, pos_id, definition])?;
+          }
+        }
+      }
+    }
+  }
+  tx.commit()?;
+
+  Ok(inserted)
+}
+
+/// Entry point for `dictionary_lsp convert <input.json> [--output <output.db>]`.
+/// The output defaults to the input path with a `.db` extension.
+pub fn run_convert(args: &[String]) -> ConvertResult<()> {
+  let (input, output) = parse_io_args(args)
+    .ok_or("Usage: dictionary_lsp convert <input.json> [--output <output.db>]")?;
+  let output = output.unwrap_or_else(|| {
+    Path::new(&input)
+      .with_extension("db")
+      .to_string_lossy()
+      .into_owned()
+  });
+
+  let inserted = json_to_sqlite(&input, &output)?;
+  eprintln!("Converted {} definitions into {}", inserted, output);
+  Ok(())
+}
+
+/// Exports a SQLite dictionary into the canonical JSON shape (`{word: {pos: [definitions]}}`)
+/// consumed by `JsonDictionaryProvider`.
+/// Rows are streamed straight into the output file so large databases are never held in memory.
+/// ## Returns
+/// - usize - Number of exported definitions
+pub fn sqlite_to_json(input: &str, output: &str) -> ConvertResult<usize> {
+  let conn = Connection::open_with_flags(input, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+  let mut stmt = conn.prepare(EXPORT_QUERY)?;
+  let mut rows = stmt.query([])?;
+  let mut writer = BufWriter::new(fs::File::create(output)?);
+
+  let mut current_word: Option<String> = None;
+  let mut current_pos: Option<String> = None;
+  let mut exported = 0;
+
+  writer.write_all(b"{")?;
+  while let Some(row) = rows.next()? {
+    let word = get_lossy_text(row, 0)?;
+    let pos = get_lossy_text(row, 1)?;
+    let definition = get_lossy_text(row, 2)?;
+
+    if current_word.as_deref() != Some(word.as_str()) {
+      // Close the previous word before opening a new one
+      if current_word.is_some() {
+        writer.write_all(b"]},")?;
+      }
+      serde_json::to_writer(&mut writer, &word)?;
+      writer.write_all(b":{")?;
+      serde_json::to_writer(&mut writer, &pos)?;
+      writer.write_all(b":[")?;
+      current_word = Some(word);
+      current_pos = Some(pos);
+    } else if current_pos.as_deref() != Some(pos.as_str()) {
+      writer.write_all(b"],")?;
+      serde_json::to_writer(&mut writer, &pos)?;
+      writer.write_all(b":[")?;
+      current_pos = Some(pos);
+    } else {
+      writer.write_all(b",")?;
+    }
+
+    serde_json::to_writer(&mut writer, &definition)?;
+    exported += 1;
+  }
+
+  if current_word.is_some() {
+    writer.write_all(b"]}")?;
+  }
+  writer.write_all(b"}")?;
+  writer.flush()?;
+
+  Ok(exported)
+}
+
+/// Entry point for `dictionary_lsp export-json <input.db> [--output <output.json>]`.
+/// The output defaults to the input path with a `.json` extension.
+pub fn run_export_json(args: &[String]) -> ConvertResult<()> {
+  let (input, output) = parse_io_args(args)
+    .ok_or("Usage: dictionary_lsp export-json <input.db> [--output <output.json>]")?;
+  let output = output.unwrap_or_else(|| {
+    Path::new(&input)
+      .with_extension("json")
+      .to_string_lossy()
+      .into_owned()
+  });
+
+  let exported = sqlite_to_json(&input, &output)?;
+  eprintln!("Exported {} definitions into {}", exported, output);
+  Ok(())
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::dictionary_data::{DictionaryProvider, SqliteDictionaryProvider};
+
+  fn write_json_fixture(dir: &Path) -> String {
+    let path = dir.join("dictionary.json");
+    fs::write(
+      &path,
+      r#"{
+        "passion": { "noun": ["strong feeling"] },
+        "run": {
+          "noun": ["an act of running"],
+          "verb": ["move quickly on foot", "operate"]
+        }
+      }"#,
+    )
+    .unwrap();
+    path.to_string_lossy().into_owned()
+  }
+
+  #[test]
+  fn test_parse_io_args() {
+    let args = vec![
+      "dict.json".to_string(),
+      "--output".to_string(),
+      "out.db".to_string(),
+    ];
+    assert_eq!(
+      parse_io_args(&args),
+      Some(("dict.json".to_string(), Some("out.db".to_string())))
+    );
+    assert_eq!(parse_io_args(&[]), None);
+  }
+
+  #[tokio::test]
+  async fn test_json_to_sqlite_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = write_json_fixture(dir.path());
+    let output = dir
+      .path()
+      .join("dictionary.db")
+      .to_string_lossy()
+      .into_owned();
+
+    assert_eq!(json_to_sqlite(&input, &output).unwrap(), 4);
+    // Converting again must not duplicate rows
+    assert_eq!(json_to_sqlite(&input, &output).unwrap(), 0);
+
+    let provider = SqliteDictionaryProvider::new(Some(output), None);
+    let response = provider.get_meaning("passion").await.unwrap().unwrap();
+    assert_eq!(response.word, "passion");
+    assert_eq!(response.meanings[0].part_of_speech, "noun");
+    assert_eq!(
+      response.meanings[0].definitions[0].definition,
+      "strong feeling"
+    );
+  }
+
+  #[test]
+  fn test_sqlite_to_json_loads_back_identically() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = write_json_fixture(dir.path());
+    let database = dir
+      .path()
+      .join("dictionary.db")
+      .to_string_lossy()
+      .into_owned();
+    let exported = dir
+      .path()
+      .join("exported.json")
+      .to_string_lossy()
+      .into_owned();
+
+    json_to_sqlite(&input, &database).unwrap();
+    assert_eq!(sqlite_to_json(&database, &exported).unwrap(), 4);
+
+    let original: serde_json::Value =
+      serde_json::from_str(&fs::read_to_string(&input).unwrap()).unwrap();
+    let round_tripped: serde_json::Value =
+      serde_json::from_str(&fs::read_to_string(&exported).unwrap()).unwrap();
+    assert_eq!(original, round_tripped);
+  }
+}
@@ -1,13 +1,18 @@
-use crate::config::Config;
+use crate::config::{
+  Config, FuzzyConfig, FuzzyRanking, LookupConfig, MatchMode, SortOrder, SqlSchemaConfig,
+};
 use crate::fuzzy;
 use async_trait::async_trait;
 use rusqlite;
 use serde::{Deserialize, Serialize};
 use serde_json;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
 use std::vec;
 use tower_lsp::jsonrpc::Error;
 use tower_lsp::jsonrpc::Result;
-use tower_lsp::lsp_types::Position;
+use tower_lsp::lsp_types::{Position, Range};
+use unicode_normalization::UnicodeNormalization;
 
 /// Determines if the character is a CJK (Chinese, Japanese, Korean) character
 /// by checking if it falls within the Unicode ranges for CJK characters.
@@ -20,783 +25,4383 @@ pub fn is_cjk_char(c: char) -> bool {
         || (c >= '\u{2B740}' && c <= '\u{2B81F}') // CJK Unified Ideographs Extension D
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Dominant writing system of a document
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Script {
+  Cjk,
+  Latin,
+}
+
+/// Number of letters `detect_script` looks at, so large documents stay cheap
+const SCRIPT_SAMPLE_LETTERS: usize = 1000;
+
+/// Guesses the dominant script of `content` from its first letters.
+/// Returns `None` when there are no letters to go by.
+pub fn detect_script(content: &str) -> Option<Script> {
+  let (mut cjk, mut latin) = (0, 0);
+  for c in content
+    .chars()
+    .filter(|c| c.is_alphabetic())
+    .take(SCRIPT_SAMPLE_LETTERS)
+  {
+    if is_cjk_char(c) {
+      cjk += 1;
+    } else {
+      latin += 1;
+    }
+  }
+
+  match (cjk, latin) {
+    (0, 0) => None,
+    _ if cjk >= latin => Some(Script::Cjk),
+    _ => Some(Script::Latin),
+  }
+}
+
+/// Normalizes a word to Unicode NFC so that composed ("café") and decomposed
+/// ("cafe\u{301}") spellings compare equal
+pub fn normalize_word(word: &str) -> String {
+  word.nfc().collect()
+}
+
+/// Folds case and strips diacritics, so "Café", "cafe" and "café" share one key
+pub fn dedup_key(word: &str) -> String {
+  word
+    .nfd()
+    .filter(|c| !unicode_normalization::char::is_combining_mark(*c))
+    .flat_map(char::to_lowercase)
+    .collect()
+}
+
+/// Ranks a fuzzy match for `query`: smaller edit distance (or `fuzzy_rank`) first, then the longer common
+/// prefix with the query (typos tend to come late in a word), then the more frequent word
+fn fuzzy_match_key(
+  query: &str,
+  candidate: &str,
+  distance: usize,
+) -> (usize, std::cmp::Reverse<usize>, std::cmp::Reverse<i64>) {
+  let common_prefix = query
+    .chars()
+    .zip(candidate.chars())
+    .take_while(|(a, b)| a == b)
+    .count();
+  (
+    distance,
+    std::cmp::Reverse(common_prefix),
+    std::cmp::Reverse(crate::tire::frequency(candidate)),
+  )
+}
+
+/// First criterion of `fuzzy_match_key` for a match `distance` edits away: the distance
+/// itself, or, when `config` ranks `query` by Jaro-Winkler, the dissimilarity in millionths
+fn fuzzy_rank(config: &FuzzyConfig, query: &str, candidate: &str, distance: usize) -> usize {
+  match config.ranking_for(query) {
+    FuzzyRanking::Levenshtein => distance,
+    FuzzyRanking::JaroWinkler => {
+      ((1.0 - fuzzy::jaro_winkler(query, candidate)) * 1_000_000.0).round() as usize
+    }
+  }
+}
+
+/// Magic bytes at the start of every gzip stream
+const GZIP_MAGIC: &[u8] = b"\x1F\x8B";
+
+/// Decompresses gzip data (e.g. `dictionary.json.gz`); other data is returned unchanged
+fn decompress_if_gzip(bytes: Vec<u8>) -> std::io::Result<Vec<u8>> {
+  if !bytes.starts_with(GZIP_MAGIC) {
+    return Ok(bytes);
+  }
+
+  let mut decompressed = Vec::new();
+  flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+  Ok(decompressed)
+}
+
+/// Reads a text column, decoding invalid UTF-8 lossily rather than failing the row.
+/// Other storage classes convert as `row.get` would.
+pub(crate) fn get_lossy_text(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<String> {
+  match row.get_ref(idx)? {
+    rusqlite::types::ValueRef::Text(bytes) => Ok(String::from_utf8_lossy(bytes).into_owned()),
+    _ => row.get(idx),
+  }
+}
+
+/// Decodes a dictionary file, stripping a UTF-8 BOM.
+/// Files that are not valid UTF-8 are decoded as Latin-1, which maps every byte to a char.
+pub(crate) fn decode_dictionary_bytes(bytes: Vec<u8>) -> String {
+  let bytes = match bytes.strip_prefix(b"\xEF\xBB\xBF") {
+    Some(rest) => rest.to_vec(),
+    None => bytes,
+  };
+
+  match String::from_utf8(bytes) {
+    Ok(contents) => contents,
+    Err(e) => {
+      eprintln!(
+        "Warning: dictionary file is not valid UTF-8 ({}), decoding it as Latin-1",
+        e.utf8_error()
+      );
+      e.into_bytes().into_iter().map(char::from).collect()
+    }
+  }
+}
+
+/// Parses a JSON dictionary and normalizes its headwords to NFC
+pub(crate) fn parse_json_dictionary(contents: &str) -> serde_json::Result<serde_json::Value> {
+  Ok(normalize_json_dictionary(serde_json::from_str(contents)?))
+}
+
+/// Normalizes headwords to NFC, indexing array-shaped dictionaries by word first
+fn normalize_json_dictionary(dictionary: serde_json::Value) -> serde_json::Value {
+  let mut entries = match dictionary {
+    serde_json::Value::Object(entries) => entries
+      .into_iter()
+      .map(|(word, entry)| (normalize_word(&word), entry))
+      .collect(),
+    serde_json::Value::Array(entries) => index_json_entries(entries),
+    other => return other,
+  };
+  add_variant_entries(&mut entries);
+  serde_json::Value::Object(entries)
+}
+
+/// Field of a JSON entry listing other spellings of its headword
+pub(crate) const VARIANTS_FIELD: &str = "variants";
+
+/// Field of the entry added for a variant spelling, naming the headword it belongs to
+const VARIANT_OF_FIELD: &str = "variant_of";
+
+/// Adds a `{"variant_of": word}` entry for each spelling in an entry's `"variants"`, so
+/// variants are completed and looked up like headwords. Spellings that have an entry of
+/// their own keep it.
+fn add_variant_entries(entries: &mut serde_json::Map<String, serde_json::Value>) {
+  let variants: Vec<(String, String)> = entries
+    .iter()
+    .flat_map(|(word, entry)| {
+      json_variants(entry)
+        .into_iter()
+        .map(move |variant| (variant, word.clone()))
+    })
+    .collect();
+  for (variant, word) in variants {
+    if variant != word {
+      entries
+        .entry(variant)
+        .or_insert_with(|| serde_json::json!({ VARIANT_OF_FIELD: word }));
+    }
+  }
+}
+
+/// The normalized spellings listed in the `"variants"` field of a JSON entry
+pub(crate) fn json_variants(entry: &serde_json::Value) -> Vec<String> {
+  entry
+    .get(VARIANTS_FIELD)
+    .and_then(|variants| variants.as_array())
+    .into_iter()
+    .flatten()
+    .filter_map(|variant| variant.as_str())
+    .map(normalize_word)
+    .collect()
+}
+
+/// Whether a JSON entry only stands for a variant spelling of another headword
+pub(crate) fn is_variant_entry(entry: &serde_json::Value) -> bool {
+  entry.get(VARIANT_OF_FIELD).is_some()
+}
+
+/// The headword and entry that `word` stands for: its own entry, or the entry of the
+/// headword it is a variant spelling of
+fn resolve_variant<'a>(
+  dictionary: &'a serde_json::Value,
+  word: &'a str,
+  entry: &'a serde_json::Value,
+) -> (&'a str, &'a serde_json::Value) {
+  entry
+    .get(VARIANT_OF_FIELD)
+    .and_then(|headword| headword.as_str())
+    .and_then(|headword| Some((headword, dictionary.get(headword)?)))
+    .unwrap_or((word, entry))
+}
+
+/// Turns a list of `{"word": ..., "meanings": ...}` entries into the `{word: {pos: [...]}}`
+/// shape. Meanings are either a `{pos: [definitions]}` map or a list of
+/// `{"partOfSpeech": ..., "definitions": [...]}` objects; entries of the same word are
+/// merged, and a "phonetic" field is read as the IPA.
+fn index_json_entries(
+  entries: Vec<serde_json::Value>,
+) -> serde_json::Map<String, serde_json::Value> {
+  use serde_json::{Map, Value};
+
+  let mut dictionary = Map::new();
+  for entry in entries {
+    let Value::Object(fields) = entry else {
+      continue;
+    };
+    let Some(word) = fields.get("word").and_then(Value::as_str) else {
+      continue;
+    };
+    let Value::Object(indexed) = dictionary
+      .entry(normalize_word(word))
+      .or_insert_with(|| Value::Object(Map::new()))
+    else {
+      unreachable!("entries are inserted as objects");
+    };
+
+    let mut add_definitions = |part_of_speech: &str, definitions: &Value| {
+      if let (Some(definitions), Value::Array(existing)) = (
+        definitions.as_array(),
+        indexed
+          .entry(part_of_speech)
+          .or_insert_with(|| Value::Array(Vec::new())),
+      ) {
+        existing.extend(definitions.iter().cloned());
+      }
+    };
+    match fields.get("meanings") {
+      Some(Value::Object(by_pos)) => {
+        for (part_of_speech, definitions) in by_pos {
+          add_definitions(part_of_speech, definitions);
+        }
+      }
+      Some(Value::Array(meanings)) => {
+        for meaning in meanings {
+          let part_of_speech = meaning
+            .get("partOfSpeech")
+            .or_else(|| meaning.get("part_of_speech"))
+            .and_then(Value::as_str)
+            .unwrap_or("unknown");
+          if let Some(definitions) = meaning.get("definitions") {
+            add_definitions(part_of_speech, definitions);
+          }
+        }
+      }
+      _ => {}
+    }
+
+    if let (Some(Value::Array(variants)), Value::Array(existing)) = (
+      fields.get(VARIANTS_FIELD),
+      indexed
+        .entry(VARIANTS_FIELD)
+        .or_insert_with(|| Value::Array(Vec::new())),
+    ) {
+      existing.extend(variants.iter().cloned());
+    }
+
+    // Other text fields such as "ipa" and "lang" are kept from the first entry giving them
+    for (name, value) in &fields {
+      let Some(text) = value.as_str() else {
+        continue;
+      };
+      let (name, text) = match name.as_str() {
+        "word" => continue,
+        "phonetic" => ("ipa", text.trim_matches('/')),
+        name => (name, text),
+      };
+      indexed.entry(name).or_insert_with(|| Value::from(text));
+    }
+  }
+  dictionary
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct DictionaryResponse {
   pub word: String,
   pub meanings: Vec<Meaning>,
+  /// IPA transcription, rendered under the word
+  pub ipa: Option<String>,
+  /// URL of a pronunciation recording
+  pub audio: Option<String>,
+  /// Other spellings of the word (e.g. "colour" for "color"), which look up the same entry
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub variants: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Meaning {
   pub part_of_speech: String,
   pub definitions: Vec<Definition>,
+  /// Language of the definitions (e.g. "en", "zh" or "en-zh"), for mixed dictionaries
+  #[serde(default)]
+  pub lang: Option<String>,
+  /// Where the definitions come from (e.g. "WordNet"), for merged dictionaries
+  #[serde(default)]
+  pub source: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Definition {
   pub definition: String,
   pub example: Option<String>,
+  /// Usage examples after the first one
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub more_examples: Vec<String>,
+  /// Narrower senses of this definition (1a, 1b, ...)
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub sub_definitions: Vec<Definition>,
+  /// Usage label of the sense, such as "informal" or "archaic"
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub label: Option<String>,
 }
 
-/// Common trait for dictionary data providers
-#[async_trait]
-pub trait DictionaryProvider: Send + Sync {
-  async fn get_meaning(&self, word: &str) -> Result<Option<DictionaryResponse>>;
-  fn get_word_at_position(&self, content: &str, position: Position) -> Option<String>;
-  async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>>;
+impl Definition {
+  /// `example` followed by `more_examples`
+  pub fn examples(&self) -> impl Iterator<Item = &String> {
+    self.example.iter().chain(&self.more_examples)
+  }
 }
 
-/// Factory function to create the appropriate dictionary provider
-pub fn create_dictionary_provider(
-  dictionary_path: Option<String>,
-  freq_path: Option<String>,
-) -> Box<dyn DictionaryProvider> {
-  if Config::is_sqlite(dictionary_path.as_deref()) {
-    Box::new(SqliteDictionaryProvider::new(dictionary_path, freq_path))
-  } else {
-    Box::new(JsonDictionaryProvider::new(dictionary_path, freq_path))
+/// Reads a JSON definition given either as a string or as an object with a
+/// `definition`, optional `example`, `examples` and `label`, and nested `sub_definitions`
+fn parse_json_definition(value: &serde_json::Value) -> Definition {
+  let text = |name: &str| value.get(name).and_then(|field| field.as_str());
+  let mut examples = text("example")
+    .into_iter()
+    .chain(
+      value
+        .get("examples")
+        .and_then(|examples| examples.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|example| example.as_str()),
+    )
+    .map(|example| example.to_string());
+  match value {
+    serde_json::Value::Object(_) => Definition {
+      definition: text("definition").unwrap_or("").to_string(),
+      example: examples.next(),
+      more_examples: examples.collect(),
+      sub_definitions: value
+        .get("sub_definitions")
+        .and_then(|senses| senses.as_array())
+        .map(|senses| senses.iter().map(parse_json_definition).collect())
+        .unwrap_or_default(),
+      label: text("label").map(|label| label.to_string()),
+    },
+    _ => Definition {
+      definition: value.as_str().unwrap_or("").to_string(),
+      example: None,
+      more_examples: Vec::new(),
+      sub_definitions: Vec::new(),
+      label: None,
+    },
   }
 }
 
-/// Provider implementation for SQLite dictionaries
-pub struct SqliteDictionaryProvider {
-  dictionary_path: Option<String>,
-  freq_path: Option<String>,
-  dictionary_conn: tokio::sync::Mutex<Option<rusqlite::Connection>>,
-  freq_conn: tokio::sync::Mutex<Option<rusqlite::Connection>>,
-  prefix_cache: tokio::sync::Mutex<(String, Vec<String>)>,
+/// Size of a loaded dictionary, reported by the `dictionary.stats` command
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct DictionaryStats {
+  pub words: usize,
+  pub definitions: usize,
+  /// Distinct parts of speech, sorted
+  pub parts_of_speech: Vec<String>,
+  /// Words loaded into the completion trie from the frequency database
+  pub trie_words: usize,
 }
 
-impl SqliteDictionaryProvider {
-  pub fn new(dictionary_path: Option<String>, freq_path: Option<String>) -> Self {
-    let provider = Self {
-      dictionary_path,
-      freq_path,
-      dictionary_conn: tokio::sync::Mutex::new(None),
-      freq_conn: tokio::sync::Mutex::new(None),
-      prefix_cache: tokio::sync::Mutex::new((String::new(), Vec::new())),
-    };
+/// Result of `check_word`: whether the word is a headword and, if not, its closest corrections
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct WordCheck {
+  pub known: bool,
+  /// Best correction first; empty for known words
+  pub suggestions: Vec<String>,
+}
 
-    // Eagerly initialize connections if paths are available
-    if let Some(dict_path) = &provider.dictionary_path {
-      if let Ok(conn) = rusqlite::Connection::open(dict_path) {
-        let _ = std::mem::replace(
-          &mut *futures::executor::block_on(provider.dictionary_conn.lock()),
-          Some(conn),
-        );
-      }
-    }
+/// Why a dictionary could not be read, reported to the client as a JSON-RPC error with a
+/// code of its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DictionaryError {
+  /// No dictionary (or frequency database) path is configured
+  NotConfigured(String),
+  /// The dictionary file is missing or unreadable
+  IoError(String),
+  /// The dictionary file is not valid JSON
+  ParseError(String),
+  /// The SQLite dictionary lacks a table or column the queries need
+  SchemaError(String),
+  /// Any other database failure
+  QueryError(String),
+}
 
-    if let Some(freq_path) = &provider.freq_path {
-      if let Ok(conn) = rusqlite::Connection::open(freq_path) {
-        let _ = std::mem::replace(
-          &mut *futures::executor::block_on(provider.freq_conn.lock()),
-          Some(conn),
-        );
+impl DictionaryError {
+  /// Server error codes of the failures that `ErrorCode` has no variant for
+  pub const IO_ERROR_CODE: i64 = -32001;
+  pub const PARSE_ERROR_CODE: i64 = -32002;
+  pub const SCHEMA_ERROR_CODE: i64 = -32003;
+
+  /// Classifies a SQLite failure while doing `context` ("Error querying database")
+  pub fn sqlite(context: &str, error: rusqlite::Error) -> Self {
+    let message = format!("{}: {}", context, error);
+    match &error {
+      rusqlite::Error::SqliteFailure(_, Some(detail))
+        if detail.starts_with("no such table") || detail.starts_with("no such column") =>
+      {
+        Self::SchemaError(message)
+      }
+      rusqlite::Error::SqliteFailure(failure, _)
+        if failure.code == rusqlite::ErrorCode::CannotOpen =>
+      {
+        Self::IoError(message)
       }
+      _ => Self::QueryError(message),
     }
-
-    provider
   }
-  fn get_dictionary_path(&self) -> Result<String> {
-    match &self.dictionary_path {
-      Some(path) => Ok(path.clone()),
-      None => Err(Error::invalid_params("Dictionary path not provided")),
+
+  pub fn code(&self) -> tower_lsp::jsonrpc::ErrorCode {
+    use tower_lsp::jsonrpc::ErrorCode;
+    match self {
+      Self::NotConfigured(_) => ErrorCode::InvalidParams,
+      Self::IoError(_) => ErrorCode::ServerError(Self::IO_ERROR_CODE),
+      Self::ParseError(_) => ErrorCode::ServerError(Self::PARSE_ERROR_CODE),
+      Self::SchemaError(_) => ErrorCode::ServerError(Self::SCHEMA_ERROR_CODE),
+      Self::QueryError(_) => ErrorCode::InternalError,
     }
   }
-  fn get_freq_path(&self) -> Result<String> {
-    match &self.freq_path {
-      Some(path) => Ok(path.clone()),
-      None => Err(Error::invalid_params("Frequency path not provided")),
+
+  pub fn message(&self) -> &str {
+    match self {
+      Self::NotConfigured(message)
+      | Self::IoError(message)
+      | Self::ParseError(message)
+      | Self::SchemaError(message)
+      | Self::QueryError(message) => message,
     }
   }
-  fn get_safe_string(row: &rusqlite::Row, idx: usize) -> Option<String> {
-    match row.get::<_, Option<String>>(idx) {
-      Ok(Some(s)) => Some(s),
-      _ => match row.get::<_, Option<Vec<u8>>>(idx) {
-        Ok(Some(bytes)) => Some(String::from_utf8_lossy(&bytes).into_owned()),
-        _ => None,
-      },
+}
+
+impl std::fmt::Display for DictionaryError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.message())
+  }
+}
+
+/// Failures are logged on their way to the client, except for missing configuration, which
+/// is expected (e.g. no frequency database)
+impl From<DictionaryError> for Error {
+  fn from(error: DictionaryError) -> Self {
+    if !matches!(error, DictionaryError::NotConfigured(_)) {
+      eprintln!("{}", error);
+    }
+    Error {
+      code: error.code(),
+      message: error.message().to_string(),
+      data: None,
     }
   }
+}
 
-  fn find_exact_match(
-    &self,
-    conn: &rusqlite::Connection,
-    word: &str,
-  ) -> Result<Option<DictionaryResponse>> {
-    let mut stmt = conn
-      .prepare(
-        r#"
-        SELECT 
-            w.word,
-            p.name AS pos,
-            d.definition
-        FROM words w
-        JOIN definitions d ON w.id = d.word_id
-        JOIN parts_of_speech p ON d.pos_id = p.id
-        WHERE w.word = ?1 COLLATE NOCASE
-        ORDER BY p.name
-        "#,
-      )
-      .map_err(|e| {
-        eprintln!("Error preparing statement: {}", e);
-        Error::internal_error()
-      })?;
+/// Default number of completion items shown for a prefix (`completion.max_items`).
+/// Providers fetch one extra word so that callers can tell whether the list was truncated.
+pub const PREFIX_RESULT_LIMIT: usize = 5;
 
-    let query_result = stmt.query_map([word], |row| {
-      let word = Self::get_safe_string(row, 0).unwrap_or_default();
-      let pos = Self::get_safe_string(row, 1);
-      let translation = Self::get_safe_string(row, 2);
+/// Minimum number of characters in each part of a split compound word
+const MIN_COMPOUND_PART: usize = 2;
 
-      Ok((word, translation, pos))
-    });
+/// Tries to split `word` into two parts that are both known to `lookup`,
+/// preferring the longest leading part (e.g. "sunflower" -> "sun" + "flower").
+/// Only two parts are considered, so the search never recurses.
+fn find_compound_match<F>(word: &str, mut lookup: F) -> Result<Option<DictionaryResponse>>
+where
+  F: FnMut(&str) -> Result<Option<DictionaryResponse>>,
+{
+  let chars: Vec<char> = word.chars().collect();
+  if chars.len() < MIN_COMPOUND_PART * 2 {
+    return Ok(None);
+  }
 
-    match query_result {
-      Ok(mut rows) => {
-        if let Some(row_result) = rows.next() {
-          match row_result {
-            Ok((word, translation, pos)) => {
-              let mut definitions = Vec::new();
-              if let Some(trans) = translation {
-                definitions.push(Definition {
-                  definition: trans,
-                  example: None,
-                });
-              }
-              if definitions.is_empty() {
-                return Ok(None);
-              }
-              return Ok(Some(DictionaryResponse {
-                word,
-                meanings: vec![Meaning {
-                  part_of_speech: pos.unwrap_or_else(|| "unknown".to_string()),
-                  definitions,
-                }],
-              }));
-            }
-            Err(e) => {
-              eprintln!("Error processing row: {}", e);
-              return Err(Error::internal_error());
-            }
-          }
-        }
-        Ok(None)
-      }
-      Err(e) => {
-        eprintln!("Error querying database: {}", e);
-        Err(Error::internal_error())
+  for split in (MIN_COMPOUND_PART..=chars.len() - MIN_COMPOUND_PART).rev() {
+    let head: String = chars[..split].iter().collect();
+    let tail: String = chars[split..].iter().collect();
+
+    if let Some(head_response) = lookup(&head)? {
+      if let Some(tail_response) = lookup(&tail)? {
+        return Ok(Some(combine_compound_meanings(
+          word,
+          head_response,
+          tail_response,
+        )));
       }
     }
   }
 
-  // Function to enable benchmarking with controllable distance parameter
-  pub async fn find_words_by_prefix_with_distance(
-    &self,
-    prefix: &str,
-    include_distance_2: bool,
-  ) -> Result<Option<Vec<String>>> {
-    // Convert prefix to lowercase for case-insensitive search
-    let lowercase_prefix = prefix.to_lowercase();
+  Ok(None)
+}
 
-    // Generate candidate words with controllable distance parameter
-    let candidate_words =
-      fuzzy::generate_levenshtein_candidates(&lowercase_prefix, include_distance_2).await;
+/// Merges the meanings of both compound parts, labelling each part of speech
+/// with the part it belongs to
+fn combine_compound_meanings(
+  word: &str,
+  head: DictionaryResponse,
+  tail: DictionaryResponse,
+) -> DictionaryResponse {
+  let meanings = [head, tail]
+    .into_iter()
+    .flat_map(|part| {
+      let part_word = part.word;
+      part.meanings.into_iter().map(move |meaning| Meaning {
+        part_of_speech: format!("{}: {}", part_word, meaning.part_of_speech),
+        definitions: meaning.definitions,
+        lang: meaning.lang,
+        source: meaning.source,
+      })
+    })
+    .collect();
 
-    Ok(Some(candidate_words))
+  DictionaryResponse {
+    word: word.to_string(),
+    meanings,
+    ..Default::default()
   }
+}
 
-  fn find_fuzzy_match(
-    &self,
-    conn: &rusqlite::Connection,
-    word: &str,
-  ) -> Result<Option<DictionaryResponse>> {
-    let word_len = word.len() as i64;
-    let max_distance = 2;
-    let mut stmt = match conn.prepare(
-      r#"
-        SELECT 
-            w.word,
-            p.name AS pos,
-            d.definition
-        FROM words w
-        JOIN definitions d ON w.id = d.word_id
-        JOIN parts_of_speech p ON d.pos_id = p.id
-        WHERE length(w.word) BETWEEN ?1 - ?2 AND ?1 + ?2
-          AND substr(w.word, 1, 1) = substr(?3, 1, 1)
-          AND substr(w.word, -1, 1) = substr(?3, -1, 1)
-        ORDER BY length(w.word)
-        "#,
-    ) {
-      Ok(stmt) => stmt,
-      Err(e) => {
-        eprintln!("Error preparing statement: {}", e);
-        return Err(Error::internal_error());
-      }
-    };
+/// Common trait for dictionary data providers
+#[async_trait]
+pub trait DictionaryProvider: Send + Sync {
+  async fn get_meaning(&self, word: &str) -> Result<Option<DictionaryResponse>>;
+  fn get_word_at_position(&self, content: &str, position: Position) -> Option<String>;
+  async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>>;
 
-    let query_result = stmt.query_map(rusqlite::params![word_len, max_distance, word], |row| {
-      let word = Self::get_safe_string(row, 0).unwrap_or_default();
-      let pos = Self::get_safe_string(row, 1);
-      let translation = Self::get_safe_string(row, 2);
-      let detail = Self::get_safe_string(row, 3);
+  /// Find words matching `query` with the given strategy.
+  /// Providers that don't support a mode fall back to prefix search.
+  async fn find_words(&self, query: &str, mode: MatchMode) -> Result<Option<Vec<String>>> {
+    let _ = mode;
+    self.find_words_by_prefix(query).await
+  }
 
-      Ok((word, translation, pos, detail))
-    });
+  /// Counts the words, definitions and parts of speech in the dictionary
+  async fn stats(&self) -> Result<DictionaryStats> {
+    Err(Error::method_not_found())
+  }
 
-    let max_distance = 2;
-    let mut closest_match = None;
-    let mut min_distance = max_distance + 1;
+  /// Finds headwords whose definitions contain `query`, ignoring case (reverse lookup)
+  async fn find_words_by_definition(&self, query: &str) -> Result<Vec<String>> {
+    let _ = query;
+    Err(Error::method_not_found())
+  }
 
-    match query_result {
-      Ok(rows) => {
-        for row_result in rows {
-          match row_result {
-            Ok((dict_word, translation, pos, detail)) => {
-              let distance = self.levenshtein_distance(word, &dict_word);
-              if distance <= max_distance && distance < min_distance {
-                min_distance = distance;
-                closest_match = Some((dict_word, translation, pos, detail));
-              }
-            }
-            Err(e) => {
-              eprintln!("Error processing row: {}", e);
-              return Err(Error::internal_error());
-            }
-          }
-        }
+  /// Tells whether `word` is in the dictionary without fetching its definitions,
+  /// suggesting the closest headwords when it isn't (spell checking)
+  async fn check_word(&self, word: &str) -> Result<WordCheck> {
+    let _ = word;
+    Err(Error::method_not_found())
+  }
 
-        if let Some((word, translation, pos, detail)) = closest_match {
-          return Ok(Some(self.parse_dictionary_entry(
-            &word,
-            translation,
-            pos,
-            detail,
-          )));
-        }
+  /// Up to `count` distinct headwords picked at random
+  async fn random_words(&self, count: usize) -> Result<Vec<String>> {
+    let _ = count;
+    Err(Error::method_not_found())
+  }
 
-        Ok(None)
-      }
-      Err(e) => {
-        eprintln!("Error querying database: {}", e);
-        Err(Error::internal_error())
-      }
-    }
+  /// Loads the dictionary ahead of the first lookup, so that one is as fast as the rest
+  async fn warm_up(&self) -> Result<()> {
+    Ok(())
   }
 
-  fn parse_dictionary_entry(
-    &self,
-    word: &str,
-    translation: Option<String>,
-    pos: Option<String>,
-    detail: Option<String>,
-  ) -> DictionaryResponse {
-    let mut definitions = Vec::new();
+  /// Drops cached lookups, e.g. after the dictionary file was replaced
+  async fn clear_cache(&self) {}
 
-    if let Some(trans) = translation {
-      definitions.push(Definition {
-        definition: trans,
-        example: None,
-      });
+  /// Looks up several words at once, pairing each with its meaning in the given order.
+  /// Providers override this to share one query or dictionary scan between the words.
+  async fn get_meanings(
+    &self,
+    words: &[&str],
+  ) -> Result<Vec<(String, Option<DictionaryResponse>)>> {
+    let mut meanings = Vec::with_capacity(words.len());
+    for word in words {
+      meanings.push((word.to_string(), self.get_meaning(word).await?));
     }
+    Ok(meanings)
+  }
+}
 
-    if let Some(det) = detail {
-      if !definitions.iter().any(|d| d.definition == det) {
-        definitions.push(Definition {
-          definition: det,
-          example: None,
-        });
-      }
-    }
+/// Most corrections returned by `check_word`
+pub const SPELLING_SUGGESTION_LIMIT: usize = 5;
 
-    DictionaryResponse {
-      word: word.to_string(),
-      meanings: vec![Meaning {
-        part_of_speech: pos.unwrap_or_else(|| "unknown".to_string()),
-        definitions,
-      }],
-    }
-  }
-  // Calculate Levenshtein distance between two strings
-  fn levenshtein_distance(&self, s1: &str, s2: &str) -> usize {
-    let len1 = s1.chars().count();
-    let len2 = s2.chars().count();
-    if len1 == 0 {
-      return len2;
-    }
-    if len2 == 0 {
-      return len1;
-    }
+/// Largest edit distance between a misspelled word and its suggestions
+pub(crate) const SPELLING_MAX_DISTANCE: usize = 2;
 
-    let s1_chars: Vec<char> = s1.chars().collect();
-    let s2_chars: Vec<char> = s2.chars().collect();
+/// Orders `(headword, edit distance)` candidates the way the fuzzy fallback of `get_meaning`
+/// picks its match and keeps the best `SPELLING_SUGGESTION_LIMIT`
+pub(crate) fn rank_suggestions(query: &str, mut candidates: Vec<(String, usize)>) -> Vec<String> {
+  candidates
+    .sort_by_cached_key(|(word, distance)| (fuzzy_match_key(query, word, *distance), word.clone()));
+  candidates
+    .into_iter()
+    .map(|(word, _)| word)
+    .take(SPELLING_SUGGESTION_LIMIT)
+    .collect()
+}
 
-    let mut matrix = vec![vec![0; len2 + 1]; len1 + 1];
+/// Most words looked up by one query of `get_meanings`, below SQLite's parameter limit
+const SQL_BATCH_SIZE: usize = 500;
 
-    for i in 0..=len1 {
-      matrix[i][0] = i;
-    }
-    for j in 0..=len2 {
-      matrix[0][j] = j;
-    }
+/// A `(word, definition, part of speech, usage label)` row of the SQLite dictionary
+type DefinitionRow = (String, Option<String>, Option<String>, Option<String>);
 
-    // Fill the matrix
-    for i in 1..=len1 {
-      for j in 1..=len2 {
-        let cost = if s1_chars[i - 1] == s2_chars[j - 1] {
-          0
-        } else {
-          1
-        };
-        matrix[i][j] = std::cmp::min(
-          std::cmp::min(matrix[i - 1][j] + 1, matrix[i][j - 1] + 1),
-          matrix[i - 1][j - 1] + cost,
-        );
-      }
-    }
+/// Most headwords returned by `find_words_by_definition`
+pub const DEFINITION_SEARCH_LIMIT: usize = 100;
 
-    matrix[len1][len2]
-  }
+/// Builds a `LIKE ... ESCAPE '\'` pattern matching `fragment` anywhere in a value
+fn like_contains_pattern(fragment: &str) -> String {
+  format!(
+    "%{}%",
+    fragment
+      .replace('\\', "\\\\")
+      .replace('%', "\\%")
+      .replace('_', "\\_")
+  )
 }
 
-#[async_trait]
-impl DictionaryProvider for SqliteDictionaryProvider {
+/// Factory function to create the appropriate dictionary provider, configured by `config`
+pub fn create_dictionary_provider(
+  dictionary_path: Option<String>,
+  freq_path: Option<String>,
+  config: &Config,
+) -> Box<dyn DictionaryProvider> {
+  let provider: Box<dyn DictionaryProvider> = if Config::is_sqlite(dictionary_path.as_deref()) {
+    Box::new(SqliteDictionaryProvider::new(dictionary_path, freq_path).with_config(config.clone()))
+  } else if Config::is_mdx(dictionary_path.as_deref()) {
+    Box::new(crate::mdx::MdxDictionaryProvider::new(dictionary_path).with_config(config.clone()))
+  } else if Config::is_word_list(dictionary_path.as_deref()) {
+    Box::new(crate::word_list::WordListProvider::new(dictionary_path).with_config(config.clone()))
+  } else {
+    Box::new(JsonDictionaryProvider::new(dictionary_path, freq_path).with_config(config.clone()))
+  };
+  let provider =
+    Box::new(CachedDictionaryProvider::new(provider).with_ttl(meaning_cache_ttl(config)));
+
+  // Personal entries are edited at runtime, so they sit in front of the cache
+  match &config.personal_dictionary_path {
+    Some(path) => Box::new(
+      crate::personal_dictionary::PersonalDictionaryProvider::new(
+        crate::personal_dictionary::PersonalDictionary::shared(path),
+        provider,
+      )
+      .with_config(config.clone()),
+    ),
+    None => provider,
+  }
+}
+
+/// Factory function for a JSON dictionary held in memory instead of a file
+pub fn create_in_memory_provider(dictionary: serde_json::Value) -> Box<dyn DictionaryProvider> {
+  Box::new(CachedDictionaryProvider::new(Box::new(
+    JsonDictionaryProvider::from_value(dictionary),
+  )))
+}
+
+/// Number of `get_meaning` results kept by `CachedDictionaryProvider`
+const MEANING_CACHE_SIZE: usize = 256;
+
+/// A cached `get_meaning` result with the time it was looked up
+type CachedMeaning = (std::time::Instant, Option<DictionaryResponse>);
+
+/// Wraps a provider with an LRU of recent `get_meaning` results, so hovering the same
+/// word again doesn't query the dictionary. Misses are cached too.
+pub struct CachedDictionaryProvider {
+  inner: Box<dyn DictionaryProvider>,
+  meanings: std::sync::Mutex<lru::LruCache<String, CachedMeaning>>,
+  /// How long a result is served from the cache, `None` for as long as it stays in the LRU
+  ttl: Option<std::time::Duration>,
+  now: Box<dyn Fn() -> std::time::Instant + Send + Sync>,
+}
+
+/// `meaning_cache_ttl_secs` of `config`, `None` when unset or 0
+fn meaning_cache_ttl(config: &Config) -> Option<std::time::Duration> {
+  config
+    .meaning_cache_ttl_secs
+    .filter(|&secs| secs > 0)
+    .map(std::time::Duration::from_secs)
+}
+
+impl CachedDictionaryProvider {
+  pub fn new(inner: Box<dyn DictionaryProvider>) -> Self {
+    Self {
+      inner,
+      meanings: std::sync::Mutex::new(lru::LruCache::new(
+        std::num::NonZeroUsize::new(MEANING_CACHE_SIZE).unwrap(),
+      )),
+      ttl: meaning_cache_ttl(&Config::get()),
+      now: Box::new(std::time::Instant::now),
+    }
+  }
+
+  /// Overrides `meaning_cache_ttl_secs`
+  pub fn with_ttl(mut self, ttl: Option<std::time::Duration>) -> Self {
+    self.ttl = ttl;
+    self
+  }
+
+  /// Replaces the clock that timestamps and expires cached results
+  pub fn with_clock(
+    mut self,
+    now: impl Fn() -> std::time::Instant + Send + Sync + 'static,
+  ) -> Self {
+    self.now = Box::new(now);
+    self
+  }
+
+  /// The cached result for `key`, dropping it once older than the TTL
+  fn cached(
+    &self,
+    meanings: &mut lru::LruCache<String, CachedMeaning>,
+    key: &str,
+  ) -> Option<Option<DictionaryResponse>> {
+    let (looked_up, response) = meanings.get(key)?;
+    if let Some(ttl) = self.ttl {
+      if (self.now)().saturating_duration_since(*looked_up) >= ttl {
+        meanings.pop(key);
+        return None;
+      }
+    }
+    Some(response.clone())
+  }
+}
+
+#[async_trait]
+impl DictionaryProvider for CachedDictionaryProvider {
   async fn get_meaning(&self, word: &str) -> Result<Option<DictionaryResponse>> {
-    let word_lower = word;
+    let key = normalize_word(word);
+    if let Some(cached) = self.cached(&mut self.meanings.lock().unwrap(), &key) {
+      return Ok(cached);
+    }
+
+    // Errors are not cached so that a transient failure is retried on the next lookup
+    let response = self.inner.get_meaning(word).await?;
+    self
+      .meanings
+      .lock()
+      .unwrap()
+      .put(key, ((self.now)(), response.clone()));
+    Ok(response)
+  }
+
+  async fn get_meanings(
+    &self,
+    words: &[&str],
+  ) -> Result<Vec<(String, Option<DictionaryResponse>)>> {
+    let keys: Vec<String> = words.iter().map(|word| normalize_word(word)).collect();
+    let mut responses: Vec<Option<Option<DictionaryResponse>>> = {
+      let mut meanings = self.meanings.lock().unwrap();
+      keys
+        .iter()
+        .map(|key| self.cached(&mut meanings, key))
+        .collect()
+    };
+
+    // Only the words missing from the cache reach the inner provider, in one batch
+    let missed: Vec<&str> = words
+      .iter()
+      .zip(&responses)
+      .filter(|(_, response)| response.is_none())
+      .map(|(word, _)| *word)
+      .collect();
+    if !missed.is_empty() {
+      let mut fetched = self.inner.get_meanings(&missed).await?.into_iter();
+      let mut meanings = self.meanings.lock().unwrap();
+      let looked_up = (self.now)();
+      for (key, response) in keys.into_iter().zip(&mut responses) {
+        if response.is_none() {
+          let (_, meaning) = fetched.next().unwrap_or_default();
+          meanings.put(key, (looked_up, meaning.clone()));
+          *response = Some(meaning);
+        }
+      }
+    }
+
+    // Every word has its response by now
+    Ok(
+      words
+        .iter()
+        .map(|word| word.to_string())
+        .zip(responses.into_iter().flatten())
+        .collect(),
+    )
+  }
+
+  fn get_word_at_position(&self, content: &str, position: Position) -> Option<String> {
+    self.inner.get_word_at_position(content, position)
+  }
+
+  async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>> {
+    self.inner.find_words_by_prefix(prefix).await
+  }
+
+  async fn find_words(&self, query: &str, mode: MatchMode) -> Result<Option<Vec<String>>> {
+    self.inner.find_words(query, mode).await
+  }
+
+  async fn stats(&self) -> Result<DictionaryStats> {
+    self.inner.stats().await
+  }
+
+  async fn find_words_by_definition(&self, query: &str) -> Result<Vec<String>> {
+    self.inner.find_words_by_definition(query).await
+  }
+
+  async fn check_word(&self, word: &str) -> Result<WordCheck> {
+    self.inner.check_word(word).await
+  }
+
+  async fn random_words(&self, count: usize) -> Result<Vec<String>> {
+    self.inner.random_words(count).await
+  }
+
+  async fn warm_up(&self) -> Result<()> {
+    self.inner.warm_up().await
+  }
+
+  async fn clear_cache(&self) {
+    self.meanings.lock().unwrap().clear();
+    self.inner.clear_cache().await;
+  }
+}
+
+/// Headwords sorted by their case-folded form, so a prefix query is a binary search
+/// instead of a scan over every word
+pub struct PrefixIndex {
+  /// (folded word, word) pairs
+  keys: Vec<(String, String)>,
+}
+
+impl PrefixIndex {
+  pub fn new<'a>(
+    words: impl Iterator<Item = &'a String>,
+    fold_case: impl Fn(&str) -> String,
+  ) -> Self {
+    let mut keys: Vec<(String, String)> =
+      words.map(|word| (fold_case(word), word.clone())).collect();
+    keys.sort();
+    Self { keys }
+  }
+
+  /// Words whose folded form starts with `prefix`, in folded alphabetical order
+  pub fn words_with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a String> {
+    let start = self
+      .keys
+      .partition_point(|(folded, _)| folded.as_str() < prefix);
+    self.keys[start..]
+      .iter()
+      .take_while(move |(folded, _)| folded.starts_with(prefix))
+      .map(|(_, word)| word)
+  }
+}
+
+/// Results of the last prefix search, reused while the user keeps typing.
+///
+/// Invariant: narrowing the prefix yields exactly the cached words that match the
+/// narrower prefix, in cached order. That only holds when the cached list was not
+/// truncated, so truncated or fuzzy lists are never narrowed and a fresh search runs.
+/// Results are also only reused under the case mode they were searched with.
+#[derive(Debug, Default)]
+struct PrefixCache {
+  prefix: String,
+  /// `LookupConfig::case_sensitive` when the results were searched
+  case_sensitive: bool,
+  results: Vec<String>,
+  /// Every word matching `prefix` is in `results`
+  complete: bool,
+}
+
+impl PrefixCache {
+  /// Returns the cached words matching the folded `prefix` when the cache covers it
+  fn narrow(&self, prefix: &str, lookup: &LookupConfig) -> Option<Vec<String>> {
+    if !self.complete
+      || self.case_sensitive != lookup.case_sensitive
+      || self.prefix.is_empty()
+      || !prefix.starts_with(&self.prefix)
+    {
+      return None;
+    }
+
+    Some(
+      self
+        .results
+        .iter()
+        .filter(|word| lookup.fold_case(word).starts_with(prefix))
+        .cloned()
+        .collect(),
+    )
+  }
+
+  fn store(&mut self, prefix: String, lookup: &LookupConfig, results: Vec<String>, complete: bool) {
+    *self = Self {
+      prefix,
+      case_sensitive: lookup.case_sensitive,
+      results,
+      complete,
+    };
+  }
+
+  fn clear(&mut self) {
+    *self = Self::default();
+  }
+}
+
+/// A pseudo-random number below `bound`, which must not be 0. Each `RandomState` is seeded
+/// differently, which is random enough for picking words without another dependency.
+pub(crate) fn random_below(bound: usize) -> usize {
+  use std::hash::{BuildHasher, Hasher};
+
+  let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+  hasher.write_u128(
+    std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_nanos(),
+  );
+  (hasher.finish() % bound as u64) as usize
+}
+
+/// Whether the frequency database lists `word` or its lowercase form, using the trie when
+/// it is loaded and querying the database otherwise
+pub async fn is_in_frequency_list(freq_path: &str, word: &str) -> bool {
+  let lowercase = word.to_lowercase();
+  if crate::tire::is_trie_initialized() {
+    return crate::tire::frequency(word) > 0 || crate::tire::frequency(&lowercase) > 0;
+  }
+
+  let freq_path = freq_path.to_string();
+  let word = word.to_string();
+  tokio::task::spawn_blocking(move || {
+    rusqlite::Connection::open_with_flags(&freq_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+      .and_then(|conn| {
+        conn.query_row(
+          "SELECT EXISTS(SELECT 1 FROM word_frequencies WHERE word IN (?1, ?2))",
+          [&word, &lowercase],
+          |row| row.get::<_, bool>(0),
+        )
+      })
+      .unwrap_or(false)
+  })
+  .await
+  .unwrap_or(false)
+}
+
+/// Logs the first failure to query the frequency database; later ones are silent
+/// since completion keeps working from the trie.
+fn warn_freq_db_unavailable(error: &DictionaryError) {
+  static WARNED: std::sync::Once = std::sync::Once::new();
+  WARNED.call_once(|| {
+    eprintln!(
+      "Frequency database unavailable, completing from the trie only: {}",
+      error
+    );
+  });
+}
+
+/// Opens the SQLite database at `path`, which must exist: opening a missing file would
+/// create an empty database and turn every lookup into a schema error
+fn open_database(
+  path: &str,
+  what: &str,
+) -> std::result::Result<rusqlite::Connection, DictionaryError> {
+  if !std::path::Path::new(path).exists() {
+    return Err(DictionaryError::IoError(format!(
+      "{} not found: {}",
+      what, path
+    )));
+  }
+  rusqlite::Connection::open(path)
+    .map_err(|e| DictionaryError::sqlite(&format!("Error opening {}", path), e))
+}
+
+/// Quotes a configured table or column name for use in SQL
+fn quote_identifier(name: &str) -> String {
+  format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quoted table and column names of the configured SQLite schema
+struct SqlNames {
+  words: String,
+  word_id: String,
+  word: String,
+  definitions: String,
+  definition: String,
+  definition_word: String,
+  definition_pos: String,
+  pos: String,
+  pos_id: String,
+  pos_name: String,
+  label: Option<String>,
+  variants: String,
+}
+
+impl SqlNames {
+  fn new(schema: &SqlSchemaConfig) -> Self {
+    Self {
+      words: quote_identifier(&schema.words_table),
+      word_id: quote_identifier(&schema.word_id_column),
+      word: quote_identifier(&schema.word_column),
+      definitions: quote_identifier(&schema.definitions_table),
+      definition: quote_identifier(&schema.definition_column),
+      definition_word: quote_identifier(&schema.definition_word_column),
+      definition_pos: quote_identifier(&schema.definition_pos_column),
+      pos: quote_identifier(&schema.pos_table),
+      pos_id: quote_identifier(&schema.pos_id_column),
+      pos_name: quote_identifier(&schema.pos_name_column),
+      variants: quote_identifier(&schema.variants_table),
+      label: schema.label_column.as_deref().map(quote_identifier),
+    }
+  }
+
+  /// The usage label of a definition (`d`), or NULL without a label column
+  fn label_expression(&self) -> String {
+    match &self.label {
+      Some(label) => format!("d.{}", label),
+      None => "NULL".to_string(),
+    }
+  }
+
+  /// Words (`w`) joined with their definitions (`d`) and parts of speech (`p`)
+  fn joined_tables(&self) -> String {
+    format!(
+      "{} w JOIN {} d ON w.{} = d.{} JOIN {} p ON d.{} = p.{}",
+      self.words,
+      self.definitions,
+      self.word_id,
+      self.definition_word,
+      self.pos,
+      self.definition_pos,
+      self.pos_id
+    )
+  }
+}
+
+/// Checks that the tables and columns named by `schema` exist in the dictionary database
+pub fn validate_sql_schema(
+  conn: &rusqlite::Connection,
+  schema: &SqlSchemaConfig,
+) -> std::result::Result<(), String> {
+  let expected = [
+    (
+      &schema.words_table,
+      vec![&schema.word_id_column, &schema.word_column],
+    ),
+    (
+      &schema.definitions_table,
+      [
+        &schema.definition_column,
+        &schema.definition_word_column,
+        &schema.definition_pos_column,
+      ]
+      .into_iter()
+      .chain(&schema.label_column)
+      .collect(),
+    ),
+    (
+      &schema.pos_table,
+      vec![&schema.pos_id_column, &schema.pos_name_column],
+    ),
+  ];
+
+  let mut missing = Vec::new();
+  for (table, columns) in expected {
+    let existing: Vec<String> = conn
+      .prepare("SELECT name FROM pragma_table_info(?1)")
+      .and_then(|mut stmt| {
+        stmt
+          .query_map([table], |row| row.get::<_, String>(0))?
+          .collect::<rusqlite::Result<Vec<_>>>()
+      })
+      .map_err(|e| e.to_string())?;
+    if existing.is_empty() {
+      missing.push(format!("table {}", table));
+      continue;
+    }
+    for column in columns {
+      if !existing
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(column))
+      {
+        missing.push(format!("column {}.{}", table, column));
+      }
+    }
+  }
+
+  if missing.is_empty() {
+    Ok(())
+  } else {
+    Err(format!("missing {}", missing.join(", ")))
+  }
+}
+
+/// Names of the optional `words` columns read by `attach_optional_columns`
+const OPTIONAL_COLUMNS_QUERY: &str =
+  "SELECT name FROM pragma_table_info(?1) WHERE name IN ('ipa', 'audio', 'lang')";
+
+/// Provider implementation for SQLite dictionaries
+pub struct SqliteDictionaryProvider {
+  dictionary_path: Option<String>,
+  freq_path: Option<String>,
+  dictionary_conn: tokio::sync::Mutex<Option<rusqlite::Connection>>,
+  prefix_cache: tokio::sync::Mutex<PrefixCache>,
+  config: Config,
+}
+
+impl SqliteDictionaryProvider {
+  /// Connections are opened on first use, so construction never blocks and is safe
+  /// inside the async runtime.
+  pub fn new(dictionary_path: Option<String>, freq_path: Option<String>) -> Self {
+    Self {
+      dictionary_path,
+      freq_path,
+      dictionary_conn: tokio::sync::Mutex::new(None),
+      prefix_cache: tokio::sync::Mutex::new(PrefixCache::default()),
+      config: Config::get(),
+    }
+  }
+
+  /// Overrides the configuration snapshot taken at construction time
+  pub fn with_config(mut self, config: Config) -> Self {
+    self.config = config;
+    self
+  }
 
+  fn get_dictionary_path(&self) -> std::result::Result<String, DictionaryError> {
+    match &self.dictionary_path {
+      Some(path) => Ok(path.clone()),
+      None => Err(DictionaryError::NotConfigured(
+        "Dictionary path not provided".to_string(),
+      )),
+    }
+  }
+  fn get_freq_path(&self) -> std::result::Result<String, DictionaryError> {
+    match &self.freq_path {
+      Some(path) => Ok(path.clone()),
+      None => Err(DictionaryError::NotConfigured(
+        "Frequency path not provided".to_string(),
+      )),
+    }
+  }
+  /// Reads a column as text regardless of its storage class.
+  /// Blobs are decoded lossily and numbers are stringified, so cells written with the
+  /// wrong type by a sloppy import still show up in definitions.
+  fn get_safe_string(row: &rusqlite::Row, idx: usize) -> Option<String> {
+    use rusqlite::types::ValueRef;
+
+    match row.get_ref(idx) {
+      Ok(ValueRef::Text(bytes)) | Ok(ValueRef::Blob(bytes)) => {
+        Some(String::from_utf8_lossy(bytes).into_owned())
+      }
+      Ok(ValueRef::Integer(value)) => Some(value.to_string()),
+      Ok(ValueRef::Real(value)) => Some(value.to_string()),
+      Ok(ValueRef::Null) | Err(_) => None,
+    }
+  }
+
+  /// Locks the dictionary connection, opening it on first use
+  async fn dictionary_connection(
+    &self,
+  ) -> Result<tokio::sync::MutexGuard<'_, Option<rusqlite::Connection>>> {
     let mut conn_guard = self.dictionary_conn.lock().await;
     if conn_guard.is_none() {
       let dict_path = self.get_dictionary_path()?;
-      *conn_guard = Some(rusqlite::Connection::open(&dict_path).map_err(|e| {
-        eprintln!("error connecting to sqlite database: {}", e);
-        Error::internal_error()
-      })?);
+      *conn_guard = Some(open_database(&dict_path, "Dictionary")?);
+    }
+    Ok(conn_guard)
+  }
+
+  /// Collation clause for comparing headwords, empty when lookups are case sensitive
+  fn word_collation(&self) -> &'static str {
+    if self.config.lookup.case_sensitive {
+      ""
+    } else {
+      " COLLATE NOCASE"
+    }
+  }
+
+  fn sql_names(&self) -> SqlNames {
+    SqlNames::new(&self.config.sql_schema)
+  }
+
+  fn find_exact_match(
+    &self,
+    conn: &rusqlite::Connection,
+    word: &str,
+  ) -> Result<Option<DictionaryResponse>> {
+    Ok(
+      self
+        .find_exact_matches(conn, &[word.to_string()])?
+        .remove(&self.match_key(word)),
+    )
+  }
+
+  /// Key under which `find_exact_matches` groups a headword: the word as SQLite's
+  /// `NOCASE` compares it (ASCII folded), or unchanged when lookups are case sensitive
+  fn match_key(&self, word: &str) -> String {
+    if self.config.lookup.case_sensitive {
+      word.to_string()
+    } else {
+      word.to_ascii_lowercase()
+    }
+  }
+
+  /// Query for the definition rows of `count` headwords
+  fn exact_match_query(&self, count: usize) -> String {
+    let names = self.sql_names();
+    format!(
+      r#"
+        SELECT 
+            w.{word},
+            p.{pos_name} AS pos,
+            d.{definition},
+            {label}
+        FROM {tables}
+        WHERE w.{word}{collation} IN ({placeholders})
+        ORDER BY p.{pos_name}
+        "#,
+      word = names.word,
+      pos_name = names.pos_name,
+      definition = names.definition,
+      label = names.label_expression(),
+      tables = names.joined_tables(),
+      collation = self.word_collation(),
+      placeholders = vec!["?"; count].join(",")
+    )
+  }
+
+  /// Looks up `words` as headwords and as variant spellings of a headword, keyed by the
+  /// `match_key` of the looked up word. Each response lists its headword's variants.
+  fn find_exact_matches(
+    &self,
+    conn: &rusqlite::Connection,
+    words: &[String],
+  ) -> Result<HashMap<String, DictionaryResponse>> {
+    let mut matches = self.find_headwords(conn, words)?;
+
+    let missed: Vec<String> = words
+      .iter()
+      .filter(|word| !matches.contains_key(&self.match_key(word)))
+      .cloned()
+      .collect();
+    let variant_of = self.variant_rows(conn, "variant", &missed)?;
+    if !variant_of.is_empty() {
+      let headwords: Vec<String> = variant_of.iter().map(|(_, word)| word.clone()).collect();
+      let found = self.find_headwords(conn, &headwords)?;
+      for (variant, word) in variant_of {
+        if let Some(response) = found.get(&self.match_key(&word)) {
+          matches
+            .entry(self.match_key(&variant))
+            .or_insert_with(|| response.clone());
+        }
+      }
+    }
+
+    let headwords: Vec<String> = matches
+      .values()
+      .map(|response| response.word.clone())
+      .collect::<HashSet<_>>()
+      .into_iter()
+      .collect();
+    for (variant, word) in self.variant_rows(conn, "word", &headwords)? {
+      let key = self.match_key(&word);
+      for response in matches.values_mut() {
+        if self.match_key(&response.word) == key && !response.variants.contains(&variant) {
+          response.variants.push(variant.clone());
+        }
+      }
+    }
+    Ok(matches)
+  }
+
+  /// `(variant, headword)` rows of the variants table whose `column` is one of `words`,
+  /// none when the dictionary has no variants table
+  fn variant_rows(
+    &self,
+    conn: &rusqlite::Connection,
+    column: &str,
+    words: &[String],
+  ) -> Result<Vec<(String, String)>> {
+    let has_table = conn
+      .prepare_cached("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")
+      .and_then(|mut stmt| stmt.exists([&self.config.sql_schema.variants_table]))
+      .unwrap_or(false);
+    if words.is_empty() || !has_table {
+      return Ok(Vec::new());
+    }
+
+    let mut rows = Vec::new();
+    for batch in words.chunks(SQL_BATCH_SIZE) {
+      let query = format!(
+        "SELECT variant, word FROM {} WHERE {}{} IN ({}) ORDER BY rowid",
+        self.sql_names().variants,
+        column,
+        self.word_collation(),
+        vec!["?"; batch.len()].join(",")
+      );
+      conn
+        .prepare_cached(&query)
+        .and_then(|mut stmt| {
+          rows.extend(
+            stmt
+              .query_map(rusqlite::params_from_iter(batch), |row| {
+                Ok((get_lossy_text(row, 0)?, get_lossy_text(row, 1)?))
+              })?
+              .collect::<rusqlite::Result<Vec<_>>>()?,
+          );
+          Ok(())
+        })
+        .map_err(|e| DictionaryError::sqlite("Error querying spelling variants", e))?;
+    }
+    Ok(rows)
+  }
+
+  /// Looks up `words` with one `IN` query per `SQL_BATCH_SIZE` words, keyed by `match_key`
+  fn find_headwords(
+    &self,
+    conn: &rusqlite::Connection,
+    words: &[String],
+  ) -> Result<HashMap<String, DictionaryResponse>> {
+    // Rows of each headword, alphabetically by part of speech
+    let mut rows_by_word: HashMap<String, Vec<DefinitionRow>> = HashMap::new();
+
+    for batch in words.chunks(SQL_BATCH_SIZE) {
+      // Cached, as hover looks up one word at a time with the same statement
+      let mut stmt = conn
+        .prepare_cached(&self.exact_match_query(batch.len()))
+        .map_err(|e| DictionaryError::sqlite("Error preparing statement", e))?;
+
+      let rows = stmt
+        .query_map(rusqlite::params_from_iter(batch), |row| {
+          let word = Self::get_safe_string(row, 0).unwrap_or_default();
+          let pos = Self::get_safe_string(row, 1);
+          let translation = Self::get_safe_string(row, 2);
+          let label = Self::get_safe_string(row, 3);
+
+          Ok((word, translation, pos, label))
+        })
+        .map_err(|e| DictionaryError::sqlite("Error querying database", e))?;
+
+      for row in rows {
+        let row = row.map_err(|e| DictionaryError::sqlite("Error processing row", e))?;
+        rows_by_word
+          .entry(self.match_key(&row.0))
+          .or_default()
+          .push(row);
+      }
+    }
+
+    Ok(
+      rows_by_word
+        .into_iter()
+        .filter_map(|(key, rows)| Some((key, self.response_from_rows(rows)?)))
+        .collect(),
+    )
+  }
+
+  /// Builds the response of one headword from its rows
+  fn response_from_rows(&self, rows: Vec<DefinitionRow>) -> Option<DictionaryResponse> {
+    // Rows come alphabetically by part of speech; the preferred one wins
+    let preferred = rows.into_iter().min_by_key(|(_, _, pos, _)| {
+      self
+        .config
+        .lookup
+        .pos_rank(pos.as_deref().unwrap_or_default())
+    });
+    let (word, Some(translation), pos, label) = preferred? else {
+      return None;
+    };
+    Some(DictionaryResponse {
+      word,
+      meanings: vec![Meaning {
+        part_of_speech: pos.unwrap_or_else(|| "unknown".to_string()),
+        definitions: vec![Definition {
+          definition: translation,
+          example: None,
+          more_examples: Vec::new(),
+          sub_definitions: Vec::new(),
+          label,
+        }],
+        lang: None,
+        source: None,
+      }],
+      ..Default::default()
+    })
+  }
+
+  /// Fallbacks of `get_meaning` for a word without an exact match
+  fn find_inexact_match(
+    &self,
+    conn: &rusqlite::Connection,
+    word: &str,
+  ) -> Result<Option<DictionaryResponse>> {
+    if self.config.fuzzy.enabled {
+      if let Some(mut response) = self.find_fuzzy_match(conn, word)? {
+        self.attach_optional_columns(conn, &mut response);
+        return Ok(Some(response));
+      }
     }
+
+    if self.config.lookup.compound_words {
+      return find_compound_match(word, |part| self.find_exact_match(conn, part));
+    }
+
+    Ok(None)
+  }
+
+  /// Opens the connection and prepares the statements of a single word lookup
+  async fn prepare_lookup_statements(&self) -> Result<()> {
+    let conn_guard = self.dictionary_connection().await?;
     let conn = conn_guard.as_ref().unwrap();
+    for query in [self.exact_match_query(1).as_str(), OPTIONAL_COLUMNS_QUERY] {
+      conn
+        .prepare_cached(query)
+        .map_err(|e| DictionaryError::sqlite("Error preparing statement", e))?;
+    }
+    Ok(())
+  }
 
-    if let Some(response) = self.find_exact_match(&conn, &word_lower)? {
-      return Ok(Some(response));
+  /// Fills `ipa`/`audio` and the meanings' `lang` from the optional columns of the same
+  /// name on the `words` table. Dictionaries without these columns are left untouched.
+  fn attach_optional_columns(
+    &self,
+    conn: &rusqlite::Connection,
+    response: &mut DictionaryResponse,
+  ) {
+    let columns: Vec<String> =
+      match conn
+        .prepare_cached(OPTIONAL_COLUMNS_QUERY)
+        .and_then(|mut stmt| {
+          stmt
+            .query_map([&self.config.sql_schema.words_table], |row| {
+              row.get::<_, String>(0)
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        }) {
+        Ok(columns) => columns,
+        Err(_) => return,
+      };
+
+    if columns.is_empty() {
+      return;
+    }
+
+    let select = |name: &str| {
+      if columns.iter().any(|column| column == name) {
+        name.to_string()
+      } else {
+        "NULL".to_string()
+      }
+    };
+    let names = self.sql_names();
+    let query = format!(
+      "SELECT {}, {}, {} FROM {} WHERE {} = ?1{}",
+      select("ipa"),
+      select("audio"),
+      select("lang"),
+      names.words,
+      names.word,
+      self.word_collation()
+    );
+
+    if let Ok((ipa, audio, lang)) = conn.query_row(&query, [&response.word], |row| {
+      Ok((
+        Self::get_safe_string(row, 0),
+        Self::get_safe_string(row, 1),
+        Self::get_safe_string(row, 2),
+      ))
+    }) {
+      response.ipa = ipa;
+      response.audio = audio;
+      for meaning in &mut response.meanings {
+        meaning.lang = lang.clone();
+      }
     }
+  }
+
+  /// Keeps the candidates present in the frequency database, most frequent first
+  async fn rank_by_frequency(
+    &self,
+    candidate_words: Vec<String>,
+  ) -> std::result::Result<Vec<String>, DictionaryError> {
+    let freq_path = self.get_freq_path()?;
+    let limit = self.config.completion.item_limit() + 1;
+
+    // Process all candidates in one go since our generation is now more targeted
+    tokio::task::spawn_blocking(move || {
+      let conn = open_database(&freq_path, "Frequency database")?;
+      let placeholders = vec!["?"; candidate_words.len()].join(",");
+
+      // Query with proper result limit
+      let query = format!(
+        "SELECT word FROM word_frequencies WHERE word IN ({}) ORDER BY frequency DESC LIMIT {}",
+        placeholders, limit
+      );
+
+      let mut stmt = conn
+        .prepare(&query)
+        .map_err(|e| DictionaryError::sqlite("Error preparing frequency query", e))?;
+
+      // Convert words to SQL parameters
+      let params: Vec<&dyn rusqlite::types::ToSql> = candidate_words
+        .iter()
+        .map(|w| w as &dyn rusqlite::types::ToSql)
+        .collect();
+
+      // Execute query and collect results
+      let mut result = Vec::new();
+      let rows = stmt
+        .query_map(params.as_slice(), |row| get_lossy_text(row, 0))
+        .map_err(|e| DictionaryError::sqlite("Error querying frequencies", e))?;
+
+      for word_result in rows {
+        if let Ok(word) = word_result {
+          result.push(word);
+        }
+      }
+
+      Ok(result)
+    })
+    .await
+    .map_err(|e| DictionaryError::QueryError(format!("Frequency lookup failed: {}", e)))?
+  }
+
+  /// Find words containing `fragment`, using the in-memory frequency map when the trie
+  /// is loaded and a `LIKE` scan of the frequency database otherwise
+  async fn find_words_containing(&self, fragment: &str) -> Result<Option<Vec<String>>> {
+    if fragment.is_empty() {
+      return Ok(None);
+    }
+
+    let fragment = self.config.lookup.fold_case(fragment);
+    let case_sensitive = self.config.lookup.case_sensitive;
+    let limit = self.config.completion.item_limit() + 1;
+
+    let results = if crate::tire::is_trie_initialized() {
+      crate::tire::find_words_containing(&fragment, limit)
+    } else {
+      let freq_path = self.get_freq_path()?;
+      tokio::task::spawn_blocking(move || {
+        let conn = open_database(&freq_path, "Frequency database")?;
+        if case_sensitive {
+          // LIKE ignores ASCII case unless told otherwise
+          conn
+            .execute_batch("PRAGMA case_sensitive_like = ON")
+            .map_err(|e| DictionaryError::sqlite("Error configuring LIKE", e))?;
+        }
+        let pattern = like_contains_pattern(&fragment);
+        let mut stmt = conn
+          .prepare(
+            "SELECT word FROM word_frequencies WHERE word LIKE ?1 ESCAPE '\\' \
+             ORDER BY frequency DESC LIMIT ?2",
+          )
+          .map_err(|e| DictionaryError::sqlite("Error preparing frequency query", e))?;
+        let rows = stmt
+          .query_map(rusqlite::params![pattern, limit as i64], |row| {
+            get_lossy_text(row, 0)
+          })
+          .map_err(|e| DictionaryError::sqlite("Error querying frequencies", e))?;
+        Ok(rows.flatten().collect::<Vec<String>>())
+      })
+      .await
+      .map_err(|e| DictionaryError::QueryError(format!("Frequency search failed: {}", e)))?
+      .unwrap_or_else(|e| {
+        warn_freq_db_unavailable(&e);
+        Vec::new()
+      })
+    };
+
+    if results.is_empty() {
+      Ok(None)
+    } else {
+      Ok(Some(results))
+    }
+  }
+
+  // Function to enable benchmarking with controllable distance parameter
+  pub async fn find_words_by_prefix_with_distance(
+    &self,
+    prefix: &str,
+    include_distance_2: bool,
+  ) -> Result<Option<Vec<String>>> {
+    // Convert prefix to lowercase for case-insensitive search
+    let lowercase_prefix = prefix.to_lowercase();
+
+    // Generate candidate words with controllable distance parameter
+    let candidate_words = fuzzy::generate_levenshtein_candidates(
+      &lowercase_prefix,
+      include_distance_2,
+      &self.config.fuzzy,
+    )
+    .await;
+
+    Ok(Some(candidate_words))
+  }
+
+  fn find_fuzzy_match(
+    &self,
+    conn: &rusqlite::Connection,
+    word: &str,
+  ) -> Result<Option<DictionaryResponse>> {
+    let word_len = word.chars().count() as i64;
+    let max_distance = self.config.fuzzy.max_distance_for(word);
+    let names = self.sql_names();
+    let anchor_ends = match self.config.fuzzy.anchor_ends {
+      true => format!(
+        "AND substr(w.{word}, 1, 1) = substr(?3, 1, 1) AND substr(w.{word}, -1, 1) = substr(?3, -1, 1)",
+        word = names.word
+      ),
+      false => String::new(),
+    };
+    let mut stmt = match conn.prepare(&format!(
+      r#"
+        SELECT 
+            w.{word},
+            p.{pos_name} AS pos,
+            d.{definition}
+        FROM {tables}
+        WHERE length(w.{word}) BETWEEN ?1 - ?2 AND ?1 + ?2
+          {anchor_ends}
+        ORDER BY length(w.{word})
+        "#,
+      word = names.word,
+      pos_name = names.pos_name,
+      definition = names.definition,
+      tables = names.joined_tables(),
+      anchor_ends = anchor_ends
+    )) {
+      Ok(stmt) => stmt,
+      Err(e) => return Err(DictionaryError::sqlite("Error preparing statement", e).into()),
+    };
+
+    let read_row = |row: &rusqlite::Row| {
+      let word = Self::get_safe_string(row, 0).unwrap_or_default();
+      let pos = Self::get_safe_string(row, 1);
+      let translation = Self::get_safe_string(row, 2);
+      let detail = Self::get_safe_string(row, 3);
+
+      Ok((word, translation, pos, detail))
+    };
+    // The looked up word (?3) is only bound when the ends are anchored
+    let query_result = if self.config.fuzzy.anchor_ends {
+      stmt.query_map(
+        rusqlite::params![word_len, max_distance as i64, word],
+        &read_row,
+      )
+    } else {
+      stmt.query_map(rusqlite::params![word_len, max_distance as i64], &read_row)
+    };
+
+    let mut closest_match = None;
+    let mut best_key = None;
+
+    match query_result {
+      Ok(rows) => {
+        for row_result in rows {
+          match row_result {
+            Ok((dict_word, translation, pos, detail)) => {
+              let distance = fuzzy::levenshtein_distance(word, &dict_word);
+              if distance > max_distance {
+                continue;
+              }
+              let rank = fuzzy_rank(&self.config.fuzzy, word, &dict_word, distance);
+              let key = fuzzy_match_key(word, &dict_word, rank);
+              if best_key.as_ref().is_none_or(|best| key < *best) {
+                best_key = Some(key);
+                closest_match = Some((dict_word, translation, pos, detail));
+              }
+            }
+            Err(e) => return Err(DictionaryError::sqlite("Error processing row", e).into()),
+          }
+        }
+
+        if let Some((word, translation, pos, detail)) = closest_match {
+          return Ok(Some(self.parse_dictionary_entry(
+            &word,
+            translation,
+            pos,
+            detail,
+          )));
+        }
+
+        Ok(None)
+      }
+      Err(e) => Err(DictionaryError::sqlite("Error querying database", e).into()),
+    }
+  }
+
+  fn parse_dictionary_entry(
+    &self,
+    word: &str,
+    translation: Option<String>,
+    pos: Option<String>,
+    detail: Option<String>,
+  ) -> DictionaryResponse {
+    let mut definitions = Vec::new();
+
+    if let Some(trans) = translation {
+      definitions.push(Definition {
+        definition: trans,
+        example: None,
+        more_examples: Vec::new(),
+        sub_definitions: Vec::new(),
+        label: None,
+      });
+    }
+
+    if let Some(det) = detail {
+      if !definitions.iter().any(|d| d.definition == det) {
+        definitions.push(Definition {
+          definition: det,
+          example: None,
+          more_examples: Vec::new(),
+          sub_definitions: Vec::new(),
+          label: None,
+        });
+      }
+    }
+
+    DictionaryResponse {
+      word: word.to_string(),
+      meanings: vec![Meaning {
+        part_of_speech: pos.unwrap_or_else(|| "unknown".to_string()),
+        definitions,
+        lang: None,
+        source: None,
+      }],
+      ..Default::default()
+    }
+  }
+}
+
+#[async_trait]
+impl DictionaryProvider for SqliteDictionaryProvider {
+  #[tracing::instrument(level = "debug", skip(self))]
+  async fn get_meaning(&self, word: &str) -> Result<Option<DictionaryResponse>> {
+    let word_lower = normalize_word(word);
+
+    let conn_guard = self.dictionary_connection().await?;
+    let conn = conn_guard.as_ref().unwrap();
+
+    if let Some(mut response) = self.find_exact_match(conn, &word_lower)? {
+      self.attach_optional_columns(conn, &mut response);
+      return Ok(Some(response));
+    }
+
+    self.find_inexact_match(conn, &word_lower)
+  }
+
+  async fn get_meanings(
+    &self,
+    words: &[&str],
+  ) -> Result<Vec<(String, Option<DictionaryResponse>)>> {
+    let normalized: Vec<String> = words.iter().map(|word| normalize_word(word)).collect();
+
+    let conn_guard = self.dictionary_connection().await?;
+    let conn = conn_guard.as_ref().unwrap();
+    let exact = self.find_exact_matches(conn, &normalized)?;
+
+    let mut meanings = Vec::with_capacity(words.len());
+    for (word, word_lower) in words.iter().zip(&normalized) {
+      let response = match exact.get(&self.match_key(word_lower)) {
+        Some(response) => {
+          let mut response = response.clone();
+          self.attach_optional_columns(conn, &mut response);
+          Some(response)
+        }
+        None => self.find_inexact_match(conn, word_lower)?,
+      };
+      meanings.push((word.to_string(), response));
+    }
+    Ok(meanings)
+  }
+
+  fn get_word_at_position(&self, content: &str, position: Position) -> Option<String> {
+    extract_word_at_position_with(content, position, &self.config.lookup)
+  }
+
+  /// TODO: Add incremental search
+  /// Add thread pool
+  ///
+  /// Find words by prefix
+  #[tracing::instrument(level = "debug", skip(self))]
+  async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>> {
+    // Check if the prefix is empty
+    if prefix.is_empty() {
+      return Ok(None);
+    }
+
+    // Convert prefix to lowercase unless lookups are case sensitive
+    let lowercase_prefix = self.config.lookup.fold_case(prefix);
+
+    // Reuse the previous results if they cover the narrower prefix
+    let mut cache = self.prefix_cache.lock().await;
+    let lookup = &self.config.lookup;
+    if let Some(filtered) = cache.narrow(&lowercase_prefix, lookup) {
+      if !filtered.is_empty() {
+        cache.store(lowercase_prefix, lookup, filtered.clone(), true);
+        return Ok(Some(filtered));
+      }
+    }
+
+    // Try to use the global trie first
+    let max_items = self.config.completion.item_limit();
+    if crate::tire::is_trie_initialized() {
+      let order = self.config.completion.sort_order;
+      let results = if self.config.completion.preserve_case && !lookup.case_sensitive {
+        crate::tire::find_words_by_prefix_any_case(&lowercase_prefix, max_items + 1, order)
+      } else {
+        crate::tire::find_words_by_prefix_sorted(&lowercase_prefix, max_items + 1, order)
+      };
+
+      // If we got results from the global trie, update cache and return
+      if !results.is_empty() {
+        // The trie was asked for one extra word, so fewer means nothing was cut off
+        let complete = results.len() <= max_items;
+        cache.store(lowercase_prefix, lookup, results.clone(), complete);
+        return Ok(Some(results));
+      }
+    }
+
+    // Fallback to fuzzy search if trie doesn't have results
+    let mut candidate_words =
+      fuzzy::generate_levenshtein_candidates(&lowercase_prefix, true, &self.config.fuzzy).await;
+
+    candidate_words.push(lowercase_prefix.clone());
+
+    const MAX_CANDIDATES: usize = 100;
+    if candidate_words.len() > MAX_CANDIDATES {
+      candidate_words.truncate(MAX_CANDIDATES);
+    }
+
+    if candidate_words.is_empty() {
+      cache.clear();
+      return Ok(None);
+    }
+
+    let mut batch_results = match self.rank_by_frequency(candidate_words.clone()).await {
+      Ok(results) => results,
+      Err(e) => {
+        // Degrade to whatever the trie knows instead of failing the completion
+        warn_freq_db_unavailable(&e);
+        crate::tire::rank_known_words(&candidate_words, max_items + 1)
+      }
+    };
+    // Corrections are picked by frequency either way, then shown in the configured order
+    if self.config.completion.sort_order != SortOrder::Frequency {
+      crate::tire::sort_words(&mut batch_results, self.config.completion.sort_order);
+    }
+
+    // Update cache with new results
+    // Fuzzy candidates don't all share the prefix, so they are never narrowed
+    if !batch_results.is_empty() {
+      cache.store(lowercase_prefix, lookup, batch_results.clone(), false);
+      Ok(Some(batch_results))
+    } else {
+      cache.clear();
+      Ok(None)
+    }
+  }
+
+  async fn find_words(&self, query: &str, mode: MatchMode) -> Result<Option<Vec<String>>> {
+    match mode {
+      MatchMode::Prefix => self.find_words_by_prefix(query).await,
+      MatchMode::Contains => self.find_words_containing(query).await,
+      MatchMode::Fuzzy => {
+        if query.is_empty() {
+          return Ok(None);
+        }
+        let lowercase_query = self.config.lookup.fold_case(query);
+        let mut candidates =
+          fuzzy::generate_levenshtein_candidates(&lowercase_query, true, &self.config.fuzzy).await;
+        candidates.push(lowercase_query);
+        let results = self.rank_by_frequency(candidates).await?;
+        Ok(if results.is_empty() {
+          None
+        } else {
+          Some(results)
+        })
+      }
+    }
+  }
+
+  async fn stats(&self) -> Result<DictionaryStats> {
+    let conn_guard = self.dictionary_connection().await?;
+    let conn = conn_guard.as_ref().unwrap();
+
+    let count = |query: &str| -> Result<usize> {
+      conn
+        .query_row(query, [], |row| row.get::<_, i64>(0))
+        .map(|count| count as usize)
+        .map_err(|e| DictionaryError::sqlite("Error counting dictionary rows", e).into())
+    };
+
+    let names = self.sql_names();
+    let parts_of_speech = conn
+      .prepare(&format!(
+        "SELECT DISTINCT p.{name} FROM {pos} p \
+         JOIN {definitions} d ON d.{definition_pos} = p.{pos_id} ORDER BY p.{name}",
+        name = names.pos_name,
+        pos = names.pos,
+        definitions = names.definitions,
+        definition_pos = names.definition_pos,
+        pos_id = names.pos_id
+      ))
+      .and_then(|mut stmt| {
+        stmt
+          .query_map([], |row| get_lossy_text(row, 0))?
+          .collect::<rusqlite::Result<Vec<_>>>()
+      })
+      .map_err(|e| DictionaryError::sqlite("Error listing parts of speech", e))?;
+
+    Ok(DictionaryStats {
+      words: count(&format!("SELECT COUNT(*) FROM {}", names.words))?,
+      definitions: count(&format!("SELECT COUNT(*) FROM {}", names.definitions))?,
+      parts_of_speech,
+      trie_words: crate::tire::word_count(),
+    })
+  }
+
+  async fn find_words_by_definition(&self, query: &str) -> Result<Vec<String>> {
+    if query.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let conn_guard = self.dictionary_connection().await?;
+    let conn = conn_guard.as_ref().unwrap();
+
+    let names = self.sql_names();
+    conn
+      .prepare(&format!(
+        "SELECT DISTINCT w.{word} FROM {words} w \
+         JOIN {definitions} d ON d.{definition_word} = w.{word_id} \
+         WHERE d.{definition} LIKE ?1 ESCAPE '\\' ORDER BY w.{word} LIMIT ?2",
+        word = names.word,
+        words = names.words,
+        definitions = names.definitions,
+        definition_word = names.definition_word,
+        word_id = names.word_id,
+        definition = names.definition
+      ))
+      .and_then(|mut stmt| {
+        stmt
+          .query_map(
+            rusqlite::params![like_contains_pattern(query), DEFINITION_SEARCH_LIMIT as i64],
+            |row| get_lossy_text(row, 0),
+          )?
+          .collect::<rusqlite::Result<Vec<_>>>()
+      })
+      .map_err(|e| DictionaryError::sqlite("Error searching definitions", e).into())
+  }
+
+  async fn check_word(&self, word: &str) -> Result<WordCheck> {
+    let word = normalize_word(word);
+    let conn_guard = self.dictionary_connection().await?;
+    let conn = conn_guard.as_ref().unwrap();
+
+    let names = self.sql_names();
+    let known = conn
+      .query_row(
+        &format!(
+          "SELECT EXISTS(SELECT 1 FROM {} WHERE {} = ?1{})",
+          names.words,
+          names.word,
+          self.word_collation()
+        ),
+        [&word],
+        |row| row.get::<_, bool>(0),
+      )
+      .map_err(|e| DictionaryError::sqlite("Error checking word", e))?;
+    if known {
+      return Ok(WordCheck {
+        known,
+        suggestions: Vec::new(),
+      });
+    }
+
+    // Unlike the fuzzy fallback of `get_meaning`, the last letter may differ ("teh" -> "the")
+    let candidates = conn
+      .prepare(&format!(
+        "SELECT DISTINCT {word} FROM {words} \
+         WHERE length({word}) BETWEEN ?1 - ?2 AND ?1 + ?2 AND substr({word}, 1, 1) = substr(?3, 1, 1)",
+        word = names.word,
+        words = names.words
+      ))
+      .and_then(|mut stmt| {
+        stmt
+          .query_map(
+            rusqlite::params![
+              word.chars().count() as i64,
+              SPELLING_MAX_DISTANCE as i64,
+              word
+            ],
+            |row| get_lossy_text(row, 0),
+          )?
+          .collect::<rusqlite::Result<Vec<_>>>()
+      })
+      .map_err(|e| DictionaryError::sqlite("Error finding spelling suggestions", e))?
+      .into_iter()
+      .map(|candidate| {
+        let distance = fuzzy::levenshtein_distance(&word, &candidate);
+        (candidate, distance)
+      })
+      .filter(|(_, distance)| *distance <= SPELLING_MAX_DISTANCE)
+      .collect();
+
+    Ok(WordCheck {
+      known,
+      suggestions: rank_suggestions(&word, candidates),
+    })
+  }
+
+  async fn random_words(&self, count: usize) -> Result<Vec<String>> {
+    let conn_guard = self.dictionary_connection().await?;
+    let conn = conn_guard.as_ref().unwrap();
+
+    let names = self.sql_names();
+    conn
+      .prepare(&format!(
+        "SELECT DISTINCT {} FROM {} ORDER BY RANDOM() LIMIT ?1",
+        names.word, names.words
+      ))
+      .and_then(|mut stmt| {
+        stmt
+          .query_map([count as i64], |row| get_lossy_text(row, 0))?
+          .collect::<rusqlite::Result<Vec<_>>>()
+      })
+      .map_err(|e| DictionaryError::sqlite("Error picking random words", e).into())
+  }
+
+  async fn warm_up(&self) -> Result<()> {
+    self.prepare_lookup_statements().await
+  }
+
+  async fn clear_cache(&self) {
+    // A rebuilt frequency database may rank the words of a cached prefix differently
+    self.prefix_cache.lock().await.clear();
+  }
+}
+
+/// Provider implementation for JSON dictionaries
+pub struct JsonDictionaryProvider {
+  dictionary_path: Option<String>,
+  freq_path: Option<String>,
+  dictionary_cache: tokio::sync::Mutex<Option<serde_json::Value>>,
+  /// Built from the dictionary's headwords on the first prefix query
+  prefix_index: tokio::sync::Mutex<Option<std::sync::Arc<PrefixIndex>>>,
+  prefix_cache: tokio::sync::Mutex<PrefixCache>,
+  config: Config,
+}
+
+impl JsonDictionaryProvider {
+  /// The dictionary file is read on first lookup rather than here.
+  pub fn new(dictionary_path: Option<String>, freq_path: Option<String>) -> Self {
+    Self {
+      dictionary_path,
+      freq_path,
+      dictionary_cache: tokio::sync::Mutex::new(None),
+      prefix_index: tokio::sync::Mutex::new(None),
+      prefix_cache: tokio::sync::Mutex::new(PrefixCache::default()),
+      config: Config::get(),
+    }
+  }
+
+  /// Serves the given `{word: {pos: [definitions]}}` value without touching the filesystem
+  pub fn from_value(dictionary: serde_json::Value) -> Self {
+    Self {
+      dictionary_cache: tokio::sync::Mutex::new(Some(normalize_json_dictionary(dictionary))),
+      ..Self::new(None, None)
+    }
+  }
+
+  /// Overrides the configuration snapshot taken at construction time
+  pub fn with_config(mut self, config: Config) -> Self {
+    self.config = config;
+    self
+  }
+
+  fn get_dictionary_path(&self) -> std::result::Result<String, DictionaryError> {
+    match &self.dictionary_path {
+      Some(path) => Ok(path.clone()),
+      None => Err(DictionaryError::NotConfigured(
+        "Dictionary path not provided".to_string(),
+      )),
+    }
+  }
+
+  /// Returns the cached dictionary, reading it from disk on first use
+  async fn load_dictionary(&self) -> Result<serde_json::Value> {
+    let mut cache = self.dictionary_cache.lock().await;
+    if let Some(dict) = &*cache {
+      return Ok(dict.clone());
+    }
+
+    let dict_path = self.get_dictionary_path()?;
+    let dict = self.read_dictionary_file(&dict_path)?;
+    *cache = Some(dict.clone());
+    Ok(dict)
+  }
+
+  /// Returns the prefix index of the loaded dictionary, building it on first use
+  async fn prefix_index(&self) -> Result<std::sync::Arc<PrefixIndex>> {
+    let mut index = self.prefix_index.lock().await;
+    if let Some(index) = &*index {
+      return Ok(index.clone());
+    }
+
+    let dictionary = self.load_dictionary().await?;
+    let built = std::sync::Arc::new(PrefixIndex::new(
+      dictionary
+        .as_object()
+        .into_iter()
+        .flat_map(|entries| entries.keys()),
+      |word| self.config.lookup.fold_case(word),
+    ));
+    *index = Some(built.clone());
+    Ok(built)
+  }
+
+  fn read_dictionary_file(&self, dict_path: &str) -> Result<serde_json::Value> {
+    match std::fs::read(dict_path).and_then(decompress_if_gzip) {
+      Ok(bytes) => match parse_json_dictionary(&decode_dictionary_bytes(bytes)) {
+        Ok(dict) => Ok(dict),
+        Err(e) => {
+          Err(DictionaryError::ParseError(format!("Error parsing dictionary JSON: {}", e)).into())
+        }
+      },
+      Err(e) => Err(
+        DictionaryError::IoError(format!(
+          "Error reading dictionary file {}: {}",
+          dict_path, e
+        ))
+        .into(),
+      ),
+    }
+  }
+
+  fn find_exact_match(
+    &self,
+    dictionary: &serde_json::Value,
+    word: &str,
+  ) -> Option<DictionaryResponse> {
+    dictionary.get(word).map(|entry| {
+      let (headword, entry) = resolve_variant(dictionary, word, entry);
+      self.parse_dictionary_entry(headword, entry, Some(word))
+    })
+  }
+
+  fn parse_dictionary_entry(
+    &self,
+    word: &str,
+    entry: &serde_json::Value,
+    _original_query: Option<&str>,
+  ) -> DictionaryResponse {
+    let mut meanings = Vec::new();
+    let text_field = |name: &str| {
+      entry
+        .get(name)
+        .and_then(|value| value.as_str())
+        .map(|value| value.to_string())
+    };
+    let lang = text_field("lang");
+    let source = text_field("source");
+
+    if let Some(obj) = entry.as_object() {
+      for (part_of_speech, defs) in obj {
+        if part_of_speech == VARIANTS_FIELD {
+          continue;
+        }
+        if let Some(defs_array) = defs.as_array() {
+          let definitions = defs_array.iter().map(parse_json_definition).collect();
+
+          meanings.push(Meaning {
+            part_of_speech: part_of_speech.clone(),
+            definitions,
+            lang: lang.clone(),
+            source: source.clone(),
+          });
+        }
+      }
+    }
+    // Stable, so unlisted parts of speech keep their alphabetical order
+    meanings.sort_by_key(|meaning| self.config.lookup.pos_rank(&meaning.part_of_speech));
+
+    DictionaryResponse {
+      word: word.to_string(),
+      meanings,
+      ipa: text_field("ipa"),
+      audio: text_field("audio"),
+      variants: json_variants(entry),
+    }
+  }
+
+  fn find_fuzzy_match(
+    &self,
+    dictionary: &serde_json::Value,
+    word: &str,
+  ) -> Option<DictionaryResponse> {
+    self.find_fuzzy_matches(dictionary, &[word]).pop().flatten()
+  }
+
+  /// Closest headword of each of `words`, found in a single pass over the dictionary
+  fn find_fuzzy_matches(
+    &self,
+    dictionary: &serde_json::Value,
+    words: &[&str],
+  ) -> Vec<Option<DictionaryResponse>> {
+    let mut closest_matches = vec![None; words.len()];
+    let mut best_keys = vec![None; words.len()];
+
+    // Find the closest match within our threshold
+    if let Some(entries) = dictionary.as_object() {
+      for (dict_word, entry) in entries {
+        for (i, word) in words.iter().enumerate() {
+          let distance = fuzzy::levenshtein_distance(word, dict_word);
+          if distance > self.config.fuzzy.max_distance_for(word) {
+            continue;
+          }
+          let rank = fuzzy_rank(&self.config.fuzzy, word, dict_word, distance);
+          let key = fuzzy_match_key(word, dict_word, rank);
+          if best_keys[i].as_ref().is_none_or(|best| key < *best) {
+            best_keys[i] = Some(key);
+            closest_matches[i] = Some((dict_word, entry));
+          }
+        }
+      }
+    }
+
+    closest_matches
+      .into_iter()
+      .zip(words)
+      .map(|(closest_match, word)| {
+        closest_match.map(|(matched_word, entry)| {
+          let (headword, entry) = resolve_variant(dictionary, matched_word, entry);
+          self.parse_dictionary_entry(headword, entry, Some(word))
+        })
+      })
+      .collect()
+  }
+}
+
+impl std::str::FromStr for JsonDictionaryProvider {
+  type Err = serde_json::Error;
+
+  /// Parses a JSON dictionary from a string, e.g. one read from stdin
+  fn from_str(contents: &str) -> serde_json::Result<Self> {
+    Ok(Self::from_value(serde_json::from_str(contents)?))
+  }
+}
+
+#[async_trait]
+impl DictionaryProvider for JsonDictionaryProvider {
+  #[tracing::instrument(level = "debug", skip(self))]
+  async fn get_meaning(&self, word: &str) -> Result<Option<DictionaryResponse>> {
+    let word_lower = self.config.lookup.fold_case(&normalize_word(word));
+    let dictionary = self.load_dictionary().await?;
+
+    if let Some(response) = self.find_exact_match(&dictionary, &word_lower) {
+      return Ok(Some(response));
+    }
+
+    if self.config.fuzzy.enabled {
+      if let Some(response) = self.find_fuzzy_match(&dictionary, &word_lower) {
+        return Ok(Some(response));
+      }
+    }
+
+    if self.config.lookup.compound_words {
+      return find_compound_match(&word_lower, |part| {
+        Ok(self.find_exact_match(&dictionary, part))
+      });
+    }
+
+    Ok(None)
+  }
+
+  async fn get_meanings(
+    &self,
+    words: &[&str],
+  ) -> Result<Vec<(String, Option<DictionaryResponse>)>> {
+    let words_lower: Vec<String> = words
+      .iter()
+      .map(|word| self.config.lookup.fold_case(&normalize_word(word)))
+      .collect();
+    let dictionary = self.load_dictionary().await?;
+
+    let mut responses: Vec<Option<DictionaryResponse>> = words_lower
+      .iter()
+      .map(|word| self.find_exact_match(&dictionary, word))
+      .collect();
+
+    let (misses, missed_words): (Vec<usize>, Vec<&str>) = words_lower
+      .iter()
+      .enumerate()
+      .filter(|(i, _)| responses[*i].is_none())
+      .map(|(i, word)| (i, word.as_str()))
+      .unzip();
+    let fuzzy_matches = if self.config.fuzzy.enabled {
+      self.find_fuzzy_matches(&dictionary, &missed_words)
+    } else {
+      vec![None; misses.len()]
+    };
+    for (i, fuzzy_match) in misses.into_iter().zip(fuzzy_matches) {
+      responses[i] = match fuzzy_match {
+        Some(response) => Some(response),
+        None if self.config.lookup.compound_words => {
+          find_compound_match(&words_lower[i], |part| {
+            Ok(self.find_exact_match(&dictionary, part))
+          })?
+        }
+        None => None,
+      };
+    }
+
+    Ok(
+      words
+        .iter()
+        .map(|word| word.to_string())
+        .zip(responses)
+        .collect(),
+    )
+  }
+
+  fn get_word_at_position(&self, content: &str, position: Position) -> Option<String> {
+    extract_word_at_position_with(content, position, &self.config.lookup)
+  }
+
+  #[tracing::instrument(level = "debug", skip(self))]
+  async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>> {
+    // Always generate completions, even for single characters
+    if prefix.is_empty() {
+      return Ok(None);
+    }
+
+    let lookup = &self.config.lookup;
+    let prefix_lower = lookup.fold_case(prefix);
+
+    // Reuse the previous results if they cover the narrower prefix
+    let mut cache = self.prefix_cache.lock().await;
+    if let Some(filtered) = cache.narrow(&prefix_lower, lookup) {
+      if !filtered.is_empty() {
+        cache.store(prefix_lower, lookup, filtered.clone(), true);
+        return Ok(Some(filtered));
+      }
+    }
+
+    // The index is sorted, so results are deterministic
+    let index = self.prefix_index().await?;
+
+    let max_items = self.config.completion.item_limit();
+    let order = self.config.completion.sort_order;
+    let matches = index.words_with_prefix(&prefix_lower).cloned();
+    // One extra word tells callers whether the list was cut off
+    let mut matching_words: Vec<String> = if order == SortOrder::Alphabetical {
+      matches.take(max_items + 1).collect()
+    } else {
+      let mut all: Vec<String> = matches.collect();
+      crate::tire::sort_words(&mut all, order);
+      all
+    };
+    matching_words.truncate(max_items + 1);
+    let complete = matching_words.len() <= max_items;
+
+    if !matching_words.is_empty() {
+      cache.store(prefix_lower, lookup, matching_words.clone(), complete);
+      return Ok(Some(matching_words));
+    }
+
+    // If no direct matches are found, use fuzzy matching
+    let candidates = fuzzy::generate_levenshtein_candidates(prefix, true, &self.config.fuzzy).await;
+    if candidates.is_empty() {
+      cache.clear();
+      Ok(None)
+    } else {
+      cache.store(prefix_lower, lookup, candidates.clone(), false);
+      Ok(Some(candidates))
+    }
+  }
+
+  async fn find_words(&self, query: &str, mode: MatchMode) -> Result<Option<Vec<String>>> {
+    if mode == MatchMode::Prefix {
+      return self.find_words_by_prefix(query).await;
+    }
+    if query.is_empty() {
+      return Ok(None);
+    }
+
+    let lookup = &self.config.lookup;
+    let query_lower = lookup.fold_case(&normalize_word(query));
+    let dictionary = self.load_dictionary().await?;
+    let entries = match dictionary.as_object() {
+      Some(entries) => entries,
+      None => return Ok(None),
+    };
+
+    // One extra word tells callers whether the list was cut off
+    let limit = self.config.completion.item_limit() + 1;
+    let matching_words: Vec<String> = match mode {
+      MatchMode::Contains => entries
+        .keys()
+        .filter(|word| lookup.fold_case(word).contains(&query_lower))
+        .take(limit)
+        .cloned()
+        .collect(),
+      _ => fuzzy::generate_levenshtein_candidates(&query_lower, true, &self.config.fuzzy)
+        .await
+        .into_iter()
+        .filter(|candidate| entries.contains_key(candidate))
+        .take(limit)
+        .collect(),
+    };
+
+    if matching_words.is_empty() {
+      Ok(None)
+    } else {
+      Ok(Some(matching_words))
+    }
+  }
+
+  async fn stats(&self) -> Result<DictionaryStats> {
+    let dictionary = self.load_dictionary().await?;
+    let entries = match dictionary.as_object() {
+      Some(entries) => entries,
+      None => return Ok(DictionaryStats::default()),
+    };
+
+    let mut definitions = 0;
+    let mut parts_of_speech = std::collections::BTreeSet::new();
+    for entry in entries.values().filter_map(|entry| entry.as_object()) {
+      // Non-array fields such as "ipa" are metadata, not parts of speech
+      for (part_of_speech, defs) in entry {
+        if part_of_speech == VARIANTS_FIELD {
+          continue;
+        }
+        if let Some(defs) = defs.as_array() {
+          definitions += defs.len();
+          parts_of_speech.insert(part_of_speech.clone());
+        }
+      }
+    }
+
+    Ok(DictionaryStats {
+      words: entries
+        .values()
+        .filter(|entry| !is_variant_entry(entry))
+        .count(),
+      definitions,
+      parts_of_speech: parts_of_speech.into_iter().collect(),
+      trie_words: crate::tire::word_count(),
+    })
+  }
+  async fn find_words_by_definition(&self, query: &str) -> Result<Vec<String>> {
+    if query.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let dictionary = self.load_dictionary().await?;
+    let query = query.to_lowercase();
+    let entries = match dictionary.as_object() {
+      Some(entries) => entries,
+      None => return Ok(Vec::new()),
+    };
+
+    Ok(
+      entries
+        .iter()
+        .filter(|(_, entry)| {
+          entry
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(part, _)| *part != VARIANTS_FIELD)
+            .filter_map(|(_, definitions)| definitions.as_array())
+            .flatten()
+            .filter_map(|definition| definition.as_str())
+            .any(|definition| definition.to_lowercase().contains(&query))
+        })
+        .map(|(word, _)| word.clone())
+        .take(DEFINITION_SEARCH_LIMIT)
+        .collect(),
+    )
+  }
+
+  async fn check_word(&self, word: &str) -> Result<WordCheck> {
+    let word = self.config.lookup.fold_case(&normalize_word(word));
+    let dictionary = self.load_dictionary().await?;
+    if dictionary.get(&word).is_some() {
+      return Ok(WordCheck {
+        known: true,
+        suggestions: Vec::new(),
+      });
+    }
+
+    // Same candidates as the SQLite query: the first letter must match
+    let first = word.chars().next();
+    let candidates = dictionary
+      .as_object()
+      .into_iter()
+      .flat_map(|entries| entries.keys())
+      .filter(|candidate| candidate.chars().next() == first)
+      .map(|candidate| {
+        (
+          candidate.clone(),
+          fuzzy::levenshtein_distance(&word, candidate),
+        )
+      })
+      .filter(|(_, distance)| *distance <= SPELLING_MAX_DISTANCE)
+      .collect();
+
+    Ok(WordCheck {
+      known: false,
+      suggestions: rank_suggestions(&word, candidates),
+    })
+  }
+
+  async fn random_words(&self, count: usize) -> Result<Vec<String>> {
+    let dictionary = self.load_dictionary().await?;
+    let mut words: Vec<&String> = dictionary
+      .as_object()
+      .into_iter()
+      .flat_map(|entries| entries.keys())
+      .collect();
+    // Partial Fisher-Yates shuffle of the first `count` words
+    let count = count.min(words.len());
+    for i in 0..count {
+      let j = i + random_below(words.len() - i);
+      words.swap(i, j);
+    }
+    Ok(words.into_iter().take(count).cloned().collect())
+  }
+
+  async fn warm_up(&self) -> Result<()> {
+    self.prefix_index().await.map(|_| ())
+  }
+
+  async fn clear_cache(&self) {
+    // A dictionary given in memory has no file to read it back from
+    if self.dictionary_path.is_some() {
+      *self.dictionary_cache.lock().await = None;
+    }
+    *self.prefix_index.lock().await = None;
+    self.prefix_cache.lock().await.clear();
+  }
+}
+
+/// Length of `text` in UTF-16 code units, the unit of LSP `character` offsets
+pub fn utf16_len(text: &str) -> u32 {
+  text.encode_utf16().count() as u32
+}
+
+/// Byte index in `line` of an LSP `character` offset (UTF-16 code units), or `None` past
+/// the end of the line. An offset inside a surrogate pair moves past that character.
+pub fn utf16_to_byte_index(line: &str, character: u32) -> Option<usize> {
+  let character = character as usize;
+  let mut units = 0;
+  for (i, c) in line.char_indices() {
+    if units >= character {
+      return Some(i);
+    }
+    units += c.len_utf16();
+  }
+  (units >= character).then_some(line.len())
+}
+
+/// A word of a document with its location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+  /// The word as written, without surrounding punctuation
+  pub text: String,
+  /// `text` lowercased, for lookups
+  pub normalized: String,
+  /// Location of `text`, in UTF-16 code units like every LSP range
+  pub range: Range,
+}
+
+/// Splits a document into whitespace separated words, trimming the punctuation around
+/// each one ("(don't)" -> "don't"). Chunks without a letter are skipped.
+pub fn tokenize_document(content: &str) -> Vec<Token> {
+  tokenize_document_with(content, &LookupConfig::default())
+}
+
+/// Like [`tokenize_document`], with `lookup.alphanumeric_words` keeping the digits at the
+/// edges of a word ("(mp3)" -> "mp3")
+pub fn tokenize_document_with(content: &str, lookup: &LookupConfig) -> Vec<Token> {
+  let is_punctuation =
+    |c: char| !(c.is_alphabetic() || (lookup.alphanumeric_words && c.is_ascii_digit()));
+  let mut tokens = Vec::new();
+  for (line_idx, line) in content.lines().enumerate() {
+    let mut chunk_start = None;
+    // A trailing space closes the last chunk of the line
+    for (i, c) in line
+      .char_indices()
+      .chain(std::iter::once((line.len(), ' ')))
+    {
+      if !c.is_whitespace() {
+        chunk_start.get_or_insert(i);
+        continue;
+      }
+      let Some(start) = chunk_start.take() else {
+        continue;
+      };
+      let chunk = &line[start..i];
+      let text = chunk.trim_matches(is_punctuation);
+      if !text.chars().any(char::is_alphabetic) {
+        continue;
+      }
+      let text_start = start + chunk.len() - chunk.trim_start_matches(is_punctuation).len();
+      let position = |byte: usize| Position::new(line_idx as u32, utf16_len(&line[..byte]));
+      tokens.push(Token {
+        text: text.to_string(),
+        normalized: text.to_lowercase(),
+        range: Range::new(position(text_start), position(text_start + text.len())),
+      });
+    }
+  }
+  tokens
+}
+
+/// The lowercased words of a document, for callers that don't need their locations
+pub fn parse_document(content: &str) -> Vec<String> {
+  tokenize_document(content)
+    .into_iter()
+    .map(|token| token.normalized)
+    .collect()
+}
+
+/// Common function to extract a word at a given position in text
+pub fn extract_word_at_position(
+  content: &str,
+  position: tower_lsp::lsp_types::Position,
+) -> Option<String> {
+  extract_word_at_position_with(content, position, &LookupConfig::default())
+}
+
+/// Like [`extract_word_at_position`], with `lookup.alphanumeric_words` also taking in the
+/// digits around the letters ("mp3", "3d"). Digits alone are never a word.
+pub fn extract_word_at_position_with(
+  content: &str,
+  position: tower_lsp::lsp_types::Position,
+  lookup: &LookupConfig,
+) -> Option<String> {
+  let is_word_char = |c: char| {
+    c.is_alphabetic() || is_cjk_char(c) || (lookup.alphanumeric_words && c.is_ascii_digit())
+  };
+  let lines: Vec<&str> = content.lines().collect();
+  if position.line as usize >= lines.len() {
+    return None;
+  }
+
+  let line = lines[position.line as usize];
+  let chars: Vec<char> = line.chars().collect();
+  let char_pos = position.character as usize;
+
+  if char_pos >= chars.len() {
+    return None;
+  }
+
+  let mut start = char_pos;
+  let mut end = char_pos;
+
+  while start > 0 && is_word_char(chars[start - 1]) {
+    start -= 1;
+  }
+
+  while end < chars.len() && is_word_char(chars[end]) {
+    end += 1;
+  }
+
+  // Also rejects the empty word
+  let word = &chars[start..end];
+  if word.iter().all(char::is_ascii_digit) {
+    None
+  } else {
+    Some(word.iter().collect())
+  }
+}
+
+/// Dotted token at `position` ("e.g.", "U.S.A.", "etc."), or `None` when the word there has
+/// no period. Runs of periods such as ellipses are not abbreviations.
+pub fn extract_abbreviation_at_position(
+  content: &str,
+  position: tower_lsp::lsp_types::Position,
+) -> Option<String> {
+  let line = content.lines().nth(position.line as usize)?;
+  let chars: Vec<char> = line.chars().collect();
+  let char_pos = position.character as usize;
+  let is_token_char = |c: char| c.is_alphabetic() || c == '.';
+
+  if char_pos >= chars.len() || !is_token_char(chars[char_pos]) {
+    return None;
+  }
+
+  let mut start = char_pos;
+  let mut end = char_pos;
+  while start > 0 && is_token_char(chars[start - 1]) {
+    start -= 1;
+  }
+  while end < chars.len() && is_token_char(chars[end]) {
+    end += 1;
+  }
+
+  let token: String = chars[start..end].iter().collect();
+  let token = token.trim_start_matches('.');
+  let is_abbreviation =
+    token.starts_with(char::is_alphabetic) && token.contains('.') && !token.contains("..");
+  is_abbreviation.then(|| token.to_string())
+}
+
+/// Word to look up at `position`: the provider's word there or, with `lookup.abbreviations`,
+/// the dotted abbreviation around it when the dictionary has that entry
+pub async fn lookup_word_at_position(
+  provider: &dyn DictionaryProvider,
+  content: &str,
+  position: tower_lsp::lsp_types::Position,
+  lookup: &LookupConfig,
+) -> Option<String> {
+  if lookup.abbreviations {
+    if let Some(abbreviation) = extract_abbreviation_at_position(content, position) {
+      if let Ok(WordCheck { known: true, .. }) = provider.check_word(&abbreviation).await {
+        return Some(abbreviation);
+      }
+    }
+  }
+  provider.get_word_at_position(content, position)
+}
+
+/// Text inside `range`, which may span lines, trimmed to letters at both ends.
+/// Line breaks and runs of whitespace become single spaces, so a phrase wrapped across
+/// lines is looked up as one.
+pub fn extract_selected_text(content: &str, range: tower_lsp::lsp_types::Range) -> Option<String> {
+  let (start, end) = (range.start, range.end);
+  let mut selected = String::new();
+  for (index, line) in content
+    .lines()
+    .enumerate()
+    .skip(start.line as usize)
+    .take(end.line.saturating_sub(start.line) as usize + 1)
+  {
+    let from = if index == start.line as usize {
+      start.character as usize
+    } else {
+      0
+    };
+    let chars = line.chars().skip(from);
+    if index == end.line as usize {
+      selected.extend(chars.take((end.character as usize).saturating_sub(from)));
+    } else {
+      selected.extend(chars);
+      selected.push(' ');
+    }
+  }
+
+  let is_word_char = |c: char| c.is_alphabetic() || is_cjk_char(c);
+  let selected = selected
+    .trim_matches(|c: char| !is_word_char(c))
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ");
+  (!selected.is_empty()).then_some(selected)
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::Path;
+  use std::sync::{Arc, Mutex};
+
+  const TEST_DICTIONARY: &str = r#"{
+    "passion": { "noun": ["strong feeling"], "ipa": "ˈpæʃən" },
+    "run": { "verb": ["move quickly on foot"] },
+    "sun": { "noun": ["the star at the centre of the solar system"] },
+    "flower": { "noun": ["the seed-bearing part of a plant"] }
+  }"#;
+
+  /// Provider that counts how often `get_meaning` reaches it
+  struct CountingProvider {
+    lookups: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+  }
+
+  #[async_trait]
+  impl DictionaryProvider for CountingProvider {
+    async fn get_meaning(&self, word: &str) -> Result<Option<DictionaryResponse>> {
+      self
+        .lookups
+        .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+      Ok((word == "run").then(|| DictionaryResponse {
+        word: word.to_string(),
+        ..Default::default()
+      }))
+    }
+
+    fn get_word_at_position(&self, _content: &str, _position: Position) -> Option<String> {
+      None
+    }
+
+    async fn find_words_by_prefix(&self, _prefix: &str) -> Result<Option<Vec<String>>> {
+      Ok(None)
+    }
+  }
+
+  #[tokio::test]
+  async fn test_repeated_lookup_hits_meaning_cache() {
+    let lookups = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let provider = CachedDictionaryProvider::new(Box::new(CountingProvider {
+      lookups: lookups.clone(),
+    }));
+    let count = || lookups.load(std::sync::atomic::Ordering::SeqCst);
+
+    for _ in 0..3 {
+      assert_eq!(
+        provider.get_meaning("run").await.unwrap().unwrap().word,
+        "run"
+      );
+      assert!(provider.get_meaning("walk").await.unwrap().is_none());
+    }
+    assert_eq!(count(), 2);
+
+    provider.clear_cache().await;
+    provider.get_meaning("run").await.unwrap();
+    assert_eq!(count(), 3);
+  }
+
+  #[tokio::test]
+  async fn test_cached_meanings_expire_after_ttl() {
+    let lookups = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let start = std::time::Instant::now();
+    let elapsed = std::sync::Arc::new(Mutex::new(std::time::Duration::ZERO));
+    let clock = elapsed.clone();
+    let provider = CachedDictionaryProvider::new(Box::new(CountingProvider {
+      lookups: lookups.clone(),
+    }))
+    .with_ttl(Some(std::time::Duration::from_secs(60)))
+    .with_clock(move || start + *clock.lock().unwrap());
+    let count = || lookups.load(std::sync::atomic::Ordering::SeqCst);
+    let advance = |secs| *elapsed.lock().unwrap() += std::time::Duration::from_secs(secs);
+
+    provider.get_meaning("run").await.unwrap();
+    advance(59);
+    provider.get_meaning("run").await.unwrap();
+    provider.get_meanings(&["run"]).await.unwrap();
+    assert_eq!(count(), 1);
+
+    // Expired: looked up again, and cached afresh from then on
+    advance(1);
+    provider.get_meaning("run").await.unwrap();
+    assert_eq!(count(), 2);
+    advance(30);
+    provider.get_meaning("run").await.unwrap();
+    assert_eq!(count(), 2);
+    advance(30);
+    provider.get_meanings(&["run"]).await.unwrap();
+    assert_eq!(count(), 3);
+  }
+
+  #[tokio::test]
+  async fn test_in_memory_providers() {
+    let from_str: JsonDictionaryProvider = TEST_DICTIONARY.parse().unwrap();
+    let from_str = from_str.with_config(Config::default());
+    let response = from_str.get_meaning("passion").await.unwrap().unwrap();
+    assert_eq!(
+      response.meanings[0].definitions[0].definition,
+      "strong feeling"
+    );
+    assert_eq!(
+      from_str.find_words_by_prefix("su").await.unwrap(),
+      Some(vec!["sun".to_string()])
+    );
+
+    // Headwords are normalized the same way as when read from a file
+    let from_value =
+      JsonDictionaryProvider::from_value(serde_json::json!({"cafe\u{301}": {"noun": ["coffee"]}}))
+        .with_config(Config::default());
+    assert!(from_value.get_meaning("café").await.unwrap().is_some());
+
+    let boxed = create_in_memory_provider(serde_json::json!({"run": {"verb": ["move"]}}));
+    assert!(boxed.get_meaning("run").await.unwrap().is_some());
+
+    assert!("not json".parse::<JsonDictionaryProvider>().is_err());
+  }
+
+  #[tokio::test]
+  async fn test_json_sub_senses() {
+    let provider = JsonDictionaryProvider::from_value(serde_json::json!({
+      "run": {"verb": [
+        {"definition": "move quickly", "sub_definitions": [
+          "on foot",
+          {"definition": "of a machine: operate", "example": "the car runs well"}
+        ]},
+        {"definition": "manage", "examples": ["run a shop", "run a race"]}
+      ]}
+    }))
+    .with_config(Config::default());
+
+    let response = provider.get_meaning("run").await.unwrap().unwrap();
+    let definitions = &response.meanings[0].definitions;
+    assert_eq!(definitions.len(), 2);
+    assert_eq!(definitions[0].definition, "move quickly");
+    assert_eq!(definitions[0].sub_definitions[0].definition, "on foot");
+    assert_eq!(
+      definitions[0].sub_definitions[1].example.as_deref(),
+      Some("the car runs well")
+    );
+    assert_eq!(definitions[1].definition, "manage");
+    assert!(definitions[1].sub_definitions.is_empty());
+    assert_eq!(
+      definitions[1].examples().collect::<Vec<_>>(),
+      vec!["run a shop", "run a race"]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_usage_labels() {
+    let json = JsonDictionaryProvider::from_value(serde_json::json!({
+      "swell": {"adjective": [
+        {"definition": "excellent", "label": "informal"},
+        "rising"
+      ]}
+    }))
+    .with_config(Config::default());
+    let definitions = &json.get_meaning("swell").await.unwrap().unwrap().meanings[0].definitions;
+    assert_eq!(definitions[0].label.as_deref(), Some("informal"));
+    assert_eq!(definitions[1].label, None);
+
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("labels.db").to_string_lossy().into_owned();
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn
+      .execute_batch(
+        "CREATE TABLE words (id INTEGER PRIMARY KEY, word TEXT);
+         CREATE TABLE parts_of_speech (id INTEGER PRIMARY KEY, name TEXT);
+         CREATE TABLE definitions (id INTEGER PRIMARY KEY, word_id INTEGER, pos_id INTEGER, definition TEXT, register TEXT);
+         INSERT INTO words VALUES (1, 'thou'), (2, 'swell');
+         INSERT INTO parts_of_speech VALUES (1, 'pronoun'), (2, 'adjective');
+         INSERT INTO definitions VALUES (1, 1, 1, 'you', 'archaic'), (2, 2, 2, 'excellent', NULL);",
+      )
+      .unwrap();
+    let mut config = Config::default();
+    config.sql_schema.label_column = Some("register".to_string());
+    assert_eq!(validate_sql_schema(&conn, &config.sql_schema), Ok(()));
+    drop(conn);
+
+    let sqlite = SqliteDictionaryProvider::new(Some(db_path.clone()), None).with_config(config);
+    let label = |response: Option<DictionaryResponse>| {
+      response.unwrap().meanings[0].definitions[0].label.clone()
+    };
+    assert_eq!(
+      label(sqlite.get_meaning("thou").await.unwrap()).as_deref(),
+      Some("archaic")
+    );
+    assert_eq!(label(sqlite.get_meaning("swell").await.unwrap()), None);
+
+    // Without a label column the labels are not read
+    let unlabeled =
+      SqliteDictionaryProvider::new(Some(db_path), None).with_config(Config::default());
+    assert_eq!(label(unlabeled.get_meaning("thou").await.unwrap()), None);
+  }
+
+  /// Every candidate is one edit away, so only the tiebreakers decide
+  #[tokio::test]
+  async fn test_fuzzy_match_tiebreakers() {
+    crate::tire::WORD_FREQUENCIES
+      .write()
+      .unwrap()
+      .insert("qzcut".to_string(), 10);
+
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("typos.json");
+    std::fs::write(
+      &json_path,
+      r#"{"qzbat": {"noun": ["x"]}, "qzcot": {"noun": ["x"]}, "qzcut": {"noun": ["x"]},
+          "qzlop": {"noun": ["x"]}, "qzmap": {"noun": ["x"]}}"#,
+    )
+    .unwrap();
+    let json_path = json_path.to_string_lossy().into_owned();
+    let db_path = dir.path().join("typos.db").to_string_lossy().into_owned();
+    crate::convert::json_to_sqlite(&json_path, &db_path).unwrap();
+
+    let json = JsonDictionaryProvider::new(Some(json_path), None).with_config(Config::default());
+    let sqlite = SqliteDictionaryProvider::new(Some(db_path), None).with_config(Config::default());
+    let providers: [&dyn DictionaryProvider; 2] = [&json, &sqlite];
+    for provider in providers {
+      let closest = |query: &'static str| async move {
+        provider.get_meaning(query).await.unwrap().unwrap().word
+      };
+      // "qzlop" comes first, but "qzmap" shares the longer prefix
+      assert_eq!(closest("qzmop").await, "qzmap");
+      // "qzcot" and "qzcut" share as much prefix, so frequency decides
+      assert_eq!(closest("qzcat").await, "qzcut");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_check_word() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("spelling.json");
+    std::fs::write(
+      &json_path,
+      r#"{"spell": {"verb": ["x"]}, "spelt": {"verb": ["x"]}, "spill": {"verb": ["x"]},
+          "smell": {"verb": ["x"]}, "apple": {"noun": ["x"]}}"#,
+    )
+    .unwrap();
+    let json_path = json_path.to_string_lossy().into_owned();
+    let db_path = dir
+      .path()
+      .join("spelling.db")
+      .to_string_lossy()
+      .into_owned();
+    crate::convert::json_to_sqlite(&json_path, &db_path).unwrap();
+
+    let json = JsonDictionaryProvider::new(Some(json_path), None).with_config(Config::default());
+    let sqlite = SqliteDictionaryProvider::new(Some(db_path), None).with_config(Config::default());
+    let providers: [&dyn DictionaryProvider; 2] = [&json, &sqlite];
+    for provider in providers {
+      assert_eq!(
+        provider.check_word("spill").await.unwrap(),
+        WordCheck {
+          known: true,
+          suggestions: Vec::new(),
+        }
+      );
+
+      let check = provider.check_word("spel").await.unwrap();
+      assert!(!check.known);
+      assert_eq!(check.suggestions, vec!["spell", "spelt", "spill", "smell"]);
+
+      assert!(provider
+        .check_word("zzz")
+        .await
+        .unwrap()
+        .suggestions
+        .is_empty());
+    }
+  }
+
+  #[test]
+  fn test_prefix_index_matches_linear_scan() {
+    let words: Vec<String> = ["Apple", "apply", "ape", "banana", "app", "applet", "äpfel"]
+      .iter()
+      .map(|word| word.to_string())
+      .collect();
+    let index = PrefixIndex::new(words.iter(), str::to_lowercase);
+
+    for prefix in ["", "a", "ap", "app", "appl", "apple", "b", "ä", "z"] {
+      let mut expected: Vec<&String> = words
+        .iter()
+        .filter(|word| word.to_lowercase().starts_with(prefix))
+        .collect();
+      expected.sort_by_key(|word| word.to_lowercase());
+      assert_eq!(
+        index.words_with_prefix(prefix).collect::<Vec<_>>(),
+        expected,
+        "prefix {:?}",
+        prefix
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn test_json_reload_after_clear_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_json_dictionary(dir.path());
+    let provider =
+      JsonDictionaryProvider::new(Some(path.clone()), None).with_config(Config::default());
+    assert_eq!(
+      provider.find_words_by_prefix("su").await.unwrap(),
+      Some(vec!["sun".to_string()])
+    );
+
+    std::fs::write(
+      &path,
+      r#"{"sun": {"noun": ["x"]}, "sunset": {"noun": ["y"]}}"#,
+    )
+    .unwrap();
+    provider.clear_cache().await;
+    assert_eq!(
+      provider.find_words_by_prefix("su").await.unwrap(),
+      Some(vec!["sun".to_string(), "sunset".to_string()])
+    );
+  }
+
+  #[tokio::test]
+  async fn test_gzipped_json_dictionary() {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dictionary.json.gz");
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(TEST_DICTIONARY.as_bytes()).unwrap();
+    std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+    let path = path.to_string_lossy().into_owned();
+
+    assert!(!Config::is_sqlite(Some(&path)));
+    let provider = JsonDictionaryProvider::new(Some(path), None).with_config(Config::default());
+    let response = provider.get_meaning("run").await.unwrap().unwrap();
+    assert_eq!(
+      response.meanings[0].definitions[0].definition,
+      "move quickly on foot"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_json_prefix_sort_order() {
+    let dictionary = serde_json::json!({
+      "qxobat": {"noun": ["x"]},
+      "qxobandana": {"noun": ["x"]},
+      "qxoba": {"noun": ["x"]},
+      "qxobay": {"noun": ["x"]}
+    });
+    let complete = |order| {
+      let mut config = Config::default();
+      config.completion.sort_order = order;
+      let provider = JsonDictionaryProvider::from_value(dictionary.clone()).with_config(config);
+      async move {
+        provider
+          .find_words_by_prefix("qxob")
+          .await
+          .unwrap()
+          .unwrap()
+      }
+    };
+
+    assert_eq!(
+      complete(SortOrder::Alphabetical).await,
+      ["qxoba", "qxobandana", "qxobat", "qxobay"]
+    );
+    assert_eq!(
+      complete(SortOrder::Length).await,
+      ["qxoba", "qxobat", "qxobay", "qxobandana"]
+    );
+    // Without known frequencies the dictionary order is kept
+    assert_eq!(
+      complete(SortOrder::Frequency).await,
+      ["qxoba", "qxobandana", "qxobat", "qxobay"]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_find_words_by_definition() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = JsonDictionaryProvider::new(Some(write_json_dictionary(dir.path())), None)
+      .with_config(Config::default());
+    let sqlite = SqliteDictionaryProvider::new(Some(write_sqlite_dictionary(dir.path())), None)
+      .with_config(Config::default());
+
+    let providers: [&dyn DictionaryProvider; 2] = [&json, &sqlite];
+    for provider in providers {
+      assert_eq!(
+        provider.find_words_by_definition("THE S").await.unwrap(),
+        vec!["flower".to_string(), "sun".to_string()]
+      );
+      assert_eq!(
+        provider.find_words_by_definition("feeling").await.unwrap(),
+        vec!["passion".to_string()]
+      );
+      // LIKE wildcards in the query are matched literally
+      assert!(provider
+        .find_words_by_definition("%")
+        .await
+        .unwrap()
+        .is_empty());
+    }
+  }
+
+  fn write_json_dictionary(dir: &Path) -> String {
+    let path = dir.join("dictionary.json");
+    std::fs::write(&path, TEST_DICTIONARY).unwrap();
+    path.to_string_lossy().into_owned()
+  }
+
+  fn write_sqlite_dictionary(dir: &Path) -> String {
+    let json_path = write_json_dictionary(dir);
+    let db_path = dir.join("dictionary.db").to_string_lossy().into_owned();
+    crate::convert::json_to_sqlite(&json_path, &db_path).unwrap();
+    db_path
+  }
+
+  fn config_with_fuzzy(enabled: bool) -> Config {
+    let mut config = Config::default();
+    config.fuzzy.enabled = enabled;
+    config
+  }
+
+  #[tokio::test]
+  async fn test_sqlite_fuzzy_flag_gates_fuzzy_match() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = write_sqlite_dictionary(dir.path());
+
+    let fuzzy = SqliteDictionaryProvider::new(Some(db_path.clone()), None)
+      .with_config(config_with_fuzzy(true));
+    let response = fuzzy.get_meaning("passon").await.unwrap().unwrap();
+    assert_eq!(response.word, "passion");
+
+    let exact_only =
+      SqliteDictionaryProvider::new(Some(db_path), None).with_config(config_with_fuzzy(false));
+    assert!(exact_only.get_meaning("passon").await.unwrap().is_none());
+    assert!(exact_only.get_meaning("passion").await.unwrap().is_some());
+  }
+
+  #[tokio::test]
+  async fn test_custom_sql_schema() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("custom.db").to_string_lossy().into_owned();
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn
+      .execute_batch(
+        "CREATE TABLE entries (entry_id INTEGER PRIMARY KEY, headword TEXT);
+         CREATE TABLE word_classes (class_id INTEGER PRIMARY KEY, label TEXT);
+         CREATE TABLE senses (sense_id INTEGER PRIMARY KEY, entry INTEGER, class INTEGER, gloss TEXT);
+         INSERT INTO entries VALUES (1, 'passion'), (2, 'patience');
+         INSERT INTO word_classes VALUES (1, 'noun');
+         INSERT INTO senses VALUES (1, 1, 1, 'strong emotion'), (2, 2, 1, 'calm endurance');",
+      )
+      .unwrap();
+
+    let mut config = config_with_fuzzy(true);
+    config.sql_schema = SqlSchemaConfig {
+      words_table: "entries".to_string(),
+      word_id_column: "entry_id".to_string(),
+      word_column: "headword".to_string(),
+      definitions_table: "senses".to_string(),
+      definition_column: "gloss".to_string(),
+      definition_word_column: "entry".to_string(),
+      definition_pos_column: "class".to_string(),
+      pos_table: "word_classes".to_string(),
+      pos_id_column: "class_id".to_string(),
+      pos_name_column: "label".to_string(),
+      label_column: None,
+      variants_table: "variants".to_string(),
+    };
+    assert_eq!(validate_sql_schema(&conn, &config.sql_schema), Ok(()));
+    assert_eq!(
+      validate_sql_schema(&conn, &SqlSchemaConfig::default()),
+      Err("missing table words, table definitions, table parts_of_speech".to_string())
+    );
+    let mut typo = config.sql_schema.clone();
+    typo.definition_column = "glos".to_string();
+    assert_eq!(
+      validate_sql_schema(&conn, &typo),
+      Err("missing column senses.glos".to_string())
+    );
+    drop(conn);
+
+    let provider = SqliteDictionaryProvider::new(Some(db_path), None).with_config(config);
+    let exact = provider.get_meaning("passion").await.unwrap().unwrap();
+    assert_eq!(exact.meanings[0].part_of_speech, "noun");
+    assert_eq!(
+      exact.meanings[0].definitions[0].definition,
+      "strong emotion"
+    );
+    let fuzzy = provider.get_meaning("passon").await.unwrap().unwrap();
+    assert_eq!(fuzzy.word, "passion");
+    assert!(provider.check_word("patience").await.unwrap().known);
+    assert_eq!(
+      provider
+        .find_words_by_definition("endurance")
+        .await
+        .unwrap(),
+      vec!["patience".to_string()]
+    );
+    assert_eq!(provider.stats().await.unwrap().definitions, 2);
+  }
+
+  #[tokio::test]
+  async fn test_sqlite_fuzzy_match_of_first_and_last_letter_typos() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = write_sqlite_dictionary(dir.path());
+
+    let anchored = SqliteDictionaryProvider::new(Some(db_path.clone()), None)
+      .with_config(config_with_fuzzy(true));
+    assert!(anchored.get_meaning("glower").await.unwrap().is_none());
+    assert!(anchored.get_meaning("flowez").await.unwrap().is_none());
+
+    let mut config = config_with_fuzzy(true);
+    config.fuzzy.anchor_ends = false;
+    let unanchored = SqliteDictionaryProvider::new(Some(db_path.clone()), None).with_config(config);
+    for typo in ["glower", "flowez"] {
+      let response = unanchored.get_meaning(typo).await.unwrap().unwrap();
+      assert_eq!(response.word, "flower", "{}", typo);
+    }
+
+    // The length window follows the allowed distance
+    let mut config = config_with_fuzzy(true);
+    config.fuzzy.max_distance = 1;
+    let strict = SqliteDictionaryProvider::new(Some(db_path), None).with_config(config);
+    assert!(strict.get_meaning("pasion").await.unwrap().is_some());
+    assert!(strict.get_meaning("pason").await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn test_short_words_only_match_one_edit_away() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = write_json_dictionary(dir.path());
+    let db_path = write_sqlite_dictionary(dir.path());
+    let lenient = {
+      let mut config = config_with_fuzzy(true);
+      config.fuzzy.distance_2_min_length = 0;
+      config
+    };
+    let providers = |config: Config| -> [Box<dyn DictionaryProvider>; 2] {
+      [
+        Box::new(
+          JsonDictionaryProvider::new(Some(json_path.clone()), None).with_config(config.clone()),
+        ),
+        Box::new(SqliteDictionaryProvider::new(Some(db_path.clone()), None).with_config(config)),
+      ]
+    };
+
+    for provider in providers(config_with_fuzzy(true)) {
+      assert_eq!(
+        provider.get_meaning("sxn").await.unwrap().unwrap().word,
+        "sun"
+      );
+      // Two edits from "sun", but too short for that
+      assert!(provider.get_meaning("sxxn").await.unwrap().is_none());
+      assert_eq!(
+        provider.get_meaning("pasison").await.unwrap().unwrap().word,
+        "passion"
+      );
+    }
+    for provider in providers(lenient) {
+      assert_eq!(
+        provider.get_meaning("sxxn").await.unwrap().unwrap().word,
+        "sun"
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn test_jaro_winkler_ranking_of_short_words() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("dictionary.json");
+    std::fs::write(
+      &json_path,
+      r#"{"foam": {"noun": ["bubbles"]}, "from": {"preposition": ["starting at"]}}"#,
+    )
+    .unwrap();
+    let json_path = json_path.to_string_lossy().into_owned();
+    let db_path = dir
+      .path()
+      .join("dictionary.db")
+      .to_string_lossy()
+      .into_owned();
+    crate::convert::json_to_sqlite(&json_path, &db_path).unwrap();
+    let closest = |ranking: FuzzyRanking| {
+      let mut config = config_with_fuzzy(true);
+      config.fuzzy.distance_2_min_length = 0;
+      config.fuzzy.ranking = ranking;
+      let providers: [Box<dyn DictionaryProvider>; 2] = [
+        Box::new(
+          JsonDictionaryProvider::new(Some(json_path.clone()), None).with_config(config.clone()),
+        ),
+        Box::new(SqliteDictionaryProvider::new(Some(db_path.clone()), None).with_config(config)),
+      ];
+      async move {
+        let mut words = Vec::new();
+        for provider in providers {
+          words.push(provider.get_meaning("form").await.unwrap().unwrap().word);
+        }
+        words
+      }
+    };
+
+    // One substitution away against a transposition, two edits away
+    assert_eq!(
+      closest(FuzzyRanking::Levenshtein).await,
+      vec!["foam", "foam"]
+    );
+    assert_eq!(
+      closest(FuzzyRanking::JaroWinkler).await,
+      vec!["from", "from"]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_json_fuzzy_flag_gates_fuzzy_match() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = write_json_dictionary(dir.path());
+
+    let fuzzy = JsonDictionaryProvider::new(Some(json_path.clone()), None)
+      .with_config(config_with_fuzzy(true));
+    let response = fuzzy.get_meaning("passon").await.unwrap().unwrap();
+    assert_eq!(response.word, "passion");
+
+    let exact_only =
+      JsonDictionaryProvider::new(Some(json_path), None).with_config(config_with_fuzzy(false));
+    assert!(exact_only.get_meaning("passon").await.unwrap().is_none());
+    assert!(exact_only.get_meaning("passion").await.unwrap().is_some());
+  }
+
+  fn assert_sunflower_compound(response: DictionaryResponse) {
+    assert_eq!(response.word, "sunflower");
+    let parts: Vec<&str> = response
+      .meanings
+      .iter()
+      .map(|meaning| meaning.part_of_speech.as_str())
+      .collect();
+    assert_eq!(parts, vec!["sun: noun", "flower: noun"]);
+  }
+
+  #[tokio::test]
+  async fn test_compound_word_lookup() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = Config::default();
+    config.lookup.compound_words = true;
+
+    let json = JsonDictionaryProvider::new(Some(write_json_dictionary(dir.path())), None)
+      .with_config(config.clone());
+    assert_sunflower_compound(json.get_meaning("sunflower").await.unwrap().unwrap());
+
+    let sqlite = SqliteDictionaryProvider::new(Some(write_sqlite_dictionary(dir.path())), None)
+      .with_config(config);
+    assert_sunflower_compound(sqlite.get_meaning("sunflower").await.unwrap().unwrap());
+
+    // Splitting is off by default
+    let default_json = JsonDictionaryProvider::new(Some(write_json_dictionary(dir.path())), None)
+      .with_config(Config::default());
+    assert!(default_json
+      .get_meaning("sunflower")
+      .await
+      .unwrap()
+      .is_none());
+  }
+
+  #[tokio::test]
+  async fn test_compound_word_lookup_without_fuzzy() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = config_with_fuzzy(false);
+    config.lookup.compound_words = true;
+
+    let json = JsonDictionaryProvider::new(Some(write_json_dictionary(dir.path())), None)
+      .with_config(config.clone());
+    assert_sunflower_compound(json.get_meaning("sunflower").await.unwrap().unwrap());
+    let meanings = json.get_meanings(&["sunflower"]).await.unwrap();
+    assert_sunflower_compound(meanings[0].1.clone().unwrap());
+
+    let sqlite = SqliteDictionaryProvider::new(Some(write_sqlite_dictionary(dir.path())), None)
+      .with_config(config);
+    assert_sunflower_compound(sqlite.get_meaning("sunflower").await.unwrap().unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_batch_lookup_matches_individual_lookups() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = Config::default();
+    config.lookup.compound_words = true;
+    let json_path = write_json_dictionary(dir.path());
+    let sqlite_path = write_sqlite_dictionary(dir.path());
+
+    // Exact, case-folded, decomposed, fuzzy, compound, unknown and repeated words
+    let words = [
+      "run",
+      "Sun",
+      "passion",
+      "passon",
+      "sunflower",
+      "zzyzx",
+      "run",
+      "flowe\u{301}r",
+      "",
+    ];
+    let providers: Vec<Box<dyn DictionaryProvider>> = vec![
+      Box::new(
+        JsonDictionaryProvider::new(Some(json_path.clone()), None).with_config(config.clone()),
+      ),
+      Box::new(SqliteDictionaryProvider::new(Some(sqlite_path.clone()), None).with_config(config)),
+      Box::new(CachedDictionaryProvider::new(Box::new(
+        SqliteDictionaryProvider::new(Some(sqlite_path.clone()), None)
+          .with_config(Config::default()),
+      ))),
+    ];
+    for provider in providers {
+      // Warm the cache with part of the words, so a batch mixes cached and fetched ones
+      provider.get_meaning("sun").await.unwrap();
 
-    if let Some(response) = self.find_fuzzy_match(&conn, &word_lower)? {
-      return Ok(Some(response));
+      let batch = provider.get_meanings(&words).await.unwrap();
+      assert_eq!(batch.len(), words.len());
+      for (word, (batch_word, batch_meaning)) in words.iter().zip(batch) {
+        assert_eq!(batch_word, *word);
+        assert_eq!(
+          format!("{:?}", batch_meaning),
+          format!("{:?}", provider.get_meaning(word).await.unwrap()),
+          "{}",
+          word
+        );
+      }
     }
 
-    // no matches found
-    Ok(None)
+    // More words than fit in one SQLite query
+    let sqlite =
+      SqliteDictionaryProvider::new(Some(sqlite_path), None).with_config(config_with_fuzzy(false));
+    let mut many: Vec<String> = (0..SQL_BATCH_SIZE).map(|i| format!("word{}", i)).collect();
+    many.push("flower".to_string());
+    let many: Vec<&str> = many.iter().map(String::as_str).collect();
+    let batch = sqlite.get_meanings(&many).await.unwrap();
+    assert!(batch[..SQL_BATCH_SIZE]
+      .iter()
+      .all(|(_, meaning)| meaning.is_none()));
+    assert_eq!(batch[SQL_BATCH_SIZE].1.as_ref().unwrap().word, "flower");
   }
 
-  fn get_word_at_position(&self, content: &str, position: Position) -> Option<String> {
-    extract_word_at_position(content, position)
-  }
+  #[tokio::test]
+  async fn test_pronunciation_is_populated() {
+    let dir = tempfile::tempdir().unwrap();
 
-  /// TODO: Add incremental search
-  /// Add thread pool
-  ///
-  /// Find words by prefix
-  async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>> {
-    // Check if the prefix is empty
-    if prefix.is_empty() {
-      return Ok(None);
-    }
+    let json = JsonDictionaryProvider::new(Some(write_json_dictionary(dir.path())), None)
+      .with_config(Config::default());
+    let response = json.get_meaning("passion").await.unwrap().unwrap();
+    assert_eq!(response.ipa.as_deref(), Some("ˈpæʃən"));
+    assert_eq!(response.audio, None);
 
-    // Convert prefix to lowercase for case-insensitive search
-    let lowercase_prefix = prefix.to_lowercase();
+    let db_path = write_sqlite_dictionary(dir.path());
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn
+      .execute_batch(
+        "ALTER TABLE words ADD COLUMN ipa TEXT;
+         UPDATE words SET ipa = 'rʌn' WHERE word = 'run';",
+      )
+      .unwrap();
+    let sqlite = SqliteDictionaryProvider::new(Some(db_path), None).with_config(Config::default());
+    let response = sqlite.get_meaning("run").await.unwrap().unwrap();
+    assert_eq!(response.ipa.as_deref(), Some("rʌn"));
+    assert_eq!(response.audio, None);
+  }
 
-    // Check if we can use cached results
-    let mut cache = self.prefix_cache.lock().await;
-    let (cached_prefix, cached_results) = &*cache;
+  #[test]
+  fn test_detect_script() {
+    assert_eq!(
+      detect_script("今天我们学习一个新的单词 apple，意思是苹果。"),
+      Some(Script::Cjk)
+    );
+    assert_eq!(
+      detect_script("The word 苹果 means apple in Chinese."),
+      Some(Script::Latin)
+    );
+    assert_eq!(detect_script("123 -- 456"), None);
+  }
 
-    // If the new prefix extends the cached prefix, filter the cached results
-    if !cached_prefix.is_empty()
-      && lowercase_prefix.starts_with(cached_prefix)
-      && !cached_results.is_empty()
-      && lowercase_prefix != *cached_prefix
-    {
-      // Filter cached results that match the new prefix
-      let filtered: Vec<String> = cached_results
-        .iter()
-        .filter(|word| word.to_lowercase().starts_with(&lowercase_prefix))
-        .cloned()
-        .collect();
+  #[test]
+  fn test_tokenize_document_keeps_ranges() {
+    let content = "Hello, world!\n  (don't) stop—now\r\n42 café. 😀smile";
+    let tokens = tokenize_document(content);
+    let tokens: Vec<(&str, &str, Range)> = tokens
+      .iter()
+      .map(|token| (token.text.as_str(), token.normalized.as_str(), token.range))
+      .collect();
+    let range = |line, start, end| Range::new(Position::new(line, start), Position::new(line, end));
+    assert_eq!(
+      tokens,
+      vec![
+        ("Hello", "hello", range(0, 0, 5)),
+        ("world", "world", range(0, 7, 12)),
+        ("don't", "don't", range(1, 3, 8)),
+        ("stop—now", "stop—now", range(1, 10, 18)),
+        ("café", "café", range(2, 3, 7)),
+        // The emoji takes two UTF-16 code units
+        ("smile", "smile", range(2, 11, 16)),
+      ]
+    );
+    assert_eq!(
+      parse_document(content),
+      vec!["hello", "world", "don't", "stop—now", "café", "smile"]
+    );
+  }
 
-      // If we found matches, update cache and return
-      if !filtered.is_empty() {
-        *cache = (lowercase_prefix.clone(), filtered.clone());
-        return Ok(Some(filtered));
-      }
-    }
+  #[tokio::test]
+  async fn test_random_words_are_distinct_headwords() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = JsonDictionaryProvider::new(Some(write_json_dictionary(dir.path())), None);
+    let sqlite = SqliteDictionaryProvider::new(Some(write_sqlite_dictionary(dir.path())), None);
+    let headwords = ["flower", "passion", "run", "sun"];
 
-    // Try to use the global trie first
-    if crate::tire::is_trie_initialized() {
-      let results = crate::tire::find_words_by_prefix(&lowercase_prefix, 5);
+    for provider in [&json as &dyn DictionaryProvider, &sqlite] {
+      let mut words = provider.random_words(3).await.unwrap();
+      assert_eq!(words.len(), 3);
+      assert!(words.iter().all(|word| headwords.contains(&word.as_str())));
+      words.sort();
+      words.dedup();
+      assert_eq!(words.len(), 3);
 
-      // If we got results from the global trie, update cache and return
-      if !results.is_empty() {
-        *cache = (lowercase_prefix, results.clone());
-        return Ok(Some(results));
-      }
+      let mut all = provider.random_words(10).await.unwrap();
+      all.sort();
+      assert_eq!(all, headwords);
     }
+  }
 
-    // Fallback to fuzzy search if trie doesn't have results
-    let mut candidate_words = fuzzy::generate_levenshtein_candidates(&lowercase_prefix, true).await;
-
-    candidate_words.push(lowercase_prefix.clone());
+  #[tokio::test]
+  async fn test_array_shaped_dictionary() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("entries.json");
+    std::fs::write(
+      &path,
+      r#"[
+        {"word": "run", "phonetic": "/rʌn/", "meanings": [
+          {"partOfSpeech": "verb", "definitions": [{"definition": "move quickly on foot", "example": "run home"}]}
+        ]},
+        {"word": "run", "meanings": [
+          {"partOfSpeech": "noun", "definitions": [{"definition": "an act of running"}]}
+        ]},
+        {"word": "runway", "meanings": {"noun": ["a strip for aircraft"]}}
+      ]"#,
+    )
+    .unwrap();
+    let json = JsonDictionaryProvider::new(Some(path.to_string_lossy().into_owned()), None)
+      .with_config(Config::default());
 
-    const MAX_CANDIDATES: usize = 100;
-    if candidate_words.len() > MAX_CANDIDATES {
-      candidate_words.truncate(MAX_CANDIDATES);
-    }
+    let response = json.get_meaning("run").await.unwrap().unwrap();
+    assert_eq!(response.ipa.as_deref(), Some("rʌn"));
+    let parts: Vec<&str> = response
+      .meanings
+      .iter()
+      .map(|meaning| meaning.part_of_speech.as_str())
+      .collect();
+    assert_eq!(parts, vec!["noun", "verb"]);
+    assert_eq!(
+      response.meanings[1].definitions[0].example.as_deref(),
+      Some("run home")
+    );
+    assert_eq!(
+      json.get_meaning("runway").await.unwrap().unwrap().meanings[0].definitions[0].definition,
+      "a strip for aircraft"
+    );
+    assert_eq!(
+      json.find_words_by_prefix("run").await.unwrap(),
+      Some(vec!["run".to_string(), "runway".to_string()])
+    );
+  }
 
-    if candidate_words.is_empty() {
-      *cache = (String::new(), Vec::new()); // Clear cache on failure
-      return Ok(None);
-    }
+  #[tokio::test]
+  async fn test_lang_is_populated() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("bilingual.json");
+    std::fs::write(
+      &json_path,
+      r#"{"run": {"verb": ["跑"], "lang": "en-zh"}, "跑": {"verb": ["run"], "lang": "zh-en"}}"#,
+    )
+    .unwrap();
+    let json = JsonDictionaryProvider::new(Some(json_path.to_string_lossy().into_owned()), None)
+      .with_config(Config::default());
+    let response = json.get_meaning("跑").await.unwrap().unwrap();
+    assert_eq!(response.meanings.len(), 1);
+    assert_eq!(response.meanings[0].lang.as_deref(), Some("zh-en"));
 
-    let freq_path = self.get_freq_path()?;
+    let db_path = write_sqlite_dictionary(dir.path());
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn
+      .execute_batch(
+        "ALTER TABLE words ADD COLUMN lang TEXT;
+         UPDATE words SET lang = 'en-zh' WHERE word = 'run';",
+      )
+      .unwrap();
+    let sqlite = SqliteDictionaryProvider::new(Some(db_path), None).with_config(Config::default());
+    let response = sqlite.get_meaning("run").await.unwrap().unwrap();
+    assert_eq!(response.meanings[0].lang.as_deref(), Some("en-zh"));
+  }
 
-    // Process all candidates in one go since our generation is now more targeted
-    let batch_results = tokio::task::spawn_blocking(move || -> Result<Vec<String>> {
-      let conn = rusqlite::Connection::open(&freq_path).map_err(|_e| Error::internal_error())?;
-      let placeholders = vec!["?"; candidate_words.len()].join(",");
+  #[tokio::test]
+  async fn test_invalid_utf8_is_decoded_lossily() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = write_sqlite_dictionary(dir.path());
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    // "caf\xE9" is Latin-1, so the text is not valid UTF-8
+    conn
+      .execute_batch(
+        "UPDATE definitions SET definition = CAST(X'636166E920666F6F64' AS TEXT)
+           WHERE word_id = (SELECT id FROM words WHERE word = 'run');
+         INSERT INTO words (word) VALUES (CAST(X'7275EE6E' AS TEXT));",
+      )
+      .unwrap();
+    let sqlite = SqliteDictionaryProvider::new(Some(db_path), None).with_config(Config::default());
 
-      // Query with proper result limit
-      let query = format!(
-        "SELECT word FROM word_frequencies WHERE word IN ({}) ORDER BY frequency DESC LIMIT 5",
-        placeholders
-      );
+    let response = sqlite.get_meaning("run").await.unwrap().unwrap();
+    assert_eq!(
+      response.meanings[0].definitions[0].definition,
+      "caf\u{FFFD} food"
+    );
+    assert_eq!(
+      sqlite.find_words_by_definition("food").await.unwrap(),
+      vec!["run".to_string()]
+    );
+    // The misencoded word is still offered as a spelling candidate, not an error
+    let check = sqlite.check_word("ruin").await.unwrap();
+    assert!(check.suggestions.contains(&"run".to_string()));
+  }
 
-      let mut stmt = conn.prepare(&query).map_err(|_| Error::internal_error())?;
+  #[test]
+  fn test_get_safe_string_mixed_column_types() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn
+      .query_row(
+        "SELECT 'text', CAST('blob' AS BLOB), 42, 2.5, NULL",
+        [],
+        |row| {
+          let values: Vec<Option<String>> = (0..5)
+            .map(|idx| SqliteDictionaryProvider::get_safe_string(row, idx))
+            .collect();
+          assert_eq!(
+            values,
+            vec![
+              Some("text".to_string()),
+              Some("blob".to_string()),
+              Some("42".to_string()),
+              Some("2.5".to_string()),
+              None,
+            ]
+          );
+          Ok(())
+        },
+      )
+      .unwrap();
+  }
 
-      // Convert words to SQL parameters
-      let params: Vec<&dyn rusqlite::types::ToSql> = candidate_words
-        .iter()
-        .map(|w| w as &dyn rusqlite::types::ToSql)
-        .collect();
+  #[tokio::test]
+  async fn test_dotted_abbreviation_lookup() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("abbreviations.json");
+    std::fs::write(
+      &json_path,
+      r#"{
+        "e.g.": { "abbreviation": ["for example"] },
+        "u.s.a.": { "abbreviation": ["United States of America"] },
+        "end": { "noun": ["the final part"] }
+      }"#,
+    )
+    .unwrap();
+    let json_path = json_path.to_string_lossy().into_owned();
+    let sqlite_path = dir.path().join("abbreviations.db");
+    let sqlite_path = sqlite_path.to_string_lossy().into_owned();
+    crate::convert::json_to_sqlite(&json_path, &sqlite_path).unwrap();
 
-      // Execute query and collect results
-      let mut result = Vec::new();
-      let rows = stmt
-        .query_map(params.as_slice(), |row| row.get::<_, String>(0))
-        .map_err(|_| Error::internal_error())?;
+    let mut config = config_with_fuzzy(false);
+    config.lookup.abbreviations = true;
+    let providers: Vec<Box<dyn DictionaryProvider>> = vec![
+      Box::new(JsonDictionaryProvider::new(Some(json_path), None).with_config(config.clone())),
+      Box::new(SqliteDictionaryProvider::new(Some(sqlite_path), None).with_config(config.clone())),
+    ];
+    let content = "Fruit, e.g. apples, from the U.S.A. in the end.";
+    let at = |character| Position { line: 0, character };
 
-      for word_result in rows {
-        if let Ok(word) = word_result {
-          result.push(word);
-        }
-      }
+    for provider in &providers {
+      let word = |character| {
+        lookup_word_at_position(provider.as_ref(), content, at(character), &config.lookup)
+      };
+      assert_eq!(word(9).await.as_deref(), Some("e.g."));
+      assert_eq!(word(31).await.as_deref(), Some("U.S.A."));
+      // Not an abbreviation of the dictionary: the plain word
+      assert_eq!(word(44).await.as_deref(), Some("end"));
 
-      Ok(result)
-    })
-    .await
-    .map_err(|_| Error::internal_error())??;
+      let response = provider.get_meaning("U.S.A.").await.unwrap().unwrap();
+      assert_eq!(
+        response.meanings[0].definitions[0].definition,
+        "United States of America"
+      );
 
-    // Update cache with new results
-    if !batch_results.is_empty() {
-      *cache = (lowercase_prefix, batch_results.clone());
-      Ok(Some(batch_results))
-    } else {
-      *cache = (String::new(), Vec::new());
-      Ok(None)
+      // Off by default: only the letters under the cursor
+      let plain = LookupConfig::default();
+      assert_eq!(
+        lookup_word_at_position(provider.as_ref(), content, at(9), &plain)
+          .await
+          .as_deref(),
+        Some("g")
+      );
     }
   }
-}
-
-/// Provider implementation for JSON dictionaries
-pub struct JsonDictionaryProvider {
-  dictionary_path: Option<String>,
-  freq_path: Option<String>,
-  dictionary_cache: tokio::sync::Mutex<Option<serde_json::Value>>,
-  prefix_cache: tokio::sync::Mutex<(String, Vec<String>)>,
-}
 
-impl JsonDictionaryProvider {
-  pub fn new(dictionary_path: Option<String>, freq_path: Option<String>) -> Self {
-    let provider = Self {
-      dictionary_path,
-      freq_path,
-      dictionary_cache: tokio::sync::Mutex::new(None),
-      prefix_cache: tokio::sync::Mutex::new((String::new(), Vec::new())),
+  #[tokio::test]
+  async fn test_failures_map_to_error_codes() {
+    use tower_lsp::jsonrpc::ErrorCode;
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+    let json =
+      |path: Option<String>| JsonDictionaryProvider::new(path, None).with_config(Config::default());
+    let sqlite = |path: Option<String>| {
+      SqliteDictionaryProvider::new(path, None).with_config(Config::default())
     };
+    let code = |result: Result<Option<DictionaryResponse>>| result.unwrap_err().code;
 
-    // Eagerly load dictionary if path is available
-    if let Some(dict_path) = &provider.dictionary_path {
-      if let Ok(contents) = std::fs::read_to_string(dict_path) {
-        if let Ok(dict) = serde_json::from_str(&contents) {
-          let _ = std::mem::replace(
-            &mut *futures::executor::block_on(provider.dictionary_cache.lock()),
-            Some(dict),
-          );
-        }
-      }
-    }
+    std::fs::write(path("broken.json"), "{not json").unwrap();
+    rusqlite::Connection::open(path("empty.db"))
+      .unwrap()
+      .execute_batch("CREATE TABLE unrelated (id INTEGER)")
+      .unwrap();
+    std::fs::write(path("garbage.db"), vec![b'x'; 4096]).unwrap();
 
-    provider
-  }
+    let io = ErrorCode::ServerError(DictionaryError::IO_ERROR_CODE);
+    assert_eq!(
+      code(json(None).get_meaning("run").await),
+      ErrorCode::InvalidParams
+    );
+    assert_eq!(
+      code(sqlite(None).get_meaning("run").await),
+      ErrorCode::InvalidParams
+    );
+    assert_eq!(
+      code(json(Some(path("missing.json"))).get_meaning("run").await),
+      io
+    );
+    assert_eq!(
+      code(sqlite(Some(path("missing.db"))).get_meaning("run").await),
+      io
+    );
+    assert!(!dir.path().join("missing.db").exists());
+    assert_eq!(
+      code(json(Some(path("broken.json"))).get_meaning("run").await),
+      ErrorCode::ServerError(DictionaryError::PARSE_ERROR_CODE)
+    );
+    let schema = sqlite(Some(path("empty.db")))
+      .get_meaning("run")
+      .await
+      .unwrap_err();
+    assert_eq!(
+      schema.code,
+      ErrorCode::ServerError(DictionaryError::SCHEMA_ERROR_CODE)
+    );
+    assert!(
+      schema.message.contains("no such table"),
+      "{}",
+      schema.message
+    );
+    assert_eq!(
+      code(sqlite(Some(path("garbage.db"))).get_meaning("run").await),
+      ErrorCode::InternalError
+    );
 
-  fn get_dictionary_path(&self) -> Result<String> {
-    match &self.dictionary_path {
-      Some(path) => Ok(path.clone()),
-      None => Err(Error::invalid_params("Dictionary path not provided")),
-    }
+    // MDX dictionaries and word lists
+    let mdx = |path: String| {
+      crate::mdx::MdxDictionaryProvider::new(Some(path)).with_config(Config::default())
+    };
+    assert_eq!(code(mdx(path("missing.mdx")).get_meaning("run").await), io);
+    std::fs::write(path("garbage.mdx"), [0u8; 3]).unwrap();
+    assert_eq!(
+      code(mdx(path("garbage.mdx")).get_meaning("run").await),
+      ErrorCode::ServerError(DictionaryError::PARSE_ERROR_CODE)
+    );
+    let word_list = crate::word_list::WordListProvider::new(Some(path("missing.txt")))
+      .with_config(Config::default());
+    assert_eq!(word_list.check_word("run").await.unwrap_err().code, io);
   }
 
-  fn read_dictionary_file(&self, dict_path: &str) -> Result<serde_json::Value> {
-    match std::fs::read_to_string(dict_path) {
-      Ok(contents) => match serde_json::from_str(&contents) {
-        Ok(dict) => Ok(dict),
-        Err(e) => {
-          eprintln!("Error parsing dictionary JSON: {}", e);
-          Err(Error::internal_error())
-        }
-      },
-      Err(e) => {
-        eprintln!("Error reading dictionary file: {}", e);
-        Err(Error::internal_error())
-      }
+  #[tokio::test]
+  async fn test_alphanumeric_word_lookup() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("alphanumeric.json");
+    std::fs::write(
+      &json_path,
+      r#"{
+        "mp3": { "noun": ["a compressed audio format"] },
+        "3d": { "adjective": ["three-dimensional"] }
+      }"#,
+    )
+    .unwrap();
+    let mut config = config_with_fuzzy(false);
+    config.lookup.alphanumeric_words = true;
+    let provider =
+      JsonDictionaryProvider::new(Some(json_path.to_string_lossy().into_owned()), None)
+        .with_config(config);
+    let content = "Play the mp3 in 3d, track 42.";
+    let at = |character| Position { line: 0, character };
+
+    assert_eq!(
+      provider.get_word_at_position(content, at(10)).as_deref(),
+      Some("mp3")
+    );
+    assert_eq!(
+      provider.get_word_at_position(content, at(11)).as_deref(),
+      Some("mp3")
+    );
+    assert_eq!(
+      provider.get_word_at_position(content, at(16)).as_deref(),
+      Some("3d")
+    );
+    // Digits alone are not words
+    assert_eq!(provider.get_word_at_position(content, at(26)), None);
+    for word in ["mp3", "3d"] {
+      assert!(
+        provider.get_meaning(word).await.unwrap().is_some(),
+        "{}",
+        word
+      );
     }
+
+    // Off by default: only the letters
+    assert_eq!(
+      extract_word_at_position(content, at(10)).as_deref(),
+      Some("mp")
+    );
+    assert_eq!(extract_word_at_position(content, at(16)), None);
+    assert_eq!(
+      extract_word_at_position(content, at(17)).as_deref(),
+      Some("d")
+    );
   }
 
-  fn find_exact_match(
-    &self,
-    dictionary: &serde_json::Value,
-    word: &str,
-  ) -> Option<DictionaryResponse> {
-    dictionary
-      .get(word)
-      .map(|entry| self.parse_dictionary_entry(word, entry, Some(word)))
+  #[tokio::test]
+  async fn test_lookup_ignores_normalization_form() {
+    let composed = "caf\u{e9}";
+    let decomposed = "cafe\u{301}";
+    let dir = tempfile::tempdir().unwrap();
+
+    // JSON keys are stored decomposed and normalized at load time
+    let json_path = dir.path().join("nfd.json");
+    std::fs::write(
+      &json_path,
+      format!(r#"{{"{}": {{"noun": ["coffee house"]}}}}"#, decomposed),
+    )
+    .unwrap();
+    let json = JsonDictionaryProvider::new(Some(json_path.to_string_lossy().into_owned()), None)
+      .with_config(config_with_fuzzy(false));
+    let response = json.get_meaning(composed).await.unwrap().unwrap();
+    assert_eq!(response.word, composed);
+
+    // SQLite words are stored composed and queried decomposed
+    let sqlite_json = dir.path().join("nfc.json");
+    std::fs::write(
+      &sqlite_json,
+      format!(r#"{{"{}": {{"noun": ["coffee house"]}}}}"#, composed),
+    )
+    .unwrap();
+    let db_path = dir.path().join("nfc.db").to_string_lossy().into_owned();
+    crate::convert::json_to_sqlite(&sqlite_json.to_string_lossy(), &db_path).unwrap();
+    let sqlite =
+      SqliteDictionaryProvider::new(Some(db_path), None).with_config(config_with_fuzzy(false));
+    let response = sqlite.get_meaning(decomposed).await.unwrap().unwrap();
+    assert_eq!(response.word, composed);
   }
 
-  fn parse_dictionary_entry(
-    &self,
-    word: &str,
-    entry: &serde_json::Value,
-    _original_query: Option<&str>,
-  ) -> DictionaryResponse {
-    let mut meanings = Vec::new();
+  #[tokio::test]
+  async fn test_json_find_words_modes() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = JsonDictionaryProvider::new(Some(write_json_dictionary(dir.path())), None)
+      .with_config(Config::default());
 
-    if let Some(obj) = entry.as_object() {
-      for (part_of_speech, defs) in obj {
-        if let Some(defs_array) = defs.as_array() {
-          let definitions = defs_array
-            .iter()
-            .map(|def| Definition {
-              definition: def.as_str().unwrap_or("").to_string(),
-              example: None,
-            })
-            .collect();
+    let prefix = json.find_words("su", MatchMode::Prefix).await.unwrap();
+    assert_eq!(prefix, Some(vec!["sun".to_string()]));
 
-          meanings.push(Meaning {
-            part_of_speech: part_of_speech.clone(),
-            definitions,
-          });
-        }
-      }
-    }
+    let contains = json.find_words("low", MatchMode::Contains).await.unwrap();
+    assert_eq!(contains, Some(vec!["flower".to_string()]));
 
-    DictionaryResponse {
-      word: word.to_string(),
-      meanings,
-    }
+    let fuzzy = json.find_words("rum", MatchMode::Fuzzy).await.unwrap();
+    assert_eq!(fuzzy, Some(vec!["run".to_string()]));
   }
 
-  fn find_fuzzy_match(
-    &self,
-    dictionary: &serde_json::Value,
-    word: &str,
-  ) -> Option<DictionaryResponse> {
-    let max_distance = 2;
-    let mut closest_match = None;
-    let mut min_distance = max_distance + 1;
+  #[tokio::test]
+  async fn test_json_find_words_keeps_to_the_item_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dictionary.json");
+    std::fs::write(
+      &path,
+      r#"{"bat": {"noun": ["a club"]}, "cat": {"noun": ["a pet"]},
+          "hat": {"noun": ["a cap"]}, "rat": {"noun": ["a rodent"]}}"#,
+    )
+    .unwrap();
+    let mut config = Config::default();
+    config.completion.max_items = 2;
+    let json = JsonDictionaryProvider::new(Some(path.to_string_lossy().into_owned()), None)
+      .with_config(config);
 
-    // Find the closest match within our threshold
-    if let Some(entries) = dictionary.as_object() {
-      for (dict_word, entry) in entries {
-        let distance = self.levenshtein_distance(word, dict_word);
-        if distance <= max_distance && distance < min_distance {
-          min_distance = distance;
-          closest_match = Some((dict_word.clone(), entry));
-        }
-      }
-    }
+    // One word past the limit flags the list as cut off
+    let contains = json.find_words("at", MatchMode::Contains).await.unwrap();
+    assert_eq!(contains.unwrap().len(), 3);
+    let fuzzy = json.find_words("mat", MatchMode::Fuzzy).await.unwrap();
+    assert_eq!(fuzzy.unwrap().len(), 3);
+  }
+
+  #[tokio::test]
+  async fn test_sqlite_find_words_containing() {
+    let dir = tempfile::tempdir().unwrap();
+    let freq_path = dir.path().join("freq.db");
+    let conn = rusqlite::Connection::open(&freq_path).unwrap();
+    conn
+      .execute_batch(
+        "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER);
+         INSERT INTO word_frequencies VALUES
+           ('graph', 300), ('paragraph', 200), ('photograph', 100), ('grape', 50);",
+      )
+      .unwrap();
+
+    let sqlite =
+      SqliteDictionaryProvider::new(None, Some(freq_path.to_string_lossy().into_owned()))
+        .with_config(Config::default());
+    let contains = sqlite
+      .find_words("graph", MatchMode::Contains)
+      .await
+      .unwrap();
+    assert_eq!(
+      contains,
+      Some(vec![
+        "graph".to_string(),
+        "paragraph".to_string(),
+        "photograph".to_string()
+      ])
+    );
 
-    closest_match
-      .map(|(matched_word, entry)| self.parse_dictionary_entry(&matched_word, entry, Some(word)))
+    let fuzzy = sqlite.find_words("grap", MatchMode::Fuzzy).await.unwrap();
+    assert_eq!(fuzzy, Some(vec!["graph".to_string(), "grape".to_string()]));
   }
 
-  // Calculate Levenshtein distance between two strings
-  fn levenshtein_distance(&self, s1: &str, s2: &str) -> usize {
-    let len1 = s1.chars().count();
-    let len2 = s2.chars().count();
-    if len1 == 0 {
-      return len2;
-    }
-    if len2 == 0 {
-      return len1;
-    }
+  #[tokio::test]
+  async fn test_json_dictionary_with_bom_and_latin1() {
+    let dir = tempfile::tempdir().unwrap();
 
-    let s1_chars: Vec<char> = s1.chars().collect();
-    let s2_chars: Vec<char> = s2.chars().collect();
+    let bom_path = dir.path().join("bom.json");
+    let mut bom_contents = b"\xEF\xBB\xBF".to_vec();
+    bom_contents.extend_from_slice(br#"{"run": {"verb": ["move quickly"]}}"#);
+    std::fs::write(&bom_path, bom_contents).unwrap();
+    let provider = JsonDictionaryProvider::new(Some(bom_path.to_string_lossy().into_owned()), None);
+    assert!(provider.get_meaning("run").await.unwrap().is_some());
 
-    let mut matrix = vec![vec![0; len2 + 1]; len1 + 1];
+    let latin1_path = dir.path().join("latin1.json");
+    let mut latin1_contents = br#"{"caf"#.to_vec();
+    latin1_contents.push(0xE9);
+    latin1_contents.extend_from_slice(br#"": {"noun": ["a coffee shop"]}}"#);
+    std::fs::write(&latin1_path, latin1_contents).unwrap();
+    let provider =
+      JsonDictionaryProvider::new(Some(latin1_path.to_string_lossy().into_owned()), None);
+    let response = provider.get_meaning("café").await.unwrap().unwrap();
+    assert_eq!(response.word, "café");
+  }
 
-    for i in 0..=len1 {
-      matrix[i][0] = i;
-    }
-    for j in 0..=len2 {
-      matrix[0][j] = j;
-    }
+  #[tokio::test]
+  async fn test_spelling_variants_resolve_to_headword() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("variants.json");
+    std::fs::write(
+      &json_path,
+      r#"{"color": {"noun": ["the hue of something"], "variants": ["colour"]}}"#,
+    )
+    .unwrap();
+    let db_path = dir
+      .path()
+      .join("variants.db")
+      .to_string_lossy()
+      .into_owned();
+    crate::convert::json_to_sqlite(&json_path.to_string_lossy(), &db_path).unwrap();
 
-    // Fill the matrix
-    for i in 1..=len1 {
-      for j in 1..=len2 {
-        let cost = if s1_chars[i - 1] == s2_chars[j - 1] {
-          0
-        } else {
-          1
-        };
-        matrix[i][j] = std::cmp::min(
-          std::cmp::min(matrix[i - 1][j] + 1, matrix[i][j - 1] + 1),
-          matrix[i - 1][j - 1] + cost,
-        );
-      }
+    let json = JsonDictionaryProvider::new(Some(json_path.to_string_lossy().into_owned()), None)
+      .with_config(Config::default());
+    let sqlite = SqliteDictionaryProvider::new(Some(db_path), None).with_config(Config::default());
+    let providers: [&dyn DictionaryProvider; 2] = [&json, &sqlite];
+    for provider in providers {
+      let headword = provider.get_meaning("color").await.unwrap().unwrap();
+      let variant = provider.get_meaning("colour").await.unwrap().unwrap();
+      assert_eq!(variant.word, "color");
+      assert_eq!(variant.variants, vec!["colour".to_string()]);
+      assert_eq!(
+        serde_json::to_value(&variant).unwrap(),
+        serde_json::to_value(&headword).unwrap()
+      );
     }
-
-    matrix[len1][len2]
   }
-}
 
-#[async_trait]
-impl DictionaryProvider for JsonDictionaryProvider {
-  async fn get_meaning(&self, word: &str) -> Result<Option<DictionaryResponse>> {
-    let word_lower = word.to_lowercase();
-    let dictionary = match &*self.dictionary_cache.lock().await {
-      Some(dict) => dict.clone(),
-      None => {
-        let dict_path = self.get_dictionary_path()?;
-        let dict = self.read_dictionary_file(&dict_path)?;
-        let mut cache = self.dictionary_cache.lock().await;
-        *cache = Some(dict.clone());
-        dict
-      }
+  #[tokio::test(flavor = "current_thread")]
+  async fn test_providers_construct_inside_runtime() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = write_json_dictionary(dir.path());
+    let sqlite_path = write_sqlite_dictionary(dir.path());
+
+    let lookups = async {
+      let json = JsonDictionaryProvider::new(Some(json_path), None);
+      let sqlite = SqliteDictionaryProvider::new(Some(sqlite_path), None);
+      (
+        json.get_meaning("run").await.unwrap(),
+        sqlite.get_meaning("run").await.unwrap(),
+      )
     };
 
-    if let Some(response) = self.find_exact_match(&dictionary, &word_lower) {
-      return Ok(Some(response));
-    }
+    let (json, sqlite) = tokio::time::timeout(std::time::Duration::from_secs(5), lookups)
+      .await
+      .expect("constructing providers inside the runtime must not hang");
+    assert!(json.is_some());
+    assert!(sqlite.is_some());
+  }
+  #[tokio::test]
+  async fn test_case_sensitive_homographs() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("homographs.json");
+    std::fs::write(
+      &json_path,
+      r#"{
+        "US": { "noun": ["the United States"] },
+        "us": { "pronoun": ["the speaker and others"] },
+        "Polish": { "adjective": ["relating to Poland"] },
+        "polish": { "verb": ["make shiny"] }
+      }"#,
+    )
+    .unwrap();
+    let json_path = json_path.to_string_lossy().into_owned();
+    let sqlite_path = dir
+      .path()
+      .join("homographs.db")
+      .to_string_lossy()
+      .into_owned();
+    crate::convert::json_to_sqlite(&json_path, &sqlite_path).unwrap();
 
-    if let Some(response) = self.find_fuzzy_match(&dictionary, &word_lower) {
-      return Ok(Some(response));
+    let mut case_sensitive = config_with_fuzzy(false);
+    case_sensitive.lookup.case_sensitive = true;
+
+    let providers: Vec<Box<dyn DictionaryProvider>> = vec![
+      Box::new(
+        JsonDictionaryProvider::new(Some(json_path.clone()), None)
+          .with_config(case_sensitive.clone()),
+      ),
+      Box::new(SqliteDictionaryProvider::new(Some(sqlite_path), None).with_config(case_sensitive)),
+    ];
+    for provider in providers {
+      let country = provider.get_meaning("US").await.unwrap().unwrap();
+      assert_eq!(country.meanings[0].part_of_speech, "noun");
+      let pronoun = provider.get_meaning("us").await.unwrap().unwrap();
+      assert_eq!(pronoun.meanings[0].part_of_speech, "pronoun");
+      let nationality = provider.get_meaning("Polish").await.unwrap().unwrap();
+      assert_eq!(nationality.meanings[0].part_of_speech, "adjective");
+      assert!(provider.get_meaning("POLISH").await.unwrap().is_none());
     }
 
-    Ok(None)
+    // Case-insensitive lookups keep folding "US" onto "us"
+    let json =
+      JsonDictionaryProvider::new(Some(json_path), None).with_config(config_with_fuzzy(false));
+    let folded = json.get_meaning("US").await.unwrap().unwrap();
+    assert_eq!(folded.meanings[0].part_of_speech, "pronoun");
   }
 
-  fn get_word_at_position(&self, content: &str, position: Position) -> Option<String> {
-    extract_word_at_position(content, position)
+  #[tokio::test]
+  async fn test_configured_pos_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("light.json");
+    std::fs::write(
+      &json_path,
+      r#"{"light": {
+        "adjective": ["not heavy"],
+        "noun": ["brightness"],
+        "verb": ["to ignite"]
+      }}"#,
+    )
+    .unwrap();
+    let json_path = json_path.to_string_lossy().into_owned();
+    let sqlite_path = dir.path().join("light.db").to_string_lossy().into_owned();
+    crate::convert::json_to_sqlite(&json_path, &sqlite_path).unwrap();
+
+    let mut ordered = config_with_fuzzy(false);
+    ordered.lookup.pos_order = vec!["Noun".to_string(), "verb".to_string()];
+    let parts_of_speech = |response: DictionaryResponse| {
+      response
+        .meanings
+        .into_iter()
+        .map(|meaning| meaning.part_of_speech)
+        .collect::<Vec<_>>()
+    };
+
+    let json =
+      JsonDictionaryProvider::new(Some(json_path.clone()), None).with_config(ordered.clone());
+    let response = json.get_meaning("light").await.unwrap().unwrap();
+    assert_eq!(parts_of_speech(response), vec!["noun", "verb", "adjective"]);
+    let sqlite =
+      SqliteDictionaryProvider::new(Some(sqlite_path.clone()), None).with_config(ordered);
+    let response = sqlite.get_meaning("light").await.unwrap().unwrap();
+    assert_eq!(parts_of_speech(response), vec!["noun"]);
+
+    // Without a preference parts of speech stay alphabetical
+    let json =
+      JsonDictionaryProvider::new(Some(json_path), None).with_config(config_with_fuzzy(false));
+    let response = json.get_meaning("light").await.unwrap().unwrap();
+    assert_eq!(parts_of_speech(response), vec!["adjective", "noun", "verb"]);
+    let sqlite =
+      SqliteDictionaryProvider::new(Some(sqlite_path), None).with_config(config_with_fuzzy(false));
+    let response = sqlite.get_meaning("light").await.unwrap().unwrap();
+    assert_eq!(parts_of_speech(response), vec!["adjective"]);
   }
 
-  async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>> {
-    // Always generate completions, even for single characters
-    if prefix.is_empty() {
-      return Ok(None);
-    }
+  #[tokio::test]
+  async fn test_case_sensitive_prefix_search() {
+    let dir = tempfile::tempdir().unwrap();
+    let json_path = dir.path().join("names.json");
+    std::fs::write(
+      &json_path,
+      r#"{"Paris": {"noun": ["a city"]}, "parish": {"noun": ["a district"]}}"#,
+    )
+    .unwrap();
 
-    // Check if we can use cached results
-    let mut cache = self.prefix_cache.lock().await;
-    let (cached_prefix, cached_results) = &*cache;
+    let mut config = Config::default();
+    config.lookup.case_sensitive = true;
+    let json = JsonDictionaryProvider::new(Some(json_path.to_string_lossy().into_owned()), None)
+      .with_config(config);
+    assert_eq!(
+      json.find_words_by_prefix("Par").await.unwrap(),
+      Some(vec!["Paris".to_string()])
+    );
+  }
 
-    // If the new prefix extends the cached prefix, filter the cached results
-    if !cached_prefix.is_empty()
-      && prefix.to_lowercase().starts_with(cached_prefix)
-      && !cached_results.is_empty()
-    {
-      // Filter cached results that match the new prefix
-      let filtered: Vec<String> = cached_results
-        .iter()
-        .filter(|word| word.to_lowercase().starts_with(&prefix.to_lowercase()))
-        .cloned()
-        .collect();
+  #[tokio::test]
+  async fn test_stats_count_both_providers() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = JsonDictionaryProvider::new(Some(write_json_dictionary(dir.path())), None);
+    let sqlite = SqliteDictionaryProvider::new(Some(write_sqlite_dictionary(dir.path())), None);
 
-      // If we found matches, update cache and return
-      if !filtered.is_empty() {
-        *cache = (prefix.to_lowercase(), filtered.clone());
-        return Ok(Some(filtered));
-      }
+    for stats in [json.stats().await.unwrap(), sqlite.stats().await.unwrap()] {
+      assert_eq!(stats.words, 4);
+      assert_eq!(stats.definitions, 4);
+      assert_eq!(stats.parts_of_speech, vec!["noun", "verb"]);
     }
+  }
 
-    let dictionary = match &*self.dictionary_cache.lock().await {
-      Some(dict) => dict.clone(),
-      None => {
-        let dict_path = self.get_dictionary_path()?;
-        let dict = self.read_dictionary_file(&dict_path)?;
-        let mut cache = self.dictionary_cache.lock().await;
-        *cache = Some(dict.clone());
-        dict
-      }
+  #[tokio::test]
+  async fn test_result_limit_follows_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let freq_path = dir.path().join("freq.db");
+    let conn = rusqlite::Connection::open(&freq_path).unwrap();
+    conn
+      .execute_batch(
+        "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER);
+         INSERT INTO word_frequencies VALUES
+           ('cats', 90), ('cate', 80), ('cath', 70), ('cato', 60), ('catt', 50), ('cata', 40), ('catn', 30);",
+      )
+      .unwrap();
+    let complete = |max_items: usize| {
+      let mut config = Config::default();
+      config.completion.max_items = max_items;
+      let provider = SqliteDictionaryProvider::new(
+        Some(write_sqlite_dictionary(dir.path())),
+        Some(freq_path.to_string_lossy().into_owned()),
+      )
+      .with_config(config);
+      async move { provider.find_words_by_prefix("cat").await.unwrap() }
     };
 
-    if let Some(entries) = dictionary.as_object() {
-      let prefix_lower = prefix.to_lowercase();
-      // Collect matching words, taking up to 100 for single character inputs
-      let limit = if prefix.len() <= 1 { 100 } else { 50 };
-      let matching_words: Vec<String> = entries
-        .keys()
-        .filter(|word| word.to_lowercase().starts_with(&prefix_lower))
-        .take(limit)
-        .map(|word| word.clone())
-        .collect();
-
-      if !matching_words.is_empty() {
-        *cache = (prefix_lower, matching_words.clone());
-        return Ok(Some(matching_words));
-      }
-    }
+    // One extra word tells callers that the list was cut off
+    assert_eq!(
+      complete(2).await,
+      Some(vec![
+        "cats".to_string(),
+        "cate".to_string(),
+        "cath".to_string()
+      ])
+    );
+    assert_eq!(complete(5).await.unwrap().len(), 6);
+    assert_eq!(complete(10).await.unwrap().len(), 7);
 
-    // If no direct matches are found, use fuzzy matching
-    let candidates = fuzzy::generate_levenshtein_candidates(prefix, true).await;
-    if candidates.is_empty() {
-      *cache = (String::new(), Vec::new()); // Clear cache on failure
-      Ok(None)
-    } else {
-      *cache = (prefix.to_lowercase(), candidates.clone());
-      Ok(Some(candidates))
-    }
+    let json_path = dir.path().join("cats.json");
+    let entries: Vec<String> = ["cats", "cate", "cath", "cato", "catt", "cata", "catn"]
+      .iter()
+      .map(|word| format!(r#""{}": {{ "noun": ["a word"] }}"#, word))
+      .collect();
+    std::fs::write(&json_path, format!("{{ {} }}", entries.join(", "))).unwrap();
+    let complete_json = |max_items: usize| {
+      let mut config = Config::default();
+      config.completion.max_items = max_items;
+      config.completion.sort_order = SortOrder::Alphabetical;
+      let provider =
+        JsonDictionaryProvider::new(Some(json_path.to_string_lossy().into_owned()), None)
+          .with_config(config);
+      async move { provider.find_words_by_prefix("c").await.unwrap() }
+    };
+    assert_eq!(
+      complete_json(2).await,
+      Some(vec![
+        "cata".to_string(),
+        "cate".to_string(),
+        "cath".to_string()
+      ])
+    );
+    assert_eq!(complete_json(5).await.unwrap().len(), 6);
+    assert_eq!(complete_json(10).await.unwrap().len(), 7);
   }
-}
 
-/// Common function to extract a word at a given position in text
-pub fn extract_word_at_position(
-  content: &str,
-  position: tower_lsp::lsp_types::Position,
-) -> Option<String> {
-  let lines: Vec<&str> = content.lines().collect();
-  if position.line as usize >= lines.len() {
-    return None;
+  #[test]
+  fn test_prefix_cache_only_narrows_complete_results() {
+    let mut cache = PrefixCache::default();
+    let lookup = LookupConfig::default();
+    let words = vec!["car".to_string(), "cart".to_string(), "cat".to_string()];
+
+    cache.store("ca".to_string(), &lookup, words.clone(), true);
+    assert_eq!(
+      cache.narrow("car", &lookup),
+      Some(vec!["car".to_string(), "cart".to_string()])
+    );
+    assert_eq!(cache.narrow("b", &lookup), None);
+
+    cache.store("ca".to_string(), &lookup, words, false);
+    assert_eq!(cache.narrow("car", &lookup), None);
   }
 
-  let line = lines[position.line as usize];
-  let chars: Vec<char> = line.chars().collect();
-  let char_pos = position.character as usize;
+  #[test]
+  fn test_prefix_cache_is_not_shared_across_case_modes() {
+    let mut cache = PrefixCache::default();
+    let insensitive = LookupConfig::default();
+    let sensitive = LookupConfig {
+      case_sensitive: true,
+      ..LookupConfig::default()
+    };
 
-  if char_pos >= chars.len() {
-    return None;
+    // Searched case-sensitively, "Apple" was left out of the results for "a"
+    cache.store("a".to_string(), &sensitive, vec!["apple".to_string()], true);
+    assert_eq!(
+      cache.narrow("ap", &sensitive),
+      Some(vec!["apple".to_string()])
+    );
+    assert_eq!(cache.narrow("ap", &insensitive), None);
   }
 
-  let mut start = char_pos;
-  let mut end = char_pos;
+  /// Narrowing a prefix through the cache must give the same words, in the same order,
+  /// as a fresh search, and a subset of the broader result when that one was complete
+  #[tokio::test]
+  async fn test_narrowed_prefix_matches_fresh_search() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("many.json");
+    // More "a" words than a search returns, so the broader lists are truncated
+    let entries: serde_json::Map<String, serde_json::Value> = (0..150)
+      .map(|i| (format!("a{:03}", i), serde_json::json!({"noun": ["x"]})))
+      .chain(std::iter::once((
+        "azure".to_string(),
+        serde_json::json!({"noun": ["blue"]}),
+      )))
+      .collect();
+    std::fs::write(&path, serde_json::Value::Object(entries).to_string()).unwrap();
+    let path = path.to_string_lossy().into_owned();
 
-  while start > 0 && (chars[start - 1].is_alphabetic() || is_cjk_char(chars[start - 1])) {
-    start -= 1;
+    let cached =
+      JsonDictionaryProvider::new(Some(path.clone()), None).with_config(Config::default());
+    let mut broader: Option<Vec<String>> = None;
+    for prefix in ["a", "a1", "a14", "a149", "az"] {
+      let narrowed = cached.find_words_by_prefix(prefix).await.unwrap();
+      let fresh = JsonDictionaryProvider::new(Some(path.clone()), None)
+        .with_config(Config::default())
+        .find_words_by_prefix(prefix)
+        .await
+        .unwrap();
+      assert_eq!(narrowed, fresh, "prefix {}", prefix);
+
+      if let (Some(broader), Some(narrowed)) = (&broader, &narrowed) {
+        // Only a list without the extra, cut-off word holds every narrower match
+        if prefix != "az" && broader.len() <= Config::default().completion.item_limit() {
+          assert!(narrowed.iter().all(|word| broader.contains(word)));
+        }
+      }
+      broader = narrowed;
+    }
   }
 
-  while end < chars.len() && (chars[end].is_alphabetic() || is_cjk_char(chars[end])) {
-    end += 1;
+  /// Records the names of the spans that were opened
+  struct SpanNames(Arc<Mutex<Vec<String>>>);
+
+  impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanNames {
+    fn on_new_span(
+      &self,
+      attrs: &tracing::span::Attributes<'_>,
+      _id: &tracing::span::Id,
+      _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+      self
+        .0
+        .lock()
+        .unwrap()
+        .push(attrs.metadata().name().to_string());
+    }
   }
 
-  if start == end {
-    None
-  } else {
-    Some(chars[start..end].iter().collect())
+  #[tokio::test]
+  async fn test_lookups_emit_spans() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let names = Arc::new(Mutex::new(Vec::new()));
+    let subscriber = tracing_subscriber::registry().with(SpanNames(names.clone()));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let provider: JsonDictionaryProvider = TEST_DICTIONARY.parse().unwrap();
+    assert!(provider.get_meaning("run").await.unwrap().is_some());
+    provider.find_words_by_prefix("su").await.unwrap();
+
+    let names = names.lock().unwrap();
+    assert!(names.contains(&"get_meaning".to_string()));
+    assert!(names.contains(&"find_words_by_prefix".to_string()));
   }
 }
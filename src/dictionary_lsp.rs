@@ -1,10 +1,19 @@
 use crate::completion::CompletionHandler;
 use crate::config::{self, Config};
 use crate::dictionary_data;
-use crate::hover::HoverHandler;
+use crate::document_highlight::DocumentHighlightHandler;
+use crate::formatting;
+use crate::hover::{HoverHandler, HoverRangeParams};
+use crate::personal_dictionary::PersonalDictionary;
+use crate::references::ReferencesHandler;
+use crate::selftest;
 use crate::signature_help::SignatureHelpHandler;
+use crate::workspace_symbol::WorkspaceSymbolHandler;
+use futures::future::BoxFuture;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio;
 use tokio::sync::Mutex;
@@ -15,6 +24,8 @@ use tower_lsp::lsp_types::{
   SignatureHelpOptions, SignatureHelpParams, Url,
 };
 use tower_lsp::{Client, LanguageServer, LspService, Server};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
 
 pub struct DictionaryLsp {
   client: Client,
@@ -23,28 +34,83 @@ pub struct DictionaryLsp {
   pub hover_handler: HoverHandler,
   signature_help_handler: SignatureHelpHandler,
   completion_handler: CompletionHandler,
+  document_highlight_handler: DocumentHighlightHandler,
+  references_handler: ReferencesHandler,
+  workspace_symbol_handler: WorkspaceSymbolHandler,
+  /// Words never reported by diagnostics
+  stopwords: HashSet<String>,
+  /// Set once the user was told that a document is too large to analyze
+  analysis_skipped: AtomicBool,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for DictionaryLsp {
   /// Initializes the language server and advertises server capabilities to the client.
   /// This includes what features we support, such as hover functionality.
-  async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+  async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+    self.hover_handler.set_content_format(
+      params
+        .capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.hover.as_ref())
+        .and_then(|hover| hover.content_format.as_deref()),
+    );
+    self.completion_handler.set_documentation_format(
+      params
+        .capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.completion.as_ref())
+        .and_then(|completion| completion.completion_item.as_ref())
+        .and_then(|item| item.documentation_format.as_deref()),
+    );
+
     Ok(InitializeResult {
       capabilities: ServerCapabilities {
-        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
-        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+          TextDocumentSyncOptions {
+            open_close: Some(true),
+            change: Some(TextDocumentSyncKind::FULL),
+            // Saving the dictionary file reloads it
+            save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+            ..Default::default()
+          },
+        )),
+        hover_provider: self
+          .config
+          .hover
+          .enabled
+          .then_some(HoverProviderCapability::Simple(true)),
+        document_highlight_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        workspace_symbol_provider: Some(OneOf::Left(true)),
         execute_command_provider: Some(ExecuteCommandOptions {
-          commands: vec!["dictionary.toggle-cmp".to_string()],
+          commands: vec![
+            "dictionary.toggle-cmp".to_string(),
+            "dictionary.stats".to_string(),
+            "dictionary.search-definitions".to_string(),
+            "dictionary.reload".to_string(),
+            "dictionary.add-personal".to_string(),
+            "dictionary.edit-personal".to_string(),
+            "dictionary.remove-personal".to_string(),
+            "dictionary.debug-candidates".to_string(),
+            "dictionary.random-word".to_string(),
+            "dictionary.warmup".to_string(),
+          ],
           work_done_progress_options: WorkDoneProgressOptions {
             work_done_progress: Some(true),
           },
         }),
-        signature_help_provider: Some(SignatureHelpOptions {
-          trigger_characters: Some(vec![" ".to_string()]),
-          retrigger_characters: Some(vec![" ".to_string()]),
-          work_done_progress_options: Default::default(),
-        }),
+        signature_help_provider: self
+          .config
+          .signature_help
+          .enabled
+          .then(|| SignatureHelpOptions {
+            trigger_characters: Some(vec![" ".to_string()]),
+            retrigger_characters: Some(vec![" ".to_string()]),
+            work_done_progress_options: Default::default(),
+          }),
         completion_provider: Some(CompletionOptions {
           resolve_provider: Some(true), // Explicitly enable resolve support
           completion_item: Some(CompletionOptionsCompletionItem {
@@ -60,6 +126,31 @@ impl LanguageServer for DictionaryLsp {
     })
   }
 
+  /// Indexes the frequency database and runs the self-test once the client is ready,
+  /// so a broken setup is reported up front
+  async fn initialized(&self, _: InitializedParams) {
+    self.index_dictionary().await;
+    if self.config.warm_up_on_start {
+      let token = self
+        .create_work_done_progress(NumberOrString::String("dictionary.warmup".to_string()))
+        .await
+        .ok();
+      self.warm_up(token).await;
+    }
+
+    let report = selftest::run(&self.config).await;
+    eprintln!("{}", report);
+    if !report.passed() {
+      self
+        .client
+        .show_message(
+          MessageType::WARNING,
+          format!("Dictionary self-test failed:\n{}", report),
+        )
+        .await;
+    }
+  }
+
   /// Handles the opening of a text document by storing its content and
   /// analyzing it for dictionary lookups.
   async fn did_open(&self, params: DidOpenTextDocumentParams) {
@@ -92,6 +183,14 @@ impl LanguageServer for DictionaryLsp {
     }
   }
 
+  /// Reloads the dictionary when the dictionary file itself was saved in the editor.
+  async fn did_save(&self, params: DidSaveTextDocumentParams) {
+    if self.is_dictionary_file(&params.text_document.uri) {
+      self.reload_dictionary().await;
+      eprintln!("Dictionary saved, reloaded {}", params.text_document.uri);
+    }
+  }
+
   async fn did_close(&self, params: DidCloseTextDocumentParams) {
     self
       .document_map
@@ -107,14 +206,41 @@ impl LanguageServer for DictionaryLsp {
 
   /// Processes hover requests by looking up dictionary definitions for the word under the cursor.
   async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+    if !self.config.hover.enabled {
+      return Ok(None);
+    }
     self.hover_handler.on_hover(params).await
   }
 
   /// Processes signature help requests by looking up dictionary definitions for the word under the cursor.
   async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+    if !self.config.signature_help.enabled {
+      return Ok(None);
+    }
     self.signature_help_handler.on_signature_help(params).await
   }
 
+  /// Highlights every occurrence of the word under the cursor in the document.
+  async fn document_highlight(
+    &self,
+    params: DocumentHighlightParams,
+  ) -> Result<Option<Vec<DocumentHighlight>>> {
+    self
+      .document_highlight_handler
+      .on_document_highlight(params)
+      .await
+  }
+
+  /// Finds the word under the cursor in every open document.
+  async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+    self.references_handler.on_references(params).await
+  }
+
+  /// Searches dictionary headwords from the editor's workspace symbol picker.
+  async fn symbol(&self, params: WorkspaceSymbolParams) -> Result<Option<Vec<SymbolInformation>>> {
+    self.workspace_symbol_handler.on_symbol(params).await
+  }
+
   /// Processes completion requests by looking up dictionary definitions for the word under the cursor.
   async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
     if !config::Config::get().completion.enabled {
@@ -157,6 +283,245 @@ impl LanguageServer for DictionaryLsp {
         return Ok(Some(Value::from(config.completion.enabled)));
       }
 
+      "dictionary.stats" => {
+        let provider = dictionary_data::create_dictionary_provider(
+          self.config.dictionary_path.clone(),
+          self.config.freq_path.clone(),
+          &self.config,
+        );
+        let stats = match provider.stats().await {
+          Ok(stats) => stats,
+          Err(error) => {
+            self
+              .notify_work_done(token.clone(), "Failed to read dictionary statistics")
+              .await;
+            return Err(error);
+          }
+        };
+        self
+          .notify_work_done(
+            token.clone(),
+            &format!("{} words, {} definitions", stats.words, stats.definitions),
+          )
+          .await;
+        return Ok(serde_json::to_value(stats).ok());
+      }
+
+      "dictionary.search-definitions" => {
+        let query = match params.arguments.first().and_then(Value::as_str) {
+          Some(query) => query.to_string(),
+          None => {
+            self
+              .notify_work_done(token.clone(), "Failed to search definitions")
+              .await;
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(
+              "Expected a query",
+            ));
+          }
+        };
+        let provider = dictionary_data::create_dictionary_provider(
+          self.config.dictionary_path.clone(),
+          self.config.freq_path.clone(),
+          &self.config,
+        );
+        let words = match provider.find_words_by_definition(&query).await {
+          Ok(words) => words,
+          Err(error) => {
+            self
+              .notify_work_done(token.clone(), "Failed to search definitions")
+              .await;
+            return Err(error);
+          }
+        };
+        self
+          .notify_work_done(token.clone(), &format!("{} matching words", words.len()))
+          .await;
+        return Ok(Some(Value::from(words)));
+      }
+
+      "dictionary.warmup" => {
+        let warmed_up = self.warm_up(Some(token.clone())).await;
+        return Ok(Some(Value::from(warmed_up)));
+      }
+
+      "dictionary.reload" => {
+        self.reload_dictionary().await;
+        // The frequency database may have been rebuilt in place, so skip the change check
+        if let Some(freq_path) = self.config.freq_path.clone() {
+          let rebuilt = tokio::task::spawn_blocking(move || {
+            crate::tire::initialize_global_trie(&freq_path, true)
+          })
+          .await;
+          if let Ok(Err(e)) = rebuilt {
+            eprintln!("Failed to rebuild global trie: {:?}", e);
+          }
+        }
+        self
+          .notify_work_done(token.clone(), "Dictionary reloaded")
+          .await;
+        return Ok(None);
+      }
+
+      "dictionary.add-personal" => {
+        let word = match self.add_personal_definition(&params.arguments) {
+          Ok(word) => word,
+          Err(error) => {
+            self
+              .notify_work_done(token.clone(), "Failed to update the personal dictionary")
+              .await;
+            return Err(error);
+          }
+        };
+        self
+          .notify_work_done(
+            token.clone(),
+            &format!("Added {} to the personal dictionary", word),
+          )
+          .await;
+        return Ok(None);
+      }
+
+      "dictionary.edit-personal" => {
+        let word = match self.edit_personal_definitions(&params.arguments) {
+          Ok(word) => word,
+          Err(error) => {
+            self
+              .notify_work_done(token.clone(), "Failed to update the personal dictionary")
+              .await;
+            return Err(error);
+          }
+        };
+        self
+          .notify_work_done(
+            token.clone(),
+            &format!("Updated {} in the personal dictionary", word),
+          )
+          .await;
+        return Ok(None);
+      }
+
+      "dictionary.remove-personal" => {
+        let (word, removed) = match self.remove_personal_word(&params.arguments) {
+          Ok(removed) => removed,
+          Err(error) => {
+            self
+              .notify_work_done(token.clone(), "Failed to update the personal dictionary")
+              .await;
+            return Err(error);
+          }
+        };
+        self
+          .notify_work_done(
+            token.clone(),
+            &format!("Removed {} from the personal dictionary", word),
+          )
+          .await;
+        return Ok(Some(Value::from(removed)));
+      }
+
+      "dictionary.debug-candidates" => {
+        let prefix = match string_argument(&params.arguments, 0, "prefix") {
+          Ok(prefix) => prefix,
+          Err(error) => {
+            self
+              .notify_work_done(token.clone(), "Failed to list candidates")
+              .await;
+            return Err(error);
+          }
+        };
+        let include_distance_2 = params
+          .arguments
+          .get(1)
+          .and_then(Value::as_bool)
+          .unwrap_or(false);
+        // Candidate generation only depends on the fuzzy settings, not on the database
+        let provider = dictionary_data::SqliteDictionaryProvider::new(None, None)
+          .with_config(self.config.clone());
+        let mut candidates = match provider
+          .find_words_by_prefix_with_distance(&prefix, include_distance_2)
+          .await
+        {
+          Ok(candidates) => candidates.unwrap_or_default(),
+          Err(error) => {
+            self
+              .notify_work_done(token.clone(), "Failed to list candidates")
+              .await;
+            return Err(error);
+          }
+        };
+        let total = candidates.len();
+        candidates.truncate(DEBUG_CANDIDATE_LIMIT);
+        self
+          .notify_work_done(
+            token.clone(),
+            &format!("{} candidates for {}", total, prefix),
+          )
+          .await;
+        return Ok(Some(Value::from(candidates)));
+      }
+
+      "dictionary.random-word" => {
+        let weighting = match params.arguments.first().and_then(Value::as_str) {
+          None | Some("uniform") => RandomWordWeighting::Uniform,
+          Some("frequent") => RandomWordWeighting::Frequent,
+          Some("rare") => RandomWordWeighting::Rare,
+          Some(other) => {
+            self
+              .notify_work_done(token.clone(), "Failed to pick a random word")
+              .await;
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+              "Unknown weighting {}, expected uniform, frequent or rare",
+              other
+            )));
+          }
+        };
+        let provider = dictionary_data::create_dictionary_provider(
+          self.config.dictionary_path.clone(),
+          self.config.freq_path.clone(),
+          &self.config,
+        );
+        let sample = match weighting {
+          RandomWordWeighting::Uniform => 1,
+          _ => RANDOM_WORD_SAMPLE,
+        };
+        let words = match provider.random_words(sample).await {
+          Ok(words) => words,
+          Err(error) => {
+            self
+              .notify_work_done(token.clone(), "Failed to pick a random word")
+              .await;
+            return Err(error);
+          }
+        };
+        let word = match pick_weighted(words, weighting) {
+          Some(word) => word,
+          None => {
+            self
+              .notify_work_done(token.clone(), "The dictionary is empty")
+              .await;
+            return Ok(None);
+          }
+        };
+        let markdown = match provider.get_meaning(&word).await {
+          Ok(Some(response)) => formatting::format_definition_as_markdown_with_config(
+            &word,
+            &response,
+            &self.config.formatting,
+          ),
+          Ok(None) => format!("**{}**\n", word),
+          Err(error) => {
+            self
+              .notify_work_done(token.clone(), "Failed to look up the random word")
+              .await;
+            return Err(error);
+          }
+        };
+        self
+          .notify_work_done(token.clone(), &format!("Random word: {}", word))
+          .await;
+        return Ok(Some(Value::from(markdown)));
+      }
+
       _ => {
         self
           .client
@@ -169,11 +534,300 @@ impl LanguageServer for DictionaryLsp {
 }
 
 impl DictionaryLsp {
+  /// Builds the server and its handlers from a loaded configuration.
+  pub fn new(client: Client, config: Config) -> Self {
+    // Create a shared document map wrapped in an Arc
+    let document_map = Arc::new(Mutex::new(HashMap::<Url, String>::new()));
+
+    let hover_handler = HoverHandler::new(
+      document_map.clone(),
+      config
+        .dictionary_path
+        .clone()
+        .expect("Dictionary path must be set"),
+      config
+        .freq_path
+        .clone()
+        .expect("Frequency path must be set"),
+      config.clone(),
+    );
+
+    let signature_help_handler = SignatureHelpHandler::new(
+      document_map.clone(),
+      config.dictionary_path.clone(),
+      config.freq_path.clone(),
+      config.clone(),
+    );
+
+    let stopwords = config.stopwords.load();
+
+    let completion_handler = CompletionHandler::new(
+      document_map.clone(),
+      config
+        .dictionary_path
+        .clone()
+        .expect("Dictionary path must be set"),
+      config
+        .freq_path
+        .clone()
+        .expect("Frequency path must be set"),
+      &config,
+    )
+    .with_stopwords(stopwords.clone());
+
+    let document_highlight_handler =
+      DocumentHighlightHandler::new(document_map.clone(), config.lookup.clone());
+
+    let references_handler = ReferencesHandler::new(document_map.clone(), config.lookup.clone());
+
+    let workspace_symbol_handler = WorkspaceSymbolHandler::new(
+      config
+        .dictionary_path
+        .clone()
+        .expect("Dictionary path must be set"),
+      config
+        .freq_path
+        .clone()
+        .expect("Frequency path must be set"),
+      &config,
+    );
+
+    DictionaryLsp {
+      client,
+      document_map,
+      config,
+      hover_handler,
+      signature_help_handler,
+      completion_handler,
+      document_highlight_handler,
+      references_handler,
+      workspace_symbol_handler,
+      stopwords,
+      analysis_skipped: AtomicBool::new(false),
+    }
+  }
+
+  /// Handles the `dictionary/hoverRange` request: hover over the selected text.
+  pub async fn hover_range(&self, params: HoverRangeParams) -> Result<Option<Hover>> {
+    if !self.config.hover.enabled {
+      return Ok(None);
+    }
+    self.hover_handler.on_hover_range(params).await
+  }
+
+  /// Builds the completion trie from the frequency database, showing the progress in the
+  /// editor as "Indexing dictionary… 45%".
+  async fn index_dictionary(&self) {
+    let freq_path = match self.config.freq_path.clone() {
+      Some(freq_path) => freq_path,
+      None => return,
+    };
+    let token = self
+      .create_work_done_progress(NumberOrString::String("dictionary.index".to_string()))
+      .await
+      .ok();
+    if let Some(token) = &token {
+      self
+        .notify_work_begin(token.clone(), "Indexing dictionary")
+        .await;
+    }
+
+    // The trie is built on a blocking thread, which sends its progress back here
+    let (progress, mut progress_updates) = tokio::sync::mpsc::unbounded_channel();
+    let indexing = tokio::task::spawn_blocking(move || {
+      crate::tire::initialize_global_trie_with_progress(&freq_path, false, |loaded, total| {
+        let _ = progress.send((loaded, total));
+      })
+    });
+    while let Some((loaded, total)) = progress_updates.recv().await {
+      if let Some(token) = &token {
+        let percentage = (loaded * 100 / total.max(1)) as u32;
+        self
+          .notify_work_report(
+            token.clone(),
+            format!("{}/{} words", loaded, total),
+            percentage,
+          )
+          .await;
+      }
+    }
+
+    let message = match indexing.await {
+      Ok(Ok(())) => "Dictionary indexed",
+      Ok(Err(e)) => {
+        eprintln!("Failed to initialize global trie: {:?}", e);
+        "Failed to index dictionary"
+      }
+      Err(e) => {
+        eprintln!("Trie initialization panicked: {}", e);
+        "Failed to index dictionary"
+      }
+    };
+    if let Some(token) = token {
+      self.notify_work_done(token, message).await;
+    }
+  }
+
+  /// Loads the dictionary of every handler and the completion trie ahead of the first
+  /// request, reporting each step on `token`. Returns whether every step succeeded.
+  async fn warm_up(&self, token: Option<NumberOrString>) -> bool {
+    let freq_path = self.config.freq_path.clone();
+    let steps: Vec<(&str, BoxFuture<'_, Result<()>>)> = vec![
+      ("hover", self.hover_handler.dictionary_provider.warm_up()),
+      (
+        "signature help",
+        Box::pin(self.signature_help_handler.warm_up()),
+      ),
+      ("completion", Box::pin(self.completion_handler.warm_up())),
+      (
+        "workspace symbols",
+        Box::pin(self.workspace_symbol_handler.warm_up()),
+      ),
+      (
+        "completion index",
+        Box::pin(async move {
+          let Some(freq_path) = freq_path else {
+            return Ok(());
+          };
+          // Only rebuilt if the frequency database changed since it was indexed
+          match tokio::task::spawn_blocking(move || {
+            crate::tire::initialize_global_trie(&freq_path, false)
+          })
+          .await
+          {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
+              eprintln!("Failed to initialize global trie: {:?}", e);
+              Err(tower_lsp::jsonrpc::Error::internal_error())
+            }
+            Err(_) => Err(tower_lsp::jsonrpc::Error::internal_error()),
+          }
+        }),
+      ),
+    ];
+
+    if let Some(token) = &token {
+      self
+        .notify_work_begin(token.clone(), "Warming up dictionary")
+        .await;
+    }
+    let total = steps.len();
+    let mut failed = Vec::new();
+    for (i, (name, step)) in steps.into_iter().enumerate() {
+      if let Err(e) = step.await {
+        eprintln!("Failed to warm up {}: {:?}", name, e);
+        failed.push(name);
+      }
+      if let Some(token) = &token {
+        let percentage = ((i + 1) * 100 / total) as u32;
+        self
+          .notify_work_report(token.clone(), name.to_string(), percentage)
+          .await;
+      }
+    }
+
+    if let Some(token) = token {
+      let message = match failed.is_empty() {
+        true => "Dictionary warmed up".to_string(),
+        false => format!("Failed to warm up {}", failed.join(", ")),
+      };
+      self.notify_work_done(token, &message).await;
+    }
+    failed.is_empty()
+  }
+
+  /// The personal dictionary edited by the `dictionary.*-personal` commands
+  fn personal_dictionary(&self) -> Result<Arc<PersonalDictionary>> {
+    match &self.config.personal_dictionary_path {
+      Some(path) => Ok(PersonalDictionary::shared(path)),
+      None => Err(tower_lsp::jsonrpc::Error::invalid_params(
+        "personal_dictionary_path is not set",
+      )),
+    }
+  }
+
+  /// Adds the definition in the `dictionary.add-personal` arguments, returning its word
+  fn add_personal_definition(&self, arguments: &[Value]) -> Result<String> {
+    let word = string_argument(arguments, 0, "word")?;
+    let part_of_speech = string_argument(arguments, 1, "part of speech")?;
+    let definition = string_argument(arguments, 2, "definition")?;
+    self
+      .personal_dictionary()?
+      .add_definition(&word, &part_of_speech, &definition)
+      .map_err(personal_dictionary_error)?;
+    Ok(word)
+  }
+
+  /// Replaces the definitions in the `dictionary.edit-personal` arguments, returning their word
+  fn edit_personal_definitions(&self, arguments: &[Value]) -> Result<String> {
+    let word = string_argument(arguments, 0, "word")?;
+    let part_of_speech = string_argument(arguments, 1, "part of speech")?;
+    let definitions: Vec<String> = arguments
+      .get(2)
+      .and_then(|definitions| serde_json::from_value(definitions.clone()).ok())
+      .ok_or_else(|| tower_lsp::jsonrpc::Error::invalid_params("Expected a list of definitions"))?;
+    self
+      .personal_dictionary()?
+      .set_definitions(&word, &part_of_speech, definitions)
+      .map_err(personal_dictionary_error)?;
+    Ok(word)
+  }
+
+  /// Removes the word in the `dictionary.remove-personal` arguments, returning it and
+  /// whether it was in the personal dictionary
+  fn remove_personal_word(&self, arguments: &[Value]) -> Result<(String, bool)> {
+    let word = string_argument(arguments, 0, "word")?;
+    let removed = self
+      .personal_dictionary()?
+      .remove(&word)
+      .map_err(personal_dictionary_error)?;
+    Ok((word, removed))
+  }
+
+  /// Drops every cached lookup so the next one reads the dictionary again.
+  async fn reload_dictionary(&self) {
+    // Each handler owns a provider
+    self.hover_handler.dictionary_provider.clear_cache().await;
+    self.signature_help_handler.clear_cache().await;
+    self.completion_handler.clear_cache().await;
+    self.workspace_symbol_handler.clear_cache().await;
+    crate::fuzzy::invalidate_candidate_cache();
+  }
+
+  /// Whether `uri` names the configured dictionary file
+  fn is_dictionary_file(&self, uri: &Url) -> bool {
+    let (Ok(saved), Some(dictionary)) = (uri.to_file_path(), &self.config.dictionary_path) else {
+      return false;
+    };
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    canonical(&saved) == canonical(Path::new(dictionary))
+  }
+
   /// Analyzes a document for dictionary lookups and publishes diagnostics.
   /// This function extracts words from the content and checks them against the dictionary.
   async fn analyze_document(&self, uri: Url, content: String) {
-    let words = self.parse_document(&content);
-    let diagnostics = self.check_words(words).await;
+    if let Some(limit) = self.config.max_analyze_bytes.filter(|limit| *limit > 0) {
+      if content.len() > limit {
+        // Clear diagnostics from before the document grew past the limit
+        self.client.publish_diagnostics(uri, Vec::new(), None).await;
+        if !self.analysis_skipped.swap(true, Ordering::Relaxed) {
+          self
+            .client
+            .show_message(
+              MessageType::INFO,
+              format!(
+                "Documents over {} bytes are not checked (max_analyze_bytes)",
+                limit
+              ),
+            )
+            .await;
+        }
+        return;
+      }
+    }
+
+    let tokens = dictionary_data::tokenize_document(&content);
+    let diagnostics = self.check_words(tokens).await;
 
     self
       .client
@@ -195,118 +849,568 @@ impl DictionaryLsp {
     Ok(token)
   }
 
-  // async fn notify_work_begin(&self, token: NumberOrString, message: &str) {
-  //   // begin
-  //   self
-  //     .client
-  //     .send_notification::<notification::Progress>(ProgressParams {
-  //       token,
-  //       value: ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(WorkDoneProgressBegin {
-  //         title: message.to_string(),
-  //         ..Default::default()
-  //       })),
-  //     })
-  //     .await;
-  // }
+  async fn notify_work_begin(&self, token: NumberOrString, message: &str) {
+    self
+      .client
+      .send_notification::<notification::Progress>(ProgressParams {
+        token,
+        value: ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+          title: message.to_string(),
+          percentage: Some(0),
+          ..Default::default()
+        })),
+      })
+      .await;
+  }
 
-  async fn notify_work_done(&self, token: NumberOrString, message: &str) {
+  async fn notify_work_report(&self, token: NumberOrString, message: String, percentage: u32) {
     self
       .client
       .send_notification::<notification::Progress>(ProgressParams {
         token,
-        value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
-          message: Some(message.to_string()),
+        value: ProgressParamsValue::WorkDone(WorkDoneProgress::Report(WorkDoneProgressReport {
+          message: Some(message),
+          percentage: Some(percentage),
+          ..Default::default()
         })),
       })
       .await;
   }
 
-  /// Extracts words from the document content by splitting on whitespace and
-  /// normalizing them (removing punctuation, converting to lowercase).
-  fn parse_document(&self, content: &str) -> Vec<String> {
-    content
-      .split_whitespace()
-      .map(|word| {
-        word
-          .trim_matches(|c: char| !c.is_alphabetic())
-          .to_lowercase()
+  async fn notify_work_done(&self, token: NumberOrString, message: &str) {
+    self
+      .client
+      .send_notification::<notification::Progress>(ProgressParams {
+        token,
+        value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
+          message: Some(message.to_string()),
+        })),
       })
-      .filter(|word| !word.is_empty())
-      .collect()
+      .await;
   }
 
-  /// Checks words against the dictionary and returns diagnostics.
+  /// Checks words against the dictionary and returns diagnostics at their ranges.
   /// Currently returns an empty list as implementation is pending.
-  async fn check_words(&self, _words: Vec<String>) -> Vec<Diagnostic> {
+  async fn check_words(&self, tokens: Vec<dictionary_data::Token>) -> Vec<Diagnostic> {
+    // Stopwords are skipped so function words never flood the diagnostics
+    let _tokens: Vec<dictionary_data::Token> = tokens
+      .into_iter()
+      .filter(|token| !self.stopwords.contains(&token.normalized))
+      .collect();
     Vec::new()
   }
 }
 
-#[tokio::main]
-pub async fn run_server() {
-  let stdin = tokio::io::stdin();
-  let stdout = tokio::io::stdout();
-
-  // Initialize the global trie at startup
-  if let Some(freq_path) = Config::get().freq_path {
-    if let Err(e) = crate::tire::initialize_global_trie(&freq_path) {
-      eprintln!("Failed to initialize global trie: {:?}", e);
-    } else {
-      // eprintln!("Global trie initialized successfully");
+/// Most candidates returned by the `dictionary.debug-candidates` command
+const DEBUG_CANDIDATE_LIMIT: usize = 500;
+
+/// Headwords drawn by `dictionary.random-word` before weighting one of them
+const RANDOM_WORD_SAMPLE: usize = 100;
+
+/// How `dictionary.random-word` favours words by their frequency
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RandomWordWeighting {
+  Uniform,
+  /// Common words are likelier
+  Frequent,
+  /// Rare words are likelier, for vocabulary building
+  Rare,
+}
+
+/// Picks one of `words` at random, weighted by their frequency in the completion trie.
+/// Words the trie doesn't know count as the rarest.
+fn pick_weighted(words: Vec<String>, weighting: RandomWordWeighting) -> Option<String> {
+  let weights: Vec<f64> = words
+    .iter()
+    .map(|word| {
+      let frequency = crate::tire::frequency(word).max(0) as f64 + 1.0;
+      match weighting {
+        RandomWordWeighting::Uniform => 1.0,
+        RandomWordWeighting::Frequent => frequency,
+        RandomWordWeighting::Rare => 1.0 / frequency,
+      }
+    })
+    .collect();
+  let total: f64 = weights.iter().sum();
+  if words.is_empty() || total <= 0.0 {
+    return None;
+  }
+
+  const STEPS: usize = 1 << 30;
+  let mut target = dictionary_data::random_below(STEPS) as f64 / STEPS as f64 * total;
+  for (word, weight) in words.iter().zip(&weights) {
+    if target < *weight {
+      return Some(word.clone());
     }
+    target -= weight;
   }
+  words.last().cloned()
+}
 
+/// The command argument at `index`, which must be a string
+fn string_argument(arguments: &[Value], index: usize, name: &str) -> Result<String> {
+  arguments
+    .get(index)
+    .and_then(Value::as_str)
+    .map(str::to_string)
+    .ok_or_else(|| tower_lsp::jsonrpc::Error::invalid_params(format!("Expected a {}", name)))
+}
+
+fn personal_dictionary_error(error: std::io::Error) -> tower_lsp::jsonrpc::Error {
+  eprintln!("Error writing personal dictionary: {}", error);
+  tower_lsp::jsonrpc::Error::internal_error()
+}
+
+/// How the server talks to its client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+  Stdio,
+  /// Listen on a TCP address, e.g. `127.0.0.1:9257`
+  Tcp(String),
+}
+
+impl Transport {
+  /// Picks the transport from the command line: `--listen <addr>` selects TCP, stdio otherwise
+  pub fn from_args(args: &[String]) -> std::result::Result<Self, String> {
+    match args.iter().position(|arg| arg == "--listen") {
+      Some(index) => args
+        .get(index + 1)
+        .map(|addr| Transport::Tcp(addr.clone()))
+        .ok_or_else(|| "Usage: dictionary_lsp --listen <addr>".to_string()),
+      None => Ok(Transport::Stdio),
+    }
+  }
+}
+
+/// Serves one client over the given streams until it disconnects
+pub async fn serve<I, O>(input: I, output: O, config: Config)
+where
+  I: tokio::io::AsyncRead + Unpin,
+  O: tokio::io::AsyncWrite,
+{
+  let (service, socket) = LspService::build(|client| DictionaryLsp::new(client, config))
+    .custom_method("dictionary/hoverRange", DictionaryLsp::hover_range)
+    .finish();
+  Server::new(input, output, socket).serve(service).await;
+}
+
+/// Logs the lookup spans and their durations to stderr, filtered by `RUST_LOG`
+/// (e.g. `RUST_LOG=dictionary_lsp=debug`). Only errors are logged when it is unset.
+fn init_tracing() {
+  let _ = tracing_subscriber::fmt()
+    .with_env_filter(EnvFilter::from_default_env())
+    .with_span_events(FmtSpan::CLOSE)
+    .with_writer(std::io::stderr)
+    .with_ansi(false)
+    .try_init();
+}
+
+/// Runs the server on `transport`, failing if the TCP address can't be bound
+#[tokio::main]
+pub async fn run_server(transport: Transport) -> std::io::Result<()> {
+  init_tracing();
+  // The global trie is built once the client is initialized, see `index_dictionary`
   let config = Config::load_from_disk();
   // eprint!(
   //   "Loaded config from: {}",
   //   config.dictionary_path.clone().unwrap()
   // );
 
-  // Create a shared document map wrapped in an Arc
-  let document_map = Arc::new(Mutex::new(HashMap::<Url, String>::new()));
+  match transport {
+    Transport::Stdio => {
+      serve(tokio::io::stdin(), tokio::io::stdout(), config).await;
+      Ok(())
+    }
+    Transport::Tcp(addr) => {
+      let listener = tokio::net::TcpListener::bind(&addr).await.map_err(|e| {
+        std::io::Error::new(e.kind(), format!("Failed to listen on {}: {}", addr, e))
+      })?;
+      eprintln!("Listening on {}", addr);
 
-  let (service, socket) = LspService::new(|client| {
-    let hover_handler = HoverHandler::new(
-      document_map.clone(),
-      config
-        .dictionary_path
-        .clone()
-        .expect("Dictionary path must be set"),
-      config
-        .freq_path
-        .clone()
-        .expect("Frequency path must be set"),
-      config.clone(),
-    );
+      // Every connection gets its own server, so clients can reconnect while debugging
+      loop {
+        match listener.accept().await {
+          Ok((stream, _)) => {
+            let (read, write) = tokio::io::split(stream);
+            tokio::spawn(serve(read, write, config.clone()));
+          }
+          Err(e) => eprintln!("Failed to accept connection: {}", e),
+        }
+      }
+    }
+  }
+}
 
-    let signature_help_handler = SignatureHelpHandler::new(
-      document_map.clone(),
-      config.dictionary_path.clone(),
-      config.freq_path.clone(),
-      config.clone(),
-    );
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-    let completion_handler = CompletionHandler::new(
-      document_map.clone(),
-      config
-        .dictionary_path
-        .clone()
-        .expect("Dictionary path must be set"),
-      config
-        .freq_path
-        .clone()
-        .expect("Frequency path must be set"),
+  fn position_params(uri: &Url) -> TextDocumentPositionParams {
+    TextDocumentPositionParams {
+      text_document: TextDocumentIdentifier { uri: uri.clone() },
+      position: Position {
+        line: 0,
+        character: 1,
+      },
+    }
+  }
+
+  /// Runs `initialize`, hover and signature help on "run" with the given toggles
+  async fn query(
+    hover_enabled: bool,
+    signature_help_enabled: bool,
+  ) -> (InitializeResult, Option<Hover>, Option<SignatureHelp>) {
+    let dir = tempfile::tempdir().unwrap();
+    let dictionary_path = dir.path().join("dictionary.json");
+    std::fs::write(
+      &dictionary_path,
+      r#"{"run": {"verb": ["move quickly on foot"]}}"#,
+    )
+    .unwrap();
+
+    let mut config = Config {
+      dictionary_path: Some(dictionary_path.to_string_lossy().into_owned()),
+      freq_path: Some(dir.path().join("freq.db").to_string_lossy().into_owned()),
+      ..Config::default()
+    };
+    config.hover.enabled = hover_enabled;
+    config.signature_help.enabled = signature_help_enabled;
+
+    let (service, _socket) = LspService::new(|client| DictionaryLsp::new(client, config));
+    let server = service.inner();
+    let uri = Url::parse("file:///test.md").unwrap();
+    server
+      .document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "run".to_string());
+
+    let initialize = server
+      .initialize(InitializeParams::default())
+      .await
+      .unwrap();
+    let hover = server
+      .hover(HoverParams {
+        text_document_position_params: position_params(&uri),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+      })
+      .await
+      .unwrap();
+    let signature_help = server
+      .signature_help(SignatureHelpParams {
+        context: None,
+        text_document_position_params: position_params(&uri),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+      })
+      .await
+      .unwrap();
+
+    (initialize, hover, signature_help)
+  }
+
+  #[test]
+  fn test_transport_from_args() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    assert_eq!(Transport::from_args(&[]), Ok(Transport::Stdio));
+    assert_eq!(
+      Transport::from_args(&args(&["--listen", "127.0.0.1:9257"])),
+      Ok(Transport::Tcp("127.0.0.1:9257".to_string()))
     );
+    assert!(Transport::from_args(&args(&["--listen"])).is_err());
+  }
 
-    DictionaryLsp {
-      client,
-      document_map,
-      config: config.clone(),
-      hover_handler,
-      signature_help_handler,
-      completion_handler,
+  #[tokio::test]
+  async fn test_initialize_over_tcp() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config {
+      dictionary_path: Some(
+        dir
+          .path()
+          .join("dictionary.json")
+          .to_string_lossy()
+          .into_owned(),
+      ),
+      freq_path: Some(dir.path().join("freq.db").to_string_lossy().into_owned()),
+      ..Config::default()
+    };
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+      let (stream, _) = listener.accept().await.unwrap();
+      let (read, write) = tokio::io::split(stream);
+      serve(read, write, config).await;
+    });
+
+    let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let body = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#;
+    client
+      .write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+      .await
+      .unwrap();
+
+    let mut response = String::new();
+    let mut buffer = [0u8; 4096];
+    while !response.contains("completionProvider") {
+      let read = client.read(&mut buffer).await.unwrap();
+      assert!(read > 0, "connection closed before initialize returned");
+      response.push_str(&String::from_utf8_lossy(&buffer[..read]));
     }
-  });
+    assert!(response.contains(r#""id":1"#));
+  }
+
+  #[tokio::test]
+  async fn test_oversized_document_is_not_analyzed() {
+    let dir = tempfile::tempdir().unwrap();
+    let dictionary_path = dir.path().join("dictionary.json");
+    std::fs::write(&dictionary_path, r#"{"run": {"verb": ["move quickly"]}}"#).unwrap();
+    let config = Config {
+      dictionary_path: Some(dictionary_path.to_string_lossy().into_owned()),
+      freq_path: Some(dir.path().join("freq.db").to_string_lossy().into_owned()),
+      max_analyze_bytes: Some(16),
+      ..Config::default()
+    };
+
+    let (service, _socket) = LspService::new(|client| DictionaryLsp::new(client, config));
+    let server = service.inner();
+    let open = |uri: &str, text: &str| DidOpenTextDocumentParams {
+      text_document: TextDocumentItem {
+        uri: Url::parse(uri).unwrap(),
+        language_id: "markdown".to_string(),
+        version: 1,
+        text: text.to_string(),
+      },
+    };
 
-  Server::new(stdin, stdout, socket).serve(service).await;
+    server.did_open(open("file:///small.md", "run run")).await;
+    assert!(!server.analysis_skipped.load(Ordering::Relaxed));
+
+    server
+      .did_open(open("file:///large.md", &"run ".repeat(10)))
+      .await;
+    assert!(server.analysis_skipped.load(Ordering::Relaxed));
+
+    // Hover still works on demand
+    let hover = server
+      .hover(HoverParams {
+        text_document_position_params: position_params(&Url::parse("file:///large.md").unwrap()),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+      })
+      .await
+      .unwrap();
+    assert!(hover.is_some());
+  }
+
+  #[tokio::test]
+  async fn test_saving_dictionary_reloads_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let dictionary_path = dir.path().join("dictionary.json");
+    std::fs::write(&dictionary_path, r#"{"run": {"verb": ["old meaning"]}}"#).unwrap();
+    let config = Config {
+      dictionary_path: Some(dictionary_path.to_string_lossy().into_owned()),
+      freq_path: Some(dir.path().join("freq.db").to_string_lossy().into_owned()),
+      ..Config::default()
+    };
+
+    let (service, _socket) = LspService::new(|client| DictionaryLsp::new(client, config));
+    let server = service.inner();
+    let uri = Url::parse("file:///test.md").unwrap();
+    server
+      .document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "run".to_string());
+    let hover_text = || async {
+      let hover = server
+        .hover(HoverParams {
+          text_document_position_params: position_params(&uri),
+          work_done_progress_params: WorkDoneProgressParams::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+      match hover.contents {
+        HoverContents::Markup(content) => content.value,
+        other => panic!("Expected markup hover, got {:?}", other),
+      }
+    };
+    let save = |path: &Path| DidSaveTextDocumentParams {
+      text_document: TextDocumentIdentifier {
+        uri: Url::from_file_path(path).unwrap(),
+      },
+      text: None,
+    };
+
+    assert!(hover_text().await.contains("old meaning"));
+    std::fs::write(&dictionary_path, r#"{"run": {"verb": ["new meaning"]}}"#).unwrap();
+
+    // Saving another file keeps serving the loaded dictionary
+    server.did_save(save(&dir.path().join("notes.md"))).await;
+    assert!(hover_text().await.contains("old meaning"));
+
+    server.did_save(save(&dictionary_path)).await;
+    assert!(hover_text().await.contains("new meaning"));
+  }
+
+  #[tokio::test]
+  async fn test_debug_candidates_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config {
+      dictionary_path: Some(
+        dir
+          .path()
+          .join("dictionary.json")
+          .to_string_lossy()
+          .into_owned(),
+      ),
+      freq_path: Some(dir.path().join("freq.db").to_string_lossy().into_owned()),
+      ..Config::default()
+    };
+    let (service, _socket) = LspService::new(|client| DictionaryLsp::new(client, config));
+    let server = service.inner();
+    let candidates = |include_distance_2: bool| {
+      server.execute_command(ExecuteCommandParams {
+        command: "dictionary.debug-candidates".to_string(),
+        // Long enough for distance 2 corrections (`fuzzy.distance_2_min_length`)
+        arguments: vec![Value::from("marker"), Value::from(include_distance_2)],
+        work_done_progress_params: WorkDoneProgressParams {
+          work_done_token: Some(NumberOrString::String("test".to_string())),
+        },
+      })
+    };
+
+    let distance_1: Vec<String> =
+      serde_json::from_value(candidates(false).await.unwrap().unwrap()).unwrap();
+    assert!(distance_1.contains(&"market".to_string()));
+    assert!(distance_1.len() <= DEBUG_CANDIDATE_LIMIT);
+    let distance_2: Vec<String> =
+      serde_json::from_value(candidates(true).await.unwrap().unwrap()).unwrap();
+    assert!(distance_2.len() > distance_1.len());
+  }
+
+  #[tokio::test]
+  async fn test_random_word_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let dictionary_path = dir.path().join("dictionary.json");
+    std::fs::write(
+      &dictionary_path,
+      r#"{"run": {"verb": ["move quickly on foot"]}, "sun": {"noun": ["the star"]}}"#,
+    )
+    .unwrap();
+    let config = Config {
+      dictionary_path: Some(dictionary_path.to_string_lossy().into_owned()),
+      freq_path: Some(dir.path().join("freq.db").to_string_lossy().into_owned()),
+      ..Config::default()
+    };
+    let (service, _socket) = LspService::new(|client| DictionaryLsp::new(client, config));
+    let server = service.inner();
+    let random_word = |arguments: Vec<Value>| {
+      server.execute_command(ExecuteCommandParams {
+        command: "dictionary.random-word".to_string(),
+        arguments,
+        work_done_progress_params: WorkDoneProgressParams {
+          work_done_token: Some(NumberOrString::String("test".to_string())),
+        },
+      })
+    };
+
+    for arguments in [
+      vec![],
+      vec![Value::from("rare")],
+      vec![Value::from("frequent")],
+    ] {
+      let markdown = random_word(arguments).await.unwrap().unwrap();
+      let markdown = markdown.as_str().unwrap();
+      assert!(
+        markdown == "**run**\n_verb_\n1. move quickly on foot\n"
+          || markdown == "**sun**\n_noun_\n1. the star\n",
+        "{}",
+        markdown
+      );
+    }
+    assert!(random_word(vec![Value::from("often")]).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_failed_commands_end_their_progress() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config {
+      dictionary_path: Some(
+        dir
+          .path()
+          .join("dictionary.json")
+          .to_string_lossy()
+          .into_owned(),
+      ),
+      freq_path: Some(dir.path().join("freq.db").to_string_lossy().into_owned()),
+      ..Config::default()
+    };
+    let commands = [
+      ("dictionary.search-definitions", vec![]),
+      ("dictionary.random-word", vec![Value::from("often")]),
+      ("dictionary.add-personal", vec![Value::from("word")]),
+      ("dictionary.edit-personal", vec![]),
+      ("dictionary.remove-personal", vec![Value::from("word")]),
+      ("dictionary.debug-candidates", vec![]),
+    ];
+
+    // Progress only reaches an initialized client, so the requests go through the transport
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (read, write) = tokio::io::split(server);
+    tokio::spawn(serve(read, write, config));
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+    let mut requests = vec![("initialize", serde_json::json!({ "capabilities": {} }))];
+    for (command, arguments) in &commands {
+      requests.push((
+        "workspace/executeCommand",
+        serde_json::json!({ "command": command, "arguments": arguments, "workDoneToken": command }),
+      ));
+    }
+
+    let mut output = String::new();
+    let mut buffer = [0u8; 4096];
+    for (id, (method, params)) in requests.into_iter().enumerate() {
+      let body =
+        serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+      let body = body.to_string();
+      client_write
+        .write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+        .await
+        .unwrap();
+      let response_end = format!(r#""id":{}}}"#, id);
+      while !output.contains(&response_end) {
+        let read = client_read.read(&mut buffer).await.unwrap();
+        assert!(read > 0, "connection closed before {} returned", method);
+        output.push_str(&String::from_utf8_lossy(&buffer[..read]));
+      }
+    }
+
+    for (command, _) in commands {
+      let progress_end = format!(r#""token":"{}","value":{{"kind":"end""#, command);
+      assert!(output.contains(&progress_end), "{}", output);
+    }
+  }
+
+  #[tokio::test]
+  async fn test_hover_can_be_disabled_alone() {
+    let (initialize, hover, signature_help) = query(false, true).await;
+    assert!(initialize.capabilities.hover_provider.is_none());
+    assert!(initialize.capabilities.signature_help_provider.is_some());
+    assert!(hover.is_none());
+    assert!(signature_help.is_some());
+  }
+
+  #[tokio::test]
+  async fn test_signature_help_can_be_disabled_alone() {
+    let (initialize, hover, signature_help) = query(true, false).await;
+    assert!(initialize.capabilities.hover_provider.is_some());
+    assert!(initialize.capabilities.signature_help_provider.is_none());
+    assert!(hover.is_some());
+    assert!(signature_help.is_none());
+  }
 }
@@ -0,0 +1,211 @@
+use crate::config::LookupConfig;
+use crate::dictionary_data::{
+  extract_word_at_position_with, is_cjk_char, tokenize_document_with, utf16_len,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+  DocumentHighlight, DocumentHighlightKind, DocumentHighlightParams, Position, Range, Url,
+};
+
+pub struct DocumentHighlightHandler {
+  document_map: Arc<Mutex<HashMap<Url, String>>>,
+  lookup: LookupConfig,
+}
+
+impl DocumentHighlightHandler {
+  pub fn new(document_map: Arc<Mutex<HashMap<Url, String>>>, lookup: LookupConfig) -> Self {
+    Self {
+      document_map,
+      lookup,
+    }
+  }
+
+  /// Handles document highlight requests by finding the word at the cursor position
+  /// and highlighting every occurrence of it in the document.
+  pub async fn on_document_highlight(
+    &self,
+    params: DocumentHighlightParams,
+  ) -> Result<Option<Vec<DocumentHighlight>>> {
+    let position = params.text_document_position_params.position;
+    let document_uri = params.text_document_position_params.text_document.uri;
+
+    let content = match self.document_map.lock().await.get(&document_uri) {
+      Some(content) => content.clone(),
+      None => return Ok(None),
+    };
+
+    let word = match extract_word_at_position_with(&content, position, &self.lookup) {
+      Some(word) => word,
+      None => return Ok(None),
+    };
+
+    let highlights = find_word_ranges(&content, &word, &self.lookup)
+      .into_iter()
+      .map(|range| DocumentHighlight {
+        range,
+        kind: Some(DocumentHighlightKind::TEXT),
+      })
+      .collect();
+
+    Ok(Some(highlights))
+  }
+}
+
+/// Finds every whole-word occurrence of `word` in `content`, in UTF-16 ranges.
+/// Tokens are split the same way as `extract_word_at_position_with`, so "cat" never
+/// matches inside "category" but does in "cat's".
+pub fn find_word_ranges(content: &str, word: &str, lookup: &LookupConfig) -> Vec<Range> {
+  let is_word_char = |c: char| {
+    c.is_alphabetic() || is_cjk_char(c) || (lookup.alphanumeric_words && c.is_ascii_digit())
+  };
+  let target = lookup.fold_case(word);
+  let mut ranges = Vec::new();
+
+  for token in tokenize_document_with(content, lookup) {
+    let text = token.text.as_str();
+    let mut start = None;
+    // A trailing separator closes the last word of the token
+    for (i, c) in text
+      .char_indices()
+      .chain(std::iter::once((text.len(), ' ')))
+    {
+      if is_word_char(c) {
+        start.get_or_insert(i);
+        continue;
+      }
+      let Some(word_start) = start.take() else {
+        continue;
+      };
+      if lookup.fold_case(&text[word_start..i]) == target {
+        let position = |byte: usize| Position {
+          line: token.range.start.line,
+          character: token.range.start.character + utf16_len(&text[..byte]),
+        };
+        ranges.push(Range {
+          start: position(word_start),
+          end: position(i),
+        });
+      }
+    }
+  }
+
+  ranges
+}
+
+/////// Tests ///////
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::*;
+  use tower_lsp::lsp_types::{
+    PartialResultParams, TextDocumentIdentifier, TextDocumentPositionParams, WorkDoneProgressParams,
+  };
+
+  pub(crate) fn range(line: u32, start: u32, end: u32) -> Range {
+    Range {
+      start: Position {
+        line,
+        character: start,
+      },
+      end: Position {
+        line,
+        character: end,
+      },
+    }
+  }
+
+  pub(crate) fn lookup(case_sensitive: bool) -> LookupConfig {
+    LookupConfig {
+      case_sensitive,
+      ..LookupConfig::default()
+    }
+  }
+
+  #[test]
+  fn test_find_word_ranges_respects_word_boundaries() {
+    let content = "The cat sat.\nA category of Cat\ncats and cat";
+    assert_eq!(
+      find_word_ranges(content, "cat", &lookup(false)),
+      vec![range(0, 4, 7), range(1, 14, 17), range(2, 9, 12)]
+    );
+    assert_eq!(
+      find_word_ranges(content, "cat", &lookup(true)),
+      vec![range(0, 4, 7), range(2, 9, 12)]
+    );
+  }
+
+  #[test]
+  fn test_find_word_ranges_counts_utf16_units() {
+    // The emoji takes two UTF-16 code units and "é" one
+    let content = "😀 café (cat's) cat-like, cat";
+    assert_eq!(
+      find_word_ranges(content, "cat", &lookup(false)),
+      vec![range(0, 9, 12), range(0, 16, 19), range(0, 26, 29)]
+    );
+    assert_eq!(
+      find_word_ranges(content, "café", &lookup(false)),
+      vec![range(0, 3, 7)]
+    );
+  }
+
+  #[test]
+  fn test_find_word_ranges_with_alphanumeric_words() {
+    let content = "mp3 (mp3) mp 3d";
+    assert_eq!(
+      find_word_ranges(content, "mp", &lookup(false)),
+      vec![range(0, 0, 2), range(0, 5, 7), range(0, 10, 12)]
+    );
+    let alphanumeric = LookupConfig {
+      alphanumeric_words: true,
+      ..LookupConfig::default()
+    };
+    assert_eq!(
+      find_word_ranges(content, "mp3", &alphanumeric),
+      vec![range(0, 0, 3), range(0, 5, 8)]
+    );
+    assert_eq!(
+      find_word_ranges(content, "3d", &alphanumeric),
+      vec![range(0, 13, 15)]
+    );
+  }
+
+  async fn highlights(case_sensitive: bool) -> Vec<Range> {
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    let uri = Url::parse("file:///test.txt").unwrap();
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), "word\nanother Word here".to_string());
+    let handler = DocumentHighlightHandler::new(document_map, lookup(case_sensitive));
+
+    let params = DocumentHighlightParams {
+      text_document_position_params: TextDocumentPositionParams {
+        text_document: TextDocumentIdentifier { uri },
+        position: Position {
+          line: 0,
+          character: 1,
+        },
+      },
+      work_done_progress_params: WorkDoneProgressParams::default(),
+      partial_result_params: PartialResultParams::default(),
+    };
+
+    let highlights = handler
+      .on_document_highlight(params)
+      .await
+      .unwrap()
+      .unwrap();
+    highlights.into_iter().map(|h| h.range).collect()
+  }
+
+  #[tokio::test]
+  async fn test_on_document_highlight() {
+    assert_eq!(
+      highlights(false).await,
+      vec![range(0, 0, 4), range(1, 8, 12)]
+    );
+    assert_eq!(highlights(true).await, vec![range(0, 0, 4)]);
+  }
+}
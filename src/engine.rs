@@ -0,0 +1,172 @@
+//! Embeddable lookup engine.
+//!
+//! `DictionaryEngine` wraps provider creation and formatting behind a small API that
+//! does not expose any LSP types, so dictionary lookups can be used outside the server.
+//!
+//! ```
+//! use dictionary_lsp::engine::DictionaryEngine;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let dir = tempfile::tempdir()?;
+//! # let path = dir.path().join("dictionary.json");
+//! # std::fs::write(&path, r#"{"run": {"verb": ["move quickly on foot"]}}"#)?;
+//! let engine = DictionaryEngine::new(path.to_string_lossy(), None);
+//!
+//! let response = engine.define("run").await?.expect("run is in the dictionary");
+//! assert_eq!(response.meanings[0].part_of_speech, "verb");
+//! assert!(engine.format(&response).contains("move quickly on foot"));
+//! # Ok(())
+//! # }
+//! ```
+use crate::config::{CmpConfig, Config};
+use crate::dictionary_data::{create_dictionary_provider, DictionaryProvider, DictionaryResponse};
+use crate::formatting::{self, FormattingConfig};
+use std::fmt;
+
+/// Error returned by `DictionaryEngine` lookups
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineError {
+  pub message: String,
+}
+
+impl fmt::Display for EngineError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.message)
+  }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<tower_lsp::jsonrpc::Error> for EngineError {
+  fn from(error: tower_lsp::jsonrpc::Error) -> Self {
+    Self {
+      message: error.message.to_string(),
+    }
+  }
+}
+
+pub struct DictionaryEngine {
+  provider: Box<dyn DictionaryProvider>,
+  formatting: FormattingConfig,
+  /// Most words returned by `complete`
+  item_limit: usize,
+}
+
+impl DictionaryEngine {
+  /// Creates an engine for a dictionary file and an optional frequency database used for
+  /// completion. The format follows the extension like in the server: `.db` for SQLite,
+  /// `.mdx` for MDict, `.txt`/`.dic` for word lists and JSON (optionally gzipped) otherwise.
+  pub fn new(dictionary_path: impl Into<String>, freq_path: Option<String>) -> Self {
+    let config = Config::get();
+    Self {
+      provider: create_dictionary_provider(Some(dictionary_path.into()), freq_path, &config),
+      formatting: FormattingConfig::default(),
+      item_limit: config.completion.item_limit(),
+    }
+  }
+
+  /// Creates an engine from the paths and formatting of a loaded `Config`
+  pub fn from_config(config: &Config) -> Self {
+    Self {
+      provider: create_dictionary_provider(
+        config.dictionary_path.clone(),
+        config.freq_path.clone(),
+        config,
+      ),
+      formatting: config.formatting.clone(),
+      item_limit: config.completion.item_limit(),
+    }
+  }
+
+  /// Wraps an existing provider, e.g. a custom implementation of `DictionaryProvider`
+  pub fn from_provider(provider: Box<dyn DictionaryProvider>) -> Self {
+    Self {
+      provider,
+      formatting: FormattingConfig::default(),
+      item_limit: CmpConfig::default().item_limit(),
+    }
+  }
+
+  pub fn with_formatting(mut self, formatting: FormattingConfig) -> Self {
+    self.formatting = formatting;
+    self
+  }
+
+  /// Looks up the definition of `word`, falling back to fuzzy matching as configured.
+  pub async fn define(&self, word: &str) -> Result<Option<DictionaryResponse>, EngineError> {
+    Ok(self.provider.get_meaning(word).await?)
+  }
+
+  /// Looks up several words at once, returning each word with its definition in order.
+  pub async fn define_all(
+    &self,
+    words: &[&str],
+  ) -> Result<Vec<(String, Option<DictionaryResponse>)>, EngineError> {
+    Ok(self.provider.get_meanings(words).await?)
+  }
+
+  /// Returns up to `completion.max_items` completion candidates for `prefix`, most
+  /// relevant first.
+  ///
+  /// ```
+  /// # use dictionary_lsp::engine::DictionaryEngine;
+  /// # #[tokio::main]
+  /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+  /// # let dir = tempfile::tempdir()?;
+  /// # let path = dir.path().join("dictionary.json");
+  /// # std::fs::write(&path, r#"{"word": {"noun": ["a unit of language"]}, "world": {"noun": ["the earth"]}}"#)?;
+  /// let engine = DictionaryEngine::new(path.to_string_lossy(), None);
+  /// let words = engine.complete("wor").await?;
+  /// assert!(words.contains(&"world".to_string()));
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn complete(&self, prefix: &str) -> Result<Vec<String>, EngineError> {
+    // Providers return one word past the limit to flag an incomplete list
+    let mut words = self
+      .provider
+      .find_words_by_prefix(prefix)
+      .await?
+      .unwrap_or_default();
+    words.truncate(self.item_limit);
+    Ok(words)
+  }
+
+  /// Renders a lookup result as markdown using the engine's formatting templates.
+  pub fn format(&self, response: &DictionaryResponse) -> String {
+    formatting::format_definition_as_markdown_with_config(
+      &response.word,
+      response,
+      &self.formatting,
+    )
+  }
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_complete_keeps_to_the_item_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dictionary.json");
+    std::fs::write(
+      &path,
+      r#"{"word": {"noun": ["a unit of language"]}, "world": {"noun": ["the earth"]}}"#,
+    )
+    .unwrap();
+    let mut config = Config {
+      dictionary_path: Some(path.to_string_lossy().into_owned()),
+      ..Config::default()
+    };
+    config.completion.max_items = 1;
+
+    let words = DictionaryEngine::from_config(&config)
+      .complete("wor")
+      .await
+      .unwrap();
+    assert_eq!(words.len(), 1);
+  }
+}
@@ -1,74 +1,1183 @@
-use crate::dictionary_data::DictionaryResponse;
+use crate::dictionary_data::{Definition, DictionaryResponse};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::OnceLock;
 
 /// Configuration for markdown formatting styles
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct FormattingConfig {
   /// Format for word title (e.g., "**{word}**")
   pub word_format: String,
+  /// Other spellings after the word, joined with ", " (e.g., "_also {variants}_")
+  pub variants_format: String,
+  /// Format for the IPA transcription under the word (e.g., "/{ipa}/")
+  pub ipa_format: String,
+  /// Format for the pronunciation audio link (e.g., "[audio]({audio})")
+  pub audio_format: String,
   /// Format for part of speech (e.g., "_{part}_")
   pub part_of_speech_format: String,
+  /// Badge after the part of speech for entries tagged with a language (e.g., "`{lang}`")
+  pub lang_format: String,
+  /// Attribution after the part of speech for entries naming their source (e.g., "[{source}]")
+  pub source_format: String,
   /// Format for definition numbering (e.g., "{num}. {definition}")
   pub definition_format: String,
+  /// Format for the usage label before a definition (e.g., "_({label})_")
+  pub label_format: String,
   /// Format for examples (e.g., "   > Example: _{example}_")
   pub example_format: String,
+  /// Whether examples get their own line or follow the definition
+  pub example_style: ExampleStyle,
+  /// Show at most this many examples per definition, marking the last one with "…"
+  /// when more were hidden (unset or 0: unlimited)
+  pub max_examples_per_definition: Option<usize>,
   /// Whether to add extra spacing between parts of speech
   pub add_spacing: bool,
+  /// Truncate each definition to this many characters, appending "…" (unset or 0: unlimited)
+  pub max_definition_chars: Option<usize>,
+  /// Show at most this many definitions per part of speech (unset or 0: unlimited)
+  pub max_definitions_per_pos: Option<usize>,
+  /// Message for words without a definition (e.g., "No definition found for **{word}**").
+  /// Unset keeps the built-in hover and signature help messages.
+  pub not_found_format: Option<String>,
+  /// Message for words of the frequency database that the dictionary does not define
+  /// (e.g., "**{word}** has no definition"). Unset keeps the built-in hover message.
+  pub undefined_format: Option<String>,
+  /// Render references to other headwords as links: `{ref:word}` markers and the word after
+  /// "see also" or "cf.". Markers are shown as the plain word when this is off.
+  pub cross_references: bool,
+  /// Format for cross-references; `{uri}` is the percent-encoded word
+  /// (e.g., "[{word}](dictionary:{uri})" or "**{word}**")
+  pub reference_format: String,
+  /// Backslash-escape markdown characters in the word, definitions and examples, so text
+  /// such as "2*x*y" is not rendered as emphasis
+  pub escape_markdown: bool,
+  /// Templates parsed on first use
+  #[serde(skip)]
+  compiled: OnceLock<CompiledTemplates>,
 }
 
 impl Default for FormattingConfig {
   fn default() -> Self {
     Self {
       word_format: "**{word}**".to_string(),
+      variants_format: "_also {variants}_".to_string(),
+      ipa_format: "/{ipa}/".to_string(),
+      audio_format: "[audio]({audio})".to_string(),
       part_of_speech_format: "_{part}_".to_string(),
+      lang_format: "`{lang}`".to_string(),
+      source_format: "[{source}]".to_string(),
       definition_format: "{num}. {definition}".to_string(),
+      label_format: "_({label})_".to_string(),
       example_format: "   > Example: _{example}_".to_string(),
+      example_style: ExampleStyle::Blockquote,
+      max_examples_per_definition: None,
       add_spacing: false,
+      max_definition_chars: None,
+      max_definitions_per_pos: None,
+      not_found_format: None,
+      undefined_format: None,
+      cross_references: false,
+      reference_format: "[{word}](dictionary:{uri})".to_string(),
+      escape_markdown: false,
+      compiled: OnceLock::new(),
     }
   }
 }
 
+/// Where an example is placed relative to its definition
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExampleStyle {
+  /// On its own line below the definition
+  #[default]
+  Blockquote,
+  /// After the definition on the same line, without the leading `>` of `example_format`
+  Inline,
+}
+
+impl FormattingConfig {
+  /// Returns the parsed templates, reparsing if a template was changed after the cache was built
+  fn templates(&self) -> Cow<'_, CompiledTemplates> {
+    let compiled = self.compiled.get_or_init(|| CompiledTemplates::new(self));
+    if compiled.matches(self) {
+      Cow::Borrowed(compiled)
+    } else {
+      Cow::Owned(CompiledTemplates::new(self))
+    }
+  }
+}
+
+/// A piece of a parsed template
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+  Literal(String),
+  /// Index into the values passed to `Template::render`
+  Placeholder(usize),
+}
+
+/// A format string split on its `{name}` placeholders so rendering is plain concatenation
+#[derive(Clone, Debug, PartialEq)]
+struct Template {
+  source: String,
+  segments: Vec<Segment>,
+}
+
+impl Template {
+  fn parse(source: &str, placeholders: &[&str]) -> Self {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut rest = source;
+
+    while !rest.is_empty() {
+      let placeholder = placeholders.iter().position(|name| rest.starts_with(name));
+
+      match placeholder {
+        Some(index) => {
+          if !literal.is_empty() {
+            segments.push(Segment::Literal(std::mem::take(&mut literal)));
+          }
+          segments.push(Segment::Placeholder(index));
+          rest = &rest[placeholders[index].len()..];
+        }
+        None => {
+          let c = rest.chars().next().unwrap();
+          literal.push(c);
+          rest = &rest[c.len_utf8()..];
+        }
+      }
+    }
+
+    if !literal.is_empty() {
+      segments.push(Segment::Literal(literal));
+    }
+
+    Self {
+      source: source.to_string(),
+      segments,
+    }
+  }
+
+  fn render(&self, out: &mut String, values: &[&str]) {
+    for segment in &self.segments {
+      match segment {
+        Segment::Literal(text) => out.push_str(text),
+        Segment::Placeholder(index) => out.push_str(values[*index]),
+      }
+    }
+  }
+}
+
+/// All templates of a `FormattingConfig`, parsed once
+#[derive(Clone, Debug, PartialEq)]
+struct CompiledTemplates {
+  word: Template,
+  variants: Template,
+  ipa: Template,
+  audio: Template,
+  part_of_speech: Template,
+  lang: Template,
+  source: Template,
+  definition: Template,
+  label: Template,
+  example: Template,
+  reference: Template,
+}
+
+impl CompiledTemplates {
+  fn new(config: &FormattingConfig) -> Self {
+    Self {
+      word: Template::parse(&config.word_format, &["{word}"]),
+      variants: Template::parse(&config.variants_format, &["{variants}"]),
+      ipa: Template::parse(&config.ipa_format, &["{ipa}"]),
+      audio: Template::parse(&config.audio_format, &["{audio}"]),
+      part_of_speech: Template::parse(&config.part_of_speech_format, &["{part}"]),
+      lang: Template::parse(&config.lang_format, &["{lang}"]),
+      source: Template::parse(&config.source_format, &["{source}"]),
+      definition: Template::parse(&config.definition_format, &["{num}", "{definition}"]),
+      label: Template::parse(&config.label_format, &["{label}"]),
+      example: Template::parse(&config.example_format, &["{example}"]),
+      reference: Template::parse(&config.reference_format, &["{word}", "{uri}"]),
+    }
+  }
+
+  fn matches(&self, config: &FormattingConfig) -> bool {
+    self.word.source == config.word_format
+      && self.variants.source == config.variants_format
+      && self.ipa.source == config.ipa_format
+      && self.audio.source == config.audio_format
+      && self.part_of_speech.source == config.part_of_speech_format
+      && self.lang.source == config.lang_format
+      && self.source.source == config.source_format
+      && self.definition.source == config.definition_format
+      && self.label.source == config.label_format
+      && self.example.source == config.example_format
+      && self.reference.source == config.reference_format
+  }
+}
+
 /// Formats a dictionary response as Markdown text with custom styling
 pub fn format_definition_as_markdown_with_config(
   word: &str,
   response: &DictionaryResponse,
   config: &FormattingConfig,
 ) -> String {
-  let mut markdown = config.word_format.replace("{word}", word) + "\n";
+  let templates = config.templates();
+  let mut markdown = String::new();
+  templates.word.render(
+    &mut markdown,
+    &[&escape_markdown(word, config.escape_markdown)],
+  );
+  if !response.variants.is_empty() {
+    markdown.push(' ');
+    templates.variants.render(
+      &mut markdown,
+      &[&escape_markdown(
+        &response.variants.join(", "),
+        config.escape_markdown,
+      )],
+    );
+  }
+  markdown.push('\n');
+
+  if response.ipa.is_some() || response.audio.is_some() {
+    if let Some(ipa) = &response.ipa {
+      templates.ipa.render(&mut markdown, &[ipa]);
+    }
+    if let Some(audio) = &response.audio {
+      if response.ipa.is_some() {
+        markdown.push(' ');
+      }
+      templates.audio.render(&mut markdown, &[audio]);
+    }
+    markdown.push('\n');
+  }
 
   for meaning in &response.meanings {
     if config.add_spacing {
       markdown.push('\n');
     }
 
-    markdown.push_str(
-      &config
-        .part_of_speech_format
-        .replace("{part}", &meaning.part_of_speech),
-    );
+    templates
+      .part_of_speech
+      .render(&mut markdown, &[&meaning.part_of_speech]);
+    if let Some(lang) = &meaning.lang {
+      markdown.push(' ');
+      templates.lang.render(&mut markdown, &[lang]);
+    }
+    if let Some(source) = &meaning.source {
+      markdown.push(' ');
+      templates.source.render(&mut markdown, &[source]);
+    }
     markdown.push('\n');
 
-    for (i, definition) in meaning.definitions.iter().enumerate() {
-      let num = i + 1;
-      markdown.push_str(
-        &config
-          .definition_format
-          .replace("{num}", &num.to_string())
-          .replace("{definition}", &definition.definition),
-      );
-      markdown.push('\n');
+    let shown = limit(config.max_definitions_per_pos).unwrap_or(usize::MAX);
+    for (i, definition) in meaning.definitions.iter().take(shown).enumerate() {
+      render_definition(&mut markdown, definition, i, 0, &templates, config);
+    }
+
+    let hidden = meaning.definitions.len().saturating_sub(shown);
+    if hidden > 0 {
+      markdown.push_str(&format!("_… {} more_\n", hidden));
+    }
+  }
+
+  markdown
+}
+
+/// Renders a definition and, indented one level deeper, its sub-senses
+fn render_definition(
+  markdown: &mut String,
+  definition: &Definition,
+  index: usize,
+  depth: usize,
+  templates: &CompiledTemplates,
+  config: &FormattingConfig,
+) {
+  let indent = "   ".repeat(depth);
+  let label = sense_label(index, depth);
+  let reference = config.cross_references.then_some(&templates.reference);
+  let mut text = String::new();
+  if let Some(usage) = &definition.label {
+    let usage = escape_markdown(usage, config.escape_markdown);
+    templates.label.render(&mut text, &[&usage]);
+    text.push(' ');
+  }
+  text.push_str(&definition_text(
+    &definition.definition,
+    config,
+    reference,
+    config.escape_markdown,
+  ));
+  markdown.push_str(&indent);
+  templates.definition.render(markdown, &[&label, &text]);
 
-      if let Some(example) = &definition.example {
-        markdown.push_str(&config.example_format.replace("{example}", example));
+  let shown = limit(config.max_examples_per_definition).unwrap_or(usize::MAX);
+  for example in definition.examples().take(shown) {
+    let example = escape_markdown(example, config.escape_markdown);
+    match config.example_style {
+      ExampleStyle::Blockquote => {
         markdown.push('\n');
+        markdown.push_str(&indent);
+        templates.example.render(markdown, &[&example]);
+      }
+      ExampleStyle::Inline => {
+        let mut rendered = String::new();
+        templates.example.render(&mut rendered, &[&example]);
+        markdown.push_str(" — ");
+        markdown.push_str(strip_blockquote(&rendered));
       }
     }
   }
+  if definition.examples().count() > shown {
+    markdown.push_str(" …");
+  }
+  markdown.push('\n');
 
-  markdown
+  for (i, sub_definition) in definition.sub_definitions.iter().enumerate() {
+    render_definition(markdown, sub_definition, i, depth + 1, templates, config);
+  }
+}
+
+/// Numbers top-level senses (1, 2) and letters sub-senses (a, b)
+fn sense_label(index: usize, depth: usize) -> String {
+  match depth {
+    0 => (index + 1).to_string(),
+    _ if index < 26 => char::from(b'a' + index as u8).to_string(),
+    _ => (index + 1).to_string(),
+  }
+}
+
+/// Removes the indentation and `>` marker that make a line a markdown blockquote
+fn strip_blockquote(text: &str) -> &str {
+  let text = text.trim_start();
+  text.strip_prefix('>').map_or(text, str::trim_start)
+}
+
+/// Treats `Some(0)` like `None`: no limit
+fn limit(value: Option<usize>) -> Option<usize> {
+  value.filter(|value| *value > 0)
+}
+
+/// Markers an author puts around a headword to link it (`{ref:word}`)
+const REFERENCE_MARKER: (&str, &str) = ("{ref:", "}");
+
+/// Phrases introducing a reference to another headword, matched ignoring ASCII case
+const REFERENCE_PHRASES: [&str; 2] = ["see also ", "cf. "];
+
+/// A run of definition text, or a headword it refers to
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Piece<'a> {
+  Text(&'a str),
+  Reference(&'a str),
+}
+
+/// Splits `text` on `{ref:word}` markers and, with `detect`, on the word after a reference phrase
+fn split_references(text: &str, detect: bool) -> Vec<Piece<'_>> {
+  let (open, close) = REFERENCE_MARKER;
+  let mut pieces = Vec::new();
+  let mut rest = text;
+
+  while let Some(start) = rest.find(open) {
+    let Some(length) = rest[start + open.len()..].find(close) else {
+      break;
+    };
+    push_text(&mut pieces, &rest[..start], detect);
+    let word = rest[start + open.len()..start + open.len() + length].trim();
+    if !word.is_empty() {
+      pieces.push(Piece::Reference(word));
+    }
+    rest = &rest[start + open.len() + length + close.len()..];
+  }
+  push_text(&mut pieces, rest, detect);
+  pieces
+}
+
+/// Adds plain `text`, splitting out the words following reference phrases when `detect` is set
+fn push_text<'a>(pieces: &mut Vec<Piece<'a>>, text: &'a str, detect: bool) {
+  let mut start = 0;
+  if detect {
+    let mut previous = None;
+    for (i, c) in text.char_indices() {
+      let at_word_start = !previous.is_some_and(char::is_alphanumeric);
+      previous = Some(c);
+      if i < start || !at_word_start {
+        continue;
+      }
+      let Some(phrase) = REFERENCE_PHRASES.iter().find(|phrase| {
+        text
+          .get(i..i + phrase.len())
+          .is_some_and(|candidate| candidate.eq_ignore_ascii_case(phrase))
+      }) else {
+        continue;
+      };
+
+      let word_start = i + phrase.len();
+      let word_length = text[word_start..]
+        .find(|c: char| !c.is_alphanumeric() && c != '-')
+        .unwrap_or(text.len() - word_start);
+      let word = text[word_start..word_start + word_length].trim_end_matches('-');
+      if !word.is_empty() {
+        pieces.push(Piece::Text(&text[start..word_start]));
+        pieces.push(Piece::Reference(word));
+        start = word_start + word.len();
+      }
+    }
+  }
+  if start < text.len() {
+    pieces.push(Piece::Text(&text[start..]));
+  }
+}
+
+/// Percent-encodes everything but unreserved URI characters
+fn encode_uri(word: &str) -> String {
+  word
+    .bytes()
+    .map(|b| match b {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+        (b as char).to_string()
+      }
+      _ => format!("%{:02X}", b),
+    })
+    .collect()
+}
+
+/// Characters with a meaning in markdown, escaped by `escape_markdown`
+const MARKDOWN_SPECIAL_CHARS: &[char] = &['\\', '`', '*', '_', '[', ']', '<', '>', '#', '|', '~'];
+
+/// `text` with markdown characters backslash-escaped when `escape` is set
+fn escape_markdown(text: &str, escape: bool) -> Cow<'_, str> {
+  if !escape || !text.contains(MARKDOWN_SPECIAL_CHARS) {
+    return Cow::Borrowed(text);
+  }
+  let mut escaped = String::with_capacity(text.len() + 8);
+  for c in text.chars() {
+    if MARKDOWN_SPECIAL_CHARS.contains(&c) {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  Cow::Owned(escaped)
+}
+
+/// Definition text with references rendered by `reference` (plain words when `None`),
+/// shortened to `max_definition_chars` visible characters (not bytes) and marked with "…".
+/// With `escape`, the text and referenced words are markdown-escaped.
+fn definition_text(
+  text: &str,
+  config: &FormattingConfig,
+  reference: Option<&Template>,
+  escape: bool,
+) -> String {
+  let mut remaining = limit(config.max_definition_chars).unwrap_or(usize::MAX);
+  let mut rendered = String::new();
+
+  for piece in split_references(text, reference.is_some()) {
+    let (Piece::Text(part) | Piece::Reference(part)) = piece;
+    let length = part.chars().count();
+    if length > remaining {
+      let kept: String = part.chars().take(remaining).collect();
+      rendered.push_str(&escape_markdown(&kept, escape));
+      rendered.push('…');
+      break;
+    }
+    remaining -= length;
+
+    match (piece, reference) {
+      (Piece::Reference(word), Some(template)) => template.render(
+        &mut rendered,
+        &[&escape_markdown(word, escape), &encode_uri(word)],
+      ),
+      _ => rendered.push_str(&escape_markdown(part, escape)),
+    }
+  }
+
+  rendered
+}
+
+/// Renders `not_found_format` for `word`, or `None` when no template is configured
+pub fn format_not_found(word: &str, config: &FormattingConfig) -> Option<String> {
+  config
+    .not_found_format
+    .as_ref()
+    .map(|format| format.replace("{word}", word))
+}
+
+/// One list item with the first part of speech and first definition of `response`, for
+/// hovers over several words. `None` when the response has no definition.
+pub fn format_short_definition(
+  response: &DictionaryResponse,
+  config: &FormattingConfig,
+  markdown: bool,
+) -> Option<String> {
+  let meaning = response.meanings.first()?;
+  let definition = meaning.definitions.first()?;
+  let escape = markdown && config.escape_markdown;
+  let text = definition_text(&definition.definition, config, None, escape);
+  if !markdown {
+    return Some(format!(
+      "- {} ({}): {}",
+      response.word, meaning.part_of_speech, text
+    ));
+  }
+
+  let templates = config.templates();
+  let mut line = "- ".to_string();
+  templates
+    .word
+    .render(&mut line, &[&escape_markdown(&response.word, escape)]);
+  line.push(' ');
+  templates.part_of_speech.render(
+    &mut line,
+    &[&escape_markdown(&meaning.part_of_speech, escape)],
+  );
+  line.push_str(": ");
+  line.push_str(&text);
+  Some(line)
+}
+
+/// Renders `undefined_format` for `word`, or `None` when no template is configured
+pub fn format_undefined(word: &str, config: &FormattingConfig) -> Option<String> {
+  config
+    .undefined_format
+    .as_ref()
+    .map(|format| format.replace("{word}", word))
+}
+
+/// Formats a dictionary response as plain text for clients that can't render markdown.
+/// The markdown templates are not used; truncation and definition limits still apply.
+pub fn format_definition_as_plaintext_with_config(
+  word: &str,
+  response: &DictionaryResponse,
+  config: &FormattingConfig,
+) -> String {
+  let mut text = word.to_string();
+  if !response.variants.is_empty() {
+    text.push_str(&format!(" (also {})", response.variants.join(", ")));
+  }
+  if let Some(ipa) = &response.ipa {
+    text.push_str(&format!(" /{}/", ipa));
+  }
+  text.push('\n');
+
+  for meaning in &response.meanings {
+    if config.add_spacing {
+      text.push('\n');
+    }
+    text.push_str(&meaning.part_of_speech);
+    if let Some(lang) = &meaning.lang {
+      text.push_str(&format!(" [{}]", lang));
+    }
+    if let Some(source) = &meaning.source {
+      text.push_str(&format!(" ({})", source));
+    }
+    text.push('\n');
+
+    let shown = limit(config.max_definitions_per_pos).unwrap_or(usize::MAX);
+    for (i, definition) in meaning.definitions.iter().take(shown).enumerate() {
+      render_plaintext_definition(&mut text, definition, i, 0, config);
+    }
+
+    let hidden = meaning.definitions.len().saturating_sub(shown);
+    if hidden > 0 {
+      text.push_str(&format!("… {} more\n", hidden));
+    }
+  }
+
+  text
+}
+
+fn render_plaintext_definition(
+  text: &mut String,
+  definition: &Definition,
+  index: usize,
+  depth: usize,
+  config: &FormattingConfig,
+) {
+  let indent = "   ".repeat(depth);
+  let label = sense_label(index, depth);
+  let definition_text = definition_text(&definition.definition, config, None, false);
+  let usage = match &definition.label {
+    Some(usage) => format!("({}) ", usage),
+    None => String::new(),
+  };
+  text.push_str(&format!(
+    "{}{}. {}{}\n",
+    indent, label, usage, definition_text
+  ));
+  let shown = limit(config.max_examples_per_definition).unwrap_or(usize::MAX);
+  for example in definition.examples().take(shown) {
+    text.push_str(&format!("{}   Example: {}\n", indent, example));
+  }
+  if definition.examples().count() > shown {
+    text.insert_str(text.len() - 1, " …");
+  }
+
+  for (i, sub_definition) in definition.sub_definitions.iter().enumerate() {
+    render_plaintext_definition(text, sub_definition, i, depth + 1, config);
+  }
 }
 
 /// Formats a dictionary response as Markdown text using default styling
 pub fn format_definition_as_markdown(word: &str, response: &DictionaryResponse) -> String {
   format_definition_as_markdown_with_config(word, response, &FormattingConfig::default())
 }
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::dictionary_data::Meaning;
+
+  fn response(ipa: Option<&str>, audio: Option<&str>) -> DictionaryResponse {
+    DictionaryResponse {
+      word: "run".to_string(),
+      meanings: vec![Meaning {
+        part_of_speech: "verb".to_string(),
+        definitions: vec![Definition {
+          definition: "move quickly on foot".to_string(),
+          example: None,
+          more_examples: Vec::new(),
+          sub_definitions: Vec::new(),
+          label: None,
+        }],
+        lang: None,
+        source: None,
+      }],
+      ipa: ipa.map(|ipa| ipa.to_string()),
+      audio: audio.map(|audio| audio.to_string()),
+      variants: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn test_format_without_ipa() {
+    assert_eq!(
+      format_definition_as_markdown("run", &response(None, None)),
+      "**run**\n_verb_\n1. move quickly on foot\n"
+    );
+  }
+
+  #[test]
+  fn test_format_with_ipa_and_audio() {
+    assert_eq!(
+      format_definition_as_markdown("run", &response(Some("rʌn"), None)),
+      "**run**\n/rʌn/\n_verb_\n1. move quickly on foot\n"
+    );
+    assert_eq!(
+      format_definition_as_markdown(
+        "run",
+        &response(Some("rʌn"), Some("https://example.com/run.mp3"))
+      ),
+      "**run**\n/rʌn/ [audio](https://example.com/run.mp3)\n_verb_\n1. move quickly on foot\n"
+    );
+  }
+
+  /// The original `String::replace` based renderer, kept to check the compiled templates
+  fn format_with_replace(
+    word: &str,
+    response: &DictionaryResponse,
+    config: &FormattingConfig,
+  ) -> String {
+    let mut markdown = config.word_format.replace("{word}", word) + "\n";
+
+    let pronunciation: Vec<String> = [
+      response
+        .ipa
+        .as_ref()
+        .map(|ipa| config.ipa_format.replace("{ipa}", ipa)),
+      response
+        .audio
+        .as_ref()
+        .map(|audio| config.audio_format.replace("{audio}", audio)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if !pronunciation.is_empty() {
+      markdown.push_str(&pronunciation.join(" "));
+      markdown.push('\n');
+    }
+
+    for meaning in &response.meanings {
+      if config.add_spacing {
+        markdown.push('\n');
+      }
+      markdown.push_str(
+        &config
+          .part_of_speech_format
+          .replace("{part}", &meaning.part_of_speech),
+      );
+      markdown.push('\n');
+      for (i, definition) in meaning.definitions.iter().enumerate() {
+        markdown.push_str(
+          &config
+            .definition_format
+            .replace("{num}", &(i + 1).to_string())
+            .replace("{definition}", &definition.definition),
+        );
+        markdown.push('\n');
+        if let Some(example) = &definition.example {
+          markdown.push_str(&config.example_format.replace("{example}", example));
+          markdown.push('\n');
+        }
+      }
+    }
+
+    markdown
+  }
+
+  fn rich_response() -> DictionaryResponse {
+    DictionaryResponse {
+      word: "run".to_string(),
+      meanings: (1..=2)
+        .map(|n| Meaning {
+          part_of_speech: format!("pos{}", n),
+          definitions: (1..=11)
+            .map(|i| Definition {
+              definition: format!("sense {} {{word}} 定义", i),
+              example: (i % 2 == 0).then(|| format!("example {}", i)),
+              more_examples: Vec::new(),
+              sub_definitions: Vec::new(),
+              label: None,
+            })
+            .collect(),
+          lang: None,
+          source: None,
+        })
+        .collect(),
+      ipa: Some("rʌn".to_string()),
+      audio: Some("https://example.com/run.mp3".to_string()),
+      variants: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn test_compiled_templates_match_replace() {
+    let configs = vec![
+      FormattingConfig::default(),
+      FormattingConfig {
+        word_format: "# {word} ({word})".to_string(),
+        part_of_speech_format: "**{part}**".to_string(),
+        definition_format: "{num}) {definition} [{num}]".to_string(),
+        example_format: "> {example}{example}".to_string(),
+        add_spacing: true,
+        ..Default::default()
+      },
+      FormattingConfig {
+        word_format: "no placeholders".to_string(),
+        ipa_format: "{ipa".to_string(),
+        audio_format: String::new(),
+        part_of_speech_format: "{{part}}".to_string(),
+        definition_format: "{definition}{num}".to_string(),
+        example_format: "例：{example}。".to_string(),
+        ..Default::default()
+      },
+    ];
+
+    for config in &configs {
+      for response in [
+        rich_response(),
+        response(None, Some("a.mp3")),
+        response(None, None),
+      ] {
+        assert_eq!(
+          format_definition_as_markdown_with_config("run", &response, config),
+          format_with_replace("run", &response, config)
+        );
+      }
+    }
+  }
+
+  fn response_with_definitions(definitions: &[&str]) -> DictionaryResponse {
+    DictionaryResponse {
+      word: "run".to_string(),
+      meanings: vec![Meaning {
+        part_of_speech: "verb".to_string(),
+        definitions: definitions
+          .iter()
+          .map(|definition| Definition {
+            definition: definition.to_string(),
+            example: None,
+            more_examples: Vec::new(),
+            sub_definitions: Vec::new(),
+            label: None,
+          })
+          .collect(),
+        lang: None,
+        source: None,
+      }],
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_lang_badge() {
+    let mut response = response(None, None);
+    let mut reverse = response.meanings[0].clone();
+    response.meanings[0].lang = Some("en-zh".to_string());
+    reverse.part_of_speech = "noun".to_string();
+    reverse.lang = Some("zh-en".to_string());
+    response.meanings.push(reverse);
+
+    assert_eq!(
+      format_definition_as_markdown("run", &response),
+      "**run**\n_verb_ `en-zh`\n1. move quickly on foot\n_noun_ `zh-en`\n1. move quickly on foot\n"
+    );
+
+    let config = FormattingConfig {
+      lang_format: "[{lang}]".to_string(),
+      ..Default::default()
+    };
+    assert!(
+      format_definition_as_markdown_with_config("run", &response, &config)
+        .starts_with("**run**\n_verb_ [en-zh]\n")
+    );
+  }
+
+  #[test]
+  fn test_source_attribution() {
+    let mut response = response(None, None);
+    response.meanings[0].lang = Some("en".to_string());
+    response.meanings[0].source = Some("WordNet".to_string());
+
+    assert!(format_definition_as_markdown("run", &response)
+      .starts_with("**run**\n_verb_ `en` [WordNet]\n1. move quickly on foot\n"));
+    assert!(format_definition_as_plaintext_with_config(
+      "run",
+      &response,
+      &FormattingConfig::default()
+    )
+    .contains("verb [en] (WordNet)\n"));
+
+    let config = FormattingConfig {
+      source_format: "— {source}".to_string(),
+      ..Default::default()
+    };
+    assert!(
+      format_definition_as_markdown_with_config("run", &response, &config)
+        .starts_with("**run**\n_verb_ `en` — WordNet\n")
+    );
+  }
+
+  #[test]
+  fn test_definition_truncation_boundaries() {
+    let config = FormattingConfig {
+      max_definition_chars: Some(4),
+      ..Default::default()
+    };
+    let format = |definition: &str| {
+      format_definition_as_markdown_with_config(
+        "run",
+        &response_with_definitions(&[definition]),
+        &config,
+      )
+    };
+
+    assert_eq!(format("abcd"), "**run**\n_verb_\n1. abcd\n");
+    assert_eq!(format("abcde"), "**run**\n_verb_\n1. abcd…\n");
+    // Multi-byte characters are counted as characters, never split
+    assert_eq!(format("跑步定义"), "**run**\n_verb_\n1. 跑步定义\n");
+    assert_eq!(format("跑步定义了"), "**run**\n_verb_\n1. 跑步定义…\n");
+  }
+
+  #[test]
+  fn test_definitions_per_pos_cap() {
+    let response = response_with_definitions(&["one", "two", "three"]);
+    let capped = FormattingConfig {
+      max_definitions_per_pos: Some(2),
+      ..Default::default()
+    };
+    assert_eq!(
+      format_definition_as_markdown_with_config("run", &response, &capped),
+      "**run**\n_verb_\n1. one\n2. two\n_… 1 more_\n"
+    );
+
+    let unlimited = FormattingConfig {
+      max_definitions_per_pos: Some(0),
+      max_definition_chars: Some(0),
+      ..Default::default()
+    };
+    assert_eq!(
+      format_definition_as_markdown_with_config("run", &response, &unlimited),
+      format_definition_as_markdown("run", &response)
+    );
+  }
+
+  #[test]
+  fn test_example_styles() {
+    let mut response = response(None, None);
+    response.meanings[0].definitions[0].example = Some("she runs daily".to_string());
+
+    assert_eq!(
+      format_definition_as_markdown("run", &response),
+      "**run**\n_verb_\n1. move quickly on foot\n   > Example: _she runs daily_\n"
+    );
+
+    let inline = FormattingConfig {
+      example_style: ExampleStyle::Inline,
+      ..Default::default()
+    };
+    assert_eq!(
+      format_definition_as_markdown_with_config("run", &response, &inline),
+      "**run**\n_verb_\n1. move quickly on foot — Example: _she runs daily_\n"
+    );
+  }
+
+  #[test]
+  fn test_examples_per_definition_cap() {
+    let mut response = response(None, None);
+    let definition = &mut response.meanings[0].definitions[0];
+    definition.example = Some("run home".to_string());
+    definition.more_examples = vec!["run fast".to_string()];
+    let format = |max_examples: Option<usize>, example_style: ExampleStyle| {
+      let config = FormattingConfig {
+        max_examples_per_definition: max_examples,
+        example_style,
+        ..Default::default()
+      };
+      format_definition_as_markdown_with_config("run", &response, &config)
+    };
+
+    let all = "**run**\n_verb_\n1. move quickly on foot\n   > Example: _run home_\n   > Example: _run fast_\n";
+    assert_eq!(format(None, ExampleStyle::Blockquote), all);
+    assert_eq!(format(Some(0), ExampleStyle::Blockquote), all);
+    assert_eq!(format(Some(2), ExampleStyle::Blockquote), all);
+    assert_eq!(
+      format(Some(1), ExampleStyle::Blockquote),
+      "**run**\n_verb_\n1. move quickly on foot\n   > Example: _run home_ …\n"
+    );
+    assert_eq!(
+      format(Some(1), ExampleStyle::Inline),
+      "**run**\n_verb_\n1. move quickly on foot — Example: _run home_ …\n"
+    );
+
+    let config = FormattingConfig {
+      max_examples_per_definition: Some(1),
+      ..Default::default()
+    };
+    assert_eq!(
+      format_definition_as_plaintext_with_config("run", &response, &config),
+      "run\nverb\n1. move quickly on foot\n   Example: run home …\n"
+    );
+  }
+
+  #[test]
+  fn test_sub_senses_are_indented() {
+    let sense = |text: &str, sub_definitions: Vec<Definition>| Definition {
+      definition: text.to_string(),
+      example: None,
+      more_examples: Vec::new(),
+      sub_definitions,
+      label: None,
+    };
+    let mut response = response(None, None);
+    response.meanings[0].definitions = vec![
+      sense(
+        "move quickly",
+        vec![
+          sense("on foot", Vec::new()),
+          Definition {
+            example: Some("the car runs well".to_string()),
+            ..sense("of a machine: operate", Vec::new())
+          },
+        ],
+      ),
+      sense("manage", Vec::new()),
+    ];
+
+    assert_eq!(
+      format_definition_as_markdown("run", &response),
+      "**run**\n_verb_\n1. move quickly\n   a. on foot\n   b. of a machine: operate\n      > Example: _the car runs well_\n2. manage\n"
+    );
+  }
+
+  #[test]
+  fn test_usage_labels() {
+    let mut response = response(None, None);
+    response.meanings[0].definitions[0].label = Some("informal".to_string());
+    response.meanings[0].definitions.push(Definition {
+      definition: "flee".to_string(),
+      example: None,
+      more_examples: Vec::new(),
+      sub_definitions: Vec::new(),
+      label: None,
+    });
+
+    assert_eq!(
+      format_definition_as_markdown("run", &response),
+      "**run**\n_verb_\n1. _(informal)_ move quickly on foot\n2. flee\n"
+    );
+    let config = FormattingConfig {
+      label_format: "[{label}]".to_string(),
+      ..FormattingConfig::default()
+    };
+    assert_eq!(
+      format_definition_as_markdown_with_config("run", &response, &config),
+      "**run**\n_verb_\n1. [informal] move quickly on foot\n2. flee\n"
+    );
+    assert_eq!(
+      format_definition_as_plaintext_with_config("run", &response, &config),
+      "run\nverb\n1. (informal) move quickly on foot\n2. flee\n"
+    );
+  }
+
+  #[test]
+  fn test_plaintext_format() {
+    let mut response = response(Some("rʌn"), Some("https://example.com/run.mp3"));
+    response.meanings[0].lang = Some("en".to_string());
+    response.meanings[0].definitions[0].example = Some("she runs daily".to_string());
+    response.meanings[0].definitions[0]
+      .sub_definitions
+      .push(Definition {
+        definition: "of a *horse*".to_string(),
+        example: None,
+        more_examples: Vec::new(),
+        sub_definitions: Vec::new(),
+        label: None,
+      });
+
+    assert_eq!(
+      format_definition_as_plaintext_with_config("run", &response, &FormattingConfig::default()),
+      "run /rʌn/\nverb [en]\n1. move quickly on foot\n   Example: she runs daily\n   a. of a *horse*\n"
+    );
+  }
+
+  #[test]
+  fn test_variants_follow_the_word() {
+    let mut response = response(None, None);
+    response.variants = vec!["colour".to_string(), "culler".to_string()];
+    assert_eq!(
+      format_definition_as_markdown("run", &response),
+      "**run** _also colour, culler_\n_verb_\n1. move quickly on foot\n"
+    );
+    assert!(format_definition_as_plaintext_with_config(
+      "run",
+      &response,
+      &FormattingConfig::default()
+    )
+    .starts_with("run (also colour, culler)\n"));
+  }
+
+  #[test]
+  fn test_cross_references() {
+    let response = response_with_definitions(&[
+      "a fast gait; see also {ref:jog} and {ref:sprint race}",
+      "to manage (cf. operate). See Also walk-",
+    ]);
+    let linked = FormattingConfig {
+      cross_references: true,
+      ..Default::default()
+    };
+    assert_eq!(
+      format_definition_as_markdown_with_config("run", &response, &linked),
+      "**run**\n_verb_\n1. a fast gait; see also [jog](dictionary:jog) and [sprint race](dictionary:sprint%20race)\n2. to manage (cf. [operate](dictionary:operate)). See Also [walk](dictionary:walk)-\n"
+    );
+
+    // Off: markers show as the word and phrases are left alone
+    assert_eq!(
+      format_definition_as_markdown("run", &response),
+      "**run**\n_verb_\n1. a fast gait; see also jog and sprint race\n2. to manage (cf. operate). See Also walk-\n"
+    );
+
+    let bold = FormattingConfig {
+      cross_references: true,
+      reference_format: "**{word}**".to_string(),
+      max_definition_chars: Some(17),
+      ..Default::default()
+    };
+    let response = response_with_definitions(&["unseen; cf. {ref:visible}", "cf. sight"]);
+    assert_eq!(
+      format_definition_as_markdown_with_config("run", &response, &bold),
+      "**run**\n_verb_\n1. unseen; cf. visib…\n2. cf. **sight**\n"
+    );
+    assert_eq!(
+      format_definition_as_plaintext_with_config("run", &response, &bold),
+      "run\nverb\n1. unseen; cf. visib…\n2. cf. sight\n"
+    );
+  }
+
+  #[test]
+  fn test_escape_markdown() {
+    let mut response = response_with_definitions(&["2*x*y", "snake_case_name", "the `ls` command"]);
+    response.meanings[0].definitions[0].example = Some("a*b".to_string());
+    let escaped = FormattingConfig {
+      escape_markdown: true,
+      ..Default::default()
+    };
+
+    assert_eq!(
+      format_definition_as_markdown_with_config("x_y", &response, &escaped),
+      "**x\\_y**\n_verb_\n1. 2\\*x\\*y\n   > Example: _a\\*b_\n2. snake\\_case\\_name\n3. the \\`ls\\` command\n"
+    );
+    // Off by default: values are inserted as they are
+    assert_eq!(
+      format_definition_as_markdown("x_y", &response),
+      "**x_y**\n_verb_\n1. 2*x*y\n   > Example: _a*b_\n2. snake_case_name\n3. the `ls` command\n"
+    );
+    // Plain text never needs escaping
+    assert!(
+      format_definition_as_plaintext_with_config("x_y", &response, &escaped).contains("1. 2*x*y\n")
+    );
+
+    // Truncation counts the characters before escaping; link targets are left alone
+    let linked = FormattingConfig {
+      escape_markdown: true,
+      cross_references: true,
+      max_definition_chars: Some(16),
+      ..Default::default()
+    };
+    let response = response_with_definitions(&["see also {ref:foo_bar}", "a_b_c_d_e_f_g_h_i"]);
+    assert_eq!(
+      format_definition_as_markdown_with_config("run", &response, &linked),
+      "**run**\n_verb_\n1. see also [foo\\_bar](dictionary:foo_bar)\n2. a\\_b\\_c\\_d\\_e\\_f\\_g\\_h\\_…\n"
+    );
+  }
+
+  #[test]
+  fn test_format_not_found() {
+    let mut config = FormattingConfig::default();
+    assert_eq!(format_not_found("zzyzx", &config), None);
+
+    config.not_found_format = Some("「{word}」は辞書にありません".to_string());
+    assert_eq!(
+      format_not_found("zzyzx", &config),
+      Some("「zzyzx」は辞書にありません".to_string())
+    );
+  }
+
+  #[test]
+  fn test_changed_template_is_recompiled() {
+    let mut config = FormattingConfig::default();
+    let response = response(None, None);
+    format_definition_as_markdown_with_config("run", &response, &config);
+
+    config.word_format = "## {word}".to_string();
+    assert_eq!(
+      format_definition_as_markdown_with_config("run", &response, &config),
+      format_with_replace("run", &response, &config)
+    );
+  }
+
+  #[test]
+  fn test_short_definition_uses_templates() {
+    let mut config = FormattingConfig::default();
+    let response = response(None, None);
+    assert_eq!(
+      format_short_definition(&response, &config, true).unwrap(),
+      "- **run** _verb_: move quickly on foot"
+    );
+
+    config.word_format = "`{word}`".to_string();
+    config.part_of_speech_format = "({part})".to_string();
+    assert_eq!(
+      format_short_definition(&response, &config, true).unwrap(),
+      "- `run` (verb): move quickly on foot"
+    );
+    assert_eq!(
+      format_short_definition(&response, &config, false).unwrap(),
+      "- run (verb): move quickly on foot"
+    );
+  }
+}
@@ -1,3 +1,4 @@
+use crate::config::FuzzyConfig;
 use dashmap::DashMap;
 use fxhash::FxHasher;
 use lru::LruCache;
@@ -9,18 +10,62 @@ use std::hash::Hasher;
 use std::num::NonZero;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use tokio::task;
 
-fn create_cache_key(prefix: &str, include_distance_2: bool) -> u64 {
+fn create_cache_key(
+  prefix: &str,
+  include_distance_2: bool,
+  extra_letters: &[char],
+  max_candidates: usize,
+  edit_first_half: bool,
+) -> u64 {
   let mut hasher = FxHasher::default();
   hasher.write(prefix.as_bytes());
   hasher.write_u8(include_distance_2 as u8);
+  hasher.write_u8(edit_first_half as u8);
+  hasher.write_usize(max_candidates);
+  for c in extra_letters {
+    hasher.write_u32(*c as u32);
+  }
   hasher.finish()
 }
 
+/// Letters inserted and substituted when generating candidates: a–z followed by the
+/// lowercased `extra_letters` (e.g. "éèêçñü"), skipping duplicates
+fn alphabet(extra_letters: &str) -> Vec<char> {
+  let mut alphabet: Vec<char> = ('a'..='z').collect();
+  for c in extra_letters.chars().flat_map(char::to_lowercase) {
+    if c.is_alphabetic() && !alphabet.contains(&c) {
+      alphabet.push(c);
+    }
+  }
+  alphabet
+}
+
+/// First position substituted or deleted in a word of `len` letters.
+/// Without `edit_first_half` only the second half is edited, never the first letter.
+fn modify_start(len: usize, edit_first_half: bool) -> usize {
+  if edit_first_half {
+    0
+  } else {
+    (len / 2).max(1)
+  }
+}
+
+/// Bumped whenever the dictionary changes; cached candidates from older generations are stale
+static CACHE_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Marks every cached candidate list as stale, e.g. after the dictionary was reloaded.
+/// Stale entries are dropped when next looked up rather than all at once.
+pub fn invalidate_candidate_cache() {
+  CACHE_GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
 struct CacheEntry {
   value: Vec<String>,
   access_count: AtomicUsize,
+  generation: usize,
 }
 
 impl CacheEntry {
@@ -28,6 +73,7 @@ impl CacheEntry {
     Self {
       value,
       access_count: AtomicUsize::new(1),
+      generation: CACHE_GENERATION.load(Ordering::Relaxed),
     }
   }
 
@@ -42,29 +88,66 @@ thread_local! {
   static BUFFER_POOL: RefCell<Vec<Vec<u8>>> = RefCell::new(Vec::with_capacity(32));
 }
 
-static HOT_CACHE: Lazy<Mutex<LruCache<u64, Vec<String>>>> =
+/// Candidates tagged with the cache generation they were generated in
+type GenerationCandidates = (usize, Vec<String>);
+
+static HOT_CACHE: Lazy<Mutex<LruCache<u64, GenerationCandidates>>> =
   Lazy::new(|| Mutex::new(LruCache::new(NonZero::new(100).unwrap())));
 
 pub struct FuzzyMatcher;
 
 impl FuzzyMatcher {
   pub async fn generate_candidates(prefix: String, include_distance_2: bool) -> Vec<String> {
-    let cache_key = create_cache_key(&prefix, include_distance_2);
+    Self::generate_candidates_with_config(prefix, include_distance_2, &FuzzyConfig::default()).await
+  }
 
+  /// Like `generate_candidates`, using the configured extra letters, candidate cap and
+  /// time budget. Once the budget is spent the candidates gathered so far are returned.
+  #[tracing::instrument(level = "debug", skip(config))]
+  pub async fn generate_candidates_with_config(
+    prefix: String,
+    include_distance_2: bool,
+    config: &FuzzyConfig,
+  ) -> Vec<String> {
+    let deadline = config
+      .time_budget_ms
+      .map(|budget| Instant::now() + Duration::from_millis(budget));
+    let out_of_time = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    let mut timed_out = false;
+    // Two edits turn most short words into unrelated ones
+    let include_distance_2 =
+      include_distance_2 && prefix.chars().count() >= config.distance_2_min_length;
+
+    let alphabet = alphabet(&config.extra_letters);
+    // The byte-based fast paths only know a–z
+    let ascii_only = alphabet.len() == 26;
+    let cache_key = create_cache_key(
+      &prefix,
+      include_distance_2,
+      &alphabet[26..],
+      config.max_candidates,
+      config.edit_first_half,
+    );
+
+    let generation = CACHE_GENERATION.load(Ordering::Relaxed);
     {
       let mut hot_cache = HOT_CACHE.lock().unwrap();
-      if let Some(result) = hot_cache.get(&cache_key) {
-        return result.clone();
+      if let Some((cached_generation, result)) = hot_cache.get(&cache_key) {
+        if *cached_generation == generation {
+          return result.clone();
+        }
+        hot_cache.pop(&cache_key);
       }
     }
 
+    CANDIDATE_CACHE.remove_if(&cache_key, |_, entry| entry.generation != generation);
     if let Some(entry) = CANDIDATE_CACHE.get(&cache_key) {
       entry.increment_access();
 
       // if entry.access_count.load(Ordering::Relaxed) > 5 {
       //   let mut hot_cache = HOT_CACHE.lock().unwrap();
       //   if !hot_cache.contains(&cache_key) {
-      //     hot_cache.put(cache_key, entry.value.clone());
+      //     hot_cache.put(cache_key, (entry.generation, entry.value.clone()));
       //   }
     }
 
@@ -72,7 +155,7 @@ impl FuzzyMatcher {
     // }
 
     if prefix.is_empty() {
-      return ('a'..='z').map(|c| c.to_string()).collect();
+      return alphabet.iter().map(|c| c.to_string()).collect();
     }
 
     if prefix.len() > 20 {
@@ -96,30 +179,42 @@ impl FuzzyMatcher {
     let mut result_set = HashSet::with_capacity(capacity);
     result_set.insert(prefix.clone());
 
-    if prefix.is_ascii() {
+    if ascii_only && prefix.is_ascii() {
       Self::generate_prefix_completions_ascii(&prefix, &mut result_set);
     } else {
-      Self::generate_prefix_completions_unicode(&prefix, &mut result_set);
+      Self::generate_prefix_completions_unicode(&prefix, &alphabet, &mut result_set);
     }
 
     task::consume_budget().await;
 
-    if prefix.is_ascii() {
+    if out_of_time() {
+      timed_out = true;
+    } else if ascii_only && prefix.is_ascii() {
       let prefix_clone = prefix.clone();
-      let edit1_results =
-        task::spawn_blocking(move || Self::generate_distance_1_ascii_parallel(&prefix_clone))
-          .await
-          .unwrap_or_default();
+      let edit_first_half = config.edit_first_half;
+      let edit1_results = task::spawn_blocking(move || {
+        Self::generate_distance_1_ascii_parallel(&prefix_clone, edit_first_half)
+      })
+      .await
+      .unwrap_or_default();
 
       result_set.extend(edit1_results);
     } else {
-      Self::generate_distance_1_unicode_modified(&prefix, &mut result_set);
+      Self::generate_distance_1_unicode_modified(
+        &prefix,
+        &alphabet,
+        config.edit_first_half,
+        &mut result_set,
+      );
     }
 
     task::consume_budget().await;
 
-    // if include_distance_2 && prefix.len() <= 8 {
-    if include_distance_2 {
+    if !timed_out && out_of_time() {
+      timed_out = true;
+    }
+
+    if include_distance_2 && !timed_out {
       let first_char_str = prefix.chars().next().unwrap_or('a').to_string();
 
       let base_words: Vec<String> = result_set
@@ -133,16 +228,17 @@ impl FuzzyMatcher {
         .chunks(5)
         .map(|chunk| {
           let chunk_vec = chunk.to_vec();
+          let alphabet = alphabet.clone();
           task::spawn_blocking(move || {
             chunk_vec
               .par_iter()
               // .with_min_len(if chunk_vec[0].len() > 4 { 2 } else { 1 })
               .flat_map(|base_word| {
                 let mut local_set = HashSet::new();
-                if base_word.is_ascii() {
+                if ascii_only && base_word.is_ascii() {
                   Self::generate_prefix_completions_ascii(base_word, &mut local_set);
                 } else {
-                  Self::generate_prefix_completions_unicode(base_word, &mut local_set);
+                  Self::generate_prefix_completions_unicode(base_word, &alphabet, &mut local_set);
                 }
                 local_set.into_iter().collect::<Vec<_>>()
               })
@@ -156,6 +252,10 @@ impl FuzzyMatcher {
           result_set.extend(candidates);
         }
         task::consume_budget().await;
+        if out_of_time() {
+          timed_out = true;
+          break;
+        }
       }
     }
 
@@ -173,9 +273,10 @@ impl FuzzyMatcher {
       }
     });
 
-    let result: Vec<String> = result.into_iter().take(1000).collect();
+    let result: Vec<String> = result.into_iter().take(config.max_candidates).collect();
 
-    if !result.is_empty() && prefix.len() > 1 {
+    // Cut-short results would be wrong for a later call with time to spare
+    if !result.is_empty() && prefix.len() > 1 && !timed_out {
       if prefix.len() <= 5 || result.len() < 500 {
         CANDIDATE_CACHE.insert(cache_key, CacheEntry::new(result.clone()));
       }
@@ -208,13 +309,18 @@ impl FuzzyMatcher {
     });
   }
 
-  fn generate_prefix_completions_unicode(prefix: &str, result_set: &mut HashSet<String>) {
-    let capacity = prefix.len() + 1;
-    let candidates: Vec<String> = (b'a'..=b'z')
-      .map(|c| {
+  fn generate_prefix_completions_unicode(
+    prefix: &str,
+    alphabet: &[char],
+    result_set: &mut HashSet<String>,
+  ) {
+    let capacity = prefix.len() + 4;
+    let candidates: Vec<String> = alphabet
+      .iter()
+      .map(|&c| {
         let mut new_word = String::with_capacity(capacity);
         new_word.push_str(prefix);
-        new_word.push(c as char);
+        new_word.push(c);
         new_word
       })
       .collect();
@@ -222,9 +328,10 @@ impl FuzzyMatcher {
     result_set.extend(candidates);
   }
 
-  fn generate_distance_1_ascii_parallel(prefix: &str) -> HashSet<String> {
+  fn generate_distance_1_ascii_parallel(prefix: &str, edit_first_half: bool) -> HashSet<String> {
     let bytes = prefix.as_bytes();
     let word_len = bytes.len();
+    let modify_start = modify_start(word_len, edit_first_half);
 
     let chunk_size = match word_len {
       0..=3 => 1,
@@ -233,8 +340,9 @@ impl FuzzyMatcher {
     };
 
     let insertions = Self::generate_insertions_ascii_parallel(prefix, chunk_size);
-    let substitutions = Self::generate_substitutions_ascii_parallel(prefix, chunk_size);
-    let deletions = Self::generate_deletions_ascii_parallel(prefix, chunk_size);
+    let substitutions =
+      Self::generate_substitutions_ascii_parallel(prefix, chunk_size, modify_start);
+    let deletions = Self::generate_deletions_ascii_parallel(prefix, chunk_size, modify_start);
 
     let mut result_set =
       HashSet::with_capacity(insertions.len() + substitutions.len() + deletions.len());
@@ -282,10 +390,13 @@ impl FuzzyMatcher {
       .collect()
   }
 
-  fn generate_substitutions_ascii_parallel(prefix: &str, chunk_size: usize) -> HashSet<String> {
+  fn generate_substitutions_ascii_parallel(
+    prefix: &str,
+    chunk_size: usize,
+    modify_start: usize,
+  ) -> HashSet<String> {
     let bytes = prefix.as_bytes();
     let word_len = bytes.len();
-    let modify_start = (word_len / 2).max(1);
 
     (modify_start..word_len)
       .into_par_iter()
@@ -318,10 +429,13 @@ impl FuzzyMatcher {
       .collect()
   }
 
-  fn generate_deletions_ascii_parallel(prefix: &str, chunk_size: usize) -> HashSet<String> {
+  fn generate_deletions_ascii_parallel(
+    prefix: &str,
+    chunk_size: usize,
+    modify_start: usize,
+  ) -> HashSet<String> {
     let bytes = prefix.as_bytes();
     let word_len = bytes.len();
-    let modify_start = (word_len / 2).max(1);
 
     (modify_start..word_len)
       .into_par_iter()
@@ -347,22 +461,26 @@ impl FuzzyMatcher {
       .collect()
   }
 
-  fn generate_distance_1_unicode_modified(prefix: &str, result_set: &mut HashSet<String>) {
+  fn generate_distance_1_unicode_modified(
+    prefix: &str,
+    alphabet: &[char],
+    edit_first_half: bool,
+    result_set: &mut HashSet<String>,
+  ) {
     let chars: Vec<char> = prefix.chars().collect();
     let char_len = chars.len();
-    let modify_start = (char_len / 2).max(1);
+    let modify_start = modify_start(char_len, edit_first_half);
 
-    let alphabet: Vec<char> = (b'a'..=b'z').map(|c| c as char).collect();
-
-    let expected_new_items =
-      (char_len + 1) * 26 + (char_len - modify_start) * 25 + (char_len - modify_start);
+    let expected_new_items = (char_len + 1) * alphabet.len()
+      + (char_len - modify_start) * (alphabet.len() - 1)
+      + (char_len - modify_start);
 
     if result_set.capacity() < result_set.len() + expected_new_items {
       result_set.reserve(expected_new_items);
     }
 
     for i in 0..=char_len {
-      for &c in &alphabet {
+      for &c in alphabet {
         let mut new_word = String::with_capacity(char_len + 1);
         new_word.extend(chars[..i].iter());
         new_word.push(c);
@@ -373,7 +491,7 @@ impl FuzzyMatcher {
 
     for i in modify_start..char_len {
       let original = chars[i];
-      for &c in &alphabet {
+      for &c in alphabet {
         if c != original {
           let mut new_word = String::with_capacity(char_len);
           new_word.extend(chars[..i].iter());
@@ -393,15 +511,226 @@ impl FuzzyMatcher {
   }
 }
 
+/// Levenshtein distance between two words: the number of inserted, deleted or substituted
+/// letters that turn one into the other
+pub fn levenshtein_distance(a: &str, b: &str) -> usize {
+  let b: Vec<char> = b.chars().collect();
+  let mut previous: Vec<usize> = (0..=b.len()).collect();
+  for (i, a_char) in a.chars().enumerate() {
+    let mut current = vec![i + 1; b.len() + 1];
+    for (j, b_char) in b.iter().enumerate() {
+      let cost = usize::from(a_char != *b_char);
+      current[j + 1] = (previous[j] + cost)
+        .min(previous[j + 1] + 1)
+        .min(current[j] + 1);
+    }
+    previous = current;
+  }
+  previous[b.len()]
+}
+
+/// Jaro-Winkler similarity of two words, from 0 (nothing in common) to 1 (equal).
+/// Letters match within half the longer word of each other, and a common prefix of up to
+/// four letters raises the score.
+pub fn jaro_winkler(a: &str, b: &str) -> f64 {
+  let a: Vec<char> = a.chars().collect();
+  let b: Vec<char> = b.chars().collect();
+  if a.is_empty() || b.is_empty() {
+    return if a == b { 1.0 } else { 0.0 };
+  }
+
+  let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+  let mut a_matched = vec![false; a.len()];
+  let mut b_matched = vec![false; b.len()];
+  let mut matches = 0;
+  for (i, a_char) in a.iter().enumerate() {
+    let end = (i + window + 1).min(b.len());
+    for j in i.saturating_sub(window)..end {
+      if !b_matched[j] && b[j] == *a_char {
+        a_matched[i] = true;
+        b_matched[j] = true;
+        matches += 1;
+        break;
+      }
+    }
+  }
+  if matches == 0 {
+    return 0.0;
+  }
+
+  // Matched letters that appear in a different order, counted in pairs
+  let a_order = a.iter().zip(&a_matched).filter(|(_, m)| **m);
+  let b_order = b.iter().zip(&b_matched).filter(|(_, m)| **m);
+  let transpositions = a_order
+    .zip(b_order)
+    .filter(|((x, _), (y, _))| x != y)
+    .count()
+    / 2;
+
+  let m = matches as f64;
+  let jaro = (m / a.len() as f64 + m / b.len() as f64 + (m - transpositions as f64) / m) / 3.0;
+  let prefix = a.iter().zip(&b).take(4).take_while(|(x, y)| x == y).count();
+  jaro + prefix as f64 * 0.1 * (1.0 - jaro)
+}
+
 /// Function for generate levenshtein candidates
 /// ## Parameters
 /// - `prefix`: &str - Prefix to generate candidates
 /// - `include_distance_2`: bool - Include distance 2 candidates
+/// - `config`: &FuzzyConfig - Extra letters, candidate cap and time budget
 /// ## Returns
 /// - Vec<String> - Vector of candidates
 pub async fn generate_levenshtein_candidates(
   prefix: &str,
   include_distance_2: bool,
+  config: &FuzzyConfig,
 ) -> Vec<String> {
-  FuzzyMatcher::generate_candidates(prefix.to_string(), include_distance_2).await
+  FuzzyMatcher::generate_candidates_with_config(prefix.to_string(), include_distance_2, config)
+    .await
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_jaro_winkler() {
+    let close = |a: &str, b: &str, expected: f64| {
+      let score = jaro_winkler(a, b);
+      assert!((score - expected).abs() < 0.001, "{} {}: {}", a, b, score);
+    };
+    close("martha", "marhta", 0.961);
+    close("dwayne", "duane", 0.84);
+    close("dixon", "dicksonx", 0.813);
+    close("run", "run", 1.0);
+    close("abc", "xyz", 0.0);
+    close("", "", 1.0);
+    // Swapped letters score above a wrong one, unlike with the edit distance
+    assert!(jaro_winkler("form", "from") > jaro_winkler("form", "foam"));
+  }
+
+  #[test]
+  fn test_levenshtein_distance() {
+    assert_eq!(levenshtein_distance("kitten", "sitting"), 3);
+    assert_eq!(levenshtein_distance("form", "from"), 2);
+    assert_eq!(levenshtein_distance("", "run"), 3);
+    assert_eq!(levenshtein_distance("run", "run"), 0);
+    // Counted in characters, not bytes
+    assert_eq!(levenshtein_distance("café", "cafe"), 1);
+  }
+
+  fn with_letters(extra_letters: &str) -> FuzzyConfig {
+    FuzzyConfig {
+      extra_letters: extra_letters.to_string(),
+      ..Default::default()
+    }
+  }
+
+  #[tokio::test]
+  async fn test_extended_alphabet_adds_accented_candidates() {
+    let plain = generate_levenshtein_candidates("cafe", false, &with_letters("")).await;
+    assert!(!plain.iter().any(|word| word.contains('é')));
+
+    let french = generate_levenshtein_candidates("caf", false, &with_letters("éÈç")).await;
+    // Prefix completion, insertion and substitution all use the extra letters
+    assert!(french.contains(&"café".to_string()));
+    assert!(french.contains(&"cafè".to_string()));
+    assert!(french.contains(&"caçf".to_string()));
+    assert!(french.contains(&"caf".to_string()));
+
+    let spanish = generate_levenshtein_candidates("nino", false, &with_letters("ñ")).await;
+    assert!(spanish.contains(&"niño".to_string()));
+  }
+
+  #[tokio::test]
+  async fn test_generation_respects_time_budget() {
+    let unlimited = generate_levenshtein_candidates("budgetx", true, &FuzzyConfig::default()).await;
+
+    let expired = FuzzyConfig {
+      time_budget_ms: Some(0),
+      ..Default::default()
+    };
+    let started = Instant::now();
+    let cut_short = generate_levenshtein_candidates("budgetx", true, &expired).await;
+    // Only the prefix and its one-letter completions are built before the first check
+    assert_eq!(cut_short.len(), 27);
+    assert_eq!(cut_short[0], "budgetx");
+    assert!(cut_short.len() < unlimited.len());
+    assert!(started.elapsed() < Duration::from_secs(1));
+  }
+
+  #[tokio::test]
+  async fn test_short_prefixes_skip_distance_2() {
+    let config = FuzzyConfig::default();
+    let distance_1 = generate_levenshtein_candidates("cat", false, &config).await;
+    let distance_2 = generate_levenshtein_candidates("cat", true, &config).await;
+    assert_eq!(
+      distance_1.into_iter().collect::<HashSet<_>>(),
+      distance_2.into_iter().collect::<HashSet<_>>()
+    );
+
+    let distance_1 = generate_levenshtein_candidates("marker", false, &config).await;
+    let distance_2 = generate_levenshtein_candidates("marker", true, &config).await;
+    assert!(distance_2.len() > distance_1.len());
+  }
+
+  #[tokio::test]
+  async fn test_candidate_cap_is_configurable() {
+    let capped = FuzzyConfig {
+      max_candidates: 10,
+      ..Default::default()
+    };
+    let candidates = generate_levenshtein_candidates("capped", true, &capped).await;
+    assert_eq!(candidates.len(), 10);
+    assert_eq!(candidates[0], "capped");
+  }
+
+  #[tokio::test]
+  async fn test_corrects_first_half_typos() {
+    for extra_letters in ["", "é"] {
+      let whole_word = with_letters(extra_letters);
+      let candidates = generate_levenshtein_candidates("wprld", false, &whole_word).await;
+      // Substituting the second letter, deleting the first
+      assert!(candidates.contains(&"world".to_string()));
+      assert!(
+        generate_levenshtein_candidates("xworld", false, &whole_word)
+          .await
+          .contains(&"world".to_string())
+      );
+
+      let second_half = FuzzyConfig {
+        edit_first_half: false,
+        ..with_letters(extra_letters)
+      };
+      let candidates = generate_levenshtein_candidates("wprld", false, &second_half).await;
+      assert!(!candidates.contains(&"world".to_string()));
+      assert!(candidates.contains(&"wprd".to_string()));
+    }
+  }
+
+  #[tokio::test]
+  async fn test_invalidation_drops_cached_candidates() {
+    let config = FuzzyConfig::default();
+    let key = create_cache_key("rlq", false, &[], config.max_candidates, true);
+    generate_levenshtein_candidates("rlq", false, &config).await;
+    let cached_generation = CANDIDATE_CACHE.get(&key).unwrap().generation;
+
+    invalidate_candidate_cache();
+    assert!(CACHE_GENERATION.load(Ordering::Relaxed) > cached_generation);
+
+    // The stale entry is replaced on the next lookup
+    generate_levenshtein_candidates("rlq", false, &config).await;
+    assert_eq!(
+      CANDIDATE_CACHE.get(&key).unwrap().generation,
+      CACHE_GENERATION.load(Ordering::Relaxed)
+    );
+  }
+
+  #[test]
+  fn test_alphabet_skips_duplicates() {
+    assert_eq!(alphabet("").len(), 26);
+    assert_eq!(alphabet("aÜü1").len(), 27);
+    assert_eq!(alphabet("aÜü1")[26], 'ü');
+  }
 }
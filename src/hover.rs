@@ -1,16 +1,35 @@
 use crate::config::Config;
-use crate::dictionary_data::{create_dictionary_provider, DictionaryProvider};
+use crate::dictionary_data::{self, create_dictionary_provider, DictionaryProvider};
 use crate::formatting;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tower_lsp::jsonrpc::Result;
-use tower_lsp::lsp_types::{Hover, HoverContents, HoverParams, MarkupContent, MarkupKind, Url};
+use tower_lsp::lsp_types::{
+  Hover, HoverContents, HoverParams, MarkupContent, MarkupKind, Range, TextDocumentIdentifier, Url,
+};
+
+/// Parameters of the `dictionary/hoverRange` request, which looks up the selected text
+/// instead of the word at the cursor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HoverRangeParams {
+  pub text_document: TextDocumentIdentifier,
+  /// An empty range looks up the word at its start, like a regular hover
+  pub range: Range,
+}
 
 pub struct HoverHandler {
   document_map: Arc<Mutex<HashMap<Url, String>>>,
   pub dictionary_provider: Box<dyn DictionaryProvider>,
+  freq_path: String,
   config: Config,
+  /// Words left out of hovers over several selected words
+  stopwords: HashSet<String>,
+  /// Whether the client renders markdown, learned from its capabilities on `initialize`
+  markdown_supported: AtomicBool,
 }
 
 impl HoverHandler {
@@ -22,8 +41,40 @@ impl HoverHandler {
   ) -> Self {
     Self {
       document_map,
-      dictionary_provider: create_dictionary_provider(Some(dictionary_path), Some(freq_path)),
+      dictionary_provider: create_dictionary_provider(
+        Some(dictionary_path),
+        Some(freq_path.clone()),
+        &config,
+      ),
+      freq_path,
+      stopwords: config.stopwords.load(),
       config,
+      markdown_supported: AtomicBool::new(true),
+    }
+  }
+
+  /// Records the hover formats the client accepts (`HoverClientCapabilities::content_format`).
+  /// Markdown is used unless the client lists formats without it.
+  pub fn set_content_format(&self, formats: Option<&[MarkupKind]>) {
+    let markdown_supported = formats.is_none_or(|formats| formats.contains(&MarkupKind::Markdown));
+    self
+      .markdown_supported
+      .store(markdown_supported, Ordering::Relaxed);
+  }
+
+  fn content_format(&self) -> MarkupKind {
+    if self.markdown_supported.load(Ordering::Relaxed) {
+      MarkupKind::Markdown
+    } else {
+      MarkupKind::PlainText
+    }
+  }
+
+  /// Open document content, falling back to the file on disk
+  async fn document_content(&self, uri: &Url) -> Option<String> {
+    match self.document_map.lock().await.get(uri) {
+      Some(content) => Some(content.clone()),
+      None => std::fs::read_to_string(uri.path()).ok(),
     }
   }
 
@@ -33,61 +84,343 @@ impl HoverHandler {
     let position = params.text_document_position_params.position;
     let document_uri = params.text_document_position_params.text_document.uri;
 
-    let content = match self.document_map.lock().await.get(&document_uri) {
-      Some(content) => content.clone(),
-      None => match std::fs::read_to_string(document_uri.path()) {
-        Ok(content) => content,
-        Err(_) => return Ok(None),
-      },
+    let content = match self.document_content(&document_uri).await {
+      Some(content) => content,
+      None => return Ok(None),
     };
 
     // Extract the word at position and look up its meaning
-    if let Some(word) = self
-      .dictionary_provider
-      .get_word_at_position(&content, position)
+    match dictionary_data::lookup_word_at_position(
+      self.dictionary_provider.as_ref(),
+      &content,
+      position,
+      &self.config.lookup,
+    )
+    .await
     {
-      match self.dictionary_provider.get_meaning(&word).await {
-        Ok(Some(response)) => {
-          // Format the response as Markdown
-          // eprintln!("{}", &word);
-          let markdown = formatting::format_definition_as_markdown_with_config(
-            &response.word,
-            &response,
-            &self.config.formatting,
-          );
-
-          let contents = HoverContents::Markup(MarkupContent {
-            kind: MarkupKind::Markdown,
-            value: markdown,
-          });
-          return Ok(Some(Hover {
-            contents,
-            range: None,
-          }));
-        }
-        Ok(None) => {
-          let contents = HoverContents::Markup(MarkupContent {
-            kind: MarkupKind::Markdown,
-            value: format!("No definition found for **{}**", word),
-          });
-          return Ok(Some(Hover {
-            contents,
-            range: None,
-          }));
-        }
-        Err(_) => {
-          let contents = HoverContents::Markup(MarkupContent {
-            kind: MarkupKind::Markdown,
-            value: format!("Error looking up definition for **{}**", word),
-          });
-          return Ok(Some(Hover {
-            contents,
-            range: None,
-          }));
-        }
+      Some(word) => Ok(Some(self.hover_word(&word).await)),
+      None => Ok(None),
+    }
+  }
+
+  /// Looks up the selected text, e.g. a phrase wrapped across lines
+  pub async fn on_hover_range(&self, params: HoverRangeParams) -> Result<Option<Hover>> {
+    let content = match self.document_content(&params.text_document.uri).await {
+      Some(content) => content,
+      None => return Ok(None),
+    };
+
+    let word = if params.range.start == params.range.end {
+      dictionary_data::lookup_word_at_position(
+        self.dictionary_provider.as_ref(),
+        &content,
+        params.range.start,
+        &self.config.lookup,
+      )
+      .await
+    } else {
+      dictionary_data::extract_selected_text(&content, params.range)
+    };
+    if let Some(selected) = &word {
+      if self.config.hover.define_each_selected_word && selected.contains(' ') {
+        return Ok(self.hover_each_word(selected).await);
       }
     }
+    match word {
+      Some(word) => Ok(Some(self.hover_word(&word).await)),
+      None => Ok(None),
+    }
+  }
+
+  /// Lists a short definition of each word of `selected` that the dictionary has, unless the
+  /// whole selection is an entry (e.g. "ice cream")
+  async fn hover_each_word(&self, selected: &str) -> Option<Hover> {
+    // A fuzzy or compound match of the whole selection is some other entry
+    let lookup = &self.config.lookup;
+    if let Ok(Some(response)) = self.dictionary_provider.get_meaning(selected).await {
+      if lookup.fold_case(&response.word) == lookup.fold_case(selected) {
+        return Some(self.hover_word(selected).await);
+      }
+    }
+
+    let kind = self.content_format();
+    let mut seen = HashSet::new();
+    let tokens: Vec<String> = dictionary_data::tokenize_document(selected)
+      .into_iter()
+      .filter(|token| !self.stopwords.contains(&token.normalized))
+      .filter(|token| seen.insert(token.normalized.clone()))
+      .map(|token| token.text)
+      .collect();
+    let words: Vec<&str> = tokens.iter().map(String::as_str).collect();
+    let meanings = self
+      .dictionary_provider
+      .get_meanings(&words)
+      .await
+      .unwrap_or_default();
+    let lines: Vec<String> = meanings
+      .iter()
+      .filter_map(|(_, response)| response.as_ref())
+      .filter_map(|response| {
+        formatting::format_short_definition(
+          response,
+          &self.config.formatting,
+          kind == MarkupKind::Markdown,
+        )
+      })
+      .collect();
+
+    (!lines.is_empty()).then(|| Hover {
+      contents: HoverContents::Markup(MarkupContent {
+        kind,
+        value: lines.join("\n") + "\n",
+      }),
+      range: None,
+    })
+  }
+
+  /// Renders the definition of `word` in the client's preferred format
+  async fn hover_word(&self, word: &str) -> Hover {
+    let kind = self.content_format();
+    let plaintext = kind == MarkupKind::PlainText;
+    let value = match self.dictionary_provider.get_meaning(word).await {
+      Ok(Some(response)) if plaintext => formatting::format_definition_as_plaintext_with_config(
+        &response.word,
+        &response,
+        &self.config.formatting,
+      ),
+      // Format the response as Markdown
+      Ok(Some(response)) => formatting::format_definition_as_markdown_with_config(
+        &response.word,
+        &response,
+        &self.config.formatting,
+      ),
+      // Completion offers words of the frequency database that the dictionary lacks
+      Ok(None) if dictionary_data::is_in_frequency_list(&self.freq_path, word).await => {
+        formatting::format_undefined(word, &self.config.formatting).unwrap_or_else(|| {
+          match plaintext {
+            true => format!(
+              "{} is a known word, but the dictionary has no definition",
+              word
+            ),
+            false => format!(
+              "**{}** is a known word, but the dictionary has no definition",
+              word
+            ),
+          }
+        })
+      }
+      Ok(None) => {
+        formatting::format_not_found(word, &self.config.formatting).unwrap_or_else(|| {
+          match plaintext {
+            true => format!("No definition found for {}", word),
+            false => format!("No definition found for **{}**", word),
+          }
+        })
+      }
+      Err(_) if plaintext => format!("Error looking up definition for {}", word),
+      Err(_) => format!("Error looking up definition for **{}**", word),
+    };
+
+    Hover {
+      contents: HoverContents::Markup(MarkupContent { kind, value }),
+      range: None,
+    }
+  }
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tower_lsp::lsp_types::{
+    Position, TextDocumentIdentifier, TextDocumentPositionParams, WorkDoneProgressParams,
+  };
+
+  fn handler_for(dir: &std::path::Path, dictionary: &str, document: &str) -> (HoverHandler, Url) {
+    handler_with(dir, dictionary, document, Config::default())
+  }
+
+  fn handler_with(
+    dir: &std::path::Path,
+    dictionary: &str,
+    document: &str,
+    config: Config,
+  ) -> (HoverHandler, Url) {
+    let dictionary_path = dir.join("dictionary.json");
+    std::fs::write(&dictionary_path, dictionary).unwrap();
+    let uri = Url::parse("file:///test.txt").unwrap();
+    let document_map = Arc::new(Mutex::new(HashMap::from([(
+      uri.clone(),
+      document.to_string(),
+    )])));
+    let handler = HoverHandler::new(
+      document_map,
+      dictionary_path.to_string_lossy().into_owned(),
+      dir.join("freq.db").to_string_lossy().into_owned(),
+      config,
+    );
+    (handler, uri)
+  }
+
+  async fn hover_with(formats: Option<&[MarkupKind]>) -> MarkupContent {
+    let dir = tempfile::tempdir().unwrap();
+    let (handler, uri) = handler_for(
+      dir.path(),
+      r#"{"run": {"verb": ["move quickly on foot"]}}"#,
+      "run",
+    );
+    handler.set_content_format(formats);
+
+    let hover = handler
+      .on_hover(HoverParams {
+        text_document_position_params: TextDocumentPositionParams {
+          text_document: TextDocumentIdentifier { uri },
+          position: Position {
+            line: 0,
+            character: 1,
+          },
+        },
+        work_done_progress_params: WorkDoneProgressParams::default(),
+      })
+      .await
+      .unwrap()
+      .unwrap();
+    match hover.contents {
+      HoverContents::Markup(content) => content,
+      other => panic!("Expected markup hover, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_hover_follows_client_content_format() {
+    for formats in [
+      None,
+      Some(&[MarkupKind::PlainText, MarkupKind::Markdown][..]),
+    ] {
+      let content = hover_with(formats).await;
+      assert_eq!(content.kind, MarkupKind::Markdown);
+      assert_eq!(content.value, "**run**\n_verb_\n1. move quickly on foot\n");
+    }
+
+    let content = hover_with(Some(&[MarkupKind::PlainText])).await;
+    assert_eq!(content.kind, MarkupKind::PlainText);
+    assert_eq!(content.value, "run\nverb\n1. move quickly on foot\n");
+  }
+
+  #[tokio::test]
+  async fn test_frequency_only_word_has_its_own_message() {
+    let dir = tempfile::tempdir().unwrap();
+    let conn = rusqlite::Connection::open(dir.path().join("freq.db")).unwrap();
+    conn
+      .execute_batch(
+        "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER);
+         INSERT INTO word_frequencies VALUES ('run', 90), ('runnel', 5);",
+      )
+      .unwrap();
+    let (handler, _) = handler_for(
+      dir.path(),
+      r#"{"run": {"verb": ["move quickly on foot"]}}"#,
+      "",
+    );
+    let value = |hover: Hover| match hover.contents {
+      HoverContents::Markup(content) => content.value,
+      other => panic!("Expected markup hover, got {:?}", other),
+    };
+
+    assert_eq!(
+      value(handler.hover_word("Runnel").await),
+      "**Runnel** is a known word, but the dictionary has no definition"
+    );
+    assert_eq!(
+      value(handler.hover_word("zzyzx").await),
+      "No definition found for **zzyzx**"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_selection_looks_up_phrase() {
+    let dir = tempfile::tempdir().unwrap();
+    let (handler, uri) = handler_for(
+      dir.path(),
+      r#"{"ice": {"noun": ["frozen water"]}, "ice cream": {"noun": ["a frozen dessert"]}}"#,
+      "I love (ice\n   cream), really",
+    );
+    let hover_range = |start: (u32, u32), end: (u32, u32)| {
+      handler.on_hover_range(HoverRangeParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        range: Range {
+          start: Position::new(start.0, start.1),
+          end: Position::new(end.0, end.1),
+        },
+      })
+    };
+    let value = |hover: Option<Hover>| match hover.unwrap().contents {
+      HoverContents::Markup(content) => content.value,
+      other => panic!("Expected markup hover, got {:?}", other),
+    };
+
+    // The selection spans the line wrap and includes the parentheses
+    let phrase = hover_range((0, 7), (1, 9)).await.unwrap();
+    assert_eq!(
+      value(phrase),
+      "**ice cream**\n_noun_\n1. a frozen dessert\n"
+    );
+
+    // An empty selection falls back to the word at the cursor
+    let word = hover_range((0, 9), (0, 9)).await.unwrap();
+    assert_eq!(value(word), "**ice**\n_noun_\n1. frozen water\n");
+
+    assert_eq!(hover_range((0, 6), (0, 8)).await.unwrap(), None);
+  }
+
+  #[tokio::test]
+  async fn test_selection_defines_each_word() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = Config::default();
+    config.hover.define_each_selected_word = true;
+    config.stopwords.words = vec!["the".to_string()];
+    let (handler, uri) = handler_with(
+      dir.path(),
+      r#"{
+        "the": {"article": ["a definite article"]},
+        "quick": {"adjective": ["moving fast", "bright"]},
+        "fox": {"noun": ["a wild canine"]},
+        "ice": {"noun": ["frozen water"]},
+        "ice cream": {"noun": ["a frozen dessert"]}
+      }"#,
+      "The quick fox, the fox.\nice cream\nice crem",
+      config,
+    );
+    let hover_range = |start: (u32, u32), end: (u32, u32)| {
+      handler.on_hover_range(HoverRangeParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        range: Range {
+          start: Position::new(start.0, start.1),
+          end: Position::new(end.0, end.1),
+        },
+      })
+    };
+    let value = |hover: Option<Hover>| match hover.unwrap().contents {
+      HoverContents::Markup(content) => content.value,
+      other => panic!("Expected markup hover, got {:?}", other),
+    };
+
+    // Stopwords and repeated words are left out
+    let words = hover_range((0, 0), (0, 22)).await.unwrap();
+    assert_eq!(
+      value(words),
+      "- **quick** _adjective_: moving fast\n- **fox** _noun_: a wild canine\n"
+    );
+
+    // A selected entry is still shown whole
+    let phrase = hover_range((1, 0), (1, 9)).await.unwrap();
+    assert_eq!(
+      value(phrase),
+      "**ice cream**\n_noun_\n1. a frozen dessert\n"
+    );
 
-    Ok(None)
+    // A near miss of an entry is defined word by word
+    let words = hover_range((2, 0), (2, 8)).await.unwrap();
+    assert_eq!(value(words), "- **ice** _noun_: frozen water\n");
   }
 }
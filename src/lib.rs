@@ -1,9 +1,19 @@
 pub mod completion;
 pub mod config;
+pub mod convert;
 pub mod dictionary_data;
 pub mod dictionary_lsp;
+pub mod document_highlight;
+pub mod engine;
 pub mod formatting;
 pub mod fuzzy;
 pub mod hover;
+pub mod mdx;
+pub mod personal_dictionary;
+pub mod references;
+pub mod selftest;
 pub mod signature_help;
+pub mod stemming;
 pub mod tire;
+pub mod word_list;
+pub mod workspace_symbol;
@@ -1,13 +1,40 @@
 pub mod completion;
 pub mod config;
+pub mod convert;
 pub mod dictionary_data;
 pub mod dictionary_lsp;
+pub mod document_highlight;
+pub mod engine;
 pub mod formatting;
 pub mod fuzzy;
 pub mod hover;
+pub mod mdx;
+pub mod personal_dictionary;
+pub mod references;
+pub mod selftest;
 pub mod signature_help;
+pub mod stemming;
 pub mod tire;
+pub mod word_list;
+pub mod workspace_symbol;
 
 fn main() {
-  dictionary_lsp::run_server();
+  let args: Vec<String> = std::env::args().skip(1).collect();
+
+  let result = match args.first().map(String::as_str) {
+    Some("convert") => convert::run_convert(&args[1..]),
+    Some("export-json") => convert::run_export_json(&args[1..]),
+    Some("--selftest") => selftest::run_command().map_err(Into::into),
+    Some("--print-config") => config::run_print_config(),
+    Some("--write-default-config") => config::run_write_default_config(),
+    _ => match dictionary_lsp::Transport::from_args(&args) {
+      Ok(transport) => dictionary_lsp::run_server(transport).map_err(Into::into),
+      Err(usage) => Err(usage.into()),
+    },
+  };
+
+  if let Err(e) = result {
+    eprintln!("{}", e);
+    std::process::exit(1);
+  }
 }
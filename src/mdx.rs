@@ -0,0 +1,878 @@
+//! Read-only support for MDict (`.mdx`) dictionaries.
+//!
+//! Only format version 2.0 files with unencrypted key block info are supported. Blocks may
+//! be stored raw, LZO-compressed (LZO1X, as written by MDict) or zlib-compressed.
+use crate::config::Config;
+use crate::dictionary_data::{
+  extract_word_at_position_with, normalize_word, Definition, DictionaryError, DictionaryProvider,
+  DictionaryResponse, Meaning,
+};
+use async_trait::async_trait;
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+use std::sync::Arc;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::Position;
+
+pub type MdxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Records starting with this marker redirect to another headword
+const LINK_MARKER: &str = "@@@LINK=";
+
+/// A headword and the span of its record in the decompressed record data
+#[derive(Debug, Clone, PartialEq)]
+struct MdxKey {
+  word: String,
+  start: u64,
+  end: u64,
+}
+
+/// Location of one record block in the file and in the decompressed record data
+#[derive(Debug, Clone)]
+struct RecordBlock {
+  file_offset: usize,
+  compressed_size: usize,
+  decompressed_offset: u64,
+  decompressed_size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextEncoding {
+  Utf8,
+  Utf16,
+}
+
+/// A parsed `.mdx` file. Keys are held in memory; records are decompressed on lookup.
+pub struct MdxDictionary {
+  data: Vec<u8>,
+  encoding: TextEncoding,
+  /// Sorted by lowercased headword so prefix search can binary search
+  keys: Vec<MdxKey>,
+  record_blocks: Vec<RecordBlock>,
+}
+
+/// Big-endian cursor over the file contents
+struct Reader<'a> {
+  data: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn new(data: &'a [u8], pos: usize) -> Self {
+    Self { data, pos }
+  }
+
+  fn bytes(&mut self, len: usize) -> MdxResult<&'a [u8]> {
+    let end = self
+      .pos
+      .checked_add(len)
+      .filter(|end| *end <= self.data.len())
+      .ok_or("unexpected end of MDX data")?;
+    let bytes = &self.data[self.pos..end];
+    self.pos = end;
+    Ok(bytes)
+  }
+
+  fn u16(&mut self) -> MdxResult<u16> {
+    Ok(u16::from_be_bytes(self.bytes(2)?.try_into()?))
+  }
+
+  fn u32(&mut self) -> MdxResult<u32> {
+    Ok(u32::from_be_bytes(self.bytes(4)?.try_into()?))
+  }
+
+  fn u64(&mut self) -> MdxResult<u64> {
+    Ok(u64::from_be_bytes(self.bytes(8)?.try_into()?))
+  }
+
+  fn usize(&mut self) -> MdxResult<usize> {
+    Ok(usize::try_from(self.u64()?)?)
+  }
+}
+
+impl MdxDictionary {
+  pub fn open(path: &str) -> MdxResult<Self> {
+    Self::parse(std::fs::read(path)?)
+  }
+
+  pub fn parse(data: Vec<u8>) -> MdxResult<Self> {
+    let mut reader = Reader::new(&data, 0);
+
+    // Header: length, UTF-16LE XML attributes, adler32 checksum
+    let header_len = reader.u32()? as usize;
+    let header = decode_utf16le(reader.bytes(header_len)?);
+    reader.bytes(4)?;
+
+    let version = header_attribute(&header, "GeneratedByEngineVersion")
+      .and_then(|version| version.parse::<f32>().ok())
+      .unwrap_or(0.0);
+    if version < 2.0 {
+      return Err(format!("unsupported MDX version {}", version).into());
+    }
+    let encrypted = header_attribute(&header, "Encrypted")
+      .and_then(|flags| flags.parse::<u32>().ok())
+      .unwrap_or(0);
+    if encrypted & 0b11 != 0 {
+      return Err("encrypted MDX dictionaries are not supported".into());
+    }
+    let encoding = match header_attribute(&header, "Encoding")
+      .unwrap_or_default()
+      .to_uppercase()
+      .as_str()
+    {
+      "" | "UTF-8" | "UTF8" => TextEncoding::Utf8,
+      "UTF-16" | "UTF16" => TextEncoding::Utf16,
+      other => return Err(format!("unsupported MDX encoding {}", other).into()),
+    };
+
+    // Keyword section header, followed by its checksum
+    let key_block_count = reader.usize()?;
+    let _entry_count = reader.u64()?;
+    let _key_info_decompressed_size = reader.u64()?;
+    let key_info_size = reader.usize()?;
+    let key_blocks_size = reader.usize()?;
+    reader.bytes(4)?;
+
+    let key_info = decompress_block(reader.bytes(key_info_size)?)?;
+    let key_block_sizes = parse_key_block_info(&key_info, key_block_count, encoding)?;
+
+    let mut file_order_keys = Vec::new();
+    let key_blocks_end = reader.pos + key_blocks_size;
+    for compressed_size in key_block_sizes {
+      let block = decompress_block(reader.bytes(compressed_size)?)?;
+      parse_key_block(&block, encoding, &mut file_order_keys)?;
+    }
+    reader.pos = key_blocks_end;
+
+    // Record section
+    let record_block_count = reader.usize()?;
+    let _record_count = reader.u64()?;
+    let _record_info_size = reader.u64()?;
+    let _record_blocks_size = reader.u64()?;
+
+    let mut record_sizes = Vec::with_capacity(record_block_count);
+    for _ in 0..record_block_count {
+      record_sizes.push((reader.usize()?, reader.u64()?));
+    }
+
+    let mut record_blocks = Vec::with_capacity(record_block_count);
+    let mut decompressed_offset = 0;
+    for (compressed_size, decompressed_size) in record_sizes {
+      record_blocks.push(RecordBlock {
+        file_offset: reader.pos,
+        compressed_size,
+        decompressed_offset,
+        decompressed_size,
+      });
+      // Records are only decompressed on lookup, so unreadable blocks are refused up front
+      if let Some(&compression) = reader.bytes(compressed_size)?.first().filter(|&&c| c > 2) {
+        return Err(format!("unknown MDX compression type {}", compression).into());
+      }
+      decompressed_offset += decompressed_size;
+    }
+
+    // A record ends where the next one (in file order) starts
+    let mut keys: Vec<MdxKey> = file_order_keys
+      .iter()
+      .enumerate()
+      .map(|(i, (word, start))| MdxKey {
+        word: normalize_word(word),
+        start: *start,
+        end: file_order_keys
+          .get(i + 1)
+          .map(|(_, next)| *next)
+          .unwrap_or(decompressed_offset),
+      })
+      .collect();
+    keys.sort_by_cached_key(|key| key.word.to_lowercase());
+
+    Ok(Self {
+      data,
+      encoding,
+      keys,
+      record_blocks,
+    })
+  }
+
+  pub fn len(&self) -> usize {
+    self.keys.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.keys.is_empty()
+  }
+
+  /// Returns the record text of `word`, following `@@@LINK=` redirects once
+  pub fn lookup(&self, word: &str, case_sensitive: bool) -> MdxResult<Option<String>> {
+    let record = match self.find_key(word, case_sensitive) {
+      Some(key) => self.read_record(key)?,
+      None => return Ok(None),
+    };
+
+    if let Some(target) = record.trim().strip_prefix(LINK_MARKER) {
+      return match self.find_key(target.trim(), case_sensitive) {
+        Some(key) => Ok(Some(self.read_record(key)?)),
+        None => Ok(None),
+      };
+    }
+
+    Ok(Some(record))
+  }
+
+  /// Headwords starting with `prefix`, in index order
+  pub fn words_with_prefix(&self, prefix: &str, case_sensitive: bool, limit: usize) -> Vec<String> {
+    let folded = prefix.to_lowercase();
+    let start = self
+      .keys
+      .partition_point(|key| key.word.to_lowercase() < folded);
+
+    self.keys[start..]
+      .iter()
+      .take_while(|key| key.word.to_lowercase().starts_with(&folded))
+      .filter(|key| !case_sensitive || key.word.starts_with(prefix))
+      .map(|key| key.word.clone())
+      .take(limit)
+      .collect()
+  }
+
+  fn find_key(&self, word: &str, case_sensitive: bool) -> Option<&MdxKey> {
+    let folded = word.to_lowercase();
+    let start = self
+      .keys
+      .partition_point(|key| key.word.to_lowercase() < folded);
+
+    let mut candidates = self.keys[start..]
+      .iter()
+      .take_while(|key| key.word.to_lowercase() == folded);
+
+    if case_sensitive {
+      candidates.find(|key| key.word == word)
+    } else {
+      // Prefer the spelling that matches exactly, e.g. "polish" over "Polish"
+      let candidates: Vec<&MdxKey> = candidates.collect();
+      candidates
+        .iter()
+        .find(|key| key.word == word)
+        .or_else(|| candidates.first())
+        .copied()
+    }
+  }
+
+  fn read_record(&self, key: &MdxKey) -> MdxResult<String> {
+    let block = self
+      .record_blocks
+      .iter()
+      .find(|block| {
+        key.start >= block.decompressed_offset
+          && key.start < block.decompressed_offset + block.decompressed_size
+      })
+      .ok_or("MDX record offset outside of record blocks")?;
+
+    let compressed = &self.data[block.file_offset..block.file_offset + block.compressed_size];
+    let decompressed = decompress_block(compressed)?;
+
+    let start = (key.start - block.decompressed_offset) as usize;
+    let end = ((key.end - block.decompressed_offset) as usize).min(decompressed.len());
+    let record = decompressed
+      .get(start..end)
+      .ok_or("invalid MDX record span")?;
+
+    Ok(
+      decode_text(record, self.encoding)
+        .trim_end_matches('\0')
+        .to_string(),
+    )
+  }
+}
+
+/// Unpacks a block prefixed with its compression type and checksum
+fn decompress_block(block: &[u8]) -> MdxResult<Vec<u8>> {
+  if block.len() < 8 {
+    return Err("truncated MDX block".into());
+  }
+  let payload = &block[8..];
+
+  match block[0] {
+    0 => Ok(payload.to_vec()),
+    1 => lzo1x_decompress(payload),
+    2 => {
+      let mut decompressed = Vec::new();
+      ZlibDecoder::new(payload).read_to_end(&mut decompressed)?;
+      Ok(decompressed)
+    }
+    other => Err(format!("unknown MDX compression type {}", other).into()),
+  }
+}
+
+/// Copies the next `count` bytes of `input` to `out`
+fn lzo_literals(input: &[u8], ip: &mut usize, count: usize, out: &mut Vec<u8>) -> MdxResult<()> {
+  let literals = input.get(*ip..*ip + count).ok_or("truncated LZO block")?;
+  out.extend_from_slice(literals);
+  *ip += count;
+  Ok(())
+}
+
+/// Appends `length` bytes starting `distance` bytes back; the ranges may overlap
+fn lzo_match(out: &mut Vec<u8>, distance: usize, length: usize) -> MdxResult<()> {
+  if distance == 0 || distance > out.len() {
+    return Err("invalid LZO match distance".into());
+  }
+  for _ in 0..length {
+    out.push(out[out.len() - distance]);
+  }
+  Ok(())
+}
+
+/// Decompresses an LZO1X stream, the format of minilzo's `lzo1x_1_compress`
+fn lzo1x_decompress(input: &[u8]) -> MdxResult<Vec<u8>> {
+  let mut out = Vec::with_capacity(input.len() * 3);
+  let mut ip = 0;
+  let next = |ip: &mut usize| -> MdxResult<usize> {
+    let byte = *input.get(*ip).ok_or("truncated LZO block")?;
+    *ip += 1;
+    Ok(byte as usize)
+  };
+  // Runs longer than an instruction's bits hold continue in zero bytes worth 255 each
+  let length = |ip: &mut usize, bits: usize, base: usize| -> MdxResult<usize> {
+    if bits != 0 {
+      return Ok(bits);
+    }
+    let mut length = base;
+    while next(ip)? == 0 {
+      length += 255;
+    }
+    Ok(length + input[*ip - 1] as usize)
+  };
+
+  // Literals copied by the previous instruction: 0, 1 to 3 after a match, 4 after a run
+  let mut state = 0;
+  if input.first().is_some_and(|&first| first > 17) {
+    let count = next(&mut ip)? - 17;
+    lzo_literals(input, &mut ip, count, &mut out)?;
+    state = count.min(4);
+  }
+
+  loop {
+    let instruction = next(&mut ip)?;
+    let trailing = if instruction >= 64 {
+      let distance = ((instruction >> 2) & 7) + (next(&mut ip)? << 3) + 1;
+      lzo_match(&mut out, distance, (instruction >> 5) + 1)?;
+      instruction & 3
+    } else if instruction >= 32 {
+      let count = length(&mut ip, instruction & 31, 31)? + 2;
+      let word = next(&mut ip)? | (next(&mut ip)? << 8);
+      lzo_match(&mut out, (word >> 2) + 1, count)?;
+      word & 3
+    } else if instruction >= 16 {
+      let count = length(&mut ip, instruction & 7, 7)? + 2;
+      let word = next(&mut ip)? | (next(&mut ip)? << 8);
+      let distance = ((instruction & 8) << 11) + (word >> 2);
+      // A zero distance marks the end of the stream
+      if distance == 0 {
+        return Ok(out);
+      }
+      lzo_match(&mut out, distance + 0x4000, count)?;
+      word & 3
+    } else if state == 0 {
+      let count = length(&mut ip, instruction, 15)? + 3;
+      lzo_literals(input, &mut ip, count, &mut out)?;
+      state = 4;
+      continue;
+    } else {
+      // Short matches reach further back right after a literal run
+      let (base, count) = if state == 4 { (2049, 3) } else { (1, 2) };
+      let distance = (instruction >> 2) + (next(&mut ip)? << 2) + base;
+      lzo_match(&mut out, distance, count)?;
+      instruction & 3
+    };
+
+    lzo_literals(input, &mut ip, trailing, &mut out)?;
+    state = trailing;
+  }
+}
+
+/// Returns the compressed size of every key block
+fn parse_key_block_info(
+  info: &[u8],
+  block_count: usize,
+  encoding: TextEncoding,
+) -> MdxResult<Vec<usize>> {
+  let unit = match encoding {
+    TextEncoding::Utf8 => 1,
+    TextEncoding::Utf16 => 2,
+  };
+  let mut reader = Reader::new(info, 0);
+  let mut sizes = Vec::with_capacity(block_count);
+
+  for _ in 0..block_count {
+    reader.u64()?; // entries in the block
+                   // First and last headwords, each followed by a terminator
+    let first_len = reader.u16()? as usize;
+    reader.bytes((first_len + 1) * unit)?;
+    let last_len = reader.u16()? as usize;
+    reader.bytes((last_len + 1) * unit)?;
+
+    sizes.push(reader.usize()?);
+    reader.u64()?; // decompressed size
+  }
+
+  Ok(sizes)
+}
+
+/// Appends the `(headword, record offset)` entries of a decompressed key block
+fn parse_key_block(
+  block: &[u8],
+  encoding: TextEncoding,
+  keys: &mut Vec<(String, u64)>,
+) -> MdxResult<()> {
+  let mut reader = Reader::new(block, 0);
+
+  while reader.pos < block.len() {
+    let offset = reader.u64()?;
+    let rest = &block[reader.pos..];
+    let text_len = match encoding {
+      TextEncoding::Utf8 => rest.iter().position(|b| *b == 0),
+      TextEncoding::Utf16 => rest
+        .chunks_exact(2)
+        .position(|pair| pair == [0, 0])
+        .map(|units| units * 2),
+    }
+    .ok_or("unterminated MDX headword")?;
+
+    let text = reader.bytes(text_len)?;
+    reader.bytes(match encoding {
+      TextEncoding::Utf8 => 1,
+      TextEncoding::Utf16 => 2,
+    })?;
+    keys.push((decode_text(text, encoding), offset));
+  }
+
+  Ok(())
+}
+
+fn decode_text(bytes: &[u8], encoding: TextEncoding) -> String {
+  match encoding {
+    TextEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+    TextEncoding::Utf16 => decode_utf16le(bytes),
+  }
+}
+
+fn decode_utf16le(bytes: &[u8]) -> String {
+  let units: Vec<u16> = bytes
+    .chunks_exact(2)
+    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+    .collect();
+  String::from_utf16_lossy(&units)
+}
+
+/// Reads `name="value"` from the header's XML tag
+fn header_attribute(header: &str, name: &str) -> Option<String> {
+  let pattern = format!("{}=\"", name);
+  let start = header.find(&pattern)? + pattern.len();
+  let end = header[start..].find('"')? + start;
+  Some(header[start..end].to_string())
+}
+
+/// Converts the HTML of an MDX record into lightweight markdown, one paragraph per line
+pub fn html_to_markdown(html: &str) -> String {
+  let mut markdown = String::with_capacity(html.len());
+  let mut rest = html;
+
+  while let Some(tag_start) = rest.find('<') {
+    markdown.push_str(&rest[..tag_start]);
+    let tag_end = match rest[tag_start..].find('>') {
+      Some(end) => tag_start + end,
+      None => {
+        rest = &rest[tag_start..];
+        break;
+      }
+    };
+
+    let tag = rest[tag_start + 1..tag_end].trim();
+    let name = tag
+      .trim_start_matches('/')
+      .split(|c: char| c.is_whitespace() || c == '/')
+      .next()
+      .unwrap_or_default()
+      .to_lowercase();
+    match name.as_str() {
+      "br" | "p" | "div" | "li" | "tr" => markdown.push('\n'),
+      "b" | "strong" => markdown.push_str("**"),
+      "i" | "em" => markdown.push('_'),
+      _ => {}
+    }
+
+    rest = &rest[tag_end + 1..];
+  }
+  markdown.push_str(rest);
+
+  decode_entities(&markdown)
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+fn decode_entities(text: &str) -> String {
+  text
+    .replace("&nbsp;", " ")
+    .replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&quot;", "\"")
+    .replace("&#39;", "'")
+    .replace("&amp;", "&")
+}
+
+/// Provider implementation for MDict (`.mdx`) dictionaries
+pub struct MdxDictionaryProvider {
+  dictionary_path: Option<String>,
+  dictionary: tokio::sync::Mutex<Option<Arc<MdxDictionary>>>,
+  config: Config,
+}
+
+impl MdxDictionaryProvider {
+  /// The file is parsed on first lookup rather than here.
+  pub fn new(dictionary_path: Option<String>) -> Self {
+    Self {
+      dictionary_path,
+      dictionary: tokio::sync::Mutex::new(None),
+      config: Config::get(),
+    }
+  }
+
+  /// Overrides the configuration snapshot taken at construction time
+  pub fn with_config(mut self, config: Config) -> Self {
+    self.config = config;
+    self
+  }
+
+  async fn load_dictionary(&self) -> Result<Arc<MdxDictionary>> {
+    let mut cache = self.dictionary.lock().await;
+    if let Some(dictionary) = &*cache {
+      return Ok(dictionary.clone());
+    }
+
+    let path = self
+      .dictionary_path
+      .clone()
+      .ok_or_else(|| DictionaryError::NotConfigured("Dictionary path not provided".to_string()))?;
+    let dictionary = tokio::task::spawn_blocking(move || {
+      let data = std::fs::read(&path).map_err(|e| {
+        DictionaryError::IoError(format!("Error reading MDX dictionary {}: {}", path, e))
+      })?;
+      MdxDictionary::parse(data).map_err(|e| {
+        DictionaryError::ParseError(format!("Error parsing MDX dictionary {}: {}", path, e))
+      })
+    })
+    .await
+    .map_err(|e| DictionaryError::QueryError(format!("MDX loading failed: {}", e)))??;
+
+    let dictionary = Arc::new(dictionary);
+    *cache = Some(dictionary.clone());
+    Ok(dictionary)
+  }
+}
+
+#[async_trait]
+impl DictionaryProvider for MdxDictionaryProvider {
+  async fn get_meaning(&self, word: &str) -> Result<Option<DictionaryResponse>> {
+    let dictionary = self.load_dictionary().await?;
+    let word = normalize_word(word);
+
+    let record = dictionary
+      .lookup(&word, self.config.lookup.case_sensitive)
+      .map_err(|e| {
+        DictionaryError::ParseError(format!("Error reading MDX record for {}: {}", word, e))
+      })?;
+
+    let definitions: Vec<Definition> = match record {
+      Some(record) => html_to_markdown(&record)
+        .lines()
+        .map(|line| Definition {
+          definition: line.to_string(),
+          example: None,
+          more_examples: Vec::new(),
+          sub_definitions: Vec::new(),
+          label: None,
+        })
+        .collect(),
+      None => return Ok(None),
+    };
+
+    if definitions.is_empty() {
+      return Ok(None);
+    }
+
+    Ok(Some(DictionaryResponse {
+      word,
+      meanings: vec![Meaning {
+        part_of_speech: "unknown".to_string(),
+        definitions,
+        lang: None,
+        source: None,
+      }],
+      ..Default::default()
+    }))
+  }
+
+  fn get_word_at_position(&self, content: &str, position: Position) -> Option<String> {
+    extract_word_at_position_with(content, position, &self.config.lookup)
+  }
+
+  async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>> {
+    if prefix.is_empty() {
+      return Ok(None);
+    }
+
+    let dictionary = self.load_dictionary().await?;
+    let words = dictionary.words_with_prefix(
+      &normalize_word(prefix),
+      self.config.lookup.case_sensitive,
+      self.config.completion.item_limit() + 1,
+    );
+
+    if words.is_empty() {
+      Ok(None)
+    } else {
+      Ok(Some(words))
+    }
+  }
+
+  async fn warm_up(&self) -> Result<()> {
+    self.load_dictionary().await.map(|_| ())
+  }
+
+  async fn clear_cache(&self) {
+    *self.dictionary.lock().await = None;
+  }
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use flate2::write::ZlibEncoder;
+  use flate2::Compression;
+  use std::io::Write;
+
+  fn zlib_block(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    // Type 2 (zlib) and a checksum this reader does not verify
+    let mut block = vec![2, 0, 0, 0, 0, 0, 0, 0];
+    block.extend(encoder.finish().unwrap());
+    block
+  }
+
+  fn raw_block(data: &[u8]) -> Vec<u8> {
+    let mut block = vec![0; 8];
+    block.extend_from_slice(data);
+    block
+  }
+
+  /// Stores `data` as literals in an LZO1X stream, which every decompressor accepts
+  fn lzo_block(data: &[u8]) -> Vec<u8> {
+    assert!(
+      data.len() <= 238,
+      "one leading literal run holds at most 238 bytes"
+    );
+    let mut block = vec![1, 0, 0, 0, 0, 0, 0, 0, 17 + data.len() as u8];
+    block.extend_from_slice(data);
+    // End of stream
+    block.extend([0x11, 0, 0]);
+    block
+  }
+
+  /// Builds a version 2.0 UTF-8 MDX file with one key block and one record block
+  fn build_mdx(entries: &[(&str, &str)]) -> Vec<u8> {
+    build_mdx_with(entries, raw_block)
+  }
+
+  /// Like `build_mdx`, storing the record block with `record_block`
+  fn build_mdx_with(entries: &[(&str, &str)], record_block: fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+    let mut records = Vec::new();
+    let mut key_block = Vec::new();
+    for (word, record) in entries {
+      key_block.extend((records.len() as u64).to_be_bytes());
+      key_block.extend(word.as_bytes());
+      key_block.push(0);
+      records.extend(record.as_bytes());
+      records.push(0);
+    }
+    let key_block = zlib_block(&key_block);
+    let record_block = record_block(&records);
+
+    let (first, last) = (entries[0].0, entries[entries.len() - 1].0);
+    let mut key_info = Vec::new();
+    key_info.extend((entries.len() as u64).to_be_bytes());
+    key_info.extend((first.len() as u16).to_be_bytes());
+    key_info.extend(first.as_bytes());
+    key_info.push(0);
+    key_info.extend((last.len() as u16).to_be_bytes());
+    key_info.extend(last.as_bytes());
+    key_info.push(0);
+    key_info.extend((key_block.len() as u64).to_be_bytes());
+    key_info.extend(0u64.to_be_bytes());
+    let key_info_len = key_info.len();
+    let key_info = zlib_block(&key_info);
+
+    let header = r#"<Dictionary GeneratedByEngineVersion="2.0" RequiredEngineVersion="2.0" Encrypted="0" Encoding="UTF-8" Format="Html" Title="Test"/>"#;
+    let header: Vec<u8> = header.encode_utf16().flat_map(u16::to_le_bytes).collect();
+
+    let mut mdx = Vec::new();
+    mdx.extend((header.len() as u32).to_be_bytes());
+    mdx.extend(&header);
+    mdx.extend([0; 4]);
+
+    mdx.extend(1u64.to_be_bytes());
+    mdx.extend((entries.len() as u64).to_be_bytes());
+    mdx.extend((key_info_len as u64).to_be_bytes());
+    mdx.extend((key_info.len() as u64).to_be_bytes());
+    mdx.extend((key_block.len() as u64).to_be_bytes());
+    mdx.extend([0; 4]);
+    mdx.extend(&key_info);
+    mdx.extend(&key_block);
+
+    mdx.extend(1u64.to_be_bytes());
+    mdx.extend((entries.len() as u64).to_be_bytes());
+    mdx.extend(16u64.to_be_bytes());
+    mdx.extend((record_block.len() as u64).to_be_bytes());
+    mdx.extend((record_block.len() as u64).to_be_bytes());
+    mdx.extend((records.len() as u64).to_be_bytes());
+    mdx.extend(&record_block);
+    mdx
+  }
+
+  const ENTRIES: &[(&str, &str)] = &[
+    ("apple", "<b>apple</b><br>a round fruit &amp; its tree"),
+    ("banana", "<div>a long <i>yellow</i> fruit</div>"),
+    ("bananas", "@@@LINK=banana"),
+    ("band", "<p>a group of musicians</p>"),
+  ];
+
+  fn write_mdx(dir: &std::path::Path) -> String {
+    let path = dir.join("test.mdx");
+    std::fs::write(&path, build_mdx(ENTRIES)).unwrap();
+    path.to_string_lossy().into_owned()
+  }
+
+  #[test]
+  fn test_parse_and_lookup() {
+    let dictionary = MdxDictionary::parse(build_mdx(ENTRIES)).unwrap();
+    assert_eq!(dictionary.len(), 4);
+    assert_eq!(
+      dictionary.lookup("Apple", false).unwrap().as_deref(),
+      Some("<b>apple</b><br>a round fruit &amp; its tree")
+    );
+    assert_eq!(dictionary.lookup("Apple", true).unwrap(), None);
+    assert_eq!(
+      dictionary.lookup("bananas", false).unwrap().as_deref(),
+      Some("<div>a long <i>yellow</i> fruit</div>")
+    );
+    assert_eq!(dictionary.lookup("cherry", false).unwrap(), None);
+  }
+
+  #[test]
+  fn test_words_with_prefix() {
+    let dictionary = MdxDictionary::parse(build_mdx(ENTRIES)).unwrap();
+    assert_eq!(
+      dictionary.words_with_prefix("ban", false, 10),
+      vec!["banana", "bananas", "band"]
+    );
+    assert_eq!(
+      dictionary.words_with_prefix("ban", false, 1),
+      vec!["banana"]
+    );
+    assert!(dictionary.words_with_prefix("c", false, 10).is_empty());
+  }
+
+  #[test]
+  fn test_html_to_markdown() {
+    assert_eq!(
+      html_to_markdown("<b>apple</b><br>a round fruit &amp; its tree"),
+      "**apple**\na round fruit & its tree"
+    );
+    assert_eq!(
+      html_to_markdown(r#"<div class="x">a <i>yellow</i> fruit</div>"#),
+      "a _yellow_ fruit"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_provider_get_meaning_and_prefix() {
+    let dir = tempfile::tempdir().unwrap();
+    let provider = MdxDictionaryProvider::new(Some(write_mdx(dir.path())));
+
+    let response = provider.get_meaning("apple").await.unwrap().unwrap();
+    assert_eq!(response.word, "apple");
+    let definitions: Vec<&str> = response.meanings[0]
+      .definitions
+      .iter()
+      .map(|definition| definition.definition.as_str())
+      .collect();
+    assert_eq!(definitions, vec!["**apple**", "a round fruit & its tree"]);
+
+    assert!(provider.get_meaning("cherry").await.unwrap().is_none());
+    assert_eq!(
+      provider.find_words_by_prefix("band").await.unwrap(),
+      Some(vec!["band".to_string()])
+    );
+  }
+
+  #[test]
+  fn test_rejects_unsupported_files() {
+    assert!(MdxDictionary::parse(vec![0, 0]).is_err());
+
+    let mut unknown = build_mdx(ENTRIES);
+    // Mark the record block with a compression type that doesn't exist
+    let record_block_type =
+      unknown.len() - ENTRIES.iter().map(|(_, r)| r.len() + 1).sum::<usize>() - 8;
+    unknown[record_block_type] = 3;
+    let error = MdxDictionary::parse(unknown).err().unwrap();
+    assert!(error.to_string().contains("compression type 3"));
+  }
+
+  #[test]
+  fn test_lzo_record_block() {
+    let dictionary = MdxDictionary::parse(build_mdx_with(ENTRIES, lzo_block)).unwrap();
+    assert_eq!(
+      dictionary.lookup("bananas", false).unwrap().as_deref(),
+      Some("<div>a long <i>yellow</i> fruit</div>")
+    );
+  }
+
+  #[test]
+  fn test_lzo1x_decompress() {
+    // "abc", then a match of 6 bytes 3 back that overlaps its own output
+    assert_eq!(
+      lzo1x_decompress(&[20, b'a', b'b', b'c', 0xa8, 0, 0x11, 0, 0]).unwrap(),
+      b"abcabcabc"
+    );
+
+    // A literal run whose length continues in the next byte, then a match of 5 bytes 19 back
+    let mut stream = vec![0, 1];
+    stream.extend(b"abcdefghijklmnopqrs");
+    stream.extend([0x23, 72, 0, 0x11, 0, 0]);
+    assert_eq!(
+      lzo1x_decompress(&stream).unwrap(),
+      b"abcdefghijklmnopqrsabcde"
+    );
+
+    assert!(lzo1x_decompress(&[20, b'a']).is_err());
+    assert!(lzo1x_decompress(&[18, b'a', 0xa8, 1, 0x11, 0, 0]).is_err());
+  }
+
+  #[tokio::test]
+  async fn test_clear_cache_reparses_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_mdx(dir.path());
+    let provider = MdxDictionaryProvider::new(Some(path.clone())).with_config(Config::default());
+    assert!(provider.get_meaning("cherry").await.unwrap().is_none());
+
+    std::fs::write(&path, build_mdx(&[("cherry", "a small red fruit")])).unwrap();
+    assert!(provider.get_meaning("cherry").await.unwrap().is_none());
+    provider.clear_cache().await;
+    assert!(provider.get_meaning("cherry").await.unwrap().is_some());
+  }
+}
@@ -0,0 +1,459 @@
+//! Personal dictionary layered over the main one.
+//!
+//! Entries use the JSON dictionary format (`{"word": {"noun": ["definition"]}}`) and are
+//! written back to `personal_dictionary_path` on every change. A personal entry replaces the
+//! main dictionary's entry for the same word, and personal words are offered in completion.
+use crate::config::{Config, MatchMode};
+use crate::dictionary_data::{
+  DictionaryProvider, DictionaryResponse, DictionaryStats, JsonDictionaryProvider, WordCheck,
+};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::Position;
+
+/// Personal dictionaries opened so far by path, so every handler's provider and the editing
+/// commands share the same entries
+static SHARED: Lazy<Mutex<HashMap<String, Arc<PersonalDictionary>>>> =
+  Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The writable entries of a personal dictionary file
+pub struct PersonalDictionary {
+  path: PathBuf,
+  entries: RwLock<Map<String, Value>>,
+  /// False when the file exists but could not be parsed; it is then never overwritten
+  writable: bool,
+}
+
+impl PersonalDictionary {
+  /// Reads the dictionary at `path`. A missing file is an empty dictionary.
+  pub fn open(path: impl Into<PathBuf>) -> Self {
+    let path = path.into();
+    let (entries, writable) = match std::fs::read_to_string(&path) {
+      Ok(contents) => match serde_json::from_str::<Map<String, Value>>(&contents) {
+        Ok(entries) => (entries, true),
+        Err(e) => {
+          eprintln!(
+            "Error parsing personal dictionary {}: {}",
+            path.display(),
+            e
+          );
+          (Map::new(), false)
+        }
+      },
+      Err(e) if e.kind() == io::ErrorKind::NotFound => (Map::new(), true),
+      Err(e) => {
+        eprintln!(
+          "Error reading personal dictionary {}: {}",
+          path.display(),
+          e
+        );
+        (Map::new(), false)
+      }
+    };
+
+    Self {
+      path,
+      entries: RwLock::new(entries),
+      writable,
+    }
+  }
+
+  /// The dictionary at `path`, opened once and shared by every caller
+  pub fn shared(path: &str) -> Arc<Self> {
+    SHARED
+      .lock()
+      .unwrap()
+      .entry(path.to_string())
+      .or_insert_with(|| Arc::new(Self::open(path)))
+      .clone()
+  }
+
+  /// Appends a definition of `word` under `part_of_speech`
+  pub fn add_definition(
+    &self,
+    word: &str,
+    part_of_speech: &str,
+    definition: &str,
+  ) -> io::Result<()> {
+    self.update(|entries| {
+      let entry = entries
+        .entry(word.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+      if !entry.is_object() {
+        *entry = Value::Object(Map::new());
+      }
+      let definitions = entry
+        .as_object_mut()
+        .unwrap()
+        .entry(part_of_speech.to_string())
+        .or_insert_with(|| Value::Array(Vec::new()));
+      match definitions {
+        Value::Array(definitions) => definitions.push(Value::from(definition)),
+        other => *other = Value::Array(vec![Value::from(definition)]),
+      }
+    })
+  }
+
+  /// Replaces the definitions of `word` under `part_of_speech`; an empty list removes the
+  /// part of speech, and the word once it has none left
+  pub fn set_definitions(
+    &self,
+    word: &str,
+    part_of_speech: &str,
+    definitions: Vec<String>,
+  ) -> io::Result<()> {
+    self.update(|entries| {
+      if definitions.is_empty() {
+        if let Some(Value::Object(entry)) = entries.get_mut(word) {
+          entry.remove(part_of_speech);
+          if entry.values().all(|value| !value.is_array()) {
+            entries.remove(word);
+          }
+        }
+        return;
+      }
+
+      let entry = entries
+        .entry(word.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+      if !entry.is_object() {
+        *entry = Value::Object(Map::new());
+      }
+      entry
+        .as_object_mut()
+        .unwrap()
+        .insert(part_of_speech.to_string(), Value::from(definitions));
+    })
+  }
+
+  /// Removes every definition of `word`, returning whether it had any
+  pub fn remove(&self, word: &str) -> io::Result<bool> {
+    let mut removed = false;
+    self.update(|entries| removed = entries.remove(word).is_some())?;
+    Ok(removed)
+  }
+
+  /// Applies `change` and writes the result to disk, leaving the entries untouched on failure
+  fn update(&self, change: impl FnOnce(&mut Map<String, Value>)) -> io::Result<()> {
+    if !self.writable {
+      return Err(io::Error::other(format!(
+        "not overwriting unreadable personal dictionary {}",
+        self.path.display()
+      )));
+    }
+
+    let mut entries = self.entries.write().unwrap();
+    let mut updated = entries.clone();
+    change(&mut updated);
+
+    if let Some(parent) = self.path.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(&updated).map_err(io::Error::other)?;
+    std::fs::write(&self.path, json)?;
+    *entries = updated;
+    Ok(())
+  }
+
+  /// The headword and entry matching `word`, folding case unless lookups are case sensitive
+  fn entry(&self, word: &str, config: &Config) -> Option<(String, Value)> {
+    let entries = self.entries.read().unwrap();
+    if let Some(entry) = entries.get(word) {
+      return Some((word.to_string(), entry.clone()));
+    }
+    let folded = config.lookup.fold_case(word);
+    entries
+      .iter()
+      .find(|(headword, _)| config.lookup.fold_case(headword) == folded)
+      .map(|(headword, entry)| (headword.clone(), entry.clone()))
+  }
+
+  /// Headwords starting with `prefix`, alphabetically
+  fn words_with_prefix(&self, prefix: &str, config: &Config) -> Vec<String> {
+    let prefix = config.lookup.fold_case(prefix);
+    // Map keys are already sorted
+    self
+      .entries
+      .read()
+      .unwrap()
+      .keys()
+      .filter(|headword| config.lookup.fold_case(headword).starts_with(&prefix))
+      .cloned()
+      .collect()
+  }
+}
+
+/// Source named on the meanings of personal entries
+pub const PERSONAL_SOURCE: &str = "personal";
+
+/// Serves personal entries first and falls back to the main dictionary
+pub struct PersonalDictionaryProvider {
+  personal: Arc<PersonalDictionary>,
+  main: Box<dyn DictionaryProvider>,
+  config: Config,
+}
+
+impl PersonalDictionaryProvider {
+  pub fn new(personal: Arc<PersonalDictionary>, main: Box<dyn DictionaryProvider>) -> Self {
+    Self {
+      personal,
+      main,
+      config: Config::get(),
+    }
+  }
+
+  /// Overrides the configuration snapshot taken at construction time
+  pub fn with_config(mut self, config: Config) -> Self {
+    self.config = config;
+    self
+  }
+
+  /// Puts the personal words matching `query` in front of the main dictionary's
+  fn merge_words(&self, query: &str, main: Option<Vec<String>>) -> Option<Vec<String>> {
+    let mut words = self.personal.words_with_prefix(query, &self.config);
+    for word in main.into_iter().flatten() {
+      if !words.contains(&word) {
+        words.push(word);
+      }
+    }
+    (!words.is_empty()).then_some(words)
+  }
+}
+
+#[async_trait]
+impl DictionaryProvider for PersonalDictionaryProvider {
+  async fn get_meaning(&self, word: &str) -> Result<Option<DictionaryResponse>> {
+    match self.personal.entry(word, &self.config) {
+      Some((headword, entry)) => {
+        let mut dictionary = Map::new();
+        dictionary.insert(headword.clone(), entry);
+        let response = JsonDictionaryProvider::from_value(Value::Object(dictionary))
+          .with_config(self.config.clone())
+          .get_meaning(&headword)
+          .await?;
+        Ok(response.map(|mut response| {
+          for meaning in &mut response.meanings {
+            meaning
+              .source
+              .get_or_insert_with(|| PERSONAL_SOURCE.to_string());
+          }
+          response
+        }))
+      }
+      None => self.main.get_meaning(word).await,
+    }
+  }
+
+  async fn get_meanings(
+    &self,
+    words: &[&str],
+  ) -> Result<Vec<(String, Option<DictionaryResponse>)>> {
+    let mut meanings = Vec::with_capacity(words.len());
+    let mut missed = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+      let personal = if self.personal.entry(word, &self.config).is_some() {
+        self.get_meaning(word).await?
+      } else {
+        missed.push(i);
+        None
+      };
+      meanings.push((word.to_string(), personal));
+    }
+
+    // Words without a personal entry go to the main dictionary in one batch
+    let missed_words: Vec<&str> = missed.iter().map(|&i| words[i]).collect();
+    let from_main = self.main.get_meanings(&missed_words).await?;
+    for (i, (_, meaning)) in missed.into_iter().zip(from_main) {
+      meanings[i].1 = meaning;
+    }
+    Ok(meanings)
+  }
+
+  fn get_word_at_position(&self, content: &str, position: Position) -> Option<String> {
+    self.main.get_word_at_position(content, position)
+  }
+
+  async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>> {
+    if prefix.is_empty() {
+      return Ok(None);
+    }
+    let main = self.main.find_words_by_prefix(prefix).await?;
+    Ok(self.merge_words(prefix, main))
+  }
+
+  async fn find_words(&self, query: &str, mode: MatchMode) -> Result<Option<Vec<String>>> {
+    if query.is_empty() {
+      return Ok(None);
+    }
+    let main = self.main.find_words(query, mode).await?;
+    Ok(self.merge_words(query, main))
+  }
+
+  async fn stats(&self) -> Result<DictionaryStats> {
+    self.main.stats().await
+  }
+
+  async fn find_words_by_definition(&self, query: &str) -> Result<Vec<String>> {
+    self.main.find_words_by_definition(query).await
+  }
+
+  async fn check_word(&self, word: &str) -> Result<WordCheck> {
+    if self.personal.entry(word, &self.config).is_some() {
+      return Ok(WordCheck {
+        known: true,
+        suggestions: Vec::new(),
+      });
+    }
+    self.main.check_word(word).await
+  }
+
+  async fn random_words(&self, count: usize) -> Result<Vec<String>> {
+    self.main.random_words(count).await
+  }
+
+  async fn warm_up(&self) -> Result<()> {
+    self.main.warm_up().await
+  }
+
+  async fn clear_cache(&self) {
+    self.main.clear_cache().await;
+  }
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::dictionary_data::create_in_memory_provider;
+
+  fn provider_with(personal: Arc<PersonalDictionary>) -> PersonalDictionaryProvider {
+    let main = create_in_memory_provider(serde_json::json!({
+      "rust": {"noun": ["iron oxide"]},
+      "rusty": {"adjective": ["covered with rust"]}
+    }));
+    PersonalDictionaryProvider::new(personal, main).with_config(Config::default())
+  }
+
+  async fn first_definition(provider: &PersonalDictionaryProvider, word: &str) -> Option<String> {
+    provider
+      .get_meaning(word)
+      .await
+      .unwrap()
+      .map(|response| response.meanings[0].definitions[0].definition.clone())
+  }
+
+  #[tokio::test]
+  async fn test_add_override_remove_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("personal").join("dictionary.json");
+    let personal = Arc::new(PersonalDictionary::open(&path));
+    let provider = provider_with(personal.clone());
+
+    // Add a new word, which completion offers before the main dictionary's words
+    personal
+      .add_definition("rustacean", "noun", "a Rust programmer")
+      .unwrap();
+    assert_eq!(
+      first_definition(&provider, "rustacean").await.as_deref(),
+      Some("a Rust programmer")
+    );
+    assert_eq!(
+      provider
+        .find_words_by_prefix("rust")
+        .await
+        .unwrap()
+        .unwrap(),
+      vec!["rustacean", "rust", "rusty"]
+    );
+
+    // Override a word of the main dictionary, and edit the override
+    personal
+      .add_definition("rust", "noun", "a programming language")
+      .unwrap();
+    assert_eq!(
+      first_definition(&provider, "Rust").await.as_deref(),
+      Some("a programming language")
+    );
+    personal
+      .set_definitions("rust", "noun", vec!["a systems language".to_string()])
+      .unwrap();
+    assert_eq!(
+      first_definition(&provider, "rust").await.as_deref(),
+      Some("a systems language")
+    );
+
+    // Entries survive a restart
+    let reopened = provider_with(Arc::new(PersonalDictionary::open(&path)));
+    assert_eq!(
+      first_definition(&reopened, "rust").await.as_deref(),
+      Some("a systems language")
+    );
+
+    // Removing the override brings back the main dictionary's definition
+    assert!(personal.remove("rust").unwrap());
+    assert!(!personal.remove("rust").unwrap());
+    assert_eq!(
+      first_definition(&provider, "rust").await.as_deref(),
+      Some("iron oxide")
+    );
+    personal
+      .set_definitions("rustacean", "noun", Vec::new())
+      .unwrap();
+    assert_eq!(first_definition(&provider, "rustacean").await, None);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+  }
+
+  #[tokio::test]
+  async fn test_meanings_name_their_source() {
+    let dir = tempfile::tempdir().unwrap();
+    let personal = Arc::new(PersonalDictionary::open(dir.path().join("dictionary.json")));
+    let provider = provider_with(personal.clone());
+    personal
+      .add_definition("rustacean", "noun", "a Rust programmer")
+      .unwrap();
+
+    let sources = |response: Option<DictionaryResponse>| -> Vec<Option<String>> {
+      response
+        .unwrap()
+        .meanings
+        .into_iter()
+        .map(|meaning| meaning.source)
+        .collect()
+    };
+    assert_eq!(
+      sources(provider.get_meaning("rustacean").await.unwrap()),
+      vec![Some(PERSONAL_SOURCE.to_string())]
+    );
+    assert_eq!(
+      sources(provider.get_meaning("rust").await.unwrap()),
+      vec![None]
+    );
+
+    // Sources given by the dictionary itself are kept
+    let main = create_in_memory_provider(serde_json::json!({
+      "rust": {"noun": ["iron oxide"], "source": "WordNet"}
+    }));
+    let provider = PersonalDictionaryProvider::new(personal, main).with_config(Config::default());
+    assert_eq!(
+      sources(provider.get_meaning("rust").await.unwrap()),
+      vec![Some("WordNet".to_string())]
+    );
+  }
+
+  #[test]
+  fn test_unreadable_file_is_never_overwritten() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dictionary.json");
+    std::fs::write(&path, "{not json").unwrap();
+
+    let personal = PersonalDictionary::open(&path);
+    assert!(personal.add_definition("word", "noun", "text").is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "{not json");
+  }
+}
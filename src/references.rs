@@ -0,0 +1,115 @@
+use crate::config::LookupConfig;
+use crate::dictionary_data::extract_word_at_position_with;
+use crate::document_highlight::find_word_ranges;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{Location, ReferenceParams, Url};
+
+pub struct ReferencesHandler {
+  document_map: Arc<Mutex<HashMap<Url, String>>>,
+  lookup: LookupConfig,
+}
+
+impl ReferencesHandler {
+  pub fn new(document_map: Arc<Mutex<HashMap<Url, String>>>, lookup: LookupConfig) -> Self {
+    Self {
+      document_map,
+      lookup,
+    }
+  }
+
+  /// Handles references requests by finding the word at the cursor position
+  /// and locating it in every open document, not only the current one.
+  pub async fn on_references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+    let position = params.text_document_position.position;
+    let document_uri = params.text_document_position.text_document.uri;
+
+    let documents = self.document_map.lock().await;
+    let word = match documents
+      .get(&document_uri)
+      .and_then(|content| extract_word_at_position_with(content, position, &self.lookup))
+    {
+      Some(word) => word,
+      None => return Ok(None),
+    };
+
+    // Sort by URI so results are stable across requests
+    let mut uris: Vec<&Url> = documents.keys().collect();
+    uris.sort();
+
+    let locations = uris
+      .into_iter()
+      .flat_map(|uri| {
+        find_word_ranges(&documents[uri], &word, &self.lookup)
+          .into_iter()
+          .map(move |range| Location {
+            uri: uri.clone(),
+            range,
+          })
+      })
+      .collect();
+
+    Ok(Some(locations))
+  }
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::document_highlight::tests::{lookup, range};
+  use tower_lsp::lsp_types::{
+    PartialResultParams, Position, ReferenceContext, TextDocumentIdentifier,
+    TextDocumentPositionParams, WorkDoneProgressParams,
+  };
+
+  async fn references(case_sensitive: bool) -> Vec<Location> {
+    let first = Url::parse("file:///a.md").unwrap();
+    let second = Url::parse("file:///b.md").unwrap();
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    {
+      let mut documents = document_map.lock().await;
+      documents.insert(first.clone(), "lexicon entry\nthe Lexicon".to_string());
+      documents.insert(second.clone(), "a lexicon, lexicons".to_string());
+    }
+    let handler = ReferencesHandler::new(document_map, lookup(case_sensitive));
+
+    let params = ReferenceParams {
+      text_document_position: TextDocumentPositionParams {
+        text_document: TextDocumentIdentifier { uri: first },
+        position: Position {
+          line: 0,
+          character: 2,
+        },
+      },
+      work_done_progress_params: WorkDoneProgressParams::default(),
+      partial_result_params: PartialResultParams::default(),
+      context: ReferenceContext {
+        include_declaration: true,
+      },
+    };
+
+    handler.on_references(params).await.unwrap().unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_references_span_all_open_documents() {
+    let locations: Vec<(String, _)> = references(false)
+      .await
+      .into_iter()
+      .map(|location| (location.uri.path().to_string(), location.range))
+      .collect();
+    assert_eq!(
+      locations,
+      vec![
+        ("/a.md".to_string(), range(0, 0, 7)),
+        ("/a.md".to_string(), range(1, 4, 11)),
+        ("/b.md".to_string(), range(0, 2, 9)),
+      ]
+    );
+
+    assert_eq!(references(true).await.len(), 2);
+  }
+}
@@ -0,0 +1,246 @@
+//! Startup self-test.
+//!
+//! Checks that the configured dictionary and frequency database can be read, so a wrong
+//! path or an empty database shows up in the LSP log instead of as silently empty hovers.
+//! `dictionary_lsp --selftest` runs the same checks once and exits.
+use crate::config::Config;
+use crate::dictionary_data::{create_dictionary_provider, validate_sql_schema};
+use std::fmt;
+use std::path::Path;
+use tower_lsp::jsonrpc::ErrorCode;
+
+/// Word looked up to check that definitions can be read
+const SAMPLE_WORD: &str = "the";
+/// Prefix completed to check that completion works
+const SAMPLE_PREFIX: &str = "a";
+
+/// Outcome of one self-test check
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+  pub name: &'static str,
+  pub passed: bool,
+  pub detail: String,
+}
+
+impl Check {
+  fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+    Self {
+      name,
+      passed: true,
+      detail: detail.into(),
+    }
+  }
+
+  fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+    Self {
+      name,
+      passed: false,
+      detail: detail.into(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTestReport {
+  pub checks: Vec<Check>,
+}
+
+impl SelfTestReport {
+  pub fn passed(&self) -> bool {
+    self.checks.iter().all(|check| check.passed)
+  }
+}
+
+impl fmt::Display for SelfTestReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (i, check) in self.checks.iter().enumerate() {
+      if i > 0 {
+        writeln!(f)?;
+      }
+      let status = if check.passed { "ok" } else { "FAIL" };
+      write!(f, "[{}] {}: {}", status, check.name, check.detail)?;
+    }
+    Ok(())
+  }
+}
+
+/// Runs every check against the dictionary and frequency database of `config`
+pub async fn run(config: &Config) -> SelfTestReport {
+  let provider = create_dictionary_provider(
+    config.dictionary_path.clone(),
+    config.freq_path.clone(),
+    config,
+  );
+  let mut checks = Vec::new();
+
+  checks.push(match config.dictionary_path.as_deref() {
+    None => Check::fail("dictionary", "dictionary_path is not set"),
+    Some(path) if !Path::new(path).exists() => {
+      Check::fail("dictionary", format!("{} does not exist", path))
+    }
+    Some(path) => match provider.stats().await {
+      Ok(stats) if stats.words == 0 => Check::fail("dictionary", format!("{} has no words", path)),
+      Ok(stats) => Check::pass("dictionary", format!("{} ({} words)", path, stats.words)),
+      // Formats without statistics are checked by the lookup below
+      Err(e) if e.code == ErrorCode::MethodNotFound => Check::pass("dictionary", path),
+      Err(e) => Check::fail(
+        "dictionary",
+        format!("{} could not be read: {}", path, e.message),
+      ),
+    },
+  });
+  checks.extend(check_sql_schema(config));
+
+  checks.push(match provider.get_meaning(SAMPLE_WORD).await {
+    Ok(response) => Check::pass(
+      "lookup",
+      format!(
+        "\"{}\" {}",
+        SAMPLE_WORD,
+        if response.is_some() {
+          "found"
+        } else {
+          "not found"
+        }
+      ),
+    ),
+    Err(e) => Check::fail(
+      "lookup",
+      format!("\"{}\" failed: {}", SAMPLE_WORD, e.message),
+    ),
+  });
+
+  checks.push(check_frequency_database(config.freq_path.as_deref()));
+
+  checks.push(match provider.find_words_by_prefix(SAMPLE_PREFIX).await {
+    Ok(words) => Check::pass(
+      "completion",
+      format!(
+        "{} words for \"{}\"",
+        words.map_or(0, |words| words.len()),
+        SAMPLE_PREFIX
+      ),
+    ),
+    Err(e) => Check::fail(
+      "completion",
+      format!("\"{}\" failed: {}", SAMPLE_PREFIX, e.message),
+    ),
+  });
+
+  SelfTestReport { checks }
+}
+
+/// Checks the `sql_schema` mapping against an existing SQLite dictionary
+fn check_sql_schema(config: &Config) -> Option<Check> {
+  let path = config.dictionary_path.as_deref()?;
+  if !Config::is_sqlite(Some(path)) || !Path::new(path).exists() {
+    return None;
+  }
+
+  let validated =
+    rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+      .map_err(|e| e.to_string())
+      .and_then(|conn| validate_sql_schema(&conn, &config.sql_schema));
+  Some(match validated {
+    Ok(()) => Check::pass("schema", "tables and columns found"),
+    Err(e) => Check::fail(
+      "schema",
+      format!("{} does not match sql_schema: {}", path, e),
+    ),
+  })
+}
+
+fn check_frequency_database(freq_path: Option<&str>) -> Check {
+  let path = match freq_path {
+    Some(path) => path,
+    None => return Check::fail("frequency", "freq_path is not set"),
+  };
+  if !Path::new(path).exists() {
+    return Check::fail("frequency", format!("{} does not exist", path));
+  }
+
+  let count =
+    rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+      .and_then(|conn| {
+        conn.query_row("SELECT COUNT(*) FROM word_frequencies", [], |row| {
+          row.get::<_, i64>(0)
+        })
+      });
+  match count {
+    Ok(0) => Check::fail("frequency", format!("{} has no words", path)),
+    Ok(count) => Check::pass("frequency", format!("{} ({} words)", path, count)),
+    Err(e) => Check::fail("frequency", format!("{} could not be read: {}", path, e)),
+  }
+}
+
+/// Entry point for `dictionary_lsp --selftest`: prints the report and fails if any check did
+#[tokio::main]
+pub async fn run_command() -> Result<(), String> {
+  let report = run(&Config::get()).await;
+  println!("{}", report);
+  if report.passed() {
+    Ok(())
+  } else {
+    Err("Self-test failed".to_string())
+  }
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_self_test_reports_good_and_broken_setups() {
+    let dir = tempfile::tempdir().unwrap();
+    let dictionary_path = dir.path().join("dictionary.json");
+    std::fs::write(
+      &dictionary_path,
+      r#"{"the": {"article": ["definite article"]}, "apple": {"noun": ["a fruit"]}}"#,
+    )
+    .unwrap();
+    let freq_path = dir.path().join("freq.db");
+    let conn = rusqlite::Connection::open(&freq_path).unwrap();
+    conn
+      .execute_batch(
+        "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER);
+         INSERT INTO word_frequencies VALUES ('the', 100), ('apple', 10);",
+      )
+      .unwrap();
+    drop(conn);
+
+    let good = Config {
+      dictionary_path: Some(dictionary_path.to_string_lossy().into_owned()),
+      freq_path: Some(freq_path.to_string_lossy().into_owned()),
+      ..Config::default()
+    };
+    let report = run(&good).await;
+    assert!(report.passed(), "{}", report);
+    assert!(report.to_string().contains("(2 words)"));
+    assert!(report.to_string().contains("[ok] lookup: \"the\" found"));
+
+    let broken = Config {
+      dictionary_path: Some(
+        dir
+          .path()
+          .join("missing.json")
+          .to_string_lossy()
+          .into_owned(),
+      ),
+      freq_path: None,
+      ..Config::default()
+    };
+    let report = run(&broken).await;
+    assert!(!report.passed());
+    let failed: Vec<&str> = report
+      .checks
+      .iter()
+      .filter(|check| !check.passed)
+      .map(|check| check.name)
+      .collect();
+    assert_eq!(
+      failed,
+      vec!["dictionary", "lookup", "frequency", "completion"]
+    );
+  }
+}
@@ -1,5 +1,7 @@
 use crate::config::Config;
-use crate::dictionary_data::{create_dictionary_provider, DictionaryProvider};
+use crate::dictionary_data::{
+  self, create_dictionary_provider, Definition, DictionaryProvider, DictionaryResponse,
+};
 use crate::formatting;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -23,13 +25,29 @@ impl SignatureHelpHandler {
     freq_path: Option<String>,
     config: Config,
   ) -> Self {
+    // Signature help shows full entries; truncation only applies to hover
+    let mut config = config;
+    config.formatting.max_definition_chars = None;
+    config.formatting.max_definitions_per_pos = None;
+    config.formatting.max_examples_per_definition = None;
+
     Self {
       document_map,
-      dictionary_loader: create_dictionary_provider(dictionary_path, freq_path),
+      dictionary_loader: create_dictionary_provider(dictionary_path, freq_path, &config),
       config,
     }
   }
 
+  /// Drops cached definitions, e.g. after the dictionary was reloaded
+  pub async fn clear_cache(&self) {
+    self.dictionary_loader.clear_cache().await;
+  }
+
+  /// Loads the dictionary ahead of the first signature help request
+  pub async fn warm_up(&self) -> Result<()> {
+    self.dictionary_loader.warm_up().await
+  }
+
   /// Handles signature help requests by finding the word at the cursor position
   /// and providing its dictionary definition in signature help format.
   pub async fn on_signature_help(
@@ -41,17 +59,22 @@ impl SignatureHelpHandler {
 
     let content = self.get_document_content(&document_uri).await?;
 
-    if let Some(word) = self
-      .dictionary_loader
-      .get_word_at_position(&content, position)
+    if let Some(word) = dictionary_data::lookup_word_at_position(
+      self.dictionary_loader.as_ref(),
+      &content,
+      position,
+      &self.config.lookup,
+    )
+    .await
     {
       match self.dictionary_loader.get_meaning(&word).await {
         Ok(Some(response)) => Ok(Some(
           self.create_signature_help_for_definition(&response.word, &response),
         )),
-        Ok(None) => Ok(Some(
+        Ok(None) if self.config.signature_help.show_missing_definition => Ok(Some(
           self.create_signature_help_for_missing_definition(&word),
         )),
+        Ok(None) => Ok(None),
         Err(_) => Ok(None),
       }
     } else {
@@ -74,8 +97,16 @@ impl SignatureHelpHandler {
   fn create_signature_help_for_definition(
     &self,
     _word: &str,
-    response: &crate::dictionary_data::DictionaryResponse,
+    response: &DictionaryResponse,
   ) -> SignatureHelp {
+    let without_examples;
+    let response = if self.config.signature_help.show_examples {
+      response
+    } else {
+      without_examples = strip_examples(response.clone());
+      &without_examples
+    };
+
     // Format the entire dictionary response as hover-like content
     let value = formatting::format_definition_as_markdown_with_config(
       &response.word,
@@ -103,7 +134,8 @@ impl SignatureHelpHandler {
   /// Creates signature help object for a word without a definition
   fn create_signature_help_for_missing_definition(&self, word: &str) -> SignatureHelp {
     let signatures = vec![SignatureInformation {
-      label: format!("No definition found for '{}'", word),
+      label: formatting::format_not_found(word, &self.config.formatting)
+        .unwrap_or_else(|| format!("No definition found for '{}'", word)),
       documentation: None,
       parameters: None,
       active_parameter: None,
@@ -116,3 +148,117 @@ impl SignatureHelpHandler {
     }
   }
 }
+
+/// Removes the examples of every definition and sub-definition
+fn strip_examples(mut response: DictionaryResponse) -> DictionaryResponse {
+  fn strip(definitions: &mut [Definition]) {
+    for definition in definitions {
+      definition.example = None;
+      definition.more_examples.clear();
+      strip(&mut definition.sub_definitions);
+    }
+  }
+
+  for meaning in &mut response.meanings {
+    strip(&mut meaning.definitions);
+  }
+  response
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tower_lsp::lsp_types::{
+    Position, TextDocumentIdentifier, TextDocumentPositionParams, WorkDoneProgressParams,
+  };
+
+  async fn signature_help_for(text: &str, config: Config) -> Option<SignatureHelp> {
+    let dir = tempfile::tempdir().unwrap();
+    let dictionary_path = dir.path().join("dictionary.json");
+    std::fs::write(
+      &dictionary_path,
+      r#"{"run": {"verb": [{
+        "definition": "move quickly on foot",
+        "example": "she runs daily",
+        "sub_definitions": [{"definition": "of a machine: operate", "example": "the engine runs"}]
+      }]}}"#,
+    )
+    .unwrap();
+
+    let uri = Url::parse("file:///test.txt").unwrap();
+    let document_map = Arc::new(Mutex::new(HashMap::new()));
+    document_map
+      .lock()
+      .await
+      .insert(uri.clone(), text.to_string());
+
+    let config = Config {
+      dictionary_path: Some(dictionary_path.to_string_lossy().into_owned()),
+      ..config
+    };
+    let handler =
+      SignatureHelpHandler::new(document_map, config.dictionary_path.clone(), None, config);
+
+    let params = SignatureHelpParams {
+      context: None,
+      text_document_position_params: TextDocumentPositionParams {
+        text_document: TextDocumentIdentifier { uri },
+        position: Position {
+          line: 0,
+          character: 1,
+        },
+      },
+      work_done_progress_params: WorkDoneProgressParams::default(),
+    };
+
+    handler.on_signature_help(params).await.unwrap()
+  }
+
+  fn config_showing_missing(show_missing_definition: bool) -> Config {
+    let mut config = Config::default();
+    config.signature_help.show_missing_definition = show_missing_definition;
+    config
+  }
+
+  #[tokio::test]
+  async fn test_missing_definition_is_shown_by_default() {
+    let help = signature_help_for("zzyzx", config_showing_missing(true))
+      .await
+      .unwrap();
+    assert_eq!(help.signatures[0].label, "No definition found for 'zzyzx'");
+  }
+
+  #[tokio::test]
+  async fn test_missing_definition_can_be_suppressed() {
+    assert!(signature_help_for("zzyzx", config_showing_missing(false))
+      .await
+      .is_none());
+    assert!(signature_help_for("run", config_showing_missing(false))
+      .await
+      .is_some());
+  }
+
+  #[tokio::test]
+  async fn test_examples_can_be_hidden() {
+    let shown = signature_help_for("run", Config::default()).await.unwrap();
+    assert!(shown.signatures[0].label.contains("she runs daily"));
+    assert!(shown.signatures[0].label.contains("the engine runs"));
+
+    let mut config = Config::default();
+    config.signature_help.show_examples = false;
+    let hidden = signature_help_for("run", config).await.unwrap();
+    assert_eq!(
+      hidden.signatures[0].label,
+      "**run**\n_verb_\n1. move quickly on foot\n   a. of a machine: operate\n"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_missing_definition_uses_not_found_format() {
+    let mut config = config_showing_missing(true);
+    config.formatting.not_found_format = Some("Unknown word: {word}".to_string());
+    let help = signature_help_for("zzyzx", config).await.unwrap();
+    assert_eq!(help.signatures[0].label, "Unknown word: zzyzx");
+  }
+}
@@ -0,0 +1,267 @@
+//! Rule-based English lemmatization.
+//!
+//! Strips common inflectional suffixes ("running" -> "run", "tried" -> "try") to guess the
+//! dictionary form of a word. The rules over-generate, so callers keep only candidates that
+//! are actually in the dictionary. [`inflected_forms`] goes the other way, from a headword to
+//! its plurals or conjugations.
+
+/// Words shorter than this are never stemmed ("is", "bed", "sing")
+const MIN_STEM_LEN: usize = 4;
+
+/// Possible dictionary forms of an inflected `word`, most likely first.
+/// Returns nothing for words without a known suffix or outside lowercase ASCII.
+pub fn lemma_candidates(word: &str) -> Vec<String> {
+  if word.len() < MIN_STEM_LEN || !word.bytes().all(|b| b.is_ascii_lowercase()) {
+    return Vec::new();
+  }
+
+  let mut candidates = Vec::new();
+  if let Some(stem) = word
+    .strip_suffix("ies")
+    .or_else(|| word.strip_suffix("ied"))
+  {
+    candidates.push(format!("{}y", stem));
+  }
+  for suffix in ["ing", "ed"] {
+    if let Some(stem) = word.strip_suffix(suffix) {
+      push_verb_stems(stem, &mut candidates);
+    }
+  }
+  if let Some(stem) = word.strip_suffix("es") {
+    candidates.push(stem.to_string());
+  }
+  if let Some(stem) = word.strip_suffix('s').filter(|stem| !stem.ends_with('s')) {
+    candidates.push(stem.to_string());
+  }
+
+  candidates.retain(|candidate| candidate.len() > 1 && candidate != word);
+  candidates.dedup();
+  candidates
+}
+
+/// Stems of a word that lost "-ing" or "-ed": undoubled ("runn" -> "run"),
+/// as is ("walk") and with the silent e restored ("mak" -> "make")
+fn push_verb_stems(stem: &str, candidates: &mut Vec<String>) {
+  let bytes = stem.as_bytes();
+  if bytes.len() >= 3 && bytes[bytes.len() - 1] == bytes[bytes.len() - 2] {
+    candidates.push(stem[..stem.len() - 1].to_string());
+  }
+  candidates.push(stem.to_string());
+  candidates.push(format!("{}e", stem));
+}
+
+/// Irregular verbs as (lemma, past tense, past participle)
+const IRREGULAR_VERBS: &[(&str, &str, &str)] = &[
+  ("be", "was", "been"),
+  ("begin", "began", "begun"),
+  ("break", "broke", "broken"),
+  ("bring", "brought", "brought"),
+  ("build", "built", "built"),
+  ("buy", "bought", "bought"),
+  ("catch", "caught", "caught"),
+  ("choose", "chose", "chosen"),
+  ("come", "came", "come"),
+  ("cut", "cut", "cut"),
+  ("do", "did", "done"),
+  ("drink", "drank", "drunk"),
+  ("drive", "drove", "driven"),
+  ("eat", "ate", "eaten"),
+  ("feel", "felt", "felt"),
+  ("find", "found", "found"),
+  ("fly", "flew", "flown"),
+  ("forget", "forgot", "forgotten"),
+  ("get", "got", "gotten"),
+  ("give", "gave", "given"),
+  ("go", "went", "gone"),
+  ("grow", "grew", "grown"),
+  ("have", "had", "had"),
+  ("hold", "held", "held"),
+  ("keep", "kept", "kept"),
+  ("know", "knew", "known"),
+  ("leave", "left", "left"),
+  ("lose", "lost", "lost"),
+  ("make", "made", "made"),
+  ("meet", "met", "met"),
+  ("pay", "paid", "paid"),
+  ("put", "put", "put"),
+  ("run", "ran", "run"),
+  ("say", "said", "said"),
+  ("see", "saw", "seen"),
+  ("sell", "sold", "sold"),
+  ("send", "sent", "sent"),
+  ("sing", "sang", "sung"),
+  ("sit", "sat", "sat"),
+  ("sleep", "slept", "slept"),
+  ("speak", "spoke", "spoken"),
+  ("stand", "stood", "stood"),
+  ("swim", "swam", "swum"),
+  ("take", "took", "taken"),
+  ("teach", "taught", "taught"),
+  ("tell", "told", "told"),
+  ("think", "thought", "thought"),
+  ("win", "won", "won"),
+  ("write", "wrote", "written"),
+];
+
+/// Irregular noun plurals
+const IRREGULAR_PLURALS: &[(&str, &str)] = &[
+  ("child", "children"),
+  ("foot", "feet"),
+  ("man", "men"),
+  ("mouse", "mice"),
+  ("person", "people"),
+  ("tooth", "teeth"),
+  ("woman", "women"),
+];
+
+/// Inflected forms of the headword `lemma` used as `part_of_speech`: conjugations of verbs
+/// ("run" -> "runs", "running", "ran") and plurals of nouns ("box" -> "boxes").
+/// Returns nothing for other parts of speech or words outside lowercase ASCII.
+pub fn inflected_forms(lemma: &str, part_of_speech: &str) -> Vec<String> {
+  if lemma.len() < 2 || !lemma.bytes().all(|b| b.is_ascii_lowercase()) {
+    return Vec::new();
+  }
+
+  let part_of_speech = part_of_speech.trim().to_lowercase();
+  let mut forms = if part_of_speech.starts_with("verb") || part_of_speech == "v." {
+    verb_forms(lemma)
+  } else if part_of_speech.starts_with("noun") || part_of_speech == "n." {
+    vec![plural(lemma)]
+  } else {
+    Vec::new()
+  };
+
+  let mut seen = std::collections::HashSet::new();
+  forms.retain(|form| form != lemma && seen.insert(form.clone()));
+  forms
+}
+
+/// Third person singular, present participle and past forms of a verb
+fn verb_forms(lemma: &str) -> Vec<String> {
+  let third_person = match lemma {
+    "be" => "is".to_string(),
+    "have" => "has".to_string(),
+    _ => plural(lemma),
+  };
+  let present_participle = if let Some(stem) = lemma.strip_suffix("ie") {
+    format!("{}ying", stem)
+  } else if lemma.ends_with('e') && !lemma.ends_with("ee") && lemma.len() > 2 {
+    format!("{}ing", &lemma[..lemma.len() - 1])
+  } else if doubles_final_consonant(lemma) {
+    format!("{}{}ing", lemma, &lemma[lemma.len() - 1..])
+  } else {
+    format!("{}ing", lemma)
+  };
+
+  let mut forms = vec![third_person, present_participle];
+  match IRREGULAR_VERBS.iter().find(|(verb, _, _)| *verb == lemma) {
+    Some((_, past, participle)) => {
+      forms.push(past.to_string());
+      forms.push(participle.to_string());
+    }
+    None if lemma.ends_with('e') => forms.push(format!("{}d", lemma)),
+    None if ends_with_consonant_y(lemma) => forms.push(format!("{}ied", &lemma[..lemma.len() - 1])),
+    None if doubles_final_consonant(lemma) => {
+      forms.push(format!("{}{}ed", lemma, &lemma[lemma.len() - 1..]))
+    }
+    None => forms.push(format!("{}ed", lemma)),
+  }
+  forms
+}
+
+/// Plural of a noun, also the third person singular of a verb
+fn plural(lemma: &str) -> String {
+  if let Some((_, plural)) = IRREGULAR_PLURALS.iter().find(|(noun, _)| *noun == lemma) {
+    return plural.to_string();
+  }
+  if ends_with_consonant_y(lemma) {
+    format!("{}ies", &lemma[..lemma.len() - 1])
+  } else if ["s", "x", "z", "ch", "sh", "o"]
+    .iter()
+    .any(|suffix| lemma.ends_with(suffix))
+  {
+    format!("{}es", lemma)
+  } else {
+    format!("{}s", lemma)
+  }
+}
+
+fn is_vowel(b: u8) -> bool {
+  matches!(b, b'a' | b'e' | b'i' | b'o' | b'u')
+}
+
+fn ends_with_consonant_y(word: &str) -> bool {
+  let bytes = word.as_bytes();
+  bytes.len() >= 2 && bytes[bytes.len() - 1] == b'y' && !is_vowel(bytes[bytes.len() - 2])
+}
+
+/// Whether a short consonant-vowel-consonant word doubles its last letter before a
+/// suffix ("run" -> "running"); longer words are left alone as the stress is unknown
+fn doubles_final_consonant(word: &str) -> bool {
+  let bytes = word.as_bytes();
+  let n = bytes.len();
+  n >= 3
+    && !is_vowel(bytes[n - 1])
+    && !matches!(bytes[n - 1], b'w' | b'x' | b'y')
+    && is_vowel(bytes[n - 2])
+    && !is_vowel(bytes[n - 3])
+    && bytes.iter().filter(|b| is_vowel(**b)).count() == 1
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn first_known(word: &str, known: &[&str]) -> Option<String> {
+    lemma_candidates(word)
+      .into_iter()
+      .find(|candidate| known.contains(&candidate.as_str()))
+  }
+
+  #[test]
+  fn test_lemma_candidates() {
+    let known = ["run", "walk", "make", "try", "box", "cat", "stop", "like"];
+    assert_eq!(first_known("running", &known).as_deref(), Some("run"));
+    assert_eq!(first_known("walked", &known).as_deref(), Some("walk"));
+    assert_eq!(first_known("making", &known).as_deref(), Some("make"));
+    assert_eq!(first_known("tried", &known).as_deref(), Some("try"));
+    assert_eq!(first_known("boxes", &known).as_deref(), Some("box"));
+    assert_eq!(first_known("cats", &known).as_deref(), Some("cat"));
+    assert_eq!(first_known("stopped", &known).as_deref(), Some("stop"));
+    assert_eq!(first_known("liked", &known).as_deref(), Some("like"));
+
+    assert!(lemma_candidates("glass").is_empty());
+    assert!(lemma_candidates("run").is_empty());
+    assert!(lemma_candidates("Running").is_empty());
+  }
+
+  #[test]
+  fn test_inflected_forms() {
+    assert_eq!(
+      inflected_forms("run", "verb"),
+      vec!["runs", "running", "ran"]
+    );
+    assert_eq!(
+      inflected_forms("walk", "verb"),
+      vec!["walks", "walking", "walked"]
+    );
+    assert_eq!(
+      inflected_forms("make", "verb"),
+      vec!["makes", "making", "made"]
+    );
+    assert_eq!(
+      inflected_forms("try", "verb"),
+      vec!["tries", "trying", "tried"]
+    );
+    assert_eq!(
+      inflected_forms("stop", "verb"),
+      vec!["stops", "stopping", "stopped"]
+    );
+    assert_eq!(inflected_forms("box", "noun"), vec!["boxes"]);
+    assert_eq!(inflected_forms("child", "noun"), vec!["children"]);
+
+    assert!(inflected_forms("quickly", "adverb").is_empty());
+    assert!(inflected_forms("Run", "verb").is_empty());
+  }
+}
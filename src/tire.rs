@@ -1,10 +1,13 @@
+use crate::config::SortOrder;
+use crate::dictionary_data::get_lossy_text;
 use lru::LruCache;
 use once_cell::sync::Lazy;
 use rusqlite;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::num::NonZeroUsize;
-use std::sync::RwLock;
-use std::time::Instant;
+use std::sync::{Mutex, RwLock};
+use std::time::{Instant, SystemTime};
 use tower_lsp::jsonrpc::Error;
 use trie_rs::{Trie, TrieBuilder};
 
@@ -12,20 +15,53 @@ use trie_rs::{Trie, TrieBuilder};
 pub static WORD_FREQUENCIES: Lazy<RwLock<HashMap<String, i64>>> =
   Lazy::new(|| RwLock::new(HashMap::new()));
 pub static GLOBAL_TRIE: Lazy<RwLock<Option<Trie<char>>>> = Lazy::new(|| RwLock::new(None));
-static LAST_INIT_TIME: Lazy<RwLock<Option<Instant>>> = Lazy::new(|| RwLock::new(None));
-pub static PREFIX_CACHE: Lazy<RwLock<LruCache<String, Vec<String>>>> =
+/// Lowercase forms of the words containing uppercase letters, so "nas" can find "NASA"
+static CASED_TRIE: Lazy<RwLock<Option<Trie<char>>>> = Lazy::new(|| RwLock::new(None));
+/// Stored spellings of the words in `CASED_TRIE` by lowercase form, most frequent first
+static CASED_WORDS: Lazy<RwLock<HashMap<String, Vec<String>>>> =
+  Lazy::new(|| RwLock::new(HashMap::new()));
+/// Frequency database the trie was last built from, with its modification time then
+static LAST_INIT_SOURCE: Lazy<RwLock<Option<(String, SystemTime)>>> =
+  Lazy::new(|| RwLock::new(None));
+// Serializes initializations so concurrent callers don't build the trie twice
+static INIT_LOCK: Mutex<()> = Mutex::new(());
+// Frequency-ordered prefix matches, keyed by the prefix and the limit they were cut to
+type PrefixCache = LruCache<(String, usize), Vec<String>>;
+pub static PREFIX_CACHE: Lazy<RwLock<PrefixCache>> =
   Lazy::new(|| RwLock::new(LruCache::new(NonZeroUsize::new(1000).unwrap())));
 
-/// Initialize the global trie from a frequency database
-pub fn initialize_global_trie(freq_path: &str) -> Result<(), Error> {
-  // Check if we already initialized recently (avoid repeated initializations)
-  if let Some(last_time) = *LAST_INIT_TIME.read().unwrap() {
-    if last_time.elapsed().as_secs() < 3600 {
-      // Once per hour is enough
-      return Ok(());
+/// Initialize the global trie from a frequency database.
+/// The trie is only rebuilt when the database changed since the last build, unless `force`.
+pub fn initialize_global_trie(freq_path: &str, force: bool) -> Result<(), Error> {
+  initialize_global_trie_with_progress(freq_path, force, |_, _| {})
+}
+
+/// Like [`initialize_global_trie`], calling `on_progress(loaded, total)` each time another
+/// percent of the words has been loaded
+pub fn initialize_global_trie_with_progress(
+  freq_path: &str,
+  force: bool,
+  mut on_progress: impl FnMut(usize, usize),
+) -> Result<(), Error> {
+  // Callers that lose the race wait here and then see the fresh LAST_INIT_SOURCE below
+  let _init_guard = INIT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+  // Skip the rebuild when the same, unmodified database was loaded already
+  let modified = std::fs::metadata(freq_path)
+    .and_then(|metadata| metadata.modified())
+    .ok();
+  if !force {
+    if let (Some((last_path, last_modified)), Some(modified)) =
+      (&*LAST_INIT_SOURCE.read().unwrap(), modified)
+    {
+      if last_path == freq_path && *last_modified == modified {
+        return Ok(());
+      }
     }
   }
   let mut builder = TrieBuilder::new();
+  let mut cased_builder = TrieBuilder::new();
+  let mut cased_words: HashMap<String, Vec<String>> = HashMap::new();
 
   // Connect to the SQLite frequency database
   let conn = rusqlite::Connection::open(freq_path).map_err(|e| {
@@ -33,6 +69,15 @@ pub fn initialize_global_trie(freq_path: &str) -> Result<(), Error> {
     Error::internal_error()
   })?;
 
+  let total = conn
+    .query_row("SELECT COUNT(*) FROM word_frequencies", [], |row| {
+      row.get::<_, i64>(0)
+    })
+    .map_err(|e| {
+      eprintln!("Failed to count words: {}", e);
+      Error::internal_error()
+    })? as usize;
+
   // Query words from the database with their frequencies
   let mut stmt = conn
     .prepare("SELECT word, frequency FROM word_frequencies ORDER BY frequency DESC")
@@ -43,7 +88,7 @@ pub fn initialize_global_trie(freq_path: &str) -> Result<(), Error> {
 
   let rows = stmt
     .query_map([], |row| {
-      Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+      Ok((get_lossy_text(row, 0)?, row.get::<_, i64>(1)?))
     })
     .map_err(|e| {
       eprintln!("Failed to query words: {}", e);
@@ -51,35 +96,49 @@ pub fn initialize_global_trie(freq_path: &str) -> Result<(), Error> {
     })?;
 
   let start_time = Instant::now();
-  let mut word_count = 0;
+  let mut frequencies = HashMap::new();
 
   // Add all words to the trie in frequency order (already sorted by SQL query)
-  for word_result in rows {
-    let mut freq_map = WORD_FREQUENCIES.write().unwrap();
-    if let Ok((word, freq)) = word_result {
-      let chars: Vec<char> = word.chars().collect();
-      builder.push(&chars);
-      freq_map.insert(word, freq);
-      word_count += 1;
+  let mut reported_percent = 0;
+  for (loaded, (word, freq)) in rows.flatten().enumerate() {
+    let chars: Vec<char> = word.chars().collect();
+    builder.push(&chars);
+    let lowercase = word.to_lowercase();
+    if lowercase != word {
+      if !cased_words.contains_key(&lowercase) {
+        cased_builder.push(lowercase.chars().collect::<Vec<char>>());
+      }
+      cased_words.entry(lowercase).or_default().push(word.clone());
+    }
+    frequencies.insert(word, freq);
+
+    let percent = (loaded + 1) * 100 / total.max(1);
+    if percent > reported_percent {
+      reported_percent = percent;
+      on_progress(loaded + 1, total);
     }
   }
+  let word_count = frequencies.len();
 
   // Build the trie and store it globally
   let trie = builder.build();
 
+  *WORD_FREQUENCIES.write().unwrap() = frequencies;
   {
     let mut trie_guard = GLOBAL_TRIE.write().unwrap();
     *trie_guard = Some(trie);
   }
+  *CASED_TRIE.write().unwrap() = Some(cased_builder.build());
+  *CASED_WORDS.write().unwrap() = cased_words;
 
   // Clear the cache when dictionary is reloaded
   {
     let mut cache = PREFIX_CACHE.write().unwrap();
     cache.clear();
   }
+  crate::fuzzy::invalidate_candidate_cache();
 
-  let mut last_time = LAST_INIT_TIME.write().unwrap();
-  *last_time = Some(Instant::now());
+  *LAST_INIT_SOURCE.write().unwrap() = modified.map(|modified| (freq_path.to_string(), modified));
 
   eprintln!(
     "Trie is initialized with {} words in {:?}",
@@ -95,83 +154,353 @@ pub fn is_trie_initialized() -> bool {
   GLOBAL_TRIE.read().unwrap().is_some()
 }
 
-/// Find words by prefix using the global trie
+/// Frequency of `word` in the loaded frequency database, 0 when unknown
+pub fn frequency(word: &str) -> i64 {
+  *WORD_FREQUENCIES.read().unwrap().get(word).unwrap_or(&0)
+}
+
+/// Number of words loaded from the frequency database
+pub fn word_count() -> usize {
+  WORD_FREQUENCIES.read().unwrap().len()
+}
+
+/// Find words by prefix using the global trie, most frequent first.
+/// Words are stored as they appear in the frequency database, so matching is case sensitive;
+/// callers fold the prefix according to `LookupConfig::case_sensitive`.
 pub fn find_words_by_prefix(prefix: &str, limit: usize) -> Vec<String> {
+  find_words_by_prefix_sorted(prefix, limit, SortOrder::Frequency)
+}
+
+/// Find words by prefix using the global trie, keeping the first `limit` words in `order`
+#[tracing::instrument(level = "debug")]
+pub fn find_words_by_prefix_sorted(prefix: &str, limit: usize, order: SortOrder) -> Vec<String> {
+  if order != SortOrder::Frequency {
+    let char_vec: Vec<char> = prefix.chars().collect();
+    let mut results: Vec<String> = match GLOBAL_TRIE.read().unwrap().as_ref() {
+      Some(trie) => trie
+        .predictive_search(&char_vec)
+        .into_iter()
+        .map(|chars: Vec<char>| chars.into_iter().collect())
+        .collect(),
+      None => Vec::new(),
+    };
+    sort_words(&mut results, order);
+    results.truncate(limit);
+    return results;
+  }
+
+  let key = (prefix.to_string(), limit);
+
   // Check cache with a read lock first (better concurrency)
-  if let Some(cached_results) = PREFIX_CACHE
-    .read()
-    .unwrap()
-    .clone()
-    .get(&prefix.to_string())
-  {
+  if let Some(cached_results) = PREFIX_CACHE.read().unwrap().peek(&key) {
     return cached_results.clone();
   }
 
-  let char_vec: Vec<char> = prefix.chars().collect();
-  let mut results = Vec::with_capacity(limit); // Pre-allocate memory
-
   // Define the tries we'll search in priority order
   let tries = [&GLOBAL_TRIE as &RwLock<Option<Trie<char>>>];
+  let guards: Vec<_> = tries.iter().map(|trie| trie.read().unwrap()).collect();
+  let tiers: Vec<&Trie<char>> = guards.iter().filter_map(|guard| guard.as_ref()).collect();
+  let results = search_tiers(&tiers, prefix, &WORD_FREQUENCIES.read().unwrap(), limit);
+
+  // Cache the results
+  let mut cache = PREFIX_CACHE.write().unwrap();
+  cache.put(key, results.clone());
+
+  results
+}
+
+/// Words starting with `prefix` in any of the `tiers`, ranked together by their single
+/// frequency in `freq_map`. A word stored in several tiers is returned once, so a
+/// lower tier can't push it down or repeat it.
+fn search_tiers(
+  tiers: &[&Trie<char>],
+  prefix: &str,
+  freq_map: &HashMap<String, i64>,
+  limit: usize,
+) -> Vec<String> {
+  let char_vec: Vec<char> = prefix.chars().collect();
+  let mut seen = HashSet::new();
+  let matches = tiers
+    .iter()
+    .flat_map(|trie| trie.predictive_search(&char_vec))
+    .map(|chars: Vec<char>| chars.into_iter().collect::<String>())
+    .filter(|word| seen.insert(word.clone()));
+  top_k_by_frequency(matches, freq_map, limit)
+}
 
-  // Search each trie in order until we have enough results
-  for trie_lock in tries {
-    // Skip if we already have enough results
-    if results.len() >= limit {
-      break;
+/// Selects the `k` most frequent words without sorting every match.
+/// Equal frequencies keep their input order, exactly like a stable sort by descending
+/// frequency followed by `take(k)`.
+pub fn top_k_by_frequency(
+  words: impl Iterator<Item = String>,
+  freq_map: &HashMap<String, i64>,
+  k: usize,
+) -> Vec<String> {
+  if k == 0 {
+    return Vec::new();
+  }
+
+  // Min-heap on (frequency, earlier position wins) holding the best k seen so far
+  let mut heap = BinaryHeap::with_capacity(k + 1);
+  for (index, word) in words.enumerate() {
+    let freq = *freq_map.get(&word).unwrap_or(&0);
+    heap.push(Reverse((freq, Reverse(index), word)));
+    if heap.len() > k {
+      heap.pop();
     }
+  }
 
-    // Search the current trie
-    if let Some(trie) = trie_lock.read().unwrap().as_ref() {
-      let matches = trie
-        .predictive_search(&char_vec)
-        .into_iter()
-        .map(|chars: Vec<char>| chars.into_iter().collect::<String>())
-        // .take(needed) // Only take what we need
-        .collect::<Vec<String>>();
+  heap
+    .into_sorted_vec()
+    .into_iter()
+    .map(|Reverse((_, _, word))| word)
+    .collect()
+}
 
+/// Sorts `words` in place. Frequency order uses the loaded frequencies and keeps the
+/// input order for equal frequencies.
+pub fn sort_words(words: &mut [String], order: SortOrder) {
+  match order {
+    SortOrder::Frequency => {
       let freq_map = WORD_FREQUENCIES.read().unwrap();
-      let mut sorted_matches = matches;
-      sorted_matches.sort_by(|a, b| {
-        freq_map
-          .get(b)
-          .unwrap_or(&0)
-          .cmp(freq_map.get(a).unwrap_or(&0))
+      words.sort_by_key(|word| Reverse(*freq_map.get(word).unwrap_or(&0)));
+    }
+    SortOrder::Alphabetical => words.sort(),
+    SortOrder::Length => {
+      words.sort_by(|a, b| {
+        a.chars()
+          .count()
+          .cmp(&b.chars().count())
+          .then_with(|| a.cmp(b))
       });
-
-      results.extend(sorted_matches.into_iter().take(limit - results.len()));
     }
   }
+}
 
-  // Cache the results
-  let mut cache = PREFIX_CACHE.write().unwrap();
-  cache.put(prefix.to_string(), results.clone());
+/// Keeps the candidates present in the in-memory frequency map, most frequent first.
+/// Used when the frequency database itself cannot be queried.
+pub fn rank_known_words(candidates: &[String], limit: usize) -> Vec<String> {
+  let freq_map = WORD_FREQUENCIES.read().unwrap();
+  let known = candidates
+    .iter()
+    .filter(|word| freq_map.contains_key(*word))
+    .cloned();
+  top_k_by_frequency(known, &freq_map, limit)
+}
 
-  results
+/// Find words containing `fragment` anywhere, ordered by frequency.
+/// The trie only indexes prefixes, so this scans every known word.
+pub fn find_words_containing(fragment: &str, limit: usize) -> Vec<String> {
+  let freq_map = WORD_FREQUENCIES.read().unwrap();
+  let mut matches: Vec<(&String, &i64)> = freq_map
+    .iter()
+    .filter(|(word, _)| word.contains(fragment))
+    .collect();
+
+  matches.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+  matches
+    .into_iter()
+    .take(limit)
+    .map(|(word, _)| word.clone())
+    .collect()
+}
+
+/// How [`find_words_respecting_case`] cases the words it finds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaseMode {
+  /// Capitalize the first letter of every word when the prefix starts with an uppercase one
+  #[default]
+  CapitalizeFirst,
+  /// Match words of any casing and keep it ("iPhone", "NASA"); only lowercase words follow
+  /// an uppercase prefix
+  Preserve,
 }
 
-pub fn find_words_respecting_case(prefix: &str, limit: usize) -> Vec<String> {
+pub fn find_words_respecting_case(prefix: &str, limit: usize, mode: CaseMode) -> Vec<String> {
   // Get lowercase results
-  let results = find_words_by_prefix(&prefix.to_lowercase(), limit);
+  let results = match mode {
+    CaseMode::CapitalizeFirst => find_words_by_prefix(&prefix.to_lowercase(), limit),
+    CaseMode::Preserve => {
+      find_words_by_prefix_any_case(&prefix.to_lowercase(), limit, SortOrder::Frequency)
+    }
+  };
 
   // Check if the original prefix starts with uppercase
   if prefix.chars().next().map_or(false, |c| c.is_uppercase()) {
     // Capitalize the first letter of each result
     results
       .into_iter()
-      .map(|word| {
-        let mut chars = word.chars();
-        match chars.next() {
-          None => String::new(),
-          Some(first_char) => {
-            let mut result = String::with_capacity(word.len());
-            result.extend(first_char.to_uppercase());
-            result.push_str(chars.as_str());
-            result
-          }
-        }
+      .map(|word| match mode {
+        CaseMode::Preserve if word.chars().any(char::is_uppercase) => word,
+        _ => capitalize_first(&word),
       })
       .collect()
   } else {
     results
   }
 }
+
+/// Words whose lowercase form starts with the lowercase `prefix`, in their stored casing,
+/// keeping the first `limit` words in `order`
+pub fn find_words_by_prefix_any_case(prefix: &str, limit: usize, order: SortOrder) -> Vec<String> {
+  let char_vec: Vec<char> = prefix.chars().collect();
+  let cased_words = CASED_WORDS.read().unwrap();
+  let lowercase: Vec<String> = match GLOBAL_TRIE.read().unwrap().as_ref() {
+    Some(trie) => trie
+      .predictive_search(&char_vec)
+      .map(|chars: Vec<char>| chars.into_iter().collect())
+      .collect(),
+    None => Vec::new(),
+  };
+  let cased: Vec<String> = match CASED_TRIE.read().unwrap().as_ref() {
+    Some(trie) => trie
+      .predictive_search(&char_vec)
+      .flat_map(|chars: Vec<char>| {
+        let key: String = chars.into_iter().collect();
+        cased_words.get(&key).cloned().unwrap_or_default()
+      })
+      .collect(),
+    None => Vec::new(),
+  };
+
+  // A word stored with an uppercase letter after the prefix is found by both searches
+  let mut seen = HashSet::new();
+  let matches = lowercase
+    .into_iter()
+    .chain(cased)
+    .filter(|word| seen.insert(word.clone()));
+  if order == SortOrder::Frequency {
+    return top_k_by_frequency(matches, &WORD_FREQUENCIES.read().unwrap(), limit);
+  }
+  let mut results: Vec<String> = matches.collect();
+  sort_words(&mut results, order);
+  results.truncate(limit);
+  results
+}
+
+/// Uppercases the first character of `word`, which may be multi-byte (e.g. "élan" -> "Élan")
+pub fn capitalize_first(word: &str) -> String {
+  let mut chars = word.chars();
+  match chars.next() {
+    None => String::new(),
+    Some(first_char) => {
+      let mut result = String::with_capacity(word.len());
+      result.extend(first_char.to_uppercase());
+      result.push_str(chars.as_str());
+      result
+    }
+  }
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sort_then_take(words: &[String], freq_map: &HashMap<String, i64>, k: usize) -> Vec<String> {
+    let mut sorted = words.to_vec();
+    sorted.sort_by(|a, b| {
+      freq_map
+        .get(b)
+        .unwrap_or(&0)
+        .cmp(freq_map.get(a).unwrap_or(&0))
+    });
+    sorted.into_iter().take(k).collect()
+  }
+
+  #[test]
+  fn test_top_k_matches_full_sort() {
+    // Frequencies repeat so that tie ordering is exercised too
+    let words: Vec<String> = (0..500).map(|i| format!("word{}", i)).collect();
+    let freq_map: HashMap<String, i64> = words
+      .iter()
+      .enumerate()
+      .filter(|(i, _)| i % 7 != 0)
+      .map(|(i, word)| (word.clone(), ((i * 37) % 50) as i64))
+      .collect();
+
+    for k in [0, 1, 5, 10, 499, 500, 1000] {
+      assert_eq!(
+        top_k_by_frequency(words.iter().cloned(), &freq_map, k),
+        sort_then_take(&words, &freq_map, k),
+        "k = {}",
+        k
+      );
+    }
+  }
+
+  #[test]
+  fn test_word_in_several_tiers_is_ranked_once() {
+    let build = |words: &[&str]| {
+      let mut builder = TrieBuilder::new();
+      for word in words {
+        builder.push(word.chars().collect::<Vec<char>>());
+      }
+      builder.build()
+    };
+    let common = build(&["apple", "apply"]);
+    let rare = build(&["ape", "apple"]);
+    let freq_map: HashMap<String, i64> = [("apple", 10), ("apply", 5), ("ape", 50)]
+      .into_iter()
+      .map(|(word, freq)| (word.to_string(), freq))
+      .collect();
+
+    assert_eq!(
+      search_tiers(&[&common, &rare], "ap", &freq_map, 10),
+      vec!["ape".to_string(), "apple".to_string(), "apply".to_string()]
+    );
+    assert_eq!(
+      search_tiers(&[&common, &rare], "ap", &freq_map, 2),
+      vec!["ape".to_string(), "apple".to_string()]
+    );
+  }
+
+  #[test]
+  fn test_rank_known_words_uses_loaded_frequencies() {
+    // Words unique to this test so other tests sharing the global map are unaffected
+    {
+      let mut freq_map = WORD_FREQUENCIES.write().unwrap();
+      freq_map.insert("qxjrare".to_string(), 5);
+      freq_map.insert("qxjcommon".to_string(), 50);
+    }
+
+    let candidates = vec![
+      "qxjrare".to_string(),
+      "qxjunknown".to_string(),
+      "qxjcommon".to_string(),
+    ];
+    assert_eq!(
+      rank_known_words(&candidates, 5),
+      vec!["qxjcommon".to_string(), "qxjrare".to_string()]
+    );
+  }
+
+  #[test]
+  fn test_sort_words_orders() {
+    {
+      let mut freq_map = WORD_FREQUENCIES.write().unwrap();
+      freq_map.insert("qxsbandana".to_string(), 30);
+      freq_map.insert("qxsbat".to_string(), 20);
+      freq_map.insert("qxsba".to_string(), 10);
+    }
+    let fixture = ["qxsbat", "qxsbandana", "qxsba", "qxsbay"];
+    let sorted = |order| {
+      let mut words: Vec<String> = fixture.iter().map(|word| word.to_string()).collect();
+      sort_words(&mut words, order);
+      words
+    };
+
+    assert_eq!(
+      sorted(SortOrder::Frequency),
+      ["qxsbandana", "qxsbat", "qxsba", "qxsbay"]
+    );
+    assert_eq!(
+      sorted(SortOrder::Alphabetical),
+      ["qxsba", "qxsbandana", "qxsbat", "qxsbay"]
+    );
+    assert_eq!(
+      sorted(SortOrder::Length),
+      ["qxsba", "qxsbat", "qxsbay", "qxsbandana"]
+    );
+  }
+}
@@ -0,0 +1,279 @@
+//! Plain word lists as a dictionary without definitions.
+//!
+//! Reads a newline-delimited list (`.txt`) or a hunspell dictionary (`.dic`), so an installed
+//! aspell/hunspell word list is enough for completion and spell checking. A line may carry
+//! a frequency after the word ("apple 120"); hunspell affix flags ("apple/S") and the word
+//! count on the first line of a `.dic` file are ignored.
+use crate::config::{Config, SortOrder};
+use crate::dictionary_data::{
+  decode_dictionary_bytes, extract_word_at_position_with, normalize_word, rank_suggestions,
+  DictionaryError, DictionaryProvider, DictionaryResponse, DictionaryStats, PrefixIndex, WordCheck,
+  SPELLING_MAX_DISTANCE,
+};
+use crate::fuzzy;
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::Position;
+
+/// Words of a list with the frequencies given next to them
+pub struct WordList {
+  /// Frequency of each word, 0 when the list gives none
+  frequencies: HashMap<String, i64>,
+  /// Case-folded words, for lookups
+  folded: HashSet<String>,
+  index: PrefixIndex,
+  /// Some line gave a frequency, so prefix matches are ranked by it
+  has_frequencies: bool,
+}
+
+impl WordList {
+  /// Parses a word list, folding words with `fold_case` for lookups
+  pub fn parse(contents: &str, fold_case: impl Fn(&str) -> String) -> Self {
+    let mut frequencies = HashMap::new();
+    let mut has_frequencies = false;
+
+    for (i, line) in contents.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let mut fields = line.split_whitespace();
+      let entry = fields.next().unwrap_or_default();
+      // The first line of a hunspell dictionary is its word count
+      if i == 0 && entry.parse::<usize>().is_ok() && fields.next().is_none() {
+        continue;
+      }
+      let word = normalize_word(entry.split('/').next().unwrap_or_default());
+      if word.is_empty() {
+        continue;
+      }
+      let frequency = fields.next().and_then(|field| field.parse::<i64>().ok());
+      has_frequencies |= frequency.is_some();
+      let entry = frequencies.entry(word).or_insert(0);
+      *entry = (*entry).max(frequency.unwrap_or(0));
+    }
+
+    Self {
+      folded: frequencies.keys().map(|word| fold_case(word)).collect(),
+      index: PrefixIndex::new(frequencies.keys(), &fold_case),
+      frequencies,
+      has_frequencies,
+    }
+  }
+
+  pub fn open(path: &str, fold_case: impl Fn(&str) -> String) -> std::io::Result<Self> {
+    let bytes = std::fs::read(path)?;
+    Ok(Self::parse(&decode_dictionary_bytes(bytes), fold_case))
+  }
+
+  pub fn len(&self) -> usize {
+    self.frequencies.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.frequencies.is_empty()
+  }
+
+  /// Whether the folded `word` is in the list
+  pub fn contains(&self, word: &str) -> bool {
+    self.folded.contains(word)
+  }
+
+  /// Words starting with the folded `prefix`, most frequent first when the list gives
+  /// frequencies and in `order` otherwise
+  pub fn words_with_prefix(&self, prefix: &str, order: SortOrder, limit: usize) -> Vec<String> {
+    let mut words: Vec<String> = self.index.words_with_prefix(prefix).cloned().collect();
+    if self.has_frequencies && order == SortOrder::Frequency {
+      // Stable, so equally frequent words stay alphabetical
+      words.sort_by_key(|word| std::cmp::Reverse(self.frequencies[word]));
+    } else if order != SortOrder::Alphabetical {
+      crate::tire::sort_words(&mut words, order);
+    }
+    words.truncate(limit);
+    words
+  }
+
+  /// Words within `SPELLING_MAX_DISTANCE` edits of the folded `word` sharing its first letter
+  fn suggestions(&self, word: &str) -> Vec<String> {
+    let first = word.chars().next();
+    let candidates = self
+      .frequencies
+      .keys()
+      .filter(|candidate| candidate.chars().next() == first)
+      .map(|candidate| {
+        (
+          candidate.clone(),
+          fuzzy::levenshtein_distance(word, candidate),
+        )
+      })
+      .filter(|(_, distance)| *distance <= SPELLING_MAX_DISTANCE)
+      .collect();
+    rank_suggestions(word, candidates)
+  }
+}
+
+/// Provider for plain word lists: completion and spell checking, no definitions
+pub struct WordListProvider {
+  dictionary_path: Option<String>,
+  word_list: tokio::sync::Mutex<Option<Arc<WordList>>>,
+  config: Config,
+}
+
+impl WordListProvider {
+  /// The file is read on first use rather than here.
+  pub fn new(dictionary_path: Option<String>) -> Self {
+    Self {
+      dictionary_path,
+      word_list: tokio::sync::Mutex::new(None),
+      config: Config::get(),
+    }
+  }
+
+  /// Overrides the configuration snapshot taken at construction time
+  pub fn with_config(mut self, config: Config) -> Self {
+    self.config = config;
+    self
+  }
+
+  async fn load_word_list(&self) -> Result<Arc<WordList>> {
+    let mut cache = self.word_list.lock().await;
+    if let Some(word_list) = &*cache {
+      return Ok(word_list.clone());
+    }
+
+    let path = self
+      .dictionary_path
+      .clone()
+      .ok_or_else(|| DictionaryError::NotConfigured("Dictionary path not provided".to_string()))?;
+    let lookup = self.config.lookup.clone();
+    let word_list = tokio::task::spawn_blocking(move || {
+      WordList::open(&path, |word| lookup.fold_case(word))
+        .map_err(|e| DictionaryError::IoError(format!("Error reading word list {}: {}", path, e)))
+    })
+    .await
+    .map_err(|e| DictionaryError::QueryError(format!("Word list loading failed: {}", e)))??;
+
+    let word_list = Arc::new(word_list);
+    *cache = Some(word_list.clone());
+    Ok(word_list)
+  }
+}
+
+#[async_trait]
+impl DictionaryProvider for WordListProvider {
+  /// Word lists have no definitions
+  async fn get_meaning(&self, _word: &str) -> Result<Option<DictionaryResponse>> {
+    Ok(None)
+  }
+
+  fn get_word_at_position(&self, content: &str, position: Position) -> Option<String> {
+    extract_word_at_position_with(content, position, &self.config.lookup)
+  }
+
+  async fn find_words_by_prefix(&self, prefix: &str) -> Result<Option<Vec<String>>> {
+    if prefix.is_empty() {
+      return Ok(None);
+    }
+
+    let word_list = self.load_word_list().await?;
+    let words = word_list.words_with_prefix(
+      &self.config.lookup.fold_case(&normalize_word(prefix)),
+      self.config.completion.sort_order,
+      self.config.completion.item_limit() + 1,
+    );
+
+    if words.is_empty() {
+      Ok(None)
+    } else {
+      Ok(Some(words))
+    }
+  }
+
+  async fn stats(&self) -> Result<DictionaryStats> {
+    Ok(DictionaryStats {
+      words: self.load_word_list().await?.len(),
+      trie_words: crate::tire::word_count(),
+      ..Default::default()
+    })
+  }
+
+  async fn check_word(&self, word: &str) -> Result<WordCheck> {
+    let word = self.config.lookup.fold_case(&normalize_word(word));
+    let word_list = self.load_word_list().await?;
+    if word_list.contains(&word) {
+      return Ok(WordCheck {
+        known: true,
+        suggestions: Vec::new(),
+      });
+    }
+
+    Ok(WordCheck {
+      known: false,
+      suggestions: word_list.suggestions(&word),
+    })
+  }
+
+  async fn warm_up(&self) -> Result<()> {
+    self.load_word_list().await.map(|_| ())
+  }
+
+  async fn clear_cache(&self) {
+    *self.word_list.lock().await = None;
+  }
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_hunspell_dictionary() {
+    let list = WordList::parse(
+      "4\napple/S\nApply/DSG\nbanana\n# comment\n\napple/M\n",
+      str::to_lowercase,
+    );
+    assert_eq!(list.len(), 3);
+    assert!(list.contains("apply"));
+    assert!(!list.contains("4"));
+    assert_eq!(
+      list.words_with_prefix("ap", SortOrder::Alphabetical, 10),
+      vec!["apple".to_string(), "Apply".to_string()]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_provider_completes_and_checks_words() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("words.txt");
+    std::fs::write(&path, "apple 50\napply 80\nape 10\nbanana 30\n").unwrap();
+    let provider = WordListProvider::new(Some(path.to_string_lossy().into_owned()))
+      .with_config(Config::default());
+
+    assert_eq!(
+      provider.find_words_by_prefix("ap").await.unwrap(),
+      Some(vec![
+        "apply".to_string(),
+        "apple".to_string(),
+        "ape".to_string()
+      ])
+    );
+    assert_eq!(provider.find_words_by_prefix("ch").await.unwrap(), None);
+    assert!(provider.get_meaning("apple").await.unwrap().is_none());
+
+    assert!(provider.check_word("Banana").await.unwrap().known);
+    let check = provider.check_word("appel").await.unwrap();
+    assert!(!check.known);
+    assert!(check.suggestions.contains(&"apple".to_string()));
+    assert_eq!(provider.stats().await.unwrap().words, 4);
+  }
+
+  #[test]
+  fn test_word_lists_are_detected_by_extension() {
+    assert!(Config::is_word_list(Some("/usr/share/hunspell/en_US.dic")));
+    assert!(Config::is_word_list(Some("words.TXT")));
+    assert!(!Config::is_word_list(Some("dictionary.json")));
+  }
+}
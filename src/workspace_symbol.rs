@@ -0,0 +1,152 @@
+use crate::config::Config;
+use crate::dictionary_data::{create_dictionary_provider, DictionaryProvider, DictionaryResponse};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+  Location, Range, SymbolInformation, SymbolKind, Url, WorkspaceSymbolParams,
+};
+
+/// Serves `workspace/symbol` by treating dictionary headwords as symbols,
+/// so the editor's symbol picker doubles as a dictionary browser.
+pub struct WorkspaceSymbolHandler {
+  dictionary_provider: Box<dyn DictionaryProvider>,
+  /// Every symbol points at the dictionary file itself
+  dictionary_uri: Option<Url>,
+}
+
+impl WorkspaceSymbolHandler {
+  pub fn new(dictionary_path: String, freq_path: String, config: &Config) -> Self {
+    Self {
+      dictionary_uri: Url::from_file_path(&dictionary_path).ok(),
+      dictionary_provider: create_dictionary_provider(
+        Some(dictionary_path),
+        Some(freq_path),
+        config,
+      ),
+    }
+  }
+
+  /// Drops cached lookups, e.g. after the dictionary was reloaded
+  pub async fn clear_cache(&self) {
+    self.dictionary_provider.clear_cache().await;
+  }
+
+  /// Loads the dictionary ahead of the first symbol query
+  pub async fn warm_up(&self) -> Result<()> {
+    self.dictionary_provider.warm_up().await
+  }
+
+  /// Returns the headwords starting with the query, ranked like completion items,
+  /// with each word's primary definition shown as its container name.
+  pub async fn on_symbol(
+    &self,
+    params: WorkspaceSymbolParams,
+  ) -> Result<Option<Vec<SymbolInformation>>> {
+    let uri = match &self.dictionary_uri {
+      Some(uri) => uri.clone(),
+      None => return Ok(None),
+    };
+
+    let words = match self
+      .dictionary_provider
+      .find_words_by_prefix(params.query.trim())
+      .await?
+    {
+      Some(words) => words,
+      None => return Ok(None),
+    };
+
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let meanings = self.dictionary_provider.get_meanings(&words).await?;
+
+    let mut symbols = Vec::with_capacity(meanings.len());
+    for (word, meaning) in meanings {
+      #[allow(deprecated)]
+      symbols.push(SymbolInformation {
+        name: word,
+        kind: SymbolKind::STRING,
+        tags: None,
+        deprecated: None,
+        location: Location {
+          uri: uri.clone(),
+          range: Range::default(),
+        },
+        container_name: meaning.as_ref().and_then(primary_definition),
+      });
+    }
+
+    Ok(Some(symbols))
+  }
+}
+
+/// First definition of the first part of speech, on a single line
+fn primary_definition(response: &DictionaryResponse) -> Option<String> {
+  response
+    .meanings
+    .iter()
+    .flat_map(|meaning| meaning.definitions.iter())
+    .map(|definition| {
+      definition
+        .definition
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+    })
+    .find(|definition| !definition.is_empty())
+}
+
+/////// Tests ///////
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tower_lsp::lsp_types::{PartialResultParams, WorkDoneProgressParams};
+
+  #[tokio::test]
+  async fn test_prefix_query_returns_ranked_symbols() {
+    {
+      let mut frequencies = crate::tire::WORD_FREQUENCIES.write().unwrap();
+      frequencies.insert("qxwlow".to_string(), 1);
+      frequencies.insert("qxwhigh".to_string(), 100);
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let dictionary_path = dir.path().join("dictionary.json");
+    std::fs::write(
+      &dictionary_path,
+      r#"{"qxwlow": {"noun": ["rarely\n used"]}, "qxwhigh": {"noun": ["often used"]},
+          "qxwmid": {"verb": ["sometimes used"]}, "other": {"noun": ["unrelated"]}}"#,
+    )
+    .unwrap();
+    let handler = WorkspaceSymbolHandler::new(
+      dictionary_path.to_string_lossy().into_owned(),
+      dir.path().join("freq.db").to_string_lossy().into_owned(),
+      &Config::default(),
+    );
+
+    let symbols = handler
+      .on_symbol(WorkspaceSymbolParams {
+        query: "qxw".to_string(),
+        work_done_progress_params: WorkDoneProgressParams::default(),
+        partial_result_params: PartialResultParams::default(),
+      })
+      .await
+      .unwrap()
+      .unwrap();
+
+    let found: Vec<(&str, Option<&str>)> = symbols
+      .iter()
+      .map(|symbol| (symbol.name.as_str(), symbol.container_name.as_deref()))
+      .collect();
+    assert_eq!(
+      found,
+      vec![
+        ("qxwhigh", Some("often used")),
+        ("qxwlow", Some("rarely used")),
+        ("qxwmid", Some("sometimes used")),
+      ]
+    );
+    assert_eq!(
+      symbols[0].location.uri,
+      Url::from_file_path(&dictionary_path).unwrap()
+    );
+  }
+}
@@ -0,0 +1,46 @@
+//! Trie initialization touches process-wide state, so it is exercised in its own test binary
+//! instead of alongside the unit tests that expect the global trie to be empty.
+use dictionary_lsp::tire;
+use std::thread;
+
+#[test]
+fn test_concurrent_initialize_global_trie() {
+  let dir = tempfile::tempdir().unwrap();
+  let freq_path = dir.path().join("freq.db");
+  let conn = rusqlite::Connection::open(&freq_path).unwrap();
+  conn
+    .execute(
+      "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER)",
+      [],
+    )
+    .unwrap();
+  let words = [("apple", 50), ("apply", 30), ("ape", 80), ("banana", 10)];
+  for (word, freq) in words {
+    conn
+      .execute(
+        "INSERT INTO word_frequencies (word, frequency) VALUES (?1, ?2)",
+        rusqlite::params![word, freq],
+      )
+      .unwrap();
+  }
+  drop(conn);
+
+  let freq_path = freq_path.to_string_lossy().to_string();
+  let handles: Vec<_> = (0..8)
+    .map(|_| {
+      let freq_path = freq_path.clone();
+      thread::spawn(move || tire::initialize_global_trie(&freq_path, false))
+    })
+    .collect();
+  for handle in handles {
+    assert!(handle.join().unwrap().is_ok());
+  }
+
+  assert!(tire::is_trie_initialized());
+  assert_eq!(tire::word_count(), words.len());
+  assert_eq!(tire::frequency("ape"), 80);
+  assert_eq!(
+    tire::find_words_by_prefix("ap", 10),
+    vec!["ape".to_string(), "apple".to_string(), "apply".to_string()]
+  );
+}
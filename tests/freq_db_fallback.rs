@@ -0,0 +1,34 @@
+//! Completing without the frequency database falls back to the process-wide trie, so it runs
+//! in its own test binary like `trie_reinit.rs`.
+use dictionary_lsp::config::{Config, MatchMode};
+use dictionary_lsp::dictionary_data::{DictionaryProvider, SqliteDictionaryProvider};
+use dictionary_lsp::tire;
+
+#[tokio::test]
+async fn test_missing_freq_db_degrades_instead_of_failing() {
+  let dir = tempfile::tempdir().unwrap();
+  let trie_path = dir.path().join("trie.db");
+  rusqlite::Connection::open(&trie_path)
+    .unwrap()
+    .execute_batch(
+      "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER);
+       INSERT INTO word_frequencies VALUES
+         ('apple', 50), ('maple', 30), ('ample', 20), ('banana', 10);",
+    )
+    .unwrap();
+  tire::initialize_global_trie(&trie_path.to_string_lossy(), false).unwrap();
+
+  let missing = dir.path().join("missing").join("freq.db");
+  let mut config = Config::default();
+  // Two-edit candidates are sampled from an unordered set, so keep to one edit
+  config.fuzzy.distance_2_min_length = usize::MAX;
+  let sqlite = SqliteDictionaryProvider::new(None, Some(missing.to_string_lossy().into_owned()))
+    .with_config(config);
+
+  // No word starts with "mapple", so its corrections are ranked by the trie's frequencies
+  assert_eq!(
+    sqlite.find_words_by_prefix("mapple").await.unwrap(),
+    Some(vec!["apple".to_string(), "maple".to_string()])
+  );
+  assert!(sqlite.find_words("apl", MatchMode::Contains).await.is_ok());
+}
@@ -0,0 +1,109 @@
+//! Indexing the frequency database builds the process-wide trie, so the progress reported
+//! by `initialized` is checked in its own test binary.
+use dictionary_lsp::config::Config;
+use dictionary_lsp::dictionary_lsp::serve;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+async fn send<W: AsyncWriteExt + Unpin>(writer: &mut W, message: Value) {
+  let body = message.to_string();
+  writer
+    .write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+    .await
+    .unwrap();
+}
+
+async fn receive<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Value {
+  let mut length = 0;
+  loop {
+    let mut header = String::new();
+    reader.read_line(&mut header).await.unwrap();
+    let header = header.trim();
+    if header.is_empty() {
+      break;
+    }
+    if let Some(value) = header.strip_prefix("Content-Length:") {
+      length = value.trim().parse().unwrap();
+    }
+  }
+  let mut body = vec![0; length];
+  reader.read_exact(&mut body).await.unwrap();
+  serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_trie_initialization_reports_progress() {
+  let dir = tempfile::tempdir().unwrap();
+  let freq_path = dir.path().join("freq.db");
+  let conn = rusqlite::Connection::open(&freq_path).unwrap();
+  conn
+    .execute_batch(
+      "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER);
+       INSERT INTO word_frequencies VALUES ('apple', 50), ('apply', 30), ('ape', 80), ('banana', 10);",
+    )
+    .unwrap();
+  drop(conn);
+  let config = Config {
+    dictionary_path: Some(
+      dir
+        .path()
+        .join("dictionary.json")
+        .to_string_lossy()
+        .into_owned(),
+    ),
+    freq_path: Some(freq_path.to_string_lossy().into_owned()),
+    ..Config::default()
+  };
+
+  let (client, server) = tokio::io::duplex(64 * 1024);
+  let (server_read, server_write) = tokio::io::split(server);
+  tokio::spawn(serve(server_read, server_write, config));
+  let (client_read, mut client_write) = tokio::io::split(client);
+  let mut client_read = BufReader::new(client_read);
+
+  send(
+    &mut client_write,
+    json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+  )
+  .await;
+  assert_eq!(receive(&mut client_read).await["id"], 1);
+  send(
+    &mut client_write,
+    json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+  )
+  .await;
+
+  let mut progress = Vec::new();
+  while progress.last().map(|(kind, _)| kind) != Some(&"end".to_string()) {
+    let message = receive(&mut client_read).await;
+    match message["method"].as_str() {
+      Some("window/workDoneProgress/create") => {
+        send(
+          &mut client_write,
+          json!({"jsonrpc": "2.0", "id": message["id"], "result": null}),
+        )
+        .await;
+      }
+      Some("$/progress") => {
+        let value = &message["params"]["value"];
+        progress.push((
+          value["kind"].as_str().unwrap().to_string(),
+          value["percentage"].as_u64(),
+        ));
+      }
+      _ => {}
+    }
+  }
+
+  let kinds: Vec<&str> = progress.iter().map(|(kind, _)| kind.as_str()).collect();
+  assert_eq!(
+    kinds,
+    vec!["begin", "report", "report", "report", "report", "end"]
+  );
+  let percentages: Vec<Option<u64>> = progress.iter().map(|(_, percentage)| *percentage).collect();
+  assert_eq!(
+    percentages,
+    vec![Some(0), Some(25), Some(50), Some(75), Some(100), None]
+  );
+  assert_eq!(dictionary_lsp::tire::word_count(), 4);
+}
@@ -0,0 +1,27 @@
+//! Prefix lookups are cached in the process-wide trie module, so they get their own
+//! test binary.
+use dictionary_lsp::tire;
+
+#[test]
+fn test_cached_prefix_lookup_respects_the_limit() {
+  let dir = tempfile::tempdir().unwrap();
+  let freq_path = dir.path().join("freq.db");
+  let conn = rusqlite::Connection::open(&freq_path).unwrap();
+  conn
+    .execute_batch(
+      "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER);
+       INSERT INTO word_frequencies VALUES
+         ('apple', 50), ('apply', 40), ('apricot', 30), ('banana', 10);",
+    )
+    .unwrap();
+  tire::initialize_global_trie(&freq_path.to_string_lossy(), true).unwrap();
+
+  // A short list cached first must not cut off a longer request for the same prefix
+  assert_eq!(tire::find_words_by_prefix("ap", 1), vec!["apple"]);
+  assert_eq!(
+    tire::find_words_by_prefix("ap", 10),
+    vec!["apple", "apply", "apricot"]
+  );
+  assert_eq!(tire::find_words_by_prefix("ap", 2), vec!["apple", "apply"]);
+  assert_eq!(tire::find_words_by_prefix("ap", 1), vec!["apple"]);
+}
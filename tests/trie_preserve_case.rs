@@ -0,0 +1,46 @@
+//! Case-preserving trie searches need the process-wide trie, so they run in their own test
+//! binary like `trie_reinit.rs`.
+use dictionary_lsp::config::Config;
+use dictionary_lsp::dictionary_data::{DictionaryProvider, SqliteDictionaryProvider};
+use dictionary_lsp::tire::{self, CaseMode};
+
+#[tokio::test]
+async fn test_preserve_mode_keeps_stored_casing() {
+  let dir = tempfile::tempdir().unwrap();
+  let freq_path = dir.path().join("freq.db");
+  rusqlite::Connection::open(&freq_path)
+    .unwrap()
+    .execute_batch(
+      "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER);
+       INSERT INTO word_frequencies VALUES
+         ('iPhone', 90), ('iphonography', 5), ('NASA', 80), ('nasal', 40), ('apple', 50);",
+    )
+    .unwrap();
+  tire::initialize_global_trie(&freq_path.to_string_lossy(), false).unwrap();
+
+  let find = |prefix, mode| tire::find_words_respecting_case(prefix, 10, mode);
+  assert_eq!(find("iph", CaseMode::Preserve), ["iPhone", "iphonography"]);
+  assert_eq!(find("Iph", CaseMode::Preserve), ["iPhone", "Iphonography"]);
+  assert_eq!(find("nas", CaseMode::Preserve), ["NASA", "nasal"]);
+  assert_eq!(find("Nas", CaseMode::Preserve), ["NASA", "Nasal"]);
+  assert_eq!(find("App", CaseMode::Preserve), ["Apple"]);
+
+  // The default mode only finds the words stored in lowercase
+  assert_eq!(find("iph", CaseMode::CapitalizeFirst), ["iphonography"]);
+  assert_eq!(find("Nas", CaseMode::CapitalizeFirst), ["Nasal"]);
+
+  // `completion.preserve_case` selects the mode for SQLite completion from the trie
+  let complete = |preserve_case| {
+    let mut config = Config::default();
+    config.completion.preserve_case = preserve_case;
+    SqliteDictionaryProvider::new(None, None).with_config(config)
+  };
+  assert_eq!(
+    complete(true).find_words_by_prefix("nas").await.unwrap(),
+    Some(vec!["NASA".to_string(), "nasal".to_string()])
+  );
+  assert_eq!(
+    complete(false).find_words_by_prefix("nas").await.unwrap(),
+    Some(vec!["nasal".to_string()])
+  );
+}
@@ -0,0 +1,58 @@
+//! Rebuilding the process-wide trie is exercised in its own test binary, like its
+//! initialization in `concurrent_trie_init.rs`.
+use dictionary_lsp::tire;
+use std::time::{Duration, SystemTime};
+
+/// Initializes the trie, returning whether it was rebuilt
+fn rebuild(freq_path: &str, force: bool) -> bool {
+  let mut rebuilt = false;
+  tire::initialize_global_trie_with_progress(freq_path, force, |_, _| rebuilt = true).unwrap();
+  rebuilt
+}
+
+#[test]
+fn test_trie_is_rebuilt_only_when_the_database_changes() {
+  let dir = tempfile::tempdir().unwrap();
+  let freq_path = dir.path().join("freq.db");
+  let conn = rusqlite::Connection::open(&freq_path).unwrap();
+  conn
+    .execute_batch(
+      "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER);
+       INSERT INTO word_frequencies VALUES ('apple', 50), ('banana', 10);",
+    )
+    .unwrap();
+  let freq_path_str = freq_path.to_string_lossy().into_owned();
+
+  assert!(rebuild(&freq_path_str, false));
+  assert_eq!(tire::frequency("apple"), 50);
+
+  // Unchanged file
+  assert!(!rebuild(&freq_path_str, false));
+
+  // Changed file; the modification time is moved explicitly as it may be coarse
+  conn
+    .execute("INSERT INTO word_frequencies VALUES ('cherry', 20)", [])
+    .unwrap();
+  std::fs::File::options()
+    .write(true)
+    .open(&freq_path)
+    .unwrap()
+    .set_modified(SystemTime::now() + Duration::from_secs(60))
+    .unwrap();
+  assert!(rebuild(&freq_path_str, false));
+  assert_eq!(tire::frequency("cherry"), 20);
+  assert!(!rebuild(&freq_path_str, false));
+
+  // Deleted words are dropped by the rebuild
+  assert_eq!(tire::word_count(), 3);
+  conn
+    .execute("DELETE FROM word_frequencies WHERE word = 'banana'", [])
+    .unwrap();
+  assert!(rebuild(&freq_path_str, true));
+  assert_eq!(tire::word_count(), 2);
+  assert_eq!(tire::frequency("banana"), 0);
+  assert!(tire::find_words_containing("banan", 10).is_empty());
+
+  // Explicit reloads always rebuild
+  assert!(rebuild(&freq_path_str, true));
+}
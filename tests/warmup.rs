@@ -0,0 +1,152 @@
+//! `dictionary.warmup` builds the process-wide trie, so it is exercised in its own test
+//! binary like the indexing in `trie_init_progress.rs`.
+use dictionary_lsp::config::Config;
+use dictionary_lsp::dictionary_lsp::serve;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+async fn send<W: AsyncWriteExt + Unpin>(writer: &mut W, message: Value) {
+  let body = message.to_string();
+  writer
+    .write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+    .await
+    .unwrap();
+}
+
+async fn receive<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Value {
+  let mut length = 0;
+  loop {
+    let mut header = String::new();
+    reader.read_line(&mut header).await.unwrap();
+    let header = header.trim();
+    if header.is_empty() {
+      break;
+    }
+    if let Some(value) = header.strip_prefix("Content-Length:") {
+      length = value.trim().parse().unwrap();
+    }
+  }
+  let mut body = vec![0; length];
+  reader.read_exact(&mut body).await.unwrap();
+  serde_json::from_slice(&body).unwrap()
+}
+
+/// Reads messages until the response to request `id` and, unless `token` is empty, the end
+/// of its progress, answering progress token requests and collecting the progress kinds
+/// reported on `token`. Notifications may arrive after the response.
+async fn response<R, W>(
+  reader: &mut R,
+  writer: &mut W,
+  id: u64,
+  token: &str,
+) -> (Value, Vec<String>)
+where
+  R: AsyncBufReadExt + Unpin,
+  W: AsyncWriteExt + Unpin,
+{
+  let mut progress: Vec<String> = Vec::new();
+  let mut response: Option<Value> = None;
+  loop {
+    let ended = token.is_empty() || progress.last().is_some_and(|kind| kind == "end");
+    if let (Some(response), true) = (&response, ended) {
+      return (response.clone(), progress);
+    }
+    let message = receive(reader).await;
+    if message["id"] == id && message.get("method").is_none() {
+      response = Some(message);
+      continue;
+    }
+    match message["method"].as_str() {
+      Some("window/workDoneProgress/create") => {
+        send(
+          writer,
+          json!({"jsonrpc": "2.0", "id": message["id"], "result": null}),
+        )
+        .await;
+      }
+      Some("$/progress") if message["params"]["token"] == token => {
+        let kind = message["params"]["value"]["kind"].as_str().unwrap();
+        progress.push(kind.to_string());
+      }
+      _ => {}
+    }
+  }
+}
+
+#[tokio::test]
+async fn test_warmup_loads_dictionary_and_trie() {
+  let dir = tempfile::tempdir().unwrap();
+  let json_path = dir.path().join("dictionary.json");
+  std::fs::write(&json_path, r#"{"run": {"verb": ["move quickly on foot"]}}"#).unwrap();
+  let dictionary_path = dir.path().join("dictionary.db");
+  dictionary_lsp::convert::json_to_sqlite(
+    &json_path.to_string_lossy(),
+    &dictionary_path.to_string_lossy(),
+  )
+  .unwrap();
+  let freq_path = dir.path().join("freq.db");
+  rusqlite::Connection::open(&freq_path)
+    .unwrap()
+    .execute_batch(
+      "CREATE TABLE word_frequencies (word TEXT PRIMARY KEY, frequency INTEGER);
+       INSERT INTO word_frequencies VALUES ('run', 50), ('rung', 10);",
+    )
+    .unwrap();
+  let config = Config {
+    dictionary_path: Some(dictionary_path.to_string_lossy().into_owned()),
+    freq_path: Some(freq_path.to_string_lossy().into_owned()),
+    ..Config::default()
+  };
+
+  let (client, server) = tokio::io::duplex(64 * 1024);
+  let (server_read, server_write) = tokio::io::split(server);
+  tokio::spawn(serve(server_read, server_write, config));
+  let (client_read, mut client_write) = tokio::io::split(client);
+  let mut client_read = BufReader::new(client_read);
+
+  send(
+    &mut client_write,
+    json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+  )
+  .await;
+  assert_eq!(receive(&mut client_read).await["id"], 1);
+  send(
+    &mut client_write,
+    json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+  )
+  .await;
+
+  send(
+    &mut client_write,
+    json!({"jsonrpc": "2.0", "id": 2, "method": "workspace/executeCommand", "params": {
+      "command": "dictionary.warmup", "arguments": [], "workDoneToken": "warmup"
+    }}),
+  )
+  .await;
+  let (warmup, progress) = response(&mut client_read, &mut client_write, 2, "warmup").await;
+  assert_eq!(warmup["result"], true);
+  assert_eq!(progress.first().map(String::as_str), Some("begin"));
+  assert_eq!(progress.last().map(String::as_str), Some("end"));
+  assert_eq!(dictionary_lsp::tire::word_count(), 2);
+
+  // The connection opened by the warm-up still reads the removed file, so the hover
+  // below can only be answered from it
+  std::fs::remove_file(&dictionary_path).unwrap();
+  send(
+    &mut client_write,
+    json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {"textDocument": {
+      "uri": "file:///notes.txt", "languageId": "text", "version": 1, "text": "run"
+    }}}),
+  )
+  .await;
+  send(
+    &mut client_write,
+    json!({"jsonrpc": "2.0", "id": 3, "method": "textDocument/hover", "params": {
+      "textDocument": {"uri": "file:///notes.txt"}, "position": {"line": 0, "character": 1}
+    }}),
+  )
+  .await;
+  let (hover, _) = response(&mut client_read, &mut client_write, 3, "").await;
+  let contents = hover["result"]["contents"].to_string();
+  assert!(contents.contains("move quickly on foot"), "{}", contents);
+}